@@ -0,0 +1,113 @@
+//! Measures emulation throughput in frames per second, on synthetic ROMs that each keep one
+//! part of the hardware busy, so refactors of the CPU, PPU or APU can be compared with
+//! numbers.
+//!
+//! - `cpu`: a loop of loads, arithmetic, stack operations and calls that never halts.
+//! - `ppu`: the background, window and sprites on with VRAM and OAM full of patterns, the
+//!   CPU halted.
+//! - `apu`: all four channels playing into an audio sink, the CPU halted.
+//!
+//! Run with `cargo bench`, or `cargo bench -- ppu` for a single workload. To measure a
+//! real ROM, see the `ppu_benchmark` example.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use gb_emulator::audio::{AudioSample, AudioSink};
+use gb_emulator::cartridge::{Cartridge, HeaderBuilder};
+use gb_emulator::hardware::GameboyHardware;
+
+#[rustfmt::skip]
+const CPU_PROGRAM: [u8; 20] = [
+    // DI; LD HL, 0xC000
+    0xF3, 0x21, 0x00, 0xC0,
+    // loop: LD A, (HL); ADD A, L; LD (HL+), A; RES 5, H (stay in WRAM)
+    0x7E, 0x85, 0x22, 0xCB, 0xAC,
+    // PUSH HL; CALL sub; POP HL; JR loop
+    0xE5, 0xCD, 0x60, 0x01, 0xE1, 0x18, 0xF4,
+    // sub: XOR B; RLCA; INC B; RET
+    0xA8, 0x07, 0x04, 0xC9,
+];
+
+#[rustfmt::skip]
+const PPU_PROGRAM: [u8; 41] = [
+    // DI; LD HL, 0x8000
+    0xF3, 0x21, 0x00, 0x80,
+    // Fill VRAM: LD (HL+), A; INC A; BIT 5, H; JR Z, -6
+    0x22, 0x3C, 0xCB, 0x6C, 0x28, 0xFA,
+    // LD HL, 0xFE00
+    0x21, 0x00, 0xFE,
+    // Fill OAM: LD (HL+), A; ADD A, 0x25; LD B, A; LD A, L; CP 0xA0; LD A, B; JR NZ, -10
+    0x22, 0xC6, 0x25, 0x47, 0x7D, 0xFE, 0xA0, 0x78, 0x20, 0xF6,
+    // WY = 72, WX = 87
+    0x3E, 0x48, 0xE0, 0x4A, 0x3E, 0x57, 0xE0, 0x4B,
+    // LCDC = 0xF3: LCD, window, background and sprites on
+    0x3E, 0xF3, 0xE0, 0x40,
+    // IE = 0; loop: HALT; JR loop
+    0xAF, 0xE0, 0xFF, 0x76, 0x18, 0xFD,
+];
+
+#[rustfmt::skip]
+const APU_PROGRAM: [u8; 53] = [
+    // DI; NR52 = 0x80, NR50 = 0x77, NR51 = 0xFF: sound on, full volume, both sides
+    0xF3, 0x3E, 0x80, 0xE0, 0x26, 0x3E, 0x77, 0xE0, 0x24, 0x3E, 0xFF, 0xE0, 0x25,
+    // NR12 = NR22 = NR42 = 0xF0: full volume, no envelope
+    0x3E, 0xF0, 0xE0, 0x12, 0xE0, 0x17, 0xE0, 0x21,
+    // NR11 = 0x80: 50% duty; NR30 = 0x80: wave DAC on; NR32 = 0x20: full volume
+    0x3E, 0x80, 0xE0, 0x11, 0xE0, 0x1A, 0x3E, 0x20, 0xE0, 0x1C,
+    // NR43 = 0x55: noise clock
+    0x3E, 0x55, 0xE0, 0x22,
+    // NR14 = NR24 = NR34 = 0x87, NR44 = 0x80: trigger without length
+    0x3E, 0x87, 0xE0, 0x14, 0xE0, 0x19, 0xE0, 0x1E, 0x3E, 0x80, 0xE0, 0x23,
+    // IE = 0; loop: HALT; JR loop
+    0xAF, 0xE0, 0xFF, 0x76, 0x18, 0xFD,
+];
+
+// One emulated second per iteration
+const FRAMES: u64 = 60;
+const WARMUP_FRAMES: u32 = 60;
+
+struct Sink;
+
+impl AudioSink for Sink {
+    fn sample_rate(&self) -> u32 {
+        48_000
+    }
+
+    fn push_sample(&mut self, sample: &AudioSample) {
+        black_box(sample);
+    }
+}
+
+/// Creates the hardware running `program`, past the frames it spends setting up.
+fn gameboy(program: &[u8]) -> GameboyHardware {
+    let mut gameboy = GameboyHardware::new(Cartridge::new(HeaderBuilder::new().build(program)));
+    for _ in 0..WARMUP_FRAMES {
+        gameboy.run_frame();
+    }
+    gameboy
+}
+
+fn run_frames(c: &mut Criterion) {
+    let mut apu = gameboy(&APU_PROGRAM);
+    apu.set_audio_sink(Some(Box::new(Sink)));
+    let workloads = [
+        ("cpu", gameboy(&CPU_PROGRAM)),
+        ("ppu", gameboy(&PPU_PROGRAM)),
+        ("apu", apu),
+    ];
+
+    let mut group = c.benchmark_group("run_frames");
+    group.throughput(Throughput::Elements(FRAMES));
+    for (name, mut gameboy) in workloads {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..FRAMES {
+                    gameboy.run_frame();
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run_frames);
+criterion_main!(benches);
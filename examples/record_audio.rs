@@ -0,0 +1,45 @@
+//! Runs a ROM headless for a number of seconds, recording its audio to a WAV file.
+//!
+//! With `--stems`, each channel is also written to its own file next to the mix.
+
+use gb_emulator::audio::WavWriter;
+use gb_emulator::cartridge::Cartridge;
+use gb_emulator::consts::FRAMES_PER_SECOND;
+use gb_emulator::hardware::GameboyHardware;
+use std::{env, fs, io, process};
+
+const SAMPLE_RATE: u32 = 48_000;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stems = args.iter().any(|arg| arg == "--stems");
+    let positional: Vec<&String> = args.iter().filter(|arg| *arg != "--stems").collect();
+    let (Some(rom), Some(output)) = (positional.first(), positional.get(1)) else {
+        eprintln!("Usage: record_audio <rom> <output.wav> [seconds] [--stems]");
+        process::exit(2);
+    };
+    let seconds: f64 = match positional.get(2) {
+        Some(seconds) => seconds.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid number of seconds: {seconds}");
+            process::exit(2);
+        }),
+        None => 10.0,
+    };
+
+    let writer = if stems {
+        WavWriter::create_with_stems(output, SAMPLE_RATE)?
+    } else {
+        WavWriter::create(output, SAMPLE_RATE)?
+    };
+    let mut gameboy = GameboyHardware::new(Cartridge::new(fs::read(rom)?));
+    gameboy.set_audio_sink(Some(Box::new(writer)));
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let frames = (seconds * FRAMES_PER_SECOND) as u64;
+    for _ in 0..frames {
+        gameboy.run_frame();
+    }
+    // Dropping the writer finalizes the files
+    drop(gameboy.set_audio_sink(None));
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Runs a ROM headless while reading speed controls from stdin.
+//!
+//! Type a command and press enter:
+//! `p` toggles pause, `n` advances one frame while paused,
+//! `+`/`-` double or halve the speed, `1` resets it and `q` quits.
+
+use gb_emulator::cartridge::Cartridge;
+use gb_emulator::hardware::GameboyHardware;
+use std::time::{Duration, Instant};
+use std::{env, fs, io, process, thread};
+
+fn main() -> io::Result<()> {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: speed_control <rom>");
+        process::exit(2);
+    };
+    let mut gameboy = GameboyHardware::new(Cartridge::new(fs::read(path)?));
+    let handle = gameboy.handle();
+
+    let controls = gameboy.handle();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else { break };
+            match line.trim() {
+                "p" => {
+                    let paused = controls.toggle_paused();
+                    println!("{}", if paused { "Paused" } else { "Resumed" });
+                }
+                "n" => controls.request_frame_step(),
+                "+" => controls.set_speed(controls.speed() * 2.0),
+                "-" => controls.set_speed(controls.speed() / 2.0),
+                "1" => controls.set_speed(1.0),
+                "q" => process::exit(0),
+                _ => println!("Unknown command"),
+            }
+            println!("Speed: {}x", controls.speed());
+        }
+    });
+
+    let mut frames: u64 = 0;
+    loop {
+        let start = Instant::now();
+        if gameboy.run_frame() {
+            frames += 1;
+            if frames.is_multiple_of(60) {
+                println!("Frame {frames}: {:016X}", gameboy.frame_hash());
+            }
+        }
+        let frame_duration = if handle.is_paused() {
+            Duration::from_millis(10)
+        } else {
+            handle.frame_duration()
+        };
+        thread::sleep(frame_duration.saturating_sub(start.elapsed()));
+    }
+}
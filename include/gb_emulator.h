@@ -0,0 +1,48 @@
+/* C interface of gb-emulator, see src/ffi.rs for the documentation of each function. */
+
+#ifndef GB_EMULATOR_H
+#define GB_EMULATOR_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define GB_FRAMEBUFFER_SIZE (160 * 144)
+
+#define GB_BUTTON_A 0
+#define GB_BUTTON_B 1
+#define GB_BUTTON_SELECT 2
+#define GB_BUTTON_START 3
+#define GB_BUTTON_RIGHT 4
+#define GB_BUTTON_LEFT 5
+#define GB_BUTTON_UP 6
+#define GB_BUTTON_DOWN 7
+
+#define GB_OK 0
+#define GB_ERROR_NO_ROM (-1)
+#define GB_ERROR_INVALID_STATE (-2)
+#define GB_ERROR_UNSUPPORTED_VERSION (-3)
+#define GB_ERROR_ROM_MISMATCH (-4)
+#define GB_ERROR_MODEL_MISMATCH (-5)
+
+typedef struct GbInstance GbInstance;
+
+GbInstance *gb_create(void);
+void gb_destroy(GbInstance *instance);
+bool gb_load_rom(GbInstance *instance, const uint8_t *rom, size_t len);
+bool gb_run_frame(GbInstance *instance);
+const uint8_t *gb_framebuffer(const GbInstance *instance);
+uint64_t gb_frame_generation(const GbInstance *instance);
+void gb_set_button(GbInstance *instance, uint8_t button, bool pressed);
+size_t gb_save_state(const GbInstance *instance, uint8_t *buffer, size_t capacity);
+int32_t gb_load_state(GbInstance *instance, const uint8_t *state, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
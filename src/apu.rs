@@ -1,3 +1,10 @@
+use crate::audio::{AudioChannel, AudioSample};
+use crate::clock::ClockEdges;
+use crate::consts::{AUDIO_NATIVE_HZ, CPU_HZ};
+use crate::error::SavestateError;
+use crate::hardware::Model;
+use crate::savestate::{StateReader, StateWriter};
+
 const MEM_NR10: u16 = 0xFF10;
 const MEM_NR11: u16 = 0xFF11;
 const MEM_NR12: u16 = 0xFF12;
@@ -20,6 +27,46 @@ const MEM_NR50: u16 = 0xFF24;
 const MEM_NR51: u16 = 0xFF25;
 const MEM_NR52: u16 = 0xFF26;
 
+pub const WAVE_RAM_SIZE: usize = 0xFF3F - 0xFF30 + 1;
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+// Registers cleared when the APU is powered off, everything except NR52 and wave RAM
+const POWER_OFF_CLEARED: [u16; 20] = [
+    MEM_NR10, MEM_NR11, MEM_NR12, MEM_NR13, MEM_NR14, MEM_NR21, MEM_NR22, MEM_NR23, MEM_NR24,
+    MEM_NR30, MEM_NR31, MEM_NR32, MEM_NR33, MEM_NR34, MEM_NR41, MEM_NR42, MEM_NR43, MEM_NR44,
+    MEM_NR50, MEM_NR51,
+];
+
+/// Returns the bits that always read as 1, either unused or write-only.
+const fn read_mask(addr: u16) -> u8 {
+    match addr {
+        MEM_NR10 => 0x80,
+        MEM_NR11 | MEM_NR21 => 0x3F,
+        MEM_NR13 | MEM_NR23 | MEM_NR31 | MEM_NR33 | MEM_NR41 => 0xFF,
+        MEM_NR14 | MEM_NR24 | MEM_NR34 | MEM_NR44 => 0xBF,
+        MEM_NR30 => 0x7F,
+        MEM_NR32 => 0x9F,
+        MEM_NR52 => 0x70,
+        _ => 0x00,
+    }
+}
+
+/// Returns the length timer bits of a register, which stay writable while the APU
+/// is off on DMG.
+const fn length_timer_bits(addr: u16) -> u8 {
+    match addr {
+        MEM_NR11 | MEM_NR21 | MEM_NR41 => 0x3F,
+        MEM_NR31 => 0xFF,
+        _ => 0x00,
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 struct ChannelSweep(u8);
 
@@ -29,6 +76,18 @@ impl ChannelSweep {
     const INDIVIDUAL_STEP: u8 = 0b0000_0111;
     const UNUSED: u8 = 0b1000_0000;
 
+    const fn pace(self) -> u8 {
+        (self.0 & Self::PACE) >> 4
+    }
+
+    const fn is_decreasing(self) -> bool {
+        self.0 & Self::DIRECTION != 0
+    }
+
+    const fn step(self) -> u8 {
+        self.0 & Self::INDIVIDUAL_STEP
+    }
+
     const fn empty() -> Self {
         Self::from_bits(0)
     }
@@ -49,6 +108,14 @@ impl LengthTimerAndDutyCycle {
     const WAVE_DUTY: u8 = 0b1100_0000;
     const INITIAL_LENGTH_TIMER: u8 = 0b0011_1111;
 
+    const fn duty(self) -> usize {
+        ((self.0 & Self::WAVE_DUTY) >> 6) as usize
+    }
+
+    const fn initial_length(self) -> u8 {
+        self.0 & Self::INITIAL_LENGTH_TIMER
+    }
+
     const fn from_bits(bits: u8) -> Self {
         Self(bits)
     }
@@ -66,6 +133,23 @@ impl VolumeAndEnvelope {
     const ENVELOPE_DIRECTION: u8 = 0b0000_1000;
     const SWEEP_PACE: u8 = 0b0000_0111;
 
+    const fn initial_volume(self) -> u8 {
+        self.0 >> 4
+    }
+
+    const fn is_increasing(self) -> bool {
+        self.0 & Self::ENVELOPE_DIRECTION != 0
+    }
+
+    const fn pace(self) -> u8 {
+        self.0 & Self::SWEEP_PACE
+    }
+
+    /// The DAC is off when the initial volume is 0 and the envelope decreases.
+    const fn is_dac_enabled(self) -> bool {
+        self.0 & (Self::INITIAL_VOLUME | Self::ENVELOPE_DIRECTION) != 0
+    }
+
     const fn empty() -> Self {
         Self::from_bits(0)
     }
@@ -88,6 +172,15 @@ impl PeriodHighAndControl {
     const PERIOD: u8 = 0b0000_0111;
     const UNUSED: u8 = 0b0011_1000;
 
+    const fn is_length_enabled(self) -> bool {
+        self.0 & Self::LENGTH_ENABLE != 0
+    }
+
+    /// Combines the high bits with the low bits written to NRx3 into the 11-bit period.
+    const fn period(self, low: u8) -> u16 {
+        (((self.0 & Self::PERIOD) as u16) << 8) | low as u16
+    }
+
     const fn new() -> Self {
         Self::from_bits(Self::TRIGGER | Self::PERIOD)
     }
@@ -108,6 +201,10 @@ impl DacEnable {
     const ENABLE: u8 = 0b1000_0000;
     const UNUSED: u8 = 0b0111_1111;
 
+    const fn is_enabled(self) -> bool {
+        self.0 & Self::ENABLE != 0
+    }
+
     const fn empty() -> Self {
         Self::from_bits(0)
     }
@@ -128,6 +225,14 @@ impl OutputLevel {
     const OUTPUT_LEVEL: u8 = 0b0110_0000;
     const UNUSED: u8 = 0b1001_1111;
 
+    /// Returns how far wave samples are shifted right, muting them at 4.
+    const fn shift(self) -> u8 {
+        match (self.0 & Self::OUTPUT_LEVEL) >> 5 {
+            0 => 4,
+            level => level - 1,
+        }
+    }
+
     const fn empty() -> Self {
         Self::from_bits(0)
     }
@@ -148,6 +253,10 @@ impl LengthTimer {
     const INITIAL_LENGTH_TIMER: u8 = 0b0011_1111;
     const UNUSED: u8 = 0b1100_0000;
 
+    const fn initial_length(self) -> u8 {
+        self.0 & Self::INITIAL_LENGTH_TIMER
+    }
+
     const fn new() -> Self {
         Self::from_bits(Self::INITIAL_LENGTH_TIMER)
     }
@@ -169,6 +278,19 @@ impl FrequencyAndRandomness {
     const LFSR_WIDTH: u8 = 0b0000_1000;
     const CLOCK_DIVIDER: u8 = 0b0000_0111;
 
+    /// Returns the number of T-cycles between LFSR clocks.
+    const fn period(self) -> u32 {
+        let divider = match self.0 & Self::CLOCK_DIVIDER {
+            0 => 8,
+            divider => divider as u32 * 16,
+        };
+        divider << ((self.0 & Self::CLOCK_SHIFT) >> 4)
+    }
+
+    const fn is_short_mode(self) -> bool {
+        self.0 & Self::LFSR_WIDTH != 0
+    }
+
     const fn empty() -> Self {
         Self::from_bits(0)
     }
@@ -190,6 +312,10 @@ impl Control {
     const LENGTH_ENABLE: u8 = 0b0100_0000;
     const UNUSED: u8 = 0b0011_1111;
 
+    const fn is_length_enabled(self) -> bool {
+        self.0 & Self::LENGTH_ENABLE != 0
+    }
+
     const fn new() -> Self {
         Self::from_bits(Self::TRIGGER)
     }
@@ -207,11 +333,17 @@ impl Control {
 struct MasterVolume(u8);
 
 impl MasterVolume {
-    const VIN_LEFT: u8 = 0b1000_0000;
+    // Bits 7 and 3 mix in VIN from the cartridge, which no supported cartridge drives
     const LEFT_VOLUME: u8 = 0b0111_0000;
-    const VIN_RIGHT: u8 = 0b0000_1000;
     const RIGHT_VOLUME: u8 = 0b0000_0111;
 
+    /// Returns the left and right volume scaled to 0.125-1.0.
+    fn volumes(self) -> [f32; 2] {
+        let left = (self.0 & Self::LEFT_VOLUME) >> 4;
+        let right = self.0 & Self::RIGHT_VOLUME;
+        [f32::from(left + 1) / 8.0, f32::from(right + 1) / 8.0]
+    }
+
     const fn new() -> Self {
         Self::from_bits(Self::LEFT_VOLUME | Self::RIGHT_VOLUME)
     }
@@ -233,11 +365,17 @@ impl SoundPanning {
     const CHANNEL_3_LEFT: u8 = 0b0100_0000;
     const CHANNEL_2_LEFT: u8 = 0b0010_0000;
     const CHANNEL_1_LEFT: u8 = 0b0001_0000;
-    const CHANNEL_4_RIGHT: u8 = 0b0000_1000;
-    const CHANNEL_3_RIGHT: u8 = 0b0000_0100;
     const CHANNEL_2_RIGHT: u8 = 0b0000_0010;
     const CHANNEL_1_RIGHT: u8 = 0b0000_0001;
 
+    /// Returns whether a channel (0-3) is sent to the left and right outputs.
+    const fn is_panned(self, channel: usize) -> [bool; 2] {
+        [
+            self.0 & (Self::CHANNEL_1_LEFT << channel) != 0,
+            self.0 & (Self::CHANNEL_1_RIGHT << channel) != 0,
+        ]
+    }
+
     const fn new() -> Self {
         Self::from_bits(
             Self::CHANNEL_4_LEFT
@@ -270,7 +408,7 @@ impl AudioMasterControl {
     const UNUSED: u8 = 0b0111_0000;
 
     const fn new() -> Self {
-        Self::from_bits(Self::AUDIO_ENABLE | Self::CHANNEL_4_ENABLE)
+        Self::from_bits(Self::AUDIO_ENABLE)
     }
 
     const fn from_bits(bits: u8) -> Self {
@@ -282,6 +420,153 @@ impl AudioMasterControl {
     }
 }
 
+/// Disables a channel once the length timer expires, if enabled in NRx4.
+#[derive(Debug, Clone, Copy)]
+struct LengthCounter {
+    remaining: u16,
+}
+
+impl LengthCounter {
+    const fn new() -> Self {
+        Self { remaining: 0 }
+    }
+
+    fn load(&mut self, max: u16, initial: u8) {
+        self.remaining = max - u16::from(initial);
+    }
+
+    fn trigger(&mut self, max: u16) {
+        if self.remaining == 0 {
+            self.remaining = max;
+        }
+    }
+
+    /// Returns true when the timer expires.
+    fn clock(&mut self, enabled: bool) -> bool {
+        if !enabled || self.remaining == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        self.remaining == 0
+    }
+}
+
+/// Steps the volume up or down at the pace set in NRx2.
+#[derive(Debug, Clone, Copy)]
+struct Envelope {
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    const fn new() -> Self {
+        Self {
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn trigger(&mut self, register: VolumeAndEnvelope) {
+        self.volume = register.initial_volume();
+        self.timer = register.pace();
+    }
+
+    fn clock(&mut self, register: VolumeAndEnvelope) {
+        let pace = register.pace();
+        if pace == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = pace;
+            if register.is_increasing() && self.volume < 15 {
+                self.volume += 1;
+            } else if !register.is_increasing() && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+/// Advances a frequency timer by one M-cycle, returning how many times it expired.
+fn clock_timer(timer: &mut u32, period: u32) -> u32 {
+    let mut cycles = 4;
+    let mut steps = 0;
+    while cycles >= *timer {
+        cycles -= *timer;
+        *timer = period;
+        steps += 1;
+    }
+    *timer -= cycles;
+    steps
+}
+
+/// Converts a digital channel output (0-15) to the DAC's analog output (-1.0-1.0).
+fn dac_output(enabled: bool, digital: u8) -> f32 {
+    if enabled {
+        f32::from(digital) / 7.5 - 1.0
+    } else {
+        0.0
+    }
+}
+
+/// The capacitors on the DMG's outputs, which block the DC offset of the DACs.
+///
+/// Without them, enabling a DAC jumps the output to its idle level and silence sits
+/// off-center. With them, the jump is heard as a click that decays back to zero.
+struct HighPassFilter {
+    enabled: bool,
+    // Fraction of the charge kept per output sample
+    charge_factor: f32,
+    // Charge of each channel's contribution, so the channels still add up to the mix
+    capacitors: [[f32; 2]; 4],
+}
+
+impl HighPassFilter {
+    /// Fraction of the charge kept per T-cycle on the DMG, and on the CGB.
+    const DMG_CHARGE: f64 = 0.999_958;
+    const CGB_CHARGE: f64 = 0.998_943;
+
+    const fn new() -> Self {
+        Self {
+            enabled: false,
+            charge_factor: 1.0,
+            capacitors: [[0.0; 2]; 4],
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn set_sample_rate(&mut self, model: Model, sample_rate: u32) {
+        let charge = match model {
+            Model::Dmg => Self::DMG_CHARGE,
+            Model::Cgb => Self::CGB_CHARGE,
+        };
+        self.charge_factor = charge.powf(f64::from(CPU_HZ) / f64::from(sample_rate)) as f32;
+        self.capacitors = [[0.0; 2]; 4];
+    }
+
+    /// Filters a sample, only charging the capacitors while a DAC is on.
+    fn apply(&mut self, sample: AudioSample, dacs_enabled: bool) -> AudioSample {
+        if !self.enabled {
+            return sample;
+        }
+        let mut filtered = AudioSample::SILENCE;
+        if !dacs_enabled {
+            return filtered;
+        }
+        for (channel, capacitors) in self.capacitors.iter_mut().enumerate() {
+            for (side, capacitor) in capacitors.iter_mut().enumerate() {
+                let input = sample.channels[channel][side];
+                let output = input - *capacitor;
+                *capacitor = input - output * self.charge_factor;
+                filtered.channels[channel][side] = output;
+                filtered.mix[side] += output;
+            }
+        }
+        filtered
+    }
+}
+
 struct Channel1 {
     // NR10
     sweep: ChannelSweep,
@@ -293,11 +578,30 @@ struct Channel1 {
     period_low: u8,
     // NR14
     period_high_and_control: PeriodHighAndControl,
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    // T-cycles until the next duty step
+    frequency_timer: u32,
+    duty_step: u8,
+    sweep_enabled: bool,
+    // Period the sweep calculates from, the registers are only updated on success
+    sweep_shadow: u16,
+    sweep_timer: u8,
 }
 
 impl Channel1 {
     const fn new() -> Self {
         Self {
+            // Left on by the boot sound, which has faded out by now
+            enabled: true,
+            length: LengthCounter::new(),
+            envelope: Envelope::new(),
+            frequency_timer: 0,
+            duty_step: 0,
+            sweep_enabled: false,
+            sweep_shadow: 0,
+            sweep_timer: 0,
             sweep: ChannelSweep::empty(),
             length_timer_and_duty_cycle: LengthTimerAndDutyCycle::from_bits(
                 0b1000_0000 | LengthTimerAndDutyCycle::INITIAL_LENGTH_TIMER,
@@ -309,6 +613,87 @@ impl Channel1 {
             period_high_and_control: PeriodHighAndControl::new(),
         }
     }
+
+    const fn period(&self) -> u16 {
+        self.period_high_and_control.period(self.period_low)
+    }
+
+    fn set_period(&mut self, period: u16) {
+        let [low, high] = period.to_le_bytes();
+        self.period_low = low;
+        self.period_high_and_control = PeriodHighAndControl::from_bits(
+            (self.period_high_and_control.bits() & !PeriodHighAndControl::PERIOD) | high,
+        );
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.volume_and_envelope.is_dac_enabled();
+        self.length.trigger(64);
+        self.envelope.trigger(self.volume_and_envelope);
+        self.frequency_timer = (2048 - u32::from(self.period())) * 4;
+
+        self.sweep_shadow = self.period();
+        self.sweep_timer = self.sweep_reload();
+        self.sweep_enabled = self.sweep.pace() != 0 || self.sweep.step() != 0;
+        if self.sweep.step() != 0 && self.calculate_sweep() > 2047 {
+            self.enabled = false;
+        }
+    }
+
+    const fn sweep_reload(&self) -> u8 {
+        // A pace of 0 is treated as 8 by the timer
+        match self.sweep.pace() {
+            0 => 8,
+            pace => pace,
+        }
+    }
+
+    const fn calculate_sweep(&self) -> u16 {
+        let delta = self.sweep_shadow >> self.sweep.step();
+        if self.sweep.is_decreasing() {
+            self.sweep_shadow - delta
+        } else {
+            self.sweep_shadow + delta
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+        if self.sweep_timer != 0 {
+            return;
+        }
+        self.sweep_timer = self.sweep_reload();
+        if !self.sweep_enabled || self.sweep.pace() == 0 {
+            return;
+        }
+        let period = self.calculate_sweep();
+        if period > 2047 {
+            self.enabled = false;
+        } else if self.sweep.step() != 0 {
+            self.sweep_shadow = period;
+            self.set_period(period);
+            // The next period is checked for overflow right away
+            if self.calculate_sweep() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        let period = (2048 - u32::from(self.period())) * 4;
+        let steps = clock_timer(&mut self.frequency_timer, period);
+        #[allow(clippy::cast_possible_truncation)]
+        let steps = (steps % 8) as u8;
+        self.duty_step = (self.duty_step + steps) % 8;
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let duty = self.length_timer_and_duty_cycle.duty();
+        DUTY_CYCLES[duty][self.duty_step as usize] * self.envelope.volume
+    }
 }
 
 struct Channel2 {
@@ -320,11 +705,22 @@ struct Channel2 {
     period_low: u8,
     // NR24
     period_high_and_control: PeriodHighAndControl,
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    // T-cycles until the next duty step
+    frequency_timer: u32,
+    duty_step: u8,
 }
 
 impl Channel2 {
     const fn new() -> Self {
         Self {
+            enabled: false,
+            length: LengthCounter::new(),
+            envelope: Envelope::new(),
+            frequency_timer: 0,
+            duty_step: 0,
             length_timer_and_duty_cycle: LengthTimerAndDutyCycle::from_bits(
                 LengthTimerAndDutyCycle::INITIAL_LENGTH_TIMER,
             ),
@@ -333,6 +729,33 @@ impl Channel2 {
             period_high_and_control: PeriodHighAndControl::new(),
         }
     }
+
+    const fn period(&self) -> u16 {
+        self.period_high_and_control.period(self.period_low)
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.volume_and_envelope.is_dac_enabled();
+        self.length.trigger(64);
+        self.envelope.trigger(self.volume_and_envelope);
+        self.frequency_timer = (2048 - u32::from(self.period())) * 4;
+    }
+
+    fn tick(&mut self) {
+        let period = (2048 - u32::from(self.period())) * 4;
+        let steps = clock_timer(&mut self.frequency_timer, period);
+        #[allow(clippy::cast_possible_truncation)]
+        let steps = (steps % 8) as u8;
+        self.duty_step = (self.duty_step + steps) % 8;
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let duty = self.length_timer_and_duty_cycle.duty();
+        DUTY_CYCLES[duty][self.duty_step as usize] * self.envelope.volume
+    }
 }
 
 struct Channel3 {
@@ -346,11 +769,25 @@ struct Channel3 {
     period_low: u8,
     // NR34
     period_high_and_control: PeriodHighAndControl,
+    // Wave pattern RAM, two 4-bit samples per byte, high nibble first
+    wave_ram: [u8; WAVE_RAM_SIZE],
+    enabled: bool,
+    length: LengthCounter,
+    // T-cycles until the next sample
+    frequency_timer: u32,
+    position: u8,
+    sample_buffer: u8,
 }
 
 impl Channel3 {
     const fn new() -> Self {
         Self {
+            wave_ram: [0xFF; WAVE_RAM_SIZE],
+            enabled: false,
+            length: LengthCounter::new(),
+            frequency_timer: 0,
+            position: 0,
+            sample_buffer: 0,
             dac_enable: DacEnable::empty(),
             length_timer: 0xFF,
             output_level: OutputLevel::empty(),
@@ -358,6 +795,37 @@ impl Channel3 {
             period_high_and_control: PeriodHighAndControl::new(),
         }
     }
+
+    const fn period(&self) -> u16 {
+        self.period_high_and_control.period(self.period_low)
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enable.is_enabled();
+        self.length.trigger(256);
+        self.frequency_timer = (2048 - u32::from(self.period())) * 2;
+        self.position = 0;
+    }
+
+    fn tick(&mut self) {
+        let period = (2048 - u32::from(self.period())) * 2;
+        for _ in 0..clock_timer(&mut self.frequency_timer, period) {
+            self.position = (self.position + 1) % 32;
+            let byte = self.wave_ram[self.position as usize / 2];
+            self.sample_buffer = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    const fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        self.sample_buffer >> self.output_level.shift()
+    }
 }
 
 struct Channel4 {
@@ -369,20 +837,85 @@ struct Channel4 {
     frequency_and_randomness: FrequencyAndRandomness,
     // NR44
     control: Control,
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    // T-cycles until the next LFSR clock
+    frequency_timer: u32,
+    lfsr: u16,
 }
 
 impl Channel4 {
     const fn new() -> Self {
         Self {
+            enabled: false,
+            length: LengthCounter::new(),
+            envelope: Envelope::new(),
+            frequency_timer: 0,
+            lfsr: 0,
             length_timer: LengthTimer::new(),
             volume_and_envelope: VolumeAndEnvelope::empty(),
             frequency_and_randomness: FrequencyAndRandomness::empty(),
             control: Control::new(),
         }
     }
+
+    fn trigger(&mut self) {
+        self.enabled = self.volume_and_envelope.is_dac_enabled();
+        self.length.trigger(64);
+        self.envelope.trigger(self.volume_and_envelope);
+        self.frequency_timer = self.frequency_and_randomness.period();
+        self.lfsr = 0;
+    }
+
+    fn tick(&mut self) {
+        let period = self.frequency_and_randomness.period();
+        for _ in 0..clock_timer(&mut self.frequency_timer, period) {
+            let bit = !(self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr & !(1 << 15)) | (bit << 15);
+            if self.frequency_and_randomness.is_short_mode() {
+                self.lfsr = (self.lfsr & !(1 << 7)) | (bit << 7);
+            }
+            self.lfsr >>= 1;
+        }
+    }
+
+    const fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 1 == 0 {
+            return 0;
+        }
+        self.envelope.volume
+    }
+}
+
+fn save_channel(
+    writer: &mut StateWriter,
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+) {
+    writer.write_bool(enabled);
+    writer.write_u16(length.remaining);
+    writer.write_u8(envelope.volume);
+    writer.write_u8(envelope.timer);
+}
+
+fn load_channel(
+    reader: &mut StateReader,
+) -> Result<(bool, LengthCounter, Envelope), SavestateError> {
+    let enabled = reader.read_bool()?;
+    let length = LengthCounter {
+        remaining: reader.read_u16()?,
+    };
+    let envelope = Envelope {
+        volume: reader.read_u8()? & 0x0F,
+        timer: reader.read_u8()?,
+    };
+    Ok((enabled, length, envelope))
 }
 
 pub struct Apu {
+    model: Model,
     channel_1: Channel1,
     channel_2: Channel2,
     channel_3: Channel3,
@@ -393,11 +926,30 @@ pub struct Apu {
     sound_panning: SoundPanning,
     // NR52
     audio_master_control: AudioMasterControl,
+    // Stepped by DIV-APU, clocks length, sweep and envelope
+    frame_sequencer_step: u8,
+    // Output samples per second, nothing is generated while unset
+    sample_rate: Option<u32>,
+    // Averages the output between samples
+    sample_phase: u32,
+    sample_sum: AudioSample,
+    sample_count: u32,
+    high_pass: HighPassFilter,
+    // Channels left out of the output by the host, whatever the game does
+    muted: [bool; 4],
 }
 
 impl Apu {
-    pub const fn new() -> Self {
+    pub const fn new(model: Model) -> Self {
         Self {
+            model,
+            frame_sequencer_step: 0,
+            sample_rate: None,
+            sample_phase: 0,
+            sample_sum: AudioSample::SILENCE,
+            sample_count: 0,
+            high_pass: HighPassFilter::new(),
+            muted: [false; 4],
             channel_1: Channel1::new(),
             channel_2: Channel2::new(),
             channel_3: Channel3::new(),
@@ -408,7 +960,213 @@ impl Apu {
         }
     }
 
+    /// Returns the NR52 bits of the channels that are on.
+    fn channel_status(&self) -> u8 {
+        let mut status = 0;
+        for (enabled, bit) in [
+            (self.channel_1.enabled, AudioMasterControl::CHANNEL_1_ENABLE),
+            (self.channel_2.enabled, AudioMasterControl::CHANNEL_2_ENABLE),
+            (self.channel_3.enabled, AudioMasterControl::CHANNEL_3_ENABLE),
+            (self.channel_4.enabled, AudioMasterControl::CHANNEL_4_ENABLE),
+        ] {
+            if enabled {
+                status |= bit;
+            }
+        }
+        status
+    }
+
+    /// Sets the rate samples are produced at by [`Self::tick`], or stops producing them.
+    pub fn set_sample_rate(&mut self, sample_rate: Option<u32>) {
+        self.sample_rate = sample_rate;
+        self.sample_phase = 0;
+        self.sample_sum = AudioSample::SILENCE;
+        self.sample_count = 0;
+        if let Some(sample_rate) = sample_rate {
+            self.high_pass.set_sample_rate(self.model, sample_rate);
+        }
+    }
+
+    /// Enables the high-pass filter removing the DC offset of the DACs from the samples.
+    pub fn set_high_pass_filter(&mut self, enable: bool) {
+        self.high_pass.enabled = enable;
+        self.high_pass.capacitors = [[0.0; 2]; 4];
+    }
+
+    pub const fn is_high_pass_filter_enabled(&self) -> bool {
+        self.high_pass.enabled
+    }
+
+    /// Leaves a channel out of the output, or puts it back. The channel keeps running.
+    pub fn set_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.muted[channel.index()] = muted;
+    }
+
+    pub const fn is_channel_muted(&self, channel: AudioChannel) -> bool {
+        self.muted[channel.index()]
+    }
+
+    const fn are_dacs_enabled(&self) -> bool {
+        self.channel_1.volume_and_envelope.is_dac_enabled()
+            || self.channel_2.volume_and_envelope.is_dac_enabled()
+            || self.channel_3.dac_enable.is_enabled()
+            || self.channel_4.volume_and_envelope.is_dac_enabled()
+    }
+
+    /// Advances the APU by one M-cycle, returning a sample whenever one is due. The frame
+    /// sequencer steps when `edges` has DIV-APU.
+    pub fn tick(&mut self, edges: ClockEdges) -> Option<AudioSample> {
+        if self.is_powered_on() {
+            if edges.div_apu() {
+                self.clock_frame_sequencer();
+            }
+            if self.channel_1.enabled {
+                self.channel_1.tick();
+            }
+            if self.channel_2.enabled {
+                self.channel_2.tick();
+            }
+            if self.channel_3.enabled {
+                self.channel_3.tick();
+            }
+            if self.channel_4.enabled {
+                self.channel_4.tick();
+            }
+        }
+
+        let sample_rate = self.sample_rate?;
+        self.sample_sum.accumulate(&self.output());
+        self.sample_count += 1;
+        self.sample_phase += sample_rate;
+        if self.sample_phase < AUDIO_NATIVE_HZ {
+            return None;
+        }
+        self.sample_phase -= AUDIO_NATIVE_HZ;
+        #[allow(clippy::cast_precision_loss)]
+        let sample = self.sample_sum.scaled(1.0 / self.sample_count as f32);
+        self.sample_sum = AudioSample::SILENCE;
+        self.sample_count = 0;
+        let dacs_enabled = self.is_powered_on() && self.are_dacs_enabled();
+        Some(self.high_pass.apply(sample, dacs_enabled))
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer_step;
+        self.frame_sequencer_step = (step + 1) % 8;
+
+        if step.is_multiple_of(2) {
+            let channel = &mut self.channel_1;
+            if channel
+                .length
+                .clock(channel.period_high_and_control.is_length_enabled())
+            {
+                channel.enabled = false;
+            }
+            let channel = &mut self.channel_2;
+            if channel
+                .length
+                .clock(channel.period_high_and_control.is_length_enabled())
+            {
+                channel.enabled = false;
+            }
+            let channel = &mut self.channel_3;
+            if channel
+                .length
+                .clock(channel.period_high_and_control.is_length_enabled())
+            {
+                channel.enabled = false;
+            }
+            let channel = &mut self.channel_4;
+            if channel.length.clock(channel.control.is_length_enabled()) {
+                channel.enabled = false;
+            }
+        }
+        if step == 2 || step == 6 {
+            self.channel_1.clock_sweep();
+        }
+        if step == 7 {
+            self.channel_1
+                .envelope
+                .clock(self.channel_1.volume_and_envelope);
+            self.channel_2
+                .envelope
+                .clock(self.channel_2.volume_and_envelope);
+            self.channel_4
+                .envelope
+                .clock(self.channel_4.volume_and_envelope);
+        }
+    }
+
+    /// Mixes the channels through their DACs, panning and the master volume.
+    fn output(&self) -> AudioSample {
+        let channels = [
+            dac_output(
+                self.channel_1.volume_and_envelope.is_dac_enabled(),
+                self.channel_1.output(),
+            ),
+            dac_output(
+                self.channel_2.volume_and_envelope.is_dac_enabled(),
+                self.channel_2.output(),
+            ),
+            dac_output(
+                self.channel_3.dac_enable.is_enabled(),
+                self.channel_3.output(),
+            ),
+            dac_output(
+                self.channel_4.volume_and_envelope.is_dac_enabled(),
+                self.channel_4.output(),
+            ),
+        ];
+
+        let mut sample = AudioSample::SILENCE;
+        if !self.is_powered_on() {
+            return sample;
+        }
+        let volumes = self.master_volume.volumes();
+        for (index, output) in channels.into_iter().enumerate() {
+            if self.muted[index] {
+                continue;
+            }
+            let panning = self.sound_panning.is_panned(index);
+            for side in 0..2 {
+                if panning[side] {
+                    // Each channel gets a quarter of the output range
+                    let value = output * volumes[side] / 4.0;
+                    sample.channels[index][side] = value;
+                    sample.mix[side] += value;
+                }
+            }
+        }
+        sample
+    }
+
+    /// Resets the registers and channels, keeping wave RAM and the output settings.
+    pub fn reset(&mut self) {
+        let wave_ram = self.channel_3.wave_ram;
+        let high_pass = self.high_pass.enabled;
+        *self = Self {
+            sample_rate: self.sample_rate,
+            muted: self.muted,
+            ..Self::new(self.model)
+        };
+        self.set_sample_rate(self.sample_rate);
+        self.set_high_pass_filter(high_pass);
+        self.channel_3.wave_ram = wave_ram;
+    }
+
+    pub const fn read_wave_ram(&self, addr: u16) -> u8 {
+        self.channel_3.wave_ram[addr as usize]
+    }
+
+    pub fn write_wave_ram(&mut self, addr: u16, value: u8) {
+        self.channel_3.wave_ram[addr as usize] = value;
+    }
+
     pub fn read_audio(&self, addr: u16) -> u8 {
+        self.read_register(addr) | read_mask(addr)
+    }
+
+    fn read_register(&self, addr: u16) -> u8 {
         match addr {
             MEM_NR10 => self.channel_1.sweep.bits(),
             MEM_NR11 => self.channel_1.length_timer_and_duty_cycle.bits(),
@@ -430,15 +1188,175 @@ impl Apu {
             MEM_NR44 => self.channel_4.control.bits(),
             MEM_NR50 => self.master_volume.bits(),
             MEM_NR51 => self.sound_panning.bits(),
-            MEM_NR52 => self.audio_master_control.bits(),
-            _ => {
-                println!("Warning: Address {addr:#X} is not mapped to an I/O register.");
-                0xFF
-            }
+            MEM_NR52 => self.audio_master_control.bits() | self.channel_status(),
+            _ => unreachable!(),
         }
     }
 
+    const fn is_powered_on(&self) -> bool {
+        self.audio_master_control.bits() & AudioMasterControl::AUDIO_ENABLE != 0
+    }
+
     pub fn write_audio(&mut self, addr: u16, value: u8) {
+        if addr == MEM_NR52 {
+            self.write_master_control(value);
+        } else if self.is_powered_on() {
+            self.write_register(addr, value);
+            self.apply_write(addr, value);
+        } else if self.model == Model::Dmg && length_timer_bits(addr) != 0 {
+            // Length timers can still be written while off on DMG, other bits are ignored
+            let mask = length_timer_bits(addr);
+            let value = (self.read_register(addr) & !mask) | (value & mask);
+            self.write_register(addr, value);
+            self.apply_write(addr, value);
+        }
+    }
+
+    /// Updates channel state affected by a register write.
+    fn apply_write(&mut self, addr: u16, value: u8) {
+        let trigger = value & PeriodHighAndControl::TRIGGER != 0;
+        match addr {
+            MEM_NR11 => {
+                let initial = self.channel_1.length_timer_and_duty_cycle.initial_length();
+                self.channel_1.length.load(64, initial);
+            }
+            MEM_NR21 => {
+                let initial = self.channel_2.length_timer_and_duty_cycle.initial_length();
+                self.channel_2.length.load(64, initial);
+            }
+            MEM_NR31 => self.channel_3.length.load(256, value),
+            MEM_NR41 => {
+                let initial = self.channel_4.length_timer.initial_length();
+                self.channel_4.length.load(64, initial);
+            }
+            // Turning a DAC off also turns its channel off
+            MEM_NR12 if !self.channel_1.volume_and_envelope.is_dac_enabled() => {
+                self.channel_1.enabled = false;
+            }
+            MEM_NR22 if !self.channel_2.volume_and_envelope.is_dac_enabled() => {
+                self.channel_2.enabled = false;
+            }
+            MEM_NR30 if !self.channel_3.dac_enable.is_enabled() => {
+                self.channel_3.enabled = false;
+            }
+            MEM_NR42 if !self.channel_4.volume_and_envelope.is_dac_enabled() => {
+                self.channel_4.enabled = false;
+            }
+            MEM_NR14 if trigger => self.channel_1.trigger(),
+            MEM_NR24 if trigger => self.channel_2.trigger(),
+            MEM_NR34 if trigger => self.channel_3.trigger(),
+            MEM_NR44 if trigger => self.channel_4.trigger(),
+            _ => {}
+        }
+    }
+
+    fn write_master_control(&mut self, value: u8) {
+        let was_on = self.is_powered_on();
+        let enable = value & AudioMasterControl::AUDIO_ENABLE;
+        if was_on && enable == 0 {
+            for addr in POWER_OFF_CLEARED {
+                // Length timers are not affected by power on DMG
+                let kept = if self.model == Model::Dmg {
+                    self.read_register(addr) & length_timer_bits(addr)
+                } else {
+                    0
+                };
+                self.write_register(addr, kept);
+            }
+            self.channel_1.enabled = false;
+            self.channel_2.enabled = false;
+            self.channel_3.enabled = false;
+            self.channel_4.enabled = false;
+            if self.model != Model::Dmg {
+                self.channel_1.length = LengthCounter::new();
+                self.channel_2.length = LengthCounter::new();
+                self.channel_3.length = LengthCounter::new();
+                self.channel_4.length = LengthCounter::new();
+            }
+        } else if !was_on && enable != 0 {
+            self.frame_sequencer_step = 0;
+            self.channel_1.duty_step = 0;
+            self.channel_2.duty_step = 0;
+            self.channel_3.sample_buffer = 0;
+        }
+        // Channel status bits are read-only
+        self.audio_master_control = AudioMasterControl::from_bits(enable);
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.component("APU");
+        writer.field("registers");
+        for addr in POWER_OFF_CLEARED {
+            writer.write_u8(self.read_register(addr));
+        }
+        writer.field("NR52");
+        writer.write_u8(self.audio_master_control.bits());
+        writer.memory("wave RAM", 0xFF30);
+        writer.write_bytes(&self.channel_3.wave_ram);
+
+        writer.field("channel 1");
+        let channel = &self.channel_1;
+        save_channel(writer, channel.enabled, channel.length, channel.envelope);
+        writer.write_u32(channel.frequency_timer);
+        writer.write_u8(channel.duty_step);
+        writer.write_bool(channel.sweep_enabled);
+        writer.write_u16(channel.sweep_shadow);
+        writer.write_u8(channel.sweep_timer);
+        writer.field("channel 2");
+        let channel = &self.channel_2;
+        save_channel(writer, channel.enabled, channel.length, channel.envelope);
+        writer.write_u32(channel.frequency_timer);
+        writer.write_u8(channel.duty_step);
+        writer.field("channel 3");
+        let channel = &self.channel_3;
+        save_channel(writer, channel.enabled, channel.length, Envelope::new());
+        writer.write_u32(channel.frequency_timer);
+        writer.write_u8(channel.position);
+        writer.write_u8(channel.sample_buffer);
+        writer.field("channel 4");
+        let channel = &self.channel_4;
+        save_channel(writer, channel.enabled, channel.length, channel.envelope);
+        writer.write_u32(channel.frequency_timer);
+        writer.write_u16(channel.lfsr);
+
+        writer.field("frame sequencer");
+        writer.write_u8(self.frame_sequencer_step);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        for addr in POWER_OFF_CLEARED {
+            let value = reader.read_u8()?;
+            self.write_register(addr, value);
+        }
+        self.audio_master_control = AudioMasterControl::from_bits(reader.read_u8()?);
+        reader.read_bytes(&mut self.channel_3.wave_ram)?;
+
+        let channel = &mut self.channel_1;
+        (channel.enabled, channel.length, channel.envelope) = load_channel(reader)?;
+        channel.frequency_timer = reader.read_u32()?;
+        channel.duty_step = reader.read_u8()? % 8;
+        channel.sweep_enabled = reader.read_bool()?;
+        channel.sweep_shadow = reader.read_u16()?;
+        channel.sweep_timer = reader.read_u8()?;
+        let channel = &mut self.channel_2;
+        (channel.enabled, channel.length, channel.envelope) = load_channel(reader)?;
+        channel.frequency_timer = reader.read_u32()?;
+        channel.duty_step = reader.read_u8()? % 8;
+        let channel = &mut self.channel_3;
+        (channel.enabled, channel.length, _) = load_channel(reader)?;
+        channel.frequency_timer = reader.read_u32()?;
+        channel.position = reader.read_u8()? % 32;
+        channel.sample_buffer = reader.read_u8()?;
+        let channel = &mut self.channel_4;
+        (channel.enabled, channel.length, channel.envelope) = load_channel(reader)?;
+        channel.frequency_timer = reader.read_u32()?;
+        channel.lfsr = reader.read_u16()?;
+
+        self.frame_sequencer_step = reader.read_u8()? % 8;
+        Ok(())
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
         match addr {
             MEM_NR10 => self.channel_1.sweep = ChannelSweep::from_bits(value),
             MEM_NR11 => {
@@ -474,8 +1392,240 @@ impl Apu {
             MEM_NR44 => self.channel_4.control = Control::from_bits(value),
             MEM_NR50 => self.master_volume = MasterVolume::from_bits(value),
             MEM_NR51 => self.sound_panning = SoundPanning::from_bits(value),
-            MEM_NR52 => self.audio_master_control = AudioMasterControl::from_bits(value),
-            _ => println!("Warning: Address {addr:#X} is not mapped to an I/O register."),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apu::{read_mask, Apu, POWER_OFF_CLEARED};
+    use crate::audio::AudioChannel;
+    use crate::clock::{ClockEdges, DIV_APU_BIT};
+    use crate::consts::AUDIO_NATIVE_HZ;
+    use crate::hardware::Model;
+
+    const NR11: u16 = 0xFF11;
+    const NR12: u16 = 0xFF12;
+    const NR14: u16 = 0xFF14;
+    const NR21: u16 = 0xFF16;
+    const NR22: u16 = 0xFF17;
+    const NR23: u16 = 0xFF18;
+    const NR24: u16 = 0xFF19;
+    const NR31: u16 = 0xFF1B;
+    const NR41: u16 = 0xFF20;
+    const NR50: u16 = 0xFF24;
+    const NR51: u16 = 0xFF25;
+    const NR52: u16 = 0xFF26;
+
+    fn power_cycle(apu: &mut Apu) {
+        apu.write_audio(NR52, 0x00);
+        apu.write_audio(NR52, 0x80);
+    }
+
+    /// Returns an APU with every register written with 0xFF, then powered off.
+    fn powered_off(model: Model) -> Apu {
+        let mut apu = Apu::new(model);
+        for addr in POWER_OFF_CLEARED {
+            apu.write_audio(addr, 0xFF);
+        }
+        apu.write_audio(NR52, 0x00);
+        apu
+    }
+
+    #[test]
+    fn test_power_off_clears_registers() {
+        let apu = powered_off(Model::Cgb);
+        for addr in POWER_OFF_CLEARED {
+            assert_eq!(apu.read_audio(addr), read_mask(addr), "register {addr:#X}");
+        }
+        assert_eq!(apu.read_audio(NR52), 0x70);
+    }
+
+    #[test]
+    fn test_writes_ignored_while_off() {
+        let mut apu = powered_off(Model::Cgb);
+        for addr in POWER_OFF_CLEARED {
+            apu.write_audio(addr, 0xFF);
+        }
+        apu.write_audio(NR52, 0x80);
+        for addr in POWER_OFF_CLEARED {
+            assert_eq!(
+                apu.read_register(addr) & !read_mask(addr),
+                0,
+                "register {addr:#X}"
+            );
+        }
+        // Length timers are cleared on CGB too
+        assert_eq!(apu.read_register(NR31), 0);
+    }
+
+    #[test]
+    fn test_dmg_length_timers_survive_power_off() {
+        let mut apu = powered_off(Model::Dmg);
+        // Duty bits are cleared, length bits are kept
+        assert_eq!(apu.read_register(NR11), 0x3F);
+        assert_eq!(apu.read_register(NR31), 0xFF);
+        assert_eq!(apu.read_register(NR41) & 0x3F, 0x3F);
+
+        // Length timers stay writable, but only their length bits
+        apu.write_audio(NR11, 0xC5);
+        apu.write_audio(NR31, 0x12);
+        apu.write_audio(NR12, 0xF0);
+        assert_eq!(apu.read_register(NR11), 0x05);
+        assert_eq!(apu.read_register(NR31), 0x12);
+        assert_eq!(apu.read_register(NR12), 0x00);
+    }
+
+    #[test]
+    fn test_master_control_only_power_bit_writable() {
+        let mut apu = Apu::new(Model::Dmg);
+        let channels = apu.read_audio(NR52) & 0x0F;
+        apu.write_audio(NR52, 0xFF);
+        assert_eq!(apu.read_audio(NR52), 0xF0 | channels);
+        power_cycle(&mut apu);
+        assert_eq!(apu.read_audio(NR52), 0xF0);
+    }
+
+    #[test]
+    fn test_registers_writable_after_power_on() {
+        let mut apu = Apu::new(Model::Dmg);
+        power_cycle(&mut apu);
+        apu.write_audio(NR50, 0x77);
+        assert_eq!(apu.read_audio(NR50), 0x77);
+    }
+
+    #[test]
+    fn test_length_timer_disables_channel() {
+        let mut apu = Apu::new(Model::Dmg);
+        assert_eq!(apu.read_audio(NR52), 0xF1);
+        apu.write_audio(NR22, 0xF0);
+        // Length of 2, expiring on the second length clock
+        apu.write_audio(NR21, 62);
+        apu.write_audio(NR24, 0xC0);
+        assert_eq!(apu.read_audio(NR52) & 0x02, 0x02);
+
+        // Length is clocked every other frame sequencer step
+        let div_apu = ClockEdges::between(DIV_APU_BIT, 0);
+        for _ in 0..2 {
+            apu.tick(div_apu);
+        }
+        assert_eq!(apu.read_audio(NR52) & 0x02, 0x02);
+        for _ in 0..2 {
+            apu.tick(div_apu);
         }
+        assert_eq!(apu.read_audio(NR52) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn test_dac_off_disables_channel() {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_audio(NR12, 0xF0);
+        apu.write_audio(NR14, 0x80);
+        assert_eq!(apu.read_audio(NR52) & 0x01, 0x01);
+        apu.write_audio(NR12, 0x00);
+        assert_eq!(apu.read_audio(NR52) & 0x01, 0x00);
+        // Triggering doesn't turn it back on without the DAC
+        apu.write_audio(NR14, 0x80);
+        assert_eq!(apu.read_audio(NR52) & 0x01, 0x00);
+    }
+
+    #[test]
+    fn test_samples_follow_panning() {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.set_sample_rate(Some(AUDIO_NATIVE_HZ / 4));
+        apu.write_audio(NR50, 0x77);
+        // Channel 2 on the left only
+        apu.write_audio(NR51, 0x20);
+        apu.write_audio(NR22, 0xF0);
+        apu.write_audio(NR21, 0x80);
+        apu.write_audio(NR24, 0x87);
+
+        let samples: Vec<_> = (0..4096)
+            .filter_map(|_| apu.tick(ClockEdges::NONE))
+            .collect();
+        assert_eq!(samples.len(), 1024);
+        assert!(samples.iter().any(|sample| sample.mix[0] != 0.0));
+        for sample in &samples {
+            assert_eq!(sample.mix[1], 0.0);
+            assert_eq!(sample.mix[0], sample.channels[1][0]);
+            assert_eq!(sample.channels[0], [0.0; 2]);
+        }
+    }
+
+    /// Returns an APU playing channel 2 at 512 Hz on both sides with `NR21` and `NR22` set.
+    fn channel_2(duty: u8, volume_and_envelope: u8, high_pass: bool) -> Apu {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.set_sample_rate(Some(AUDIO_NATIVE_HZ / 4));
+        apu.set_high_pass_filter(high_pass);
+        apu.write_audio(NR50, 0x77);
+        apu.write_audio(NR51, 0x22);
+        apu.write_audio(NR21, duty);
+        apu.write_audio(NR22, volume_and_envelope);
+        apu.write_audio(NR23, 0x00);
+        apu.write_audio(NR24, 0x87);
+        apu
+    }
+
+    fn samples(apu: &mut Apu, count: usize) -> Vec<f32> {
+        std::iter::from_fn(|| Some(apu.tick(ClockEdges::NONE)))
+            .flatten()
+            .take(count)
+            .map(|sample| sample.mix[0])
+            .collect()
+    }
+
+    #[test]
+    fn test_high_pass_filter_decays_dac_offset() {
+        // DAC on at volume 0, which outputs its lowest level instead of silence
+        let mut raw = channel_2(0x80, 0x08, false);
+        assert!(samples(&mut raw, 20_000).iter().all(|&v| v == -0.25));
+
+        // The filter lets the jump through as a click, then settles back to zero
+        let mut filtered = channel_2(0x80, 0x08, true);
+        let samples = samples(&mut filtered, 20_000);
+        assert!(samples[0] < -0.2);
+        assert!(samples.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(samples[19_999].abs() < 0.001);
+    }
+
+    #[test]
+    fn test_high_pass_filter_centers_pulse_wave() {
+        let mean = |samples: &[f32]| samples.iter().sum::<f32>() / samples.len() as f32;
+
+        // A 12.5% duty cycle spends most of its time at the low level
+        let mut raw = channel_2(0x00, 0xF0, false);
+        assert!(mean(&samples(&mut raw, 20_000)[10_000..]) < -0.1);
+
+        let mut filtered = channel_2(0x00, 0xF0, true);
+        let samples = samples(&mut filtered, 20_000);
+        assert!(mean(&samples[10_000..]).abs() < 0.01);
+        // The shape of the wave is kept
+        assert!(samples[10_000..].iter().any(|&v| v > 0.3));
+    }
+
+    #[test]
+    fn test_high_pass_filter_silent_with_dacs_off() {
+        let mut apu = channel_2(0x80, 0xF0, true);
+        samples(&mut apu, 100);
+        apu.write_audio(NR12, 0x00);
+        apu.write_audio(NR22, 0x00);
+        assert!(samples(&mut apu, 100).iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_muted_channel() {
+        let mut apu = channel_2(0x80, 0xF0, false);
+        apu.set_channel_muted(AudioChannel::Pulse2, true);
+        assert!(samples(&mut apu, 1000).iter().all(|&v| v == 0.0));
+        // Muting is kept across resets, and the channel keeps running
+        apu.reset();
+        assert!(apu.is_channel_muted(AudioChannel::Pulse2));
+        let mut apu = channel_2(0x80, 0xF0, false);
+        apu.set_channel_muted(AudioChannel::Pulse2, true);
+        samples(&mut apu, 10);
+        assert_eq!(apu.read_audio(NR52) & 0x02, 0x02);
+        apu.set_channel_muted(AudioChannel::Pulse2, false);
+        assert!(samples(&mut apu, 1000).iter().any(|&v| v != 0.0));
     }
 }
@@ -0,0 +1,439 @@
+//! Audio output from the APU.
+//!
+//! Set a sink with [`GameboyHardware::set_audio_sink`](crate::hardware::GameboyHardware::set_audio_sink)
+//! to receive samples, e.g. a [`WavWriter`] to record to disk or an [`AudioRingBuffer`] to
+//! pull them from an audio callback.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// One stereo sample of APU output, as `[left, right]` pairs in the range -1.0 to 1.0.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AudioSample {
+    /// All channels mixed together after panning and master volume.
+    pub mix: [f32; 2],
+    /// Each channel's contribution to the mix, for channels 1 to 4.
+    pub channels: [[f32; 2]; 4],
+}
+
+impl AudioSample {
+    pub(crate) const SILENCE: Self = Self {
+        mix: [0.0; 2],
+        channels: [[0.0; 2]; 4],
+    };
+
+    pub(crate) fn accumulate(&mut self, other: &Self) {
+        for side in 0..2 {
+            self.mix[side] += other.mix[side];
+            for channel in 0..4 {
+                self.channels[channel][side] += other.channels[channel][side];
+            }
+        }
+    }
+
+    pub(crate) fn scaled(mut self, factor: f32) -> Self {
+        for side in 0..2 {
+            self.mix[side] *= factor;
+            for channel in 0..4 {
+                self.channels[channel][side] *= factor;
+            }
+        }
+        self
+    }
+}
+
+/// One of the APU's sound channels, in the order of [`AudioSample::channels`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioChannel {
+    Pulse1,
+    Pulse2,
+    Wave,
+    Noise,
+}
+
+impl AudioChannel {
+    pub const ALL: [Self; 4] = [Self::Pulse1, Self::Pulse2, Self::Wave, Self::Noise];
+
+    /// Returns the index of the channel, 0 for channel 1.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Destination for audio samples.
+pub trait AudioSink: Send + Sync {
+    /// Rate samples should be pushed at, in Hz.
+    fn sample_rate(&self) -> u32;
+
+    /// Receives the next sample.
+    fn push_sample(&mut self, sample: &AudioSample);
+}
+
+const WAV_HEADER_SIZE: u32 = 44;
+const WAV_CHANNELS: u16 = 2;
+const WAV_BITS_PER_SAMPLE: u16 = 16;
+
+/// A 16-bit stereo WAV file being written.
+struct WavFile {
+    writer: BufWriter<File>,
+    data_size: u32,
+}
+
+impl WavFile {
+    fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut file = Self {
+            writer: BufWriter::new(File::create(path)?),
+            data_size: 0,
+        };
+        // Sizes are filled in once the length is known
+        file.write_header(sample_rate)?;
+        Ok(file)
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> io::Result<()> {
+        let block_align = WAV_CHANNELS * WAV_BITS_PER_SAMPLE / 8;
+        let writer = &mut self.writer;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&WAV_CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&WAV_BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&self.data_size.to_le_bytes())
+    }
+
+    fn write_frame(&mut self, frame: [f32; 2]) -> io::Result<()> {
+        for value in frame {
+            self.writer.write_all(&to_i16(value).to_le_bytes())?;
+        }
+        self.data_size += u32::from(WAV_CHANNELS * WAV_BITS_PER_SAMPLE / 8);
+        Ok(())
+    }
+
+    fn finish(&mut self, sample_rate: u32) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header(sample_rate)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+/// Records audio to 16-bit stereo WAV files.
+///
+/// The mix is always written. With stems enabled, each channel is also written to its
+/// own file next to it, named after the mix with `_ch1` to `_ch4` appended
+/// (e.g. `song.wav` gets `song_ch1.wav`), so they can be remixed or inspected separately.
+///
+/// Files are finalized when the writer is dropped, use [`Self::finish`] to handle errors.
+pub struct WavWriter {
+    sample_rate: u32,
+    mix: WavFile,
+    stems: Option<[WavFile; 4]>,
+    // First write error, reported by `finish` since sinks can't fail
+    error: Option<io::Error>,
+    finished: bool,
+}
+
+impl WavWriter {
+    /// Creates a WAV file at `path` for the mix only.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be created.
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        Ok(Self {
+            sample_rate,
+            mix: WavFile::create(path.as_ref(), sample_rate)?,
+            stems: None,
+            error: None,
+            finished: false,
+        })
+    }
+
+    /// Creates a WAV file at `path` for the mix, plus one per channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the files can't be created.
+    pub fn create_with_stems(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut writer = Self::create(path, sample_rate)?;
+        writer.stems = Some([
+            WavFile::create(&stem_path(path, 1), sample_rate)?,
+            WavFile::create(&stem_path(path, 2), sample_rate)?,
+            WavFile::create(&stem_path(path, 3), sample_rate)?,
+            WavFile::create(&stem_path(path, 4), sample_rate)?,
+        ]);
+        Ok(writer)
+    }
+
+    /// Writes the final file sizes and flushes all files.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error that happened while writing.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.finalize()
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.finished = true;
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.mix.finish(self.sample_rate)?;
+        for stem in self.stems.iter_mut().flatten() {
+            stem.finish(self.sample_rate)?;
+        }
+        Ok(())
+    }
+
+    fn write_sample(&mut self, sample: &AudioSample) -> io::Result<()> {
+        self.mix.write_frame(sample.mix)?;
+        if let Some(stems) = &mut self.stems {
+            for (stem, frame) in stems.iter_mut().zip(sample.channels) {
+                stem.write_frame(frame)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_sample(&mut self, sample: &AudioSample) {
+        if self.error.is_none() {
+            if let Err(err) = self.write_sample(sample) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finalize();
+        }
+    }
+}
+
+/// Keeps the latest mixed samples for a frontend to pull, e.g. from the callback of an audio
+/// device running on another thread.
+///
+/// Clones share the same buffer: pass one to
+/// [`GameboyHardware::set_audio_sink`](crate::hardware::GameboyHardware::set_audio_sink) and
+/// read from another. Once full, the oldest samples are dropped, so latency stays bounded when
+/// emulation runs ahead of playback.
+#[derive(Debug, Clone)]
+pub struct AudioRingBuffer {
+    sample_rate: u32,
+    capacity: usize,
+    samples: Arc<Mutex<VecDeque<[f32; 2]>>>,
+}
+
+impl AudioRingBuffer {
+    /// Creates a buffer holding up to `capacity` stereo samples at `sample_rate` Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    #[must_use]
+    pub fn new(sample_rate: u32, capacity: usize) -> Self {
+        assert!(capacity > 0, "Audio buffer capacity must not be zero.");
+        Self {
+            sample_rate,
+            capacity,
+            samples: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of samples waiting to be read.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Moves the oldest samples into `output` as `[left, right]` pairs, returning how many were
+    /// written. The rest of `output` is left untouched.
+    pub fn read(&self, output: &mut [[f32; 2]]) -> usize {
+        let mut samples = self.lock();
+        let count = output.len().min(samples.len());
+        for (frame, sample) in output.iter_mut().zip(samples.drain(..count)) {
+            *frame = sample;
+        }
+        count
+    }
+
+    /// Like [`read`](Self::read), converting samples to 16-bit integers.
+    pub fn read_i16(&self, output: &mut [[i16; 2]]) -> usize {
+        let mut samples = self.lock();
+        let count = output.len().min(samples.len());
+        for (frame, sample) in output.iter_mut().zip(samples.drain(..count)) {
+            *frame = sample.map(to_i16);
+        }
+        count
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<[f32; 2]>> {
+        // Samples are plain data, a panic elsewhere can't leave them inconsistent
+        self.samples.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl AudioSink for AudioRingBuffer {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_sample(&mut self, sample: &AudioSample) {
+        let mut samples = self.lock();
+        if samples.len() == self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(sample.mix);
+    }
+}
+
+/// Converts a sample value from -1.0 to 1.0 to a 16-bit integer.
+fn to_i16(value: f32) -> i16 {
+    #[allow(clippy::cast_possible_truncation)]
+    let value = (value.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+    value
+}
+
+/// Returns the path of a channel's stem, e.g. `song_ch1.wav` for `song.wav`.
+fn stem_path(path: &Path, channel: u8) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}_ch{channel}");
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::{stem_path, AudioRingBuffer, AudioSample, AudioSink, WavWriter};
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_stem_path() {
+        assert_eq!(
+            stem_path(Path::new("out/song.wav"), 3),
+            Path::new("out/song_ch3.wav")
+        );
+        assert_eq!(stem_path(Path::new("song"), 1), Path::new("song_ch1"));
+    }
+
+    #[test]
+    fn test_wav_writer_stems() {
+        let dir = std::env::temp_dir().join(format!("gb-emulator-wav-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mix.wav");
+
+        let mut writer = WavWriter::create_with_stems(&path, 44_100).unwrap();
+        let sample = AudioSample {
+            mix: [0.5, -0.5],
+            channels: [[0.0; 2], [0.0; 2], [0.5, -0.5], [0.0; 2]],
+        };
+        for _ in 0..10 {
+            writer.push_sample(&sample);
+        }
+        writer.finish().unwrap();
+
+        let mix = fs::read(&path).unwrap();
+        assert_eq!(mix.len(), 44 + 10 * 4);
+        assert_eq!(&mix[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(mix[4..8].try_into().unwrap()), 36 + 40);
+        assert_eq!(u32::from_le_bytes(mix[24..28].try_into().unwrap()), 44_100);
+        assert_eq!(u32::from_le_bytes(mix[40..44].try_into().unwrap()), 40);
+        assert_eq!(i16::from_le_bytes([mix[44], mix[45]]), 16383);
+        assert_eq!(i16::from_le_bytes([mix[46], mix[47]]), -16383);
+
+        let silent = fs::read(dir.join("mix_ch1.wav")).unwrap();
+        assert!(silent[44..].iter().all(|byte| *byte == 0));
+        assert_eq!(fs::read(dir.join("mix_ch3.wav")).unwrap(), mix);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let buffer = AudioRingBuffer::new(32_000, 4);
+        let mut sink = buffer.clone();
+        for index in 0..6u8 {
+            let value = f32::from(index) / 10.0;
+            sink.push_sample(&AudioSample {
+                mix: [value, -value],
+                ..AudioSample::SILENCE
+            });
+        }
+        // The two oldest samples were dropped
+        assert_eq!(buffer.len(), 4);
+        let mut output = [[0.0; 2]; 3];
+        assert_eq!(buffer.read(&mut output), 3);
+        assert_eq!(output, [[0.2, -0.2], [0.3, -0.3], [0.4, -0.4]]);
+        let mut output = [[0; 2]; 3];
+        assert_eq!(buffer.read_i16(&mut output), 1);
+        assert_eq!(output[0], [16383, -16383]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_ring_buffer_receives_apu_output() {
+        // Channel 2 playing a square wave at full volume on both sides
+        #[rustfmt::skip]
+        let program = [
+            0x3E, 0x77, 0xE0, 0x24, // LD A, 0x77; LDH (NR50), A
+            0x3E, 0x22, 0xE0, 0x25, // LD A, 0x22; LDH (NR51), A
+            0x3E, 0xF0, 0xE0, 0x17, // LD A, 0xF0; LDH (NR22), A
+            0x3E, 0x87, 0xE0, 0x19, // LD A, 0x87; LDH (NR24), A
+            0x18, 0xFE,             // loop: JR loop
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        let buffer = AudioRingBuffer::new(48_000, 48_000);
+        gameboy.set_audio_sink(Some(Box::new(buffer.clone())));
+        for _ in 0..6 {
+            gameboy.run_frame();
+        }
+
+        // About 100 ms of audio
+        let mut output = vec![[0.0; 2]; buffer.len()];
+        assert!((4700..4900).contains(&buffer.read(&mut output)));
+        // A square wave, not silence
+        assert!(output.iter().any(|frame| frame[0] != output[0][0]));
+        assert!(output.iter().all(|frame| frame[0] == frame[1]));
+    }
+}
@@ -0,0 +1,345 @@
+//! Logging every bus transaction, for debugging hardware behavior that depends on the exact
+//! order of accesses (e.g. DMA conflicts or mid-instruction register writes).
+//!
+//! Logs are written in a compact binary format: an 8 byte header followed by a 12 byte
+//! record per transaction. [`convert_to_text`] turns them into one line per transaction.
+
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+const LOG_MAGIC: &[u8; 7] = b"GBBUSLG";
+const LOG_VERSION: u8 = 1;
+const RECORD_SIZE: usize = 12;
+// Flags byte: bit 0 is set for writes, the component is in the upper bits
+const FLAG_WRITE: u8 = 0x01;
+
+/// Direction of a bus transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Part of the hardware making a bus transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    /// The CPU running an instruction.
+    Cpu,
+    /// The CPU pushing the return address while dispatching an interrupt.
+    InterruptDispatch,
+}
+
+impl Component {
+    const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(Self::Cpu),
+            1 => Some(Self::InterruptDispatch),
+            _ => None,
+        }
+    }
+
+    const fn bits(self) -> u8 {
+        match self {
+            Self::Cpu => 0,
+            Self::InterruptDispatch => 1,
+        }
+    }
+}
+
+impl Display for Component {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cpu => write!(f, "CPU"),
+            Self::InterruptDispatch => write!(f, "INT"),
+        }
+    }
+}
+
+/// A read or write of the address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusTransaction {
+    /// T-cycles since logging started, at the start of the instruction making the access.
+    pub cycle: u64,
+    pub addr: u16,
+    pub value: u8,
+    pub access: Access,
+    pub component: Component,
+}
+
+impl BusTransaction {
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut record = [0; RECORD_SIZE];
+        record[0..8].copy_from_slice(&self.cycle.to_le_bytes());
+        record[8..10].copy_from_slice(&self.addr.to_le_bytes());
+        record[10] = self.value;
+        record[11] = self.component.bits() << 1 | u8::from(self.access == Access::Write);
+        record
+    }
+
+    fn decode(record: &[u8; RECORD_SIZE]) -> io::Result<Self> {
+        let component = Component::from_bits(record[11] >> 1).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown bus component {}", record[11] >> 1),
+            )
+        })?;
+        Ok(Self {
+            cycle: u64::from_le_bytes(record[0..8].try_into().unwrap()),
+            addr: u16::from_le_bytes([record[8], record[9]]),
+            value: record[10],
+            access: if record[11] & FLAG_WRITE != 0 {
+                Access::Write
+            } else {
+                Access::Read
+            },
+            component,
+        })
+    }
+}
+
+impl Display for BusTransaction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let access = match self.access {
+            Access::Read => 'R',
+            Access::Write => 'W',
+        };
+        write!(
+            f,
+            "{:>12} {} {access} {:04X} {:02X}",
+            self.cycle, self.component, self.addr, self.value
+        )
+    }
+}
+
+/// Selects the transactions logged, by default all of them.
+#[derive(Debug, Default, Clone)]
+pub struct BusLogFilter {
+    ranges: Vec<RangeInclusive<u16>>,
+    components: Vec<Component>,
+}
+
+impl BusLogFilter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Logs accesses to `range`, in addition to other ranges added.
+    #[must_use]
+    pub fn range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Logs accesses made by `component`, in addition to other components added.
+    #[must_use]
+    pub fn component(mut self, component: Component) -> Self {
+        self.components.push(component);
+        self
+    }
+
+    fn matches(&self, addr: u16, component: Component) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&addr)))
+            && (self.components.is_empty() || self.components.contains(&component))
+    }
+}
+
+/// Writes the transactions selected by a [`BusLogFilter`], see
+/// [`GameboyHardware::set_bus_logger`].
+///
+/// [`GameboyHardware::set_bus_logger`]: crate::hardware::GameboyHardware::set_bus_logger
+pub struct BusLogger {
+    filter: BusLogFilter,
+    output: BufWriter<Box<dyn Write + Send + Sync>>,
+    // T-cycles since logging started
+    cycle: u64,
+    // The first write error, logging stops after it
+    error: Option<io::Error>,
+}
+
+impl BusLogger {
+    /// Creates a logger writing to `output`.
+    pub fn new(output: impl Write + Send + Sync + 'static, filter: BusLogFilter) -> Self {
+        let mut logger = Self {
+            filter,
+            output: BufWriter::new(Box::new(output)),
+            cycle: 0,
+            error: None,
+        };
+        let mut header = LOG_MAGIC.to_vec();
+        header.push(LOG_VERSION);
+        logger.write(&header);
+        logger
+    }
+
+    /// Creates a logger writing to a new file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be created.
+    pub fn create(path: impl AsRef<Path>, filter: BusLogFilter) -> io::Result<Self> {
+        Ok(Self::new(File::create(path)?, filter))
+    }
+
+    pub(crate) fn advance(&mut self, cycles: usize) {
+        self.cycle += cycles as u64;
+    }
+
+    /// Records a transaction if it matches the filter, returning the error if it just
+    /// stopped logging.
+    pub(crate) fn record(
+        &mut self,
+        addr: u16,
+        value: u8,
+        access: Access,
+        component: Component,
+    ) -> Option<&io::Error> {
+        if self.filter.matches(addr, component) {
+            let transaction = BusTransaction {
+                cycle: self.cycle,
+                addr,
+                value,
+                access,
+                component,
+            };
+            return self.write(&transaction.encode());
+        }
+        None
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Option<&io::Error> {
+        if self.error.is_none() {
+            if let Err(err) = self.output.write_all(bytes) {
+                return Some(self.error.insert(err));
+            }
+        }
+        None
+    }
+
+    /// Flushes the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped logging, if writing failed.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.output.flush()
+    }
+}
+
+/// Reads the transactions of a binary log.
+///
+/// A record cut short at the end, left by a process killed while logging, is ignored.
+///
+/// # Errors
+///
+/// Returns an error if reading fails or the data isn't a bus log.
+pub fn read_transactions(mut input: impl Read) -> io::Result<Vec<BusTransaction>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let records = match data.split_first_chunk::<8>() {
+        Some((header, records)) if header[..7] == *LOG_MAGIC && header[7] == LOG_VERSION => records,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a bus log, or one from another version",
+            ))
+        }
+    };
+    records
+        .chunks_exact(RECORD_SIZE)
+        .map(|record| BusTransaction::decode(record.try_into().unwrap()))
+        .collect()
+}
+
+/// Converts a binary log to text, one transaction per line: cycle, component, `R` or `W`,
+/// address and value.
+///
+/// # Errors
+///
+/// Returns an error if reading or writing fails or the input isn't a bus log.
+pub fn convert_to_text(input: impl Read, mut output: impl Write) -> io::Result<()> {
+    for transaction in read_transactions(input)? {
+        writeln!(output, "{transaction}")?;
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus_log::{
+        convert_to_text, read_transactions, Access, BusLogFilter, BusLogger, Component,
+    };
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_logs_filtered_transactions() {
+        // LD A, 0x42; LD (0xC000), A; LD A, (0xC000); LD (0xD000), A
+        let code = [
+            0x3E, 0x42, 0xEA, 0x00, 0xC0, 0xFA, 0x00, 0xC0, 0xEA, 0x00, 0xD0,
+        ];
+        let rom = HeaderBuilder::new().build(&code);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        // Entry point: NOP; JP 0x0150
+        gameboy.step();
+        gameboy.step();
+
+        let buffer = SharedBuffer::default();
+        let filter = BusLogFilter::new()
+            .range(0xC000..=0xCFFF)
+            .component(Component::Cpu);
+        gameboy.set_bus_logger(Some(BusLogger::new(buffer.clone(), filter)));
+        for _ in 0..4 {
+            gameboy.step();
+        }
+        gameboy.set_bus_logger(None).unwrap().finish().unwrap();
+
+        let log = buffer.0.lock().unwrap().clone();
+        let transactions = read_transactions(log.as_slice()).unwrap();
+        let summary: Vec<_> = transactions
+            .iter()
+            .map(|t| (t.cycle, t.access, t.addr, t.value))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (8, Access::Write, 0xC000, 0x42),
+                (24, Access::Read, 0xC000, 0x42)
+            ]
+        );
+
+        let mut text = Vec::new();
+        convert_to_text(log.as_slice(), &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "           8 CPU W C000 42\n          24 CPU R C000 42\n"
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(read_transactions(&b"GBSTATE!"[..]).is_err());
+    }
+}
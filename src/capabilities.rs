@@ -0,0 +1,65 @@
+//! Report of what this version of the core emulates, for frontends and test harnesses.
+
+use crate::hardware::Model;
+
+/// Features and hardware quirks implemented by the core.
+///
+/// Frontends can use this to hide options that would have no effect, and test harnesses
+/// to skip tests for behavior that isn't emulated yet instead of reporting failures.
+/// New fields are added as the core grows, so this can't be constructed outside the crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Version of the core, following the crate version.
+    pub version: &'static str,
+    /// Model the instance is emulating.
+    pub model: Model,
+    /// MBC3 real-time clock.
+    pub rtc: bool,
+    /// MBC5 rumble motor output.
+    pub rumble: bool,
+    /// Game Boy Camera captures, from images supplied by the host.
+    pub camera: bool,
+    /// Game Boy Color features beyond DMG compatibility (palettes, VRAM/WRAM banks, HDMA).
+    pub cgb: bool,
+    /// DMG OAM corruption bug triggered by accesses and 16-bit register operations in
+    /// 0xFE00-0xFEFF during OAM scan.
+    pub oam_bug: bool,
+    /// DMG STAT write bug, requesting a STAT interrupt on writes in HBlank, VBlank or on
+    /// LY=LYC.
+    pub stat_write_bug: bool,
+    /// Super Game Boy commands, palettes and borders, see
+    /// [`GameboyHardware::set_sgb`](crate::hardware::GameboyHardware::set_sgb).
+    pub sgb: bool,
+    /// Pixel FIFO based PPU with cycle-accurate mode 3.
+    pub fifo_ppu: bool,
+    /// Mode 3 length depends on scroll, window and sprites.
+    pub variable_mode3_length: bool,
+    /// Audio samples are generated.
+    pub audio_output: bool,
+    /// Serial transfers shift one bit at a time and can be clocked externally.
+    pub serial_bit_timing: bool,
+    /// Only the highest priority interrupt is serviced at a time.
+    pub interrupt_priority: bool,
+}
+
+impl Capabilities {
+    pub(crate) const fn new(model: Model) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            model,
+            rtc: false,
+            rumble: false,
+            camera: true,
+            cgb: false,
+            oam_bug: true,
+            stat_write_bug: true,
+            sgb: true,
+            fifo_ppu: true,
+            variable_mode3_length: true,
+            audio_output: true,
+            serial_bit_timing: true,
+            interrupt_priority: true,
+        }
+    }
+}
@@ -1,32 +1,146 @@
+mod builder;
+mod camera;
 mod mbc;
 mod metadata;
+mod multi_rom;
+mod overrides;
 
+pub use crate::cartridge::builder::HeaderBuilder;
+pub use crate::cartridge::camera::{CAMERA_HEIGHT, CAMERA_WIDTH};
+pub use crate::cartridge::metadata::{CgbSupport, Destination, Licensee, MapperKind};
+pub use crate::cartridge::multi_rom::{find_sub_roms, SubRom};
+pub use crate::cartridge::overrides::{HeaderOverride, OverrideTable};
+
+use crate::cartridge::camera::PocketCamera;
 use crate::cartridge::mbc::{MemoryBankController, NoMBC, MBC1, MBC3, MBC5};
-use crate::cartridge::metadata::Metadata;
+use crate::cartridge::metadata::{Metadata, CART_HEADER_END};
+use crate::error::{EmulatorError, SaveFileError, SavestateError};
+use crate::savestate::{StateReader, StateWriter};
+use crate::util::fnv1a_64;
+use std::ops::RangeInclusive;
 
 const ROM_BANK_SIZE: usize = 16 * 1024;
 const RAM_BANK_SIZE: usize = 8 * 1024;
 
-// TODO: add support for save files
+// Save files end with a footer identifying the ROM they belong to
+const SAVE_FOOTER_MAGIC: &[u8; 4] = b"GBSV";
+const SAVE_FOOTER_SIZE: usize = SAVE_FOOTER_MAGIC.len() + size_of::<u64>();
+
+const ROM_ADDRESSES: RangeInclusive<u16> = 0x0000..=0x7FFF;
+const RAM_ADDRESSES: RangeInclusive<u16> = 0xA000..=0xBFFF;
+
+/// Reads from ROM or RAM, wrapping out of range indexes like the unconnected
+/// upper address lines of a smaller chip would.
+fn read_wrapped(data: &[u8], index: usize) -> u8 {
+    if data.is_empty() {
+        0xFF
+    } else {
+        data[index % data.len()]
+    }
+}
+
+/// Custom hardware mapped into the cartridge address space (0x0000-0x7FFF and 0xA000-0xBFFF).
+///
+/// Devices are checked before the memory bank controller, so they can override ROM/RAM
+/// contents or add MMIO ports (e.g. flash cart registers or debug output for test ROMs).
+/// Addresses passed to a device are absolute.
+pub trait CartridgeDevice: Send + Sync {
+    /// Reads a byte without side effects, returns `None` to let the cartridge handle the read.
+    fn peek(&self, addr: u16) -> Option<u8>;
+
+    /// Reads a byte on behalf of the CPU, returns `None` to let the cartridge handle the read.
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    /// Writes a byte, returns `false` to let the cartridge handle the write.
+    fn write(&mut self, addr: u16, value: u8) -> bool;
+}
+
+struct MappedDevice {
+    range: RangeInclusive<u16>,
+    device: Box<dyn CartridgeDevice>,
+}
+
+/// A write to the memory bank controller registers, with the banks selected after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MbcWrite {
+    pub addr: u16,
+    pub value: u8,
+    /// ROM bank mapped at 0x4000-0x7FFF.
+    pub rom_bank: usize,
+    /// RAM bank mapped at 0xA000-0xBFFF.
+    pub ram_bank: usize,
+}
+
+// Bank switches within one frame that are treated as a storm, games switch a few times per frame
+const BANK_SWITCH_STORM_THRESHOLD: u32 = 1000;
+// Frames to wait between storm warnings, about 10 seconds
+const BANK_SWITCH_STORM_WARNING_INTERVAL: u32 = 600;
+
 pub struct Cartridge {
     rom: Vec<u8>,
+    // Hash of `rom`, computed once as ROMs take up to 8 MiB
+    rom_hash: u64,
     ram: Option<Vec<u8>>,
     mbc: Box<dyn MemoryBankController>,
     metadata: Metadata,
+    devices: Vec<MappedDevice>,
+    // Warnings not raised yet, see `Self::warnings`
+    warnings: Vec<String>,
+    // Only warn once about banks outside the ROM
+    warned_bank_out_of_range: bool,
+    // Only recorded when enabled for debugging
+    mbc_write_log: Option<Vec<MbcWrite>>,
+    // Bank switches since the last frame ended
+    bank_switches: u32,
+    // Frames since the last storm warning, `None` if there hasn't been one
+    frames_since_storm_warning: Option<u32>,
+    // Storms not warned about since the last warning
+    suppressed_storms: u32,
+    // Supplied by the host for the Game Boy Camera, empty until set
+    camera_image: Vec<u8>,
+}
+
+fn create_mbc(metadata: &Metadata) -> Box<dyn MemoryBankController> {
+    match metadata.mapper {
+        MapperKind::None => Box::new(NoMBC::new()),
+        MapperKind::Mbc1 => Box::new(MBC1::new(metadata.rom_bank_count, metadata.rom_bank_count)),
+        MapperKind::Mbc3 => Box::new(MBC3::new()),
+        MapperKind::Mbc5 => Box::new(MBC5::new()),
+        MapperKind::PocketCamera => Box::new(PocketCamera::new()),
+    }
 }
 
 impl Cartridge {
+    /// Creates a cartridge from its ROM, correcting the header for known bad cases, see
+    /// [`OverrideTable`].
     #[must_use]
     pub fn new(rom: Vec<u8>) -> Self {
-        let metadata = Metadata::new(&rom);
+        Self::with_overrides(rom, &OverrideTable::new())
+    }
 
-        let mbc: Box<dyn MemoryBankController> = match metadata.mbc_number {
-            0 => Box::new(NoMBC::new()),
-            1 => Box::new(MBC1::new(metadata.rom_bank_count, metadata.rom_bank_count)),
-            3 => Box::new(MBC3::new()),
-            5 => Box::new(MBC5::new()),
-            _ => unreachable!(),
-        };
+    /// Creates a cartridge from its ROM, correcting the header with an override for the ROM
+    /// if `overrides` has one.
+    ///
+    /// Loading never fails: checksums and the logo aren't required, invalid header fields
+    /// are guessed with a warning, and a ROM too short to have a header is padded with
+    /// zeros to 32 KiB.
+    #[must_use]
+    pub fn with_overrides(mut rom: Vec<u8>, overrides: &OverrideTable) -> Self {
+        let mut warnings = Vec::new();
+        if rom.len() < CART_HEADER_END {
+            warnings.push(format!(
+                "ROM is {} bytes, too short for a header. Padding it to 32 KiB.",
+                rom.len()
+            ));
+            rom.resize(2 * ROM_BANK_SIZE, 0);
+        }
+        let rom_hash = fnv1a_64(&rom);
+        let mut metadata = Metadata::new(&rom, &mut warnings);
+        overrides.apply(&mut metadata, rom_hash, &mut warnings);
+
+        let mbc = create_mbc(&metadata);
 
         let ram = if metadata.has_ram {
             let capacity = RAM_BANK_SIZE * metadata.ram_bank_count;
@@ -36,51 +150,370 @@ impl Cartridge {
             None
         };
 
+        let expected_size = ROM_BANK_SIZE * metadata.rom_bank_count;
+        if rom.len() != expected_size {
+            warnings.push(format!(
+                "ROM is {} bytes but the header declares {expected_size} bytes. Out of range reads will wrap around.",
+                rom.len()
+            ));
+        }
+
         Self {
             rom,
+            rom_hash,
             ram,
             mbc,
             metadata,
+            devices: Vec::new(),
+            warnings,
+            warned_bank_out_of_range: false,
+            mbc_write_log: None,
+            bank_switches: 0,
+            frames_since_storm_warning: None,
+            suppressed_storms: 0,
+            camera_image: Vec::new(),
+        }
+    }
+
+    /// Creates an empty cartridge slot: the bus floats, so ROM and RAM read 0xFF and writes
+    /// are ignored.
+    #[must_use]
+    pub(crate) fn empty() -> Self {
+        let mut cartridge = Self::new(vec![0; 2 * ROM_BANK_SIZE]);
+        cartridge.rom = Vec::new();
+        cartridge.rom_hash = fnv1a_64(&cartridge.rom);
+        cartridge
+    }
+
+    /// Maps a device into the cartridge address space on top of the memory bank controller.
+    ///
+    /// Devices attached later take precedence when ranges overlap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is not contained in 0x0000-0x7FFF or 0xA000-0xBFFF.
+    pub fn attach_device(&mut self, range: RangeInclusive<u16>, device: Box<dyn CartridgeDevice>) {
+        let in_rom = ROM_ADDRESSES.contains(range.start()) && ROM_ADDRESSES.contains(range.end());
+        let in_ram = RAM_ADDRESSES.contains(range.start()) && RAM_ADDRESSES.contains(range.end());
+        assert!(
+            in_rom || in_ram,
+            "Address range {:#X}-{:#X} is not part of the cartridge address space.",
+            range.start(),
+            range.end()
+        );
+        self.devices.push(MappedDevice { range, device });
+    }
+
+    /// Returns a hash identifying the ROM contents.
+    #[must_use]
+    pub const fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Loads battery-backed RAM from a save file.
+    ///
+    /// The save may end with the footer written by [`Self::save_data`], in which case
+    /// it must belong to this ROM. Saves without a footer (e.g. from other emulators)
+    /// are only checked for size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error and leaves RAM untouched if the cartridge has no battery,
+    /// the size doesn't match the header's RAM size, or the save belongs to another ROM.
+    pub fn load_save(&mut self, save: &[u8]) -> Result<(), SaveFileError> {
+        if !self.metadata.has_battery {
+            return Err(SaveFileError::NoSaveRam);
+        }
+        let Some(ram) = &mut self.ram else {
+            return Err(SaveFileError::NoSaveRam);
+        };
+
+        let expected = ram.len();
+        let data = if save.len() == expected + SAVE_FOOTER_SIZE
+            && save[expected..].starts_with(SAVE_FOOTER_MAGIC)
+        {
+            let mut hash = [0; size_of::<u64>()];
+            hash.copy_from_slice(&save[expected + SAVE_FOOTER_MAGIC.len()..]);
+            let actual = u64::from_le_bytes(hash);
+            let expected = self.rom_hash;
+            if actual != expected {
+                return Err(SaveFileError::RomMismatch { expected, actual });
+            }
+            &save[..ram.len()]
+        } else if save.len() == expected {
+            save
+        } else {
+            return Err(SaveFileError::SizeMismatch {
+                expected,
+                actual: save.len(),
+            });
+        };
+
+        ram.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns the contents of battery-backed RAM followed by a footer identifying the ROM,
+    /// or `None` if the cartridge has no battery.
+    #[must_use]
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        if !self.metadata.has_battery {
+            return None;
+        }
+        let ram = self.ram.as_ref()?;
+        let mut save = Vec::with_capacity(ram.len() + SAVE_FOOTER_SIZE);
+        save.extend_from_slice(ram);
+        save.extend_from_slice(SAVE_FOOTER_MAGIC);
+        save.extend_from_slice(&self.rom_hash().to_le_bytes());
+        Some(save)
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.component("Cartridge");
+        if let Some(ram) = &self.ram {
+            writer.field("RAM");
+            writer.write_bytes(ram);
+        }
+        writer.field("MBC");
+        self.mbc.save_state(writer);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        if let Some(ram) = &mut self.ram {
+            reader.read_bytes(ram)?;
+        }
+        self.mbc.load_state(reader)
+    }
+
+    pub(crate) fn peek(&self, addr: u16) -> u8 {
+        let device = self
+            .devices
+            .iter()
+            .rev()
+            .filter(|mapped| mapped.range.contains(&addr))
+            .find_map(|mapped| mapped.device.peek(addr));
+        device.unwrap_or_else(|| self.peek_mbc(addr))
+    }
+
+    pub(crate) fn read(&mut self, addr: u16) -> u8 {
+        let device = self
+            .devices
+            .iter_mut()
+            .rev()
+            .filter(|mapped| mapped.range.contains(&addr))
+            .find_map(|mapped| mapped.device.read(addr));
+        device.unwrap_or_else(|| self.peek_mbc(addr))
+    }
+
+    /// Writes to ROM or RAM, returning an error for writes to ROM without a memory bank
+    /// controller, which are ignored.
+    pub(crate) fn write(&mut self, addr: u16, value: u8) -> Result<(), EmulatorError> {
+        let handled = self
+            .devices
+            .iter_mut()
+            .rev()
+            .filter(|mapped| mapped.range.contains(&addr))
+            .any(|mapped| mapped.device.write(addr, value));
+        if handled {
+            return Ok(());
+        }
+
+        match addr {
+            0x0000..=0x7FFF if !self.mbc.has_registers() => {
+                return Err(EmulatorError::RomWrite { addr, value });
+            }
+            0x0000..=0x7FFF => self.write_rom(addr, value),
+            0xA000..=0xBFFF => self.write_ram(addr - 0xA000, value),
+            _ => unreachable!(),
         }
+        Ok(())
     }
 
-    pub(crate) fn read_rom_bank0(&self, addr: u16) -> u8 {
+    /// Sets the image the Game Boy Camera sensor sees, used by captures started from now on.
+    ///
+    /// `image` holds one shade per pixel from 0 (black) to 255 (white), row by row, and must
+    /// be [`CAMERA_WIDTH`] by [`CAMERA_HEIGHT`]. Frontends feeding a webcam can call this
+    /// every frame. Captures see a white image until this is called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image` is not the size of a camera image.
+    pub fn set_camera_image(&mut self, image: &[u8]) {
+        assert_eq!(
+            image.len(),
+            CAMERA_WIDTH * CAMERA_HEIGHT,
+            "Camera images are {CAMERA_WIDTH}x{CAMERA_HEIGHT}."
+        );
+        self.camera_image.clear();
+        self.camera_image.extend_from_slice(image);
+    }
+
+    /// Advances hardware on the cartridge (the camera) by `cycles` T-cycles.
+    pub(crate) fn tick(&mut self, cycles: usize) {
+        let ram = self.ram.as_deref_mut().unwrap_or_default();
+        self.mbc.tick(cycles, ram, &self.camera_image);
+    }
+
+    /// Returns the memory bank controller to its power on state, RAM is kept.
+    pub(crate) fn reset(&mut self) {
+        self.mbc = create_mbc(&self.metadata);
+        self.bank_switches = 0;
+    }
+
+    /// Returns the byte of the RAM bank currently mapped at `addr` (0xA000-0xBFFF), whether
+    /// RAM is enabled or not.
+    pub(crate) fn ram_byte_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        let ram = self.ram.as_mut().filter(|ram| !ram.is_empty())?;
+        let offset = RAM_BANK_SIZE * self.mbc.get_ram_bank();
+        let index = (offset + (addr - 0xA000) as usize) % ram.len();
+        Some(&mut ram[index])
+    }
+
+    /// Returns RAM that loses its contents when powered off, i.e. RAM without a battery.
+    pub(crate) fn volatile_ram_mut(&mut self) -> Option<&mut [u8]> {
+        if self.metadata.has_battery {
+            None
+        } else {
+            self.ram.as_deref_mut()
+        }
+    }
+
+    fn selected_banks(&self) -> [usize; 3] {
+        [
+            self.mbc.get_rom_bank0(),
+            self.mbc.get_rom_bank1(),
+            self.mbc.get_ram_bank(),
+        ]
+    }
+
+    /// Starts or stops recording writes to the memory bank controller registers.
+    pub(crate) fn set_mbc_write_logging(&mut self, enable: bool) {
+        self.mbc_write_log = enable.then(Vec::new);
+    }
+
+    pub(crate) fn take_mbc_writes(&mut self) -> Vec<MbcWrite> {
+        self.mbc_write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Checks the bank switches made during the frame that just ended.
+    ///
+    /// Thousands of switches in a frame usually mean an emulation bug or a malformed ROM.
+    /// Warnings are rate limited so a storm lasting many frames doesn't flood the user.
+    pub(crate) fn end_frame(&mut self) {
+        if let Some(frames) = &mut self.frames_since_storm_warning {
+            *frames = frames.saturating_add(1);
+        }
+        let switches = std::mem::take(&mut self.bank_switches);
+        if switches < BANK_SWITCH_STORM_THRESHOLD {
+            return;
+        }
+        if self
+            .frames_since_storm_warning
+            .is_some_and(|frames| frames < BANK_SWITCH_STORM_WARNING_INTERVAL)
+        {
+            self.suppressed_storms += 1;
+            return;
+        }
+
+        let suppressed = std::mem::take(&mut self.suppressed_storms);
+        if suppressed == 0 {
+            self.warnings.push(format!("{switches} bank switches in one frame. This usually means an emulation bug or a malformed ROM."));
+        } else {
+            self.warnings.push(format!("{switches} bank switches in one frame, {suppressed} more frames with storms since the last warning. This usually means an emulation bug or a malformed ROM."));
+        }
+        self.frames_since_storm_warning = Some(0);
+    }
+
+    /// Returns the bank mapped at `addr`, as it would be shown in a debugger.
+    ///
+    /// Banks beyond the ROM are wrapped like reads are. Addresses outside the cartridge are bank 0.
+    pub(crate) fn bank_at(&self, addr: u16) -> usize {
+        let rom_banks = (self.rom.len() / ROM_BANK_SIZE).max(1);
+        match addr {
+            0x0000..=0x3FFF => self.mbc.get_rom_bank0() % rom_banks,
+            0x4000..=0x7FFF => self.mbc.get_rom_bank1() % rom_banks,
+            0xA000..=0xBFFF => self.mbc.get_ram_bank(),
+            _ => 0,
+        }
+    }
+
+    fn peek_mbc(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.read_rom_bank0(addr),
+            0x4000..=0x7FFF => self.read_rom_bank1(addr - 0x4000),
+            0xA000..=0xBFFF => self.read_ram(addr - 0xA000),
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_rom_bank0(&self, addr: u16) -> u8 {
         let offset = ROM_BANK_SIZE * self.mbc.get_rom_bank0();
-        self.rom[(addr as usize) + offset]
+        read_wrapped(&self.rom, offset + addr as usize)
     }
 
-    pub(crate) fn read_rom_bank1(&self, addr: u16) -> u8 {
+    fn read_rom_bank1(&self, addr: u16) -> u8 {
         let offset = ROM_BANK_SIZE * self.mbc.get_rom_bank1();
-        self.rom[(addr as usize) + offset]
+        read_wrapped(&self.rom, offset + addr as usize)
     }
 
-    pub(crate) fn write_rom(&mut self, addr: u16, value: u8) {
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        let banks = self.selected_banks();
         self.mbc.write_registers(addr, value);
+        if self.selected_banks() != banks {
+            self.bank_switches = self.bank_switches.saturating_add(1);
+        }
+        if let Some(log) = &mut self.mbc_write_log {
+            log.push(MbcWrite {
+                addr,
+                value,
+                rom_bank: self.mbc.get_rom_bank1(),
+                ram_bank: self.mbc.get_ram_bank(),
+            });
+        }
+
+        let bank = self.mbc.get_rom_bank0().max(self.mbc.get_rom_bank1());
+        if !self.warned_bank_out_of_range && ROM_BANK_SIZE * bank >= self.rom.len() {
+            self.warnings.push(format!(
+                "ROM bank {bank} selected but the ROM only has {} banks. Reads will wrap around.",
+                self.rom.len().div_ceil(ROM_BANK_SIZE)
+            ));
+            self.warned_bank_out_of_range = true;
+        }
     }
 
-    pub(crate) fn read_ram(&self, addr: u16) -> u8 {
-        if !self.mbc.is_ram_enabled() {
+    fn read_ram(&self, addr: u16) -> u8 {
+        if let Some(value) = self.mbc.read_ram_register(addr) {
+            return value;
+        }
+        if !self.mbc.is_ram_readable() {
             return 0xFF;
         }
 
-        if let Some(ram) = &self.ram {
+        // Without RAM the data bus is left floating
+        self.ram.as_ref().map_or(0xFF, |ram| {
             let offset = RAM_BANK_SIZE * self.mbc.get_ram_bank();
-            ram[(addr as usize) + offset]
-        } else {
-            panic!("Unable to read from cartridge RAM. No RAM included in cartridge.");
-        }
+            read_wrapped(ram, offset + addr as usize)
+        })
     }
 
-    pub(crate) fn write_ram(&mut self, addr: u16, value: u8) {
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        if self.mbc.write_ram_register(addr, value) {
+            return;
+        }
         if !self.mbc.is_ram_enabled() {
             return;
         }
 
         if let Some(ram) = &mut self.ram {
+            if ram.is_empty() {
+                return;
+            }
             let offset = RAM_BANK_SIZE * self.mbc.get_ram_bank();
-            ram[(addr as usize) + offset] = value;
-        } else {
-            panic!("Unable to write to cartridge RAM. No RAM included in cartridge.")
+            let index = (offset + addr as usize) % ram.len();
+            ram[index] = value;
         }
     }
 
@@ -89,6 +522,106 @@ impl Cartridge {
         &self.metadata.title
     }
 
+    /// Returns the name of the memory bank controller, or "ROM ONLY" without one.
+    #[must_use]
+    pub const fn get_mbc_name(&self) -> &'static str {
+        self.metadata.mapper.name()
+    }
+
+    /// Returns the mapper emulated, guessed with a warning for unsupported cartridge types.
+    #[must_use]
+    pub const fn mapper(&self) -> MapperKind {
+        self.metadata.mapper
+    }
+
+    /// Returns the cartridge type byte of the header (0x147), see [`Self::mapper`].
+    #[must_use]
+    pub const fn cartridge_type(&self) -> u8 {
+        self.metadata.cartridge_type
+    }
+
+    #[must_use]
+    pub const fn cgb_support(&self) -> CgbSupport {
+        CgbSupport::from_flag(self.cgb_flag())
+    }
+
+    /// Returns the CGB flag byte of the header (0x143), see [`Self::cgb_support`].
+    #[must_use]
+    pub const fn cgb_flag(&self) -> u8 {
+        self.metadata.cgb_flag
+    }
+
+    /// Returns whether the header declares Super Game Boy support, which takes 0x03 in the
+    /// SGB flag (0x146) and the new licensee code (old licensee code 0x33). The Super Game
+    /// Boy ignores commands from other games.
+    #[must_use]
+    pub const fn supports_sgb(&self) -> bool {
+        self.metadata.sgb_flag == 0x03 && matches!(self.metadata.licensee, Licensee::New(_))
+    }
+
+    #[must_use]
+    pub const fn destination(&self) -> Destination {
+        Destination::from_code(self.destination_code())
+    }
+
+    /// Returns the destination code byte of the header (0x14A), see [`Self::destination`].
+    #[must_use]
+    pub const fn destination_code(&self) -> u8 {
+        self.metadata.destination_code
+    }
+
+    #[must_use]
+    pub const fn licensee(&self) -> Licensee {
+        self.metadata.licensee
+    }
+
+    /// Returns the name of the licensee, see [`Licensee::name`].
+    #[must_use]
+    pub const fn get_licensee(&self) -> &'static str {
+        self.metadata.licensee.name()
+    }
+
+    #[must_use]
+    pub const fn has_ram(&self) -> bool {
+        self.metadata.has_ram
+    }
+
+    /// Returns warnings about the ROM not raised yet, i.e. header fields guessed while
+    /// loading it and problems found while running it.
+    ///
+    /// Hardware raises them as [`Notification::DiagnosticRaised`] as they happen, this is
+    /// for frontends inspecting a cartridge on its own.
+    ///
+    /// [`Notification::DiagnosticRaised`]: crate::notification::Notification::DiagnosticRaised
+    #[must_use]
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub(crate) fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub(crate) fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+
+    #[must_use]
+    pub const fn has_battery(&self) -> bool {
+        self.metadata.has_battery
+    }
+
+    /// Returns true if the cartridge includes a real-time clock.
+    #[must_use]
+    pub const fn has_timer(&self) -> bool {
+        self.metadata.has_timer
+    }
+
+    #[must_use]
+    pub const fn has_rumble(&self) -> bool {
+        self.metadata.has_rumble
+    }
+
     #[must_use]
     pub const fn get_rom_size(&self) -> usize {
         ROM_BANK_SIZE * self.get_rom_bank_count()
@@ -116,4 +649,308 @@ impl Cartridge {
     pub const fn passed_global_check(&self) -> bool {
         self.metadata.passed_global_check
     }
+
+    /// Returns whether the header has the Nintendo logo, which the boot ROM refuses to run
+    /// without. Homebrew skipping the boot ROM often leaves it out.
+    #[must_use]
+    pub const fn passed_logo_check(&self) -> bool {
+        self.metadata.passed_logo_check
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{
+        read_wrapped, Cartridge, CartridgeDevice, CgbSupport, Destination, HeaderBuilder, Licensee,
+        MapperKind, MbcWrite, BANK_SWITCH_STORM_THRESHOLD, BANK_SWITCH_STORM_WARNING_INTERVAL,
+        RAM_BANK_SIZE, ROM_BANK_SIZE,
+    };
+    use crate::error::SaveFileError;
+
+    // Returns reads counted from `count`, and takes every write as the new count
+    struct Counter {
+        count: u8,
+    }
+
+    impl CartridgeDevice for Counter {
+        fn peek(&self, _addr: u16) -> Option<u8> {
+            Some(self.count)
+        }
+
+        fn read(&mut self, _addr: u16) -> Option<u8> {
+            self.count += 1;
+            Some(self.count)
+        }
+
+        fn write(&mut self, _addr: u16, value: u8) -> bool {
+            self.count = value;
+            true
+        }
+    }
+
+    // Leaves every access to the cartridge
+    struct Transparent;
+
+    impl CartridgeDevice for Transparent {
+        fn peek(&self, _addr: u16) -> Option<u8> {
+            None
+        }
+
+        fn write(&mut self, _addr: u16, _value: u8) -> bool {
+            false
+        }
+    }
+
+    // MBC1 with 4 ROM banks, each starting with its number
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+        for bank in 1..4 {
+            rom[usize::from(bank) * ROM_BANK_SIZE] = bank;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_device() {
+        let mut cartridge = Cartridge::new(rom());
+        cartridge.attach_device(0x5000..=0x5000, Box::new(Counter { count: 0x10 }));
+        assert_eq!(cartridge.peek(0x5000), 0x10);
+        assert_eq!(cartridge.read(0x5000), 0x11);
+        assert_eq!(cartridge.peek(0x5000), 0x11);
+        cartridge.write(0x5000, 0x20).unwrap();
+        assert_eq!(cartridge.read(0x5000), 0x21);
+
+        // Outside its range, the MBC handles accesses
+        assert_eq!(cartridge.read(0x4000), 1);
+        cartridge.write(0x2000, 2).unwrap();
+        assert_eq!(cartridge.read(0x4000), 2);
+
+        // Devices attached later come first, unless they leave the access to the next one
+        cartridge.attach_device(0x2000..=0x5000, Box::new(Transparent));
+        assert_eq!(cartridge.peek(0x5000), 0x21);
+        cartridge.write(0x2000, 3).unwrap();
+        assert_eq!(cartridge.peek(0x4000), 3);
+        cartridge.attach_device(0x4000..=0x7FFF, Box::new(Counter { count: 0x40 }));
+        assert_eq!(cartridge.peek(0x4000), 0x40);
+        assert_eq!(cartridge.peek(0x5000), 0x40);
+        cartridge.write(0x5000, 0x50).unwrap();
+        assert_eq!(cartridge.peek(0x4000), 0x50);
+    }
+
+    #[test]
+    #[should_panic(expected = "not part of the cartridge address space")]
+    fn test_device_outside_cartridge() {
+        Cartridge::new(rom()).attach_device(0x7000..=0xA000, Box::new(Transparent));
+    }
+
+    // MBC1 with one bank of RAM, battery-backed if `battery`
+    fn ram_rom(battery: bool) -> Vec<u8> {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x147] = if battery { 0x03 } else { 0x02 };
+        rom[0x149] = 0x02;
+        rom
+    }
+
+    #[test]
+    fn test_load_save() {
+        let mut cartridge = Cartridge::new(ram_rom(true));
+        let ram = vec![0x42; RAM_BANK_SIZE];
+
+        // Saves from other emulators have no footer
+        cartridge.load_save(&ram).unwrap();
+        let save = cartridge.save_data().unwrap();
+        assert_eq!(&save[..RAM_BANK_SIZE], &ram[..]);
+
+        let mut other_save = save.clone();
+        other_save[0] = 0x24;
+        cartridge.load_save(&other_save).unwrap();
+        assert_eq!(cartridge.save_data().unwrap(), other_save);
+    }
+
+    #[test]
+    fn test_load_save_errors() {
+        let mut cartridge = Cartridge::new(ram_rom(true));
+        let save = cartridge.save_data().unwrap();
+
+        // The footer ends with the ROM hash, RAM stays untouched
+        let mut other_rom = save.clone();
+        other_rom[0] = 0x42;
+        *other_rom.last_mut().unwrap() ^= 1;
+        let expected = cartridge.rom_hash();
+        assert_eq!(
+            cartridge.load_save(&other_rom),
+            Err(SaveFileError::RomMismatch {
+                expected,
+                actual: expected ^ (1 << 56),
+            })
+        );
+        assert_eq!(cartridge.save_data().unwrap(), save);
+
+        assert_eq!(
+            cartridge.load_save(&save[1..]),
+            Err(SaveFileError::SizeMismatch {
+                expected: RAM_BANK_SIZE,
+                actual: save.len() - 1,
+            })
+        );
+
+        let mut cartridge = Cartridge::new(ram_rom(false));
+        assert_eq!(
+            cartridge.load_save(&save[..RAM_BANK_SIZE]),
+            Err(SaveFileError::NoSaveRam)
+        );
+    }
+
+    #[test]
+    fn test_lenient_header() {
+        // Homebrew payload with a blank header, not even the logo
+        let mut rom = vec![0; 0x120];
+        rom[0x100..0x103].copy_from_slice(&[0x18, 0xFE, 0x00]);
+        let cartridge = Cartridge::new(rom);
+        assert_eq!(cartridge.get_rom_size(), 32 * 1024);
+        assert!(!cartridge.passed_logo_check());
+        assert_eq!(cartridge.peek(0x0100), 0x18);
+
+        // Unknown cartridge type, ROM size and RAM size
+        let mut rom = HeaderBuilder::new().rom_banks(4).build(&[]);
+        rom[0x147] = 0xFE;
+        rom[0x148] = 0x52;
+        rom[0x149] = 0x07;
+        let cartridge = Cartridge::new(rom);
+        assert_eq!(cartridge.get_mbc_name(), "MBC5");
+        assert_eq!(cartridge.mapper(), MapperKind::Mbc5);
+        assert_eq!(cartridge.cartridge_type(), 0xFE);
+        assert_eq!(cartridge.get_rom_size(), 64 * 1024);
+        assert_eq!(cartridge.get_ram_size(), 0);
+        assert!(!cartridge.passed_header_check());
+        assert!(cartridge.passed_logo_check());
+    }
+
+    #[test]
+    fn test_typed_header() {
+        let mut rom = HeaderBuilder::new()
+            .cartridge_type(0x13)
+            .sgb(true)
+            .build(&[]);
+        rom[0x143] = 0xC0;
+        rom[0x14A] = 0x01;
+        rom[0x14B] = 0x33;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        let cartridge = Cartridge::new(rom);
+        assert_eq!(cartridge.mapper(), MapperKind::Mbc3);
+        assert_eq!(cartridge.cgb_support(), CgbSupport::Required);
+        assert_eq!(cartridge.cgb_flag(), 0xC0);
+        assert_eq!(cartridge.destination(), Destination::Overseas);
+        assert_eq!(cartridge.licensee(), Licensee::New(*b"01"));
+        assert_eq!(cartridge.get_licensee(), cartridge.licensee().name());
+        assert!(cartridge.supports_sgb());
+
+        let cartridge = Cartridge::new(HeaderBuilder::new().build(&[]));
+        assert_eq!(cartridge.mapper(), MapperKind::None);
+        assert_eq!(cartridge.cgb_support(), CgbSupport::None);
+        assert_eq!(cartridge.destination(), Destination::Japan);
+        assert!(matches!(cartridge.licensee(), Licensee::Old(_)));
+        assert!(!cartridge.supports_sgb());
+    }
+
+    #[test]
+    fn test_truncated_rom_wraps() {
+        let mut rom = HeaderBuilder::new()
+            .cartridge_type(0x01)
+            .rom_banks(4)
+            .build(&[]);
+        rom.truncate(ROM_BANK_SIZE + 0x100);
+        rom[ROM_BANK_SIZE] = 0x42;
+        let mut cartridge = Cartridge::new(rom);
+
+        assert_eq!(cartridge.peek(0x4000), 0x42);
+        // Bank 3 wraps around the 0x4100 bytes that are left
+        cartridge.write(0x2000, 3).unwrap();
+        let wrapped = (3 * ROM_BANK_SIZE) % (ROM_BANK_SIZE + 0x100);
+        assert_eq!(cartridge.peek(0x4000), cartridge.rom[wrapped]);
+    }
+
+    #[test]
+    fn test_bank_beyond_header_size_wraps() {
+        let mut rom = HeaderBuilder::new()
+            .cartridge_type(0x19)
+            .rom_banks(2)
+            .build(&[]);
+        rom[ROM_BANK_SIZE] = 0x42;
+        let mut cartridge = Cartridge::new(rom);
+
+        // MBC5 doesn't mask the bank number to the ROM size
+        cartridge.write(0x2000, 0xFF).unwrap();
+        assert_eq!(cartridge.peek(0x4000), 0x42);
+    }
+
+    #[test]
+    fn test_ram_access_without_ram() {
+        let rom = HeaderBuilder::new().cartridge_type(0x01).build(&[]);
+        let mut cartridge = Cartridge::new(rom);
+        cartridge.write(0x0000, 0x0A).unwrap();
+        cartridge.write(0xA000, 0x12).unwrap();
+        assert_eq!(cartridge.peek(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_empty_rom_reads_open_bus() {
+        assert_eq!(read_wrapped(&[], 0x1234), 0xFF);
+    }
+
+    #[test]
+    fn test_mbc_write_log() {
+        let rom = HeaderBuilder::new()
+            .cartridge_type(0x19)
+            .rom_banks(8)
+            .build(&[]);
+        let mut cartridge = Cartridge::new(rom);
+        cartridge.set_mbc_write_logging(true);
+        cartridge.write(0x2000, 5).unwrap();
+        // Writes that keep the same bank aren't switches
+        cartridge.write(0x2000, 5).unwrap();
+        assert_eq!(cartridge.bank_switches, 1);
+        assert_eq!(
+            cartridge.take_mbc_writes(),
+            vec![
+                MbcWrite {
+                    addr: 0x2000,
+                    value: 5,
+                    rom_bank: 5,
+                    ram_bank: 0,
+                };
+                2
+            ]
+        );
+        assert!(cartridge.take_mbc_writes().is_empty());
+    }
+
+    #[test]
+    fn test_bank_switch_storm_warnings_rate_limited() {
+        let rom = HeaderBuilder::new()
+            .cartridge_type(0x19)
+            .rom_banks(4)
+            .build(&[]);
+        let mut cartridge = Cartridge::new(rom);
+        let storm = |cartridge: &mut Cartridge| {
+            for i in 0..BANK_SWITCH_STORM_THRESHOLD {
+                cartridge.write(0x2000, (i % 2) as u8 + 1).unwrap();
+            }
+            cartridge.end_frame();
+        };
+
+        storm(&mut cartridge);
+        assert_eq!(cartridge.frames_since_storm_warning, Some(0));
+        storm(&mut cartridge);
+        assert_eq!(cartridge.suppressed_storms, 1);
+
+        for _ in 0..BANK_SWITCH_STORM_WARNING_INTERVAL {
+            cartridge.end_frame();
+        }
+        storm(&mut cartridge);
+        assert_eq!(cartridge.frames_since_storm_warning, Some(0));
+        assert_eq!(cartridge.suppressed_storms, 0);
+    }
 }
@@ -0,0 +1,176 @@
+use crate::cartridge::metadata::{
+    calculate_global_checksum, calculate_header_checksum, CART_CARTRIDGE_TYPE, CART_ENTRY_POINT,
+    CART_GLOBAL_CHECKSUM1, CART_GLOBAL_CHECKSUM2, CART_HEADER_CHECKSUM, CART_HEADER_END,
+    CART_LOGO_START, CART_OLD_LICENSEE_CODE, CART_RAM_SIZE, CART_ROM_SIZE, CART_SGB_FLAG,
+    CART_TITLE_START, NINTENDO_LOGO,
+};
+use crate::cartridge::ROM_BANK_SIZE;
+
+/// Builds ROM images with a valid header around a code payload.
+///
+/// Meant for tests and fuzzers that need a minimal ROM without committing binary fixtures.
+/// The entry point jumps to the payload, which is placed right after the header at 0x150.
+///
+/// ```
+/// use gb_emulator::cartridge::{Cartridge, HeaderBuilder};
+///
+/// // LD A, 0x42; HALT
+/// let rom = HeaderBuilder::new().title("TEST").build(&[0x3E, 0x42, 0x76]);
+/// let cartridge = Cartridge::new(rom);
+/// assert!(cartridge.passed_header_check());
+/// assert!(cartridge.passed_global_check());
+/// ```
+#[derive(Debug, Clone)]
+pub struct HeaderBuilder {
+    title: String,
+    cartridge_type: u8,
+    rom_bank_count: usize,
+    ram_size_code: u8,
+    licensee_code: u8,
+    sgb_flag: u8,
+}
+
+impl HeaderBuilder {
+    /// Creates a builder for a 32 KiB ROM without a memory bank controller or RAM.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            title: String::new(),
+            cartridge_type: 0x00,
+            rom_bank_count: 2,
+            ram_size_code: 0x00,
+            licensee_code: 0x00,
+            sgb_flag: 0x00,
+        }
+    }
+
+    /// Sets the title, truncated to 16 ASCII characters.
+    #[must_use]
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.chars().filter(char::is_ascii).take(16).collect();
+        self
+    }
+
+    /// Sets the cartridge type byte (memory bank controller and features).
+    #[must_use]
+    pub const fn cartridge_type(mut self, cartridge_type: u8) -> Self {
+        self.cartridge_type = cartridge_type;
+        self
+    }
+
+    /// Sets the number of 16 KiB ROM banks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the count is not a power of two between 2 and 512.
+    #[must_use]
+    pub fn rom_banks(mut self, count: usize) -> Self {
+        assert!(
+            count.is_power_of_two() && (2..=512).contains(&count),
+            "Invalid ROM bank count {count}."
+        );
+        self.rom_bank_count = count;
+        self
+    }
+
+    /// Sets the number of 8 KiB RAM banks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the count is not 0, 1, 4, 8, or 16.
+    #[must_use]
+    pub fn ram_banks(mut self, count: usize) -> Self {
+        self.ram_size_code = match count {
+            0 => 0x00,
+            1 => 0x02,
+            4 => 0x03,
+            16 => 0x04,
+            8 => 0x05,
+            _ => panic!("Invalid RAM bank count {count}."),
+        };
+        self
+    }
+
+    /// Sets the old licensee code, 0x33 means the new licensee code is used instead.
+    #[must_use]
+    pub const fn licensee(mut self, code: u8) -> Self {
+        self.licensee_code = code;
+        self
+    }
+
+    /// Sets the SGB flag to declare Super Game Boy support, which also takes the old licensee
+    /// code 0x33.
+    #[must_use]
+    pub const fn sgb(mut self, supported: bool) -> Self {
+        self.sgb_flag = if supported { 0x03 } else { 0x00 };
+        self
+    }
+
+    /// Builds the ROM with `code` placed at 0x150, growing it if the code doesn't fit.
+    #[must_use]
+    pub fn build(&self, code: &[u8]) -> Vec<u8> {
+        let mut rom_bank_count = self.rom_bank_count;
+        while CART_HEADER_END + code.len() > rom_bank_count * ROM_BANK_SIZE {
+            rom_bank_count *= 2;
+        }
+        let mut rom = vec![0; rom_bank_count * ROM_BANK_SIZE];
+
+        // NOP; JP 0x150
+        rom[CART_ENTRY_POINT..CART_LOGO_START].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[CART_LOGO_START..CART_TITLE_START].copy_from_slice(&NINTENDO_LOGO);
+        let title = self.title.as_bytes();
+        rom[CART_TITLE_START..CART_TITLE_START + title.len()].copy_from_slice(title);
+        rom[CART_CARTRIDGE_TYPE] = self.cartridge_type;
+        #[allow(clippy::cast_possible_truncation)]
+        let rom_size_code = rom_bank_count.trailing_zeros() as u8 - 1;
+        rom[CART_ROM_SIZE] = rom_size_code;
+        rom[CART_RAM_SIZE] = self.ram_size_code;
+        rom[CART_SGB_FLAG] = self.sgb_flag;
+        rom[CART_OLD_LICENSEE_CODE] = self.licensee_code;
+        rom[CART_HEADER_END..CART_HEADER_END + code.len()].copy_from_slice(code);
+
+        rom[CART_HEADER_CHECKSUM] = calculate_header_checksum(&rom);
+        let [high, low] = calculate_global_checksum(&rom).to_be_bytes();
+        rom[CART_GLOBAL_CHECKSUM1] = high;
+        rom[CART_GLOBAL_CHECKSUM2] = low;
+        rom
+    }
+}
+
+impl Default for HeaderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+
+    #[test]
+    fn test_valid_header() {
+        let rom = HeaderBuilder::new()
+            .title("HEADER TEST")
+            .cartridge_type(0x13)
+            .rom_banks(8)
+            .ram_banks(4)
+            .licensee(0x01)
+            .build(&[0x76]);
+        assert_eq!(rom.len(), 8 * 16 * 1024);
+
+        let cartridge = Cartridge::new(rom);
+        assert!(cartridge.passed_header_check());
+        assert!(cartridge.passed_global_check());
+        assert!(cartridge.get_title().starts_with("HEADER TEST"));
+        assert_eq!(cartridge.get_mbc_name(), "MBC3");
+        assert_eq!(cartridge.get_ram_size(), 4 * 8 * 1024);
+        assert_eq!(cartridge.get_licensee(), "Nintendo");
+    }
+
+    #[test]
+    fn test_rom_grows_to_fit_code() {
+        let rom = HeaderBuilder::new().build(&[0; 40 * 1024]);
+        assert_eq!(rom.len(), 4 * 16 * 1024);
+        assert!(Cartridge::new(rom).passed_global_check());
+    }
+}
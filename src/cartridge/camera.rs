@@ -0,0 +1,272 @@
+use crate::cartridge::mbc::MemoryBankController;
+use crate::error::SavestateError;
+use crate::savestate::{StateReader, StateWriter};
+
+/// Width of the images captured by the Game Boy Camera sensor.
+pub const CAMERA_WIDTH: usize = 128;
+/// Height of the images captured by the Game Boy Camera sensor.
+pub const CAMERA_HEIGHT: usize = 112;
+
+// Registers are mapped over RAM when bit 4 of the RAM bank is set, mirrored every 0x80 bytes
+const REGISTERS_SELECTED: u8 = 0x10;
+const REGISTER_COUNT: usize = 0x36;
+const REG_CONTROL: usize = 0x00;
+const REG_GAIN: usize = 0x01;
+const REG_EXPOSURE_HIGH: usize = 0x02;
+const REG_EXPOSURE_LOW: usize = 0x03;
+const REG_DITHER_MATRIX: usize = 0x06;
+
+const CONTROL_CAPTURE: u8 = 0x01;
+// N: exclusively set edge enhancement, skips a step of the capture
+const GAIN_N: u8 = 0x80;
+// Exposure that leaves the sensor image unchanged
+const NEUTRAL_EXPOSURE: u32 = 0x1000;
+
+// The captured image is written to RAM bank 0 as 16x14 tiles
+const IMAGE_ADDR: usize = 0x100;
+
+/// Pocket Camera mapper: MBC-like banking plus the sensor's registers.
+///
+/// Captures are high-level: the image supplied by the host is scaled by the exposure and
+/// dithered with the game's matrix, without emulating the sensor's analog processing
+/// (gain curves and edge enhancement).
+pub struct PocketCamera {
+    ram_enabled: bool,
+    rom_bank_number: u8,
+    ram_bank_number: u8,
+    registers: [u8; REGISTER_COUNT],
+    // T-cycles until the capture in progress completes
+    capture_cycles: u32,
+}
+
+impl PocketCamera {
+    pub const fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank_number: 0,
+            ram_bank_number: 0,
+            registers: [0; REGISTER_COUNT],
+            capture_cycles: 0,
+        }
+    }
+
+    const fn registers_selected(&self) -> bool {
+        self.ram_bank_number & REGISTERS_SELECTED != 0
+    }
+
+    const fn exposure(&self) -> u16 {
+        u16::from_be_bytes([
+            self.registers[REG_EXPOSURE_HIGH],
+            self.registers[REG_EXPOSURE_LOW],
+        ])
+    }
+
+    /// Returns how long a capture takes with the current registers, in T-cycles.
+    const fn capture_duration(&self) -> u32 {
+        let n_cycles = if self.registers[REG_GAIN] & GAIN_N != 0 {
+            0
+        } else {
+            512
+        };
+        4 * (32446 + n_cycles + 16 * self.exposure() as u32)
+    }
+
+    /// Returns the color a sensor pixel is stored as, thresholding it with the dither
+    /// matrix entry for its position.
+    fn dither(&self, x: usize, y: usize, pixel: u8) -> u8 {
+        let value = (pixel as u32 * self.exposure() as u32 / NEUTRAL_EXPOSURE).min(0xFF);
+        let entry = REG_DITHER_MATRIX + 3 * ((y % 4) * 4 + x % 4);
+        let thresholds = &self.registers[entry..entry + 3];
+        let brighter = thresholds
+            .iter()
+            .take_while(|threshold| value >= u32::from(**threshold))
+            .count();
+        3 - brighter as u8
+    }
+
+    /// Writes the sensor image to RAM as tiles. `sensor_image` holds one shade per pixel,
+    /// 0 being black, and is treated as white if it isn't a full image.
+    fn write_image(&self, ram: &mut [u8], sensor_image: &[u8]) {
+        let Some(image) = ram.get_mut(IMAGE_ADDR..IMAGE_ADDR + CAMERA_WIDTH * CAMERA_HEIGHT / 4)
+        else {
+            return;
+        };
+        image.fill(0);
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let pixel = if sensor_image.len() == CAMERA_WIDTH * CAMERA_HEIGHT {
+                    sensor_image[y * CAMERA_WIDTH + x]
+                } else {
+                    0xFF
+                };
+                let color = self.dither(x, y, pixel);
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let offset = tile * 16 + (y % 8) * 2;
+                let bit = 0x80 >> (x % 8);
+                if color & 0x1 != 0 {
+                    image[offset] |= bit;
+                }
+                if color & 0x2 != 0 {
+                    image[offset + 1] |= bit;
+                }
+            }
+        }
+    }
+}
+
+impl MemoryBankController for PocketCamera {
+    fn get_rom_bank0(&self) -> usize {
+        0
+    }
+
+    fn get_rom_bank1(&self) -> usize {
+        self.rom_bank_number as usize
+    }
+
+    fn get_ram_bank(&self) -> usize {
+        (self.ram_bank_number & 0x0F) as usize
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    // Only writes need RAM to be enabled
+    fn is_ram_readable(&self) -> bool {
+        true
+    }
+
+    fn write_registers(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0xF == 0xA;
+            }
+            0x2000..=0x3FFF => {
+                self.rom_bank_number = value & 0x3F;
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank_number = value & 0x1F;
+            }
+            // Not connected
+            0x6000..=0x7FFF => {}
+            _ => panic!("Address {addr:#X} not mapped in Memory Bank Controller."),
+        }
+    }
+
+    fn read_ram_register(&self, addr: u16) -> Option<u8> {
+        if !self.registers_selected() {
+            return None;
+        }
+        // Only the control register can be read, bit 0 stays set while capturing
+        let value = match addr as usize % 0x80 {
+            REG_CONTROL => self.registers[REG_CONTROL] & 0x06 | u8::from(self.capture_cycles > 0),
+            _ => 0x00,
+        };
+        Some(value)
+    }
+
+    fn write_ram_register(&mut self, addr: u16, value: u8) -> bool {
+        if !self.registers_selected() {
+            return false;
+        }
+        let index = addr as usize % 0x80;
+        if index == REG_CONTROL {
+            self.registers[REG_CONTROL] = value & 0x07;
+            if value & CONTROL_CAPTURE != 0 && self.capture_cycles == 0 {
+                self.capture_cycles = self.capture_duration();
+            } else if value & CONTROL_CAPTURE == 0 {
+                self.capture_cycles = 0;
+            }
+        } else if index < REGISTER_COUNT {
+            self.registers[index] = value;
+        }
+        true
+    }
+
+    fn tick(&mut self, cycles: usize, ram: &mut [u8], sensor_image: &[u8]) {
+        if self.capture_cycles == 0 {
+            return;
+        }
+        self.capture_cycles = self.capture_cycles.saturating_sub(cycles as u32);
+        if self.capture_cycles == 0 {
+            self.registers[REG_CONTROL] &= !CONTROL_CAPTURE;
+            self.write_image(ram, sensor_image);
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+        writer.write_bytes(&self.registers);
+        writer.write_u32(self.capture_cycles);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()? & 0x3F;
+        self.ram_bank_number = reader.read_u8()? & 0x1F;
+        reader.read_bytes(&mut self.registers)?;
+        self.capture_cycles = reader.read_u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder, CAMERA_HEIGHT, CAMERA_WIDTH};
+
+    fn camera() -> Cartridge {
+        let rom = HeaderBuilder::new()
+            .cartridge_type(0xFC)
+            .rom_banks(64)
+            .ram_banks(16)
+            .build(&[]);
+        let mut cartridge = Cartridge::new(rom);
+        cartridge.write(0x0000, 0x0A).unwrap();
+        cartridge.write(0x4000, 0x10).unwrap();
+        cartridge
+    }
+
+    #[test]
+    fn test_capture() {
+        let mut cartridge = camera();
+        // Left half black, right half white
+        let image: Vec<u8> = (0..CAMERA_WIDTH * CAMERA_HEIGHT)
+            .map(|i| if i % CAMERA_WIDTH < 64 { 0x00 } else { 0xFF })
+            .collect();
+        cartridge.set_camera_image(&image);
+
+        cartridge.write(0xA002, 0x10).unwrap();
+        cartridge.write(0xA003, 0x00).unwrap();
+        for entry in 0..16 {
+            for (addr, threshold) in (0xA006 + entry * 3..).zip([0x40, 0x80, 0xC0]) {
+                cartridge.write(addr, threshold).unwrap();
+            }
+        }
+        cartridge.write(0xA000, 0x01).unwrap();
+        assert_eq!(cartridge.peek(0xA000), 0x01);
+        // Registers are mirrored
+        assert_eq!(cartridge.peek(0xA080), 0x01);
+
+        cartridge.tick(4 * (32446 + 512 + 16 * 0x1000));
+        assert_eq!(cartridge.peek(0xA000), 0x00);
+
+        // The first tile is black and the last one white
+        cartridge.write(0x4000, 0x00).unwrap();
+        assert_eq!(cartridge.peek(0xA100), 0xFF);
+        assert_eq!(cartridge.peek(0xA101), 0xFF);
+        assert_eq!(cartridge.peek(0xA100 + 16 * 223), 0x00);
+        assert_eq!(cartridge.peek(0xA101 + 16 * 223), 0x00);
+    }
+
+    #[test]
+    fn test_ram_writes_need_enable() {
+        let mut cartridge = camera();
+        cartridge.write(0x4000, 0x01).unwrap();
+        cartridge.write(0xA000, 0x42).unwrap();
+        cartridge.write(0x0000, 0x00).unwrap();
+        cartridge.write(0xA000, 0x24).unwrap();
+        assert_eq!(cartridge.peek(0xA000), 0x42);
+    }
+}
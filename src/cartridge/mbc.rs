@@ -1,11 +1,41 @@
+use crate::error::SavestateError;
+use crate::savestate::{StateReader, StateWriter};
 use crate::util::bits_needed;
 
-pub trait MemoryBankController {
+pub trait MemoryBankController: Send + Sync {
     fn get_rom_bank0(&self) -> usize;
     fn get_rom_bank1(&self) -> usize;
     fn get_ram_bank(&self) -> usize;
     fn is_ram_enabled(&self) -> bool;
+
+    /// Returns whether RAM can be read, which usually needs it to be enabled like writes.
+    fn is_ram_readable(&self) -> bool {
+        self.is_ram_enabled()
+    }
+
+    /// Returns whether writes to ROM reach registers, false without a memory bank controller.
+    fn has_registers(&self) -> bool {
+        true
+    }
+
     fn write_registers(&mut self, addr: u16, value: u8);
+
+    /// Reads a register mapped over RAM (`addr` is relative to 0xA000), `None` if RAM is mapped.
+    fn read_ram_register(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Writes a register mapped over RAM, returns `false` if RAM is mapped.
+    fn write_ram_register(&mut self, _addr: u16, _value: u8) -> bool {
+        false
+    }
+
+    /// Advances hardware on the cartridge by `cycles` T-cycles. Hardware writing to RAM on its
+    /// own (the camera) gets `ram` and the image the host supplied for the sensor.
+    fn tick(&mut self, _cycles: usize, _ram: &mut [u8], _sensor_image: &[u8]) {}
+
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError>;
 }
 
 pub struct NoMBC {}
@@ -33,8 +63,16 @@ impl MemoryBankController for NoMBC {
         true
     }
 
-    fn write_registers(&mut self, _addr: u16, _value: u8) {
-        panic!("Cannot write to Read-Only Memory (ROM) on cartridge.");
+    fn has_registers(&self) -> bool {
+        false
+    }
+
+    fn write_registers(&mut self, _addr: u16, _value: u8) {}
+
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<(), SavestateError> {
+        Ok(())
     }
 }
 
@@ -111,6 +149,21 @@ impl MemoryBankController for MBC1 {
             _ => panic!("Address {addr:#X} not mapped in Memory Bank Controller."),
         }
     }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+        writer.write_bool(self.banking_mode);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()? & 0x1F;
+        self.ram_bank_number = reader.read_u8()? & 0x3;
+        self.banking_mode = reader.read_bool()?;
+        Ok(())
+    }
 }
 
 // TODO: add real-time clock (RTC) support
@@ -165,6 +218,19 @@ impl MemoryBankController for MBC3 {
             _ => panic!("Address {addr:#X} not mapped in Memory Bank Controller."),
         }
     }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()? & 0x1F;
+        self.ram_bank_number = reader.read_u8()? & 0x3;
+        Ok(())
+    }
 }
 
 pub struct MBC5 {
@@ -223,4 +289,19 @@ impl MemoryBankController for MBC5 {
             _ => panic!("Address {addr:#X} not mapped in Memory Bank Controller."),
         }
     }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.rom_bank_number2);
+        writer.write_u8(self.ram_bank_number);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.rom_bank_number2 = reader.read_u8()? & 0x1;
+        self.ram_bank_number = reader.read_u8()? & 0xF;
+        Ok(())
+    }
 }
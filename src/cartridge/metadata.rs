@@ -1,27 +1,157 @@
-const CART_TITLE_START: usize = 0x134;
-const CART_TITLE_END: usize = 0x143;
-const CART_CARTRIDGE_TYPE: usize = 0x147;
-const CART_ROM_SIZE: usize = 0x148;
-const CART_RAM_SIZE: usize = 0x149;
-const CART_HEADER_CHECKSUM: usize = 0x14D;
-const CART_GLOBAL_CHECKSUM1: usize = 0x14E;
-const CART_GLOBAL_CHECKSUM2: usize = 0x14F;
+use crate::cartridge::ROM_BANK_SIZE;
+
+pub const CART_ENTRY_POINT: usize = 0x100;
+pub const CART_LOGO_START: usize = 0x104;
+pub const CART_TITLE_START: usize = 0x134;
+pub const CART_TITLE_END: usize = 0x143;
+pub const CART_CGB_FLAG: usize = 0x143;
+pub const CART_NEW_LICENSEE_CODE1: usize = 0x144;
+pub const CART_NEW_LICENSEE_CODE2: usize = 0x145;
+pub const CART_SGB_FLAG: usize = 0x146;
+pub const CART_CARTRIDGE_TYPE: usize = 0x147;
+pub const CART_ROM_SIZE: usize = 0x148;
+pub const CART_RAM_SIZE: usize = 0x149;
+pub const CART_DESTINATION_CODE: usize = 0x14A;
+pub const CART_OLD_LICENSEE_CODE: usize = 0x14B;
+pub const CART_MASK_ROM_VERSION: usize = 0x14C;
+pub const CART_HEADER_CHECKSUM: usize = 0x14D;
+pub const CART_GLOBAL_CHECKSUM1: usize = 0x14E;
+pub const CART_GLOBAL_CHECKSUM2: usize = 0x14F;
+pub const CART_HEADER_END: usize = 0x150;
+
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Support for the Game Boy Color declared by the header (0x143).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    /// Made for the DMG, the CGB colorizes it with a palette.
+    None,
+    /// Runs on both, with CGB features when available.
+    Enhanced,
+    /// Only runs on the CGB.
+    Required,
+}
+
+impl CgbSupport {
+    #[must_use]
+    pub const fn from_flag(flag: u8) -> Self {
+        match flag {
+            0xC0 => Self::Required,
+            // Bit 7 alone, the other bits are ignored by the hardware
+            flag if flag & 0x80 != 0 => Self::Enhanced,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Memory bank controller (or other mapper) of a cartridge, decoded from the cartridge type
+/// (0x147).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapperKind {
+    /// 32 KiB of ROM mapped directly, maybe with RAM.
+    None,
+    Mbc1,
+    Mbc3,
+    Mbc5,
+    PocketCamera,
+}
+
+impl MapperKind {
+    /// Returns the mapper of a cartridge type, `None` for types that aren't supported.
+    #[must_use]
+    pub const fn from_cartridge_type(cartridge_type: u8) -> Option<Self> {
+        match cartridge_type {
+            0x00 | 0x08 | 0x09 => Some(Self::None),
+            0x01..=0x03 => Some(Self::Mbc1),
+            0x0F..=0x13 => Some(Self::Mbc3),
+            0x19..=0x1E => Some(Self::Mbc5),
+            0xFC => Some(Self::PocketCamera),
+            _ => None,
+        }
+    }
+
+    /// Returns the name used in cartridge type tables, "ROM ONLY" without a controller.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "ROM ONLY",
+            Self::Mbc1 => "MBC1",
+            Self::Mbc3 => "MBC3",
+            Self::Mbc5 => "MBC5",
+            Self::PocketCamera => "POCKET CAMERA",
+        }
+    }
+}
+
+/// Region the cartridge was sold in, from the destination code (0x14A).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+impl Destination {
+    #[must_use]
+    pub const fn from_code(code: u8) -> Self {
+        if code == 0x00 {
+            Self::Japan
+        } else {
+            Self::Overseas
+        }
+    }
+}
+
+/// Publisher of a cartridge, from the old licensee code (0x14B) or, when that is 0x33, the
+/// two ASCII characters of the new licensee code (0x144-0x145).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Licensee {
+    Old(u8),
+    New([u8; 2]),
+}
+
+impl Licensee {
+    /// Returns the publisher's name, "Unknown" for codes not in the tables.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Old(code) => old_licensee_name(code),
+            Self::New(code) => new_licensee_name(code),
+        }
+    }
+}
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug)]
 pub struct Metadata {
     pub title: String,
-    pub mbc_number: u8,
+    pub mapper: MapperKind,
+    pub cartridge_type: u8,
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    pub destination_code: u8,
     pub has_ram: bool,
     pub has_battery: bool,
+    pub has_timer: bool,
+    pub has_rumble: bool,
     pub rom_bank_count: usize,
     pub ram_bank_count: usize,
     pub passed_header_check: bool,
     pub passed_global_check: bool,
+    pub passed_logo_check: bool,
+    pub licensee: Licensee,
 }
 
 impl Metadata {
-    pub fn new(rom: &[u8]) -> Self {
+    /// Reads the header of `rom`, which must be at least [`CART_HEADER_END`] bytes.
+    ///
+    /// Homebrew and test payloads often leave header fields blank or invalid, so invalid
+    /// fields are replaced by a guess with a warning added to `warnings` rather than
+    /// refusing the ROM.
+    pub fn new(rom: &[u8], warnings: &mut Vec<String>) -> Self {
         let title = rom[CART_TITLE_START..=CART_TITLE_END]
             .iter()
             .map(|byte| char::from(*byte))
@@ -30,13 +160,22 @@ impl Metadata {
 
         let cartridge_type = rom[CART_CARTRIDGE_TYPE];
 
-        let mbc_number = match cartridge_type {
-            0x00 | 0x08 | 0x09 => 0,
-            0x01..=0x03 => 1,
-            0x0F..=0x13 => 3,
-            0x19..=0x1E => 5,
-            val => panic!("Memory bank controller for {val:#X} not implemented"),
-        };
+        let mapper = MapperKind::from_cartridge_type(cartridge_type).unwrap_or_else(|| {
+            // Without a memory bank controller, only 32 KiB can be mapped
+            let mapper = if rom.len() <= 2 * ROM_BANK_SIZE {
+                MapperKind::None
+            } else {
+                MapperKind::Mbc5
+            };
+            let name = match mapper {
+                MapperKind::None => "no memory bank controller",
+                _ => mapper.name(),
+            };
+            warnings.push(format!(
+                "Cartridge type {cartridge_type:#04X} is not supported. Assuming {name}."
+            ));
+            mapper
+        });
 
         let has_ram = matches!(
             cartridge_type,
@@ -53,26 +192,41 @@ impl Metadata {
                 | 0x1D
                 | 0x1E
                 | 0x22
+                | 0xFC
                 | 0xFF
         );
 
         let has_battery = matches!(
             cartridge_type,
-            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFF
         );
 
+        let has_timer = matches!(cartridge_type, 0x0F | 0x10);
+
+        let has_rumble = matches!(cartridge_type, 0x1C..=0x1E);
+
         let rom_bank_count = match rom[CART_ROM_SIZE] {
             n @ 0x00..=0x08 => 1 << (n + 1),
-            val => panic!("Invalid value {val:#X} for ROM size in cartridge header."),
+            val => {
+                let count = rom.len().div_ceil(ROM_BANK_SIZE).next_power_of_two().max(2);
+                warnings.push(format!("Invalid value {val:#04X} for ROM size in cartridge header. Assuming {} KiB from the file size.", count * 16));
+                count
+            }
         };
 
         let ram_bank_count = match rom[CART_RAM_SIZE] {
             0x00 => 0,
-            0x02 => 1,
+            // 2 KiB, never used by licensed games, rounded up to a bank
+            0x01 | 0x02 => 1,
             0x03 => 4,
             0x04 => 16,
             0x05 => 8,
-            val => panic!("Invalid value {val:#02X} for RAM size in cartridge header."),
+            val => {
+                warnings.push(format!(
+                    "Invalid value {val:#04X} for RAM size in cartridge header. Ignoring it."
+                ));
+                0
+            }
         };
 
         let passed_header_check = rom[CART_HEADER_CHECKSUM] == calculate_header_checksum(rom);
@@ -81,28 +235,44 @@ impl Metadata {
             u16::from_be_bytes([rom[CART_GLOBAL_CHECKSUM1], rom[CART_GLOBAL_CHECKSUM2]])
                 == calculate_global_checksum(rom);
 
+        let passed_logo_check =
+            rom[CART_LOGO_START..CART_LOGO_START + NINTENDO_LOGO.len()] == NINTENDO_LOGO;
+
+        let licensee = match rom[CART_OLD_LICENSEE_CODE] {
+            0x33 => Licensee::New([rom[CART_NEW_LICENSEE_CODE1], rom[CART_NEW_LICENSEE_CODE2]]),
+            code => Licensee::Old(code),
+        };
+
         Self {
             title,
-            mbc_number,
+            mapper,
+            cartridge_type,
+            cgb_flag: rom[CART_CGB_FLAG],
+            sgb_flag: rom[CART_SGB_FLAG],
+            destination_code: rom[CART_DESTINATION_CODE],
             has_ram,
             has_battery,
+            has_timer,
+            has_rumble,
             rom_bank_count,
             ram_bank_count,
             passed_header_check,
             passed_global_check,
+            passed_logo_check,
+            licensee,
         }
     }
 }
 
-fn calculate_header_checksum(rom: &[u8]) -> u8 {
+pub fn calculate_header_checksum(rom: &[u8]) -> u8 {
     let mut checksum: u8 = 0;
-    for byte in &rom[CART_TITLE_START..CART_TITLE_END] {
+    for byte in &rom[CART_TITLE_START..=CART_MASK_ROM_VERSION] {
         checksum = checksum.wrapping_sub(*byte).wrapping_sub(1);
     }
     checksum
 }
 
-fn calculate_global_checksum(rom: &[u8]) -> u16 {
+pub fn calculate_global_checksum(rom: &[u8]) -> u16 {
     let mut checksum: u16 = 0;
     for (addr, byte) in rom.iter().enumerate() {
         if addr != CART_GLOBAL_CHECKSUM1 && addr != CART_GLOBAL_CHECKSUM2 {
@@ -111,3 +281,193 @@ fn calculate_global_checksum(rom: &[u8]) -> u16 {
     }
     checksum
 }
+
+const fn new_licensee_name(code: [u8; 2]) -> &'static str {
+    match &code {
+        b"00" => "None",
+        b"01" => "Nintendo Research & Development 1",
+        b"08" => "Capcom",
+        b"13" | b"69" => "EA (Electronic Arts)",
+        b"18" | b"38" => "Hudson Soft",
+        b"19" => "B-AI",
+        b"20" => "KSS",
+        b"22" => "Planning Office WADA",
+        b"24" => "PCM Complete",
+        b"25" => "San-X",
+        b"28" => "Kemco",
+        b"29" => "SETA Corporation",
+        b"30" => "Viacom",
+        b"31" => "Nintendo",
+        b"32" => "Bandai",
+        b"33" | b"93" => "Ocean Software/Acclaim Entertainment",
+        b"34" | b"54" => "Konami",
+        b"35" => "HectorSoft",
+        b"37" => "Taito",
+        b"39" => "Banpresto",
+        b"41" => "Ubi Soft",
+        b"42" => "Atlus",
+        b"44" => "Malibu Interactive",
+        b"46" => "Angel",
+        b"47" => "Bullet-Proof Software",
+        b"49" => "Irem",
+        b"50" => "Absolute",
+        b"51" => "Acclaim Entertainment",
+        b"52" => "Activision",
+        b"53" => "Sammy USA Corporation",
+        b"55" => "Hi Tech Expressions",
+        b"56" => "LJN",
+        b"57" => "Matchbox",
+        b"58" => "Mattel",
+        b"59" => "Milton Bradley Company",
+        b"60" => "Titus Interactive",
+        b"61" => "Virgin Games Ltd.",
+        b"64" => "Lucasfilm Games",
+        b"67" => "Ocean Software",
+        b"70" => "Infogrames",
+        b"71" => "Interplay Entertainment",
+        b"72" => "Broderbund",
+        b"73" => "Sculptured Software",
+        b"75" => "The Sales Curve Limited",
+        b"78" => "THQ",
+        b"79" => "Accolade",
+        b"80" => "Misawa Entertainment",
+        b"83" => "LOZC G.",
+        b"86" => "Tokuma Shoten",
+        b"87" => "Tsukuda Original",
+        b"91" => "Chunsoft Co.",
+        b"92" => "Video System",
+        b"95" => "Varie",
+        b"96" => "Yonezawa/S'Pal",
+        b"97" => "Kaneko",
+        b"99" => "Pack-In-Video",
+        b"9H" => "Bottom Up",
+        b"A4" => "Konami (Yu-Gi-Oh!)",
+        b"BL" => "MTO",
+        b"DK" => "Kodansha",
+        _ => "Unknown",
+    }
+}
+
+const fn old_licensee_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "None",
+        0x01 | 0x31 => "Nintendo",
+        0x08 | 0x38 => "Capcom",
+        0x09 => "HOT-B",
+        0x0A | 0xE0 => "Jaleco",
+        0x0B => "Coconuts Japan",
+        0x0C | 0x6E => "Elite Systems",
+        0x13 | 0x69 => "EA (Electronic Arts)",
+        0x18 => "Hudson Soft",
+        0x19 => "ITC Entertainment",
+        0x1A => "Yanoman",
+        0x1D => "Japan Clary",
+        0x1F | 0x4A | 0x61 => "Virgin Games Ltd.",
+        0x24 => "PCM Complete",
+        0x25 => "San-X",
+        0x28 | 0x7F | 0x97 | 0xC2 => "Kemco",
+        0x29 => "SETA Corporation",
+        0x30 | 0x70 => "Infogrames",
+        0x32 | 0xA2 | 0xB2 => "Bandai",
+        0x34 | 0xA4 => "Konami",
+        0x35 => "HectorSoft",
+        0x39 | 0x9D | 0xD9 => "Banpresto",
+        0x3C => "Entertainment Interactive",
+        0x3E => "Gremlin",
+        0x41 => "Ubi Soft",
+        0x42 | 0xEB => "Atlus",
+        0x44 | 0x4D => "Malibu Interactive",
+        0x46 | 0xCF => "Angel",
+        0x47 => "Spectrum HoloByte",
+        0x49 => "Irem",
+        0x4F => "U.S. Gold",
+        0x50 => "Absolute",
+        0x51 | 0xB0 => "Acclaim Entertainment",
+        0x52 => "Activision",
+        0x53 => "Sammy USA Corporation",
+        0x54 => "GameTek",
+        0x55 => "Park Place",
+        0x56 | 0xDB | 0xFF => "LJN",
+        0x57 => "Matchbox",
+        0x59 => "Milton Bradley Company",
+        0x5A => "Mindscape",
+        0x5B => "Romstar",
+        0x5C | 0xD6 => "Naxat Soft",
+        0x5D => "Tradewest",
+        0x60 => "Titus Interactive",
+        0x67 => "Ocean Software",
+        0x6F => "Electro Brain",
+        0x71 => "Interplay Entertainment",
+        0x72 | 0xAA => "Broderbund",
+        0x73 => "Sculptured Software",
+        0x75 => "The Sales Curve Limited",
+        0x78 => "THQ",
+        0x79 => "Accolade",
+        0x7A => "Triffix Entertainment",
+        0x7C => "MicroProse",
+        0x80 => "Misawa Entertainment",
+        0x83 => "LOZC G.",
+        0x86 | 0xC4 => "Tokuma Shoten",
+        0x8B => "Bullet-Proof Software",
+        0x8C => "Vic Tokai Corp.",
+        0x8E => "Ape Inc.",
+        0x8F => "I'Max",
+        0x91 => "Chunsoft Co.",
+        0x92 => "Video System",
+        0x93 => "Tsubaraya Productions",
+        0x95 | 0xE3 => "Varie",
+        0x96 => "Yonezawa/S'Pal",
+        0x99 => "Arc",
+        0x9A => "Nihon Bussan",
+        0x9B => "Tecmo",
+        0x9C => "Imagineer",
+        0x9F => "Nova",
+        0xA1 => "Hori Electric",
+        0xA6 => "Kawada",
+        0xA7 => "Takara",
+        0xA9 => "Technos Japan",
+        0xAC => "Toei Animation",
+        0xAD => "Toho",
+        0xAF => "Namco",
+        0xB1 => "ASCII Corporation or Nexsoft",
+        0xB4 => "Square Enix",
+        0xB6 => "HAL Laboratory",
+        0xB7 => "SNK",
+        0xB9 | 0xCE => "Pony Canyon",
+        0xBA => "Culture Brain",
+        0xBB => "Sunsoft",
+        0xBD => "Sony Imagesoft",
+        0xBF => "Sammy Corporation",
+        0xC0 | 0xD0 => "Taito",
+        0xC3 => "Square",
+        0xC5 => "Data East",
+        0xC6 => "Tonkin House",
+        0xC8 => "Koei",
+        0xC9 => "UFL",
+        0xCA => "Ultra Games",
+        0xCB => "VAP, Inc.",
+        0xCC => "Use Corporation",
+        0xCD => "Meldac",
+        0xD1 => "SOFEL",
+        0xD2 => "Quest",
+        0xD3 => "Sigma Enterprises",
+        0xD4 => "ASK Kodansha Co.",
+        0xD7 => "Copya System",
+        0xDA => "Tomy",
+        0xDD => "Nippon Computer Systems",
+        0xDE => "Human Ent.",
+        0xDF => "Altron",
+        0xE1 => "Towa Chiki",
+        0xE2 => "Yutaka",
+        0xE5 => "Epoch",
+        0xE7 => "Athena",
+        0xE8 => "Asmik Ace Entertainment",
+        0xE9 => "Natsume",
+        0xEA => "King Records",
+        0xEC => "Epic/Sony Records",
+        0xEE => "IGS",
+        0xF0 => "A Wave",
+        0xF3 => "Extreme Entertainment",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,111 @@
+use crate::cartridge::metadata::{
+    calculate_header_checksum, CART_HEADER_CHECKSUM, CART_HEADER_END, CART_LOGO_START,
+    CART_ROM_SIZE, CART_TITLE_END, CART_TITLE_START, NINTENDO_LOGO,
+};
+use crate::cartridge::{Cartridge, ROM_BANK_SIZE};
+
+// Flash carts place each game at a multiple of the smallest ROM size
+const SUB_ROM_ALIGNMENT: usize = 2 * ROM_BANK_SIZE;
+
+/// A game found inside a multi-ROM flash cart image, see [`find_sub_roms`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubRom {
+    /// Offset of the game in the image.
+    pub offset: usize,
+    /// Size declared by the game's header, cut short if the image ends first.
+    pub size: usize,
+    pub title: String,
+}
+
+impl SubRom {
+    /// Returns the game's ROM, ready for [`Cartridge::new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the game doesn't fit in `image`, i.e. it was found in another image.
+    #[must_use]
+    pub fn extract(&self, image: &[u8]) -> Vec<u8> {
+        image[self.offset..self.offset + self.size].to_vec()
+    }
+}
+
+/// Returns the games in a flash cart dump holding several ROMs (e.g. an EMS 64M image),
+/// starting with the menu if there is one.
+///
+/// Games are found by a valid header (Nintendo logo and header checksum) at each 32 KiB
+/// boundary not covered by the game before it. A regular ROM returns only itself, so an
+/// image is a multi-ROM image if this returns more than one game.
+#[must_use]
+pub fn find_sub_roms(image: &[u8]) -> Vec<SubRom> {
+    let mut sub_roms = Vec::new();
+    let mut offset = 0;
+    while offset + CART_HEADER_END <= image.len() {
+        let rom = &image[offset..];
+        let size_code = rom[CART_ROM_SIZE];
+        if !has_valid_header(rom) || size_code > 0x08 {
+            offset += SUB_ROM_ALIGNMENT;
+            continue;
+        }
+        let size = (SUB_ROM_ALIGNMENT << size_code).min(rom.len());
+        let title = rom[CART_TITLE_START..=CART_TITLE_END]
+            .iter()
+            .take_while(|byte| **byte != 0)
+            .map(|byte| char::from(*byte))
+            .filter(char::is_ascii)
+            .collect();
+        sub_roms.push(SubRom {
+            offset,
+            size,
+            title,
+        });
+        offset += size.next_multiple_of(SUB_ROM_ALIGNMENT);
+    }
+    sub_roms
+}
+
+fn has_valid_header(rom: &[u8]) -> bool {
+    rom[CART_LOGO_START..CART_LOGO_START + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        && rom[CART_HEADER_CHECKSUM] == calculate_header_checksum(rom)
+}
+
+impl Cartridge {
+    /// Creates a cartridge running one of the games in a multi-ROM image.
+    #[must_use]
+    pub fn from_sub_rom(image: &[u8], sub_rom: &SubRom) -> Self {
+        Self::new(sub_rom.extract(image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{find_sub_roms, Cartridge, HeaderBuilder, ROM_BANK_SIZE};
+
+    #[test]
+    fn test_finds_sub_roms() {
+        let mut image = HeaderBuilder::new().title("MENU").build(&[]);
+        image.extend(HeaderBuilder::new().title("GAME A").rom_banks(4).build(&[]));
+        // Padding between games is skipped
+        image.resize(image.len() + 2 * ROM_BANK_SIZE, 0xFF);
+        image.extend(HeaderBuilder::new().title("GAME B").build(&[0x76]));
+
+        let sub_roms = find_sub_roms(&image);
+        let found: Vec<_> = sub_roms
+            .iter()
+            .map(|sub_rom| (sub_rom.offset / ROM_BANK_SIZE, sub_rom.title.as_str()))
+            .collect();
+        assert_eq!(found, [(0, "MENU"), (2, "GAME A"), (8, "GAME B")]);
+
+        let cartridge = Cartridge::from_sub_rom(&image, &sub_roms[2]);
+        assert!(cartridge.get_title().starts_with("GAME B"));
+        assert!(cartridge.passed_global_check());
+    }
+
+    #[test]
+    fn test_single_rom() {
+        let rom = HeaderBuilder::new().rom_banks(8).build(&[]);
+        let sub_roms = find_sub_roms(&rom);
+        assert_eq!(sub_roms.len(), 1);
+        assert_eq!(sub_roms[0].size, rom.len());
+        assert!(find_sub_roms(&[0; 0x8000]).is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::cartridge::metadata::{MapperKind, Metadata};
+use crate::error::OverrideError;
+use std::collections::BTreeMap;
+
+// Titles of games with an MBC3 real-time clock, some dumps and bootlegs drop the timer
+// from the cartridge type
+const RTC_TITLES: [&str; 3] = ["POKEMON_GLD", "POKEMON_SLV", "PM_CRYSTAL"];
+// RAM assumed when the cartridge type has RAM but the header gives no size: 32 KiB, the
+// most MBC1 and MBC3 can address
+const ASSUMED_RAM_BANKS: usize = 4;
+
+/// Corrections for a cartridge whose header doesn't describe its hardware, see
+/// [`OverrideTable`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeaderOverride {
+    /// RAM size in 8 KiB banks, 0 for no RAM.
+    pub ram_banks: Option<usize>,
+    /// Whether the cartridge has a real-time clock.
+    pub has_rtc: Option<bool>,
+}
+
+/// Header overrides keyed by ROM hash (see [`Cartridge::rom_hash`]), applied by
+/// [`Cartridge::with_overrides`].
+///
+/// Without an override, headers are still corrected for a few known cases: games known to
+/// have a real-time clock get one, and RAM is assumed when the cartridge type has RAM but
+/// the header gives no size.
+///
+/// [`Cartridge::rom_hash`]: crate::cartridge::Cartridge::rom_hash
+/// [`Cartridge::with_overrides`]: crate::cartridge::Cartridge::with_overrides
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverrideTable {
+    overrides: BTreeMap<u64, HeaderOverride>,
+}
+
+impl OverrideTable {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses overrides written one ROM per line, as the ROM hash in hex followed by
+    /// `ram=<banks>` and/or `rtc=yes|no`. Empty lines and lines starting with `#` are ignored.
+    ///
+    /// ```text
+    /// # Bootleg with a 32 KiB RAM chip and no RAM size in the header
+    /// 0123456789ABCDEF ram=4 rtc=no
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the number of the first line that can't be parsed.
+    pub fn parse(text: &str) -> Result<Self, OverrideError> {
+        let mut table = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (hash, header_override) =
+                parse_line(line).ok_or(OverrideError::InvalidLine(index + 1))?;
+            table.insert(hash, header_override);
+        }
+        Ok(table)
+    }
+
+    /// Adds an override for the ROM with hash `rom_hash`, replacing any previous one.
+    pub fn insert(&mut self, rom_hash: u64, header_override: HeaderOverride) {
+        self.overrides.insert(rom_hash, header_override);
+    }
+
+    #[must_use]
+    pub fn get(&self, rom_hash: u64) -> Option<HeaderOverride> {
+        self.overrides.get(&rom_hash).copied()
+    }
+
+    /// Corrects the metadata read from the header of the ROM with hash `rom_hash`, adding
+    /// guesses made without an override to `warnings`.
+    pub(crate) fn apply(&self, metadata: &mut Metadata, rom_hash: u64, warnings: &mut Vec<String>) {
+        let title = metadata.title.trim_end_matches('\0');
+        if metadata.mapper == MapperKind::Mbc3 && RTC_TITLES.contains(&title) {
+            metadata.has_timer = true;
+        }
+        if metadata.has_ram && metadata.ram_bank_count == 0 {
+            warnings.push(format!(
+                "Cartridge type has RAM but the header gives no RAM size. Assuming {} KiB.",
+                ASSUMED_RAM_BANKS * 8
+            ));
+            metadata.ram_bank_count = ASSUMED_RAM_BANKS;
+        }
+
+        let Some(header_override) = self.get(rom_hash) else {
+            return;
+        };
+        if let Some(ram_banks) = header_override.ram_banks {
+            metadata.has_ram = ram_banks > 0;
+            metadata.ram_bank_count = ram_banks;
+        }
+        if let Some(has_rtc) = header_override.has_rtc {
+            metadata.has_timer = has_rtc;
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<(u64, HeaderOverride)> {
+    let mut words = line.split_whitespace();
+    let hash = u64::from_str_radix(words.next()?.trim_start_matches("0x"), 16).ok()?;
+    let mut header_override = HeaderOverride::default();
+    for word in words {
+        match word.split_once('=')? {
+            ("ram", banks) => header_override.ram_banks = Some(banks.parse().ok()?),
+            ("rtc", "yes") => header_override.has_rtc = Some(true),
+            ("rtc", "no") => header_override.has_rtc = Some(false),
+            _ => return None,
+        }
+    }
+    Some((hash, header_override))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder, HeaderOverride, OverrideTable};
+    use crate::error::OverrideError;
+
+    #[test]
+    fn test_parse() {
+        let table =
+            OverrideTable::parse("# comment\n\n0x00000000000000FF ram=4\n1234 rtc=yes\n").unwrap();
+        let ram = HeaderOverride {
+            ram_banks: Some(4),
+            has_rtc: None,
+        };
+        assert_eq!(table.get(0xFF), Some(ram));
+        assert_eq!(table.get(0x1234).unwrap().has_rtc, Some(true));
+        assert_eq!(
+            OverrideTable::parse("1234\n5678 ram=big"),
+            Err(OverrideError::InvalidLine(2))
+        );
+    }
+
+    #[test]
+    fn test_overrides_header() {
+        // MBC3+RAM+BATTERY declaring no RAM size
+        let rom = HeaderBuilder::new().cartridge_type(0x13).build(&[]);
+        let hash = Cartridge::new(rom.clone()).rom_hash();
+        assert_eq!(Cartridge::new(rom.clone()).get_ram_size(), 32 * 1024);
+
+        let mut table = OverrideTable::new();
+        table.insert(
+            hash,
+            HeaderOverride {
+                ram_banks: Some(1),
+                has_rtc: Some(true),
+            },
+        );
+        let cartridge = Cartridge::with_overrides(rom, &table);
+        assert_eq!(cartridge.get_ram_size(), 8 * 1024);
+        assert!(cartridge.has_timer());
+        assert_eq!(cartridge.save_data().unwrap().len(), 8 * 1024 + 12);
+    }
+
+    #[test]
+    fn test_rtc_heuristic() {
+        let rom = HeaderBuilder::new()
+            .title("PM_CRYSTAL")
+            .cartridge_type(0x13)
+            .ram_banks(4)
+            .build(&[]);
+        assert!(Cartridge::new(rom).has_timer());
+    }
+}
@@ -0,0 +1,330 @@
+//! Game Genie and GameShark cheats, and importing them from RetroArch `.cht` files.
+
+use crate::error::CheatError;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// A single cheat code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatCode {
+    /// Replaces the ROM byte at `addr` as it is read, only while it is `compare` if given
+    /// (so the code only hits the intended ROM bank). Written `ABC-DEF` or `ABC-DEF-GHI`.
+    GameGenie {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes `value` to the RAM byte at `addr` at the start of every VBlank. Written
+    /// `BBVVLLHH`, with the RAM bank, the value and the address low byte first.
+    ///
+    /// The bank is kept for reference, the write goes to whichever bank is mapped.
+    GameShark { bank: u8, addr: u16, value: u8 },
+}
+
+impl FromStr for CheatCode {
+    type Err = CheatError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let invalid = || CheatError::InvalidCode(code.to_string());
+        let digits: Vec<u8> = code
+            .trim()
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).and_then(|digit| u8::try_from(digit).ok()))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        let byte = |index: usize| digits[index] << 4 | digits[index + 1];
+        match digits.len() {
+            6 | 9 => {
+                let addr = u16::from(digits[5] ^ 0xF) << 12
+                    | u16::from(digits[2]) << 8
+                    | u16::from(digits[3]) << 4
+                    | u16::from(digits[4]);
+                if addr > 0x7FFF {
+                    return Err(invalid());
+                }
+                // The third group holds the compare value rotated and scrambled, its middle
+                // digit is ignored
+                let compare = (digits.len() == 9)
+                    .then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Self::GameGenie {
+                    addr,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            8 => Ok(Self::GameShark {
+                bank: byte(0),
+                value: byte(2),
+                addr: u16::from_le_bytes([byte(4), byte(6)]),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Cheats imported from a `.cht` file by [`parse_cht`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChtFile {
+    pub cheats: Vec<Cheat>,
+    /// Why entries of the file were skipped, for frontends to show as warnings.
+    pub warnings: Vec<String>,
+}
+
+/// A named set of codes turned on and off together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub description: String,
+    pub codes: Vec<CheatCode>,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    /// Parses codes written in libretro syntax, separated by `+`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a code is neither a Game Genie nor a GameShark code.
+    pub fn new(description: &str, codes: &str, enabled: bool) -> Result<Self, CheatError> {
+        Ok(Self {
+            description: description.to_string(),
+            codes: codes.split('+').map(str::parse).collect::<Result<_, _>>()?,
+            enabled,
+        })
+    }
+}
+
+/// Parses a RetroArch `.cht` file: `cheats = <count>` followed by `cheat<n>_desc`,
+/// `cheat<n>_code` and `cheat<n>_enable` for each cheat. Other keys are ignored, and cheats
+/// without a code (RetroArch's own RAM search cheats) are skipped with a warning in
+/// [`ChtFile::warnings`].
+///
+/// # Errors
+///
+/// Returns an error for lines that aren't `key = value`, a missing or invalid count and
+/// invalid codes.
+pub fn parse_cht(text: &str) -> Result<ChtFile, CheatError> {
+    let mut values = BTreeMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(CheatError::InvalidLine(index + 1))?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        values.insert(key.trim(), value);
+    }
+
+    let count: usize = values
+        .get("cheats")
+        .and_then(|count| count.parse().ok())
+        .ok_or(CheatError::MissingCount)?;
+    let mut cheats = Vec::with_capacity(count);
+    let mut warnings = Vec::new();
+    for index in 0..count {
+        let key = |name: &str| values.get(format!("cheat{index}_{name}").as_str()).copied();
+        let description = key("desc").unwrap_or_default();
+        let Some(codes) = key("code").filter(|codes| !codes.is_empty()) else {
+            warnings.push(format!(
+                "Cheat {index} ({description}) has no code, skipping it."
+            ));
+            continue;
+        };
+        let enabled = key("enable") == Some("true");
+        cheats.push(Cheat::new(description, codes, enabled)?);
+    }
+    Ok(ChtFile { cheats, warnings })
+}
+
+/// Cheats added to the hardware, see [`GameboyHardware::add_cheat`].
+///
+/// [`GameboyHardware::add_cheat`]: crate::hardware::GameboyHardware::add_cheat
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cheats {
+    cheats: Vec<Cheat>,
+    // Game Genie codes of enabled cheats, checked on every ROM read
+    rom_patches: Vec<(u16, u8, Option<u8>)>,
+    // GameShark codes of enabled cheats
+    ram_writes: Vec<(u16, u8)>,
+}
+
+impl Cheats {
+    pub(crate) const fn new() -> Self {
+        Self {
+            cheats: Vec::new(),
+            rom_patches: Vec::new(),
+            ram_writes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub(crate) fn push(&mut self, cheat: Cheat) -> usize {
+        self.cheats.push(cheat);
+        self.update();
+        self.cheats.len() - 1
+    }
+
+    /// Returns the cheat, or `None` if there is no cheat at `index`.
+    pub(crate) fn set_enabled(&mut self, index: usize, enabled: bool) -> Option<&Cheat> {
+        self.cheats.get_mut(index)?.enabled = enabled;
+        self.update();
+        self.cheats.get(index)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    fn update(&mut self) {
+        self.rom_patches.clear();
+        self.ram_writes.clear();
+        let codes = self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .flat_map(|cheat| &cheat.codes);
+        for code in codes {
+            match *code {
+                CheatCode::GameGenie {
+                    addr,
+                    value,
+                    compare,
+                } => self.rom_patches.push((addr, value, compare)),
+                CheatCode::GameShark { addr, value, .. } => self.ram_writes.push((addr, value)),
+            }
+        }
+    }
+
+    /// Returns `value` read from ROM at `addr` as patched by Game Genie codes.
+    pub(crate) fn patch_rom(&self, addr: u16, value: u8) -> u8 {
+        self.rom_patches
+            .iter()
+            .find(|(patch_addr, _, compare)| {
+                *patch_addr == addr && compare.is_none_or(|compare| compare == value)
+            })
+            .map_or(value, |(_, patched, _)| *patched)
+    }
+
+    pub(crate) fn ram_writes(&self) -> &[(u16, u8)] {
+        &self.ram_writes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::cheat::{parse_cht, Cheat, CheatCode};
+    use crate::error::CheatError;
+    use crate::hardware::GameboyHardware;
+
+    #[test]
+    fn test_parse_codes() {
+        assert_eq!(
+            "010238CD".parse(),
+            Ok(CheatCode::GameShark {
+                bank: 0x01,
+                addr: 0xCD38,
+                value: 0x02
+            })
+        );
+        // Compare value 0x18 scrambled: (0x18 ^ 0xBA).rotate_left(2) = 0x8A
+        assert_eq!(
+            "3EA-35F-8EA".parse(),
+            Ok(CheatCode::GameGenie {
+                addr: 0x0A35,
+                value: 0x3E,
+                compare: Some(0x18)
+            })
+        );
+        assert_eq!(
+            "3EA35F".parse(),
+            Ok(CheatCode::GameGenie {
+                addr: 0x0A35,
+                value: 0x3E,
+                compare: None
+            })
+        );
+        // Game Genie only reaches ROM
+        assert!("3EA-350".parse::<CheatCode>().is_err());
+        assert!("01XX38CD".parse::<CheatCode>().is_err());
+    }
+
+    #[test]
+    fn test_parse_cht() {
+        let text = r#"cheats = 3
+
+cheat0_desc = "Infinite Lives"
+cheat0_code = "00A-17B-C49+010238CD"
+cheat0_enable = true
+
+cheat1_desc = "RAM search"
+cheat1_address = "49208"
+cheat1_enable = false
+
+cheat2_desc = "Moon Jump"
+cheat2_code = "01FF10C1"
+cheat2_enable = false
+"#;
+        let file = parse_cht(text).unwrap();
+        let cheats = file.cheats;
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].description, "Infinite Lives");
+        assert_eq!(cheats[0].codes.len(), 2);
+        assert!(cheats[0].enabled);
+        assert!(!cheats[1].enabled);
+        assert_eq!(
+            file.warnings,
+            ["Cheat 1 (RAM search) has no code, skipping it."]
+        );
+
+        assert_eq!(
+            parse_cht("cheat0_code = 010238CD"),
+            Err(CheatError::MissingCount)
+        );
+        assert_eq!(
+            parse_cht("cheats = 1\nnonsense"),
+            Err(CheatError::InvalidLine(2))
+        );
+        assert_eq!(
+            parse_cht("cheats = 1\ncheat0_code = \"12345\""),
+            Err(CheatError::InvalidCode("12345".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_apply_cheats() {
+        // loop: LD A, (0x0200); LD (0xC001), A; JR loop
+        let program = [0xFA, 0x00, 0x02, 0xEA, 0x01, 0xC0, 0x18, 0xF8];
+        let mut rom = HeaderBuilder::new().build(&program);
+        rom[0x0200] = 0x11;
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        // ROM byte 0x0200 becomes 0x22 if it is 0x11, WRAM 0xC000 is kept at 0x99
+        let compare = (0x11u8 ^ 0xBA).rotate_left(2);
+        let genie = format!("222-00F-{:X}0{:X}", compare >> 4, compare & 0xF);
+        let index =
+            gameboy.add_cheat(Cheat::new("Test", &format!("{genie}+019900C0"), false).unwrap());
+        gameboy.run_frame();
+        assert_eq!(gameboy.peek_byte(0xC001), 0x11);
+        assert_ne!(gameboy.peek_byte(0xC000), 0x99);
+
+        assert!(gameboy.set_cheat_enabled(index, true));
+        gameboy.run_frame();
+        assert_eq!(gameboy.peek_byte(0x0200), 0x22);
+        assert_eq!(gameboy.peek_byte(0xC001), 0x22);
+        assert_eq!(gameboy.peek_byte(0xC000), 0x99);
+        assert!(!gameboy.set_cheat_enabled(1, true));
+
+        gameboy.clear_cheats();
+        assert_eq!(gameboy.peek_byte(0x0200), 0x11);
+        assert!(gameboy.cheats().is_empty());
+    }
+}
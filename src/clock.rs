@@ -0,0 +1,66 @@
+//! The clock shared by every subsystem.
+//!
+//! Time advances in M-cycles (4 T-cycles) from the address bus, which ticks the timer
+//! first, then the serial port, the PPU and the APU. Periodic events aren't counted by each
+//! subsystem, they derive from the timer's system counter: a 16-bit counter incremented
+//! every T-cycle whose upper byte is DIV. An event happens on the falling edge of a counter
+//! bit, which is how writing to DIV can trigger them early.
+//!
+//! | Event                     | Counter bit         | Rate            |
+//! |---------------------------|---------------------|-----------------|
+//! | TIMA increment            | 3, 5, 7 or 9 (TAC)  | 262144-4096 Hz  |
+//! | Serial internal clock     | 8                   | 8192 Hz         |
+//! | DIV-APU (frame sequencer) | 12 (DIV bit 4)      | 512 Hz          |
+//!
+//! The PPU runs one dot per T-cycle, four per M-cycle. Its phase isn't tied to the system
+//! counter but to when the LCD was last turned on. Cartridge hardware (the camera) advances
+//! by the T-cycles of each CPU step.
+
+// Serial transfers shift one bit per falling edge, 8192 Hz
+pub(crate) const SERIAL_CLOCK_BIT: u16 = 1 << 8;
+// The APU frame sequencer steps on each falling edge, 512 Hz
+pub(crate) const DIV_APU_BIT: u16 = 1 << 12;
+
+/// System counter bits that fell during an M-cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ClockEdges(u16);
+
+impl ClockEdges {
+    #[cfg(test)]
+    pub(crate) const NONE: Self = Self(0);
+
+    /// Returns the bits set in `previous` but not in `current`.
+    pub(crate) const fn between(previous: u16, current: u16) -> Self {
+        Self(previous & !current)
+    }
+
+    pub(crate) const fn contains(self, bit: u16) -> bool {
+        self.0 & bit != 0
+    }
+
+    /// Returns whether the serial port's internal clock ticked.
+    pub(crate) const fn serial(self) -> bool {
+        self.contains(SERIAL_CLOCK_BIT)
+    }
+
+    /// Returns whether the APU frame sequencer should step.
+    pub(crate) const fn div_apu(self) -> bool {
+        self.contains(DIV_APU_BIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{ClockEdges, DIV_APU_BIT};
+
+    #[test]
+    fn test_edges() {
+        assert!(ClockEdges::between(0x00FC, 0x0100).contains(0x80));
+        assert!(!ClockEdges::between(0x00FC, 0x0100).serial());
+        assert!(ClockEdges::between(0x01FC, 0x0200).serial());
+        assert!(ClockEdges::between(0x1FFC, 0x2000).div_apu());
+        // Resetting DIV makes every set bit fall
+        assert!(ClockEdges::between(DIV_APU_BIT | 0x0100, 0x0004).div_apu());
+        assert_eq!(ClockEdges::between(0x1230, 0x1234), ClockEdges::NONE);
+    }
+}
@@ -0,0 +1,44 @@
+//! Hardware constants for frontends, so they don't need to hardcode them, and conversions
+//! between T-cycles and emulated time.
+
+use std::time::Duration;
+
+/// Width of the screen in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+/// Height of the screen in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// T-cycles per second at normal speed.
+pub const CPU_HZ: u32 = 4_194_304;
+/// T-cycles per second in CGB double speed mode.
+pub const DOUBLE_SPEED_CPU_HZ: u32 = 2 * CPU_HZ;
+
+/// T-cycles per frame at normal speed (154 scanlines of 456 dots).
+///
+/// The PPU runs at the same rate in double speed mode, so a frame takes twice
+/// as many CPU cycles there.
+pub const FRAME_CYCLES: u32 = 70_224;
+/// Frames per second, roughly 59.73.
+pub const FRAMES_PER_SECOND: f64 = CPU_HZ as f64 / FRAME_CYCLES as f64;
+
+/// Rate at which the APU updates its output, once per M-cycle.
+///
+/// Frontends resample from this rate to the rate of their audio device.
+pub const AUDIO_NATIVE_HZ: u32 = CPU_HZ / 4;
+
+/// Returns the emulated time `cycles` T-cycles take at normal speed.
+#[must_use]
+pub const fn cycles_to_duration(cycles: u64) -> Duration {
+    let hz = CPU_HZ as u64;
+    #[allow(clippy::cast_possible_truncation)]
+    let nanos = (cycles % hz * 1_000_000_000 / hz) as u32;
+    Duration::new(cycles / hz, nanos)
+}
+
+/// Returns the T-cycles run at normal speed in `duration` of emulated time, rounded down.
+#[must_use]
+pub const fn duration_to_cycles(duration: Duration) -> u64 {
+    #[allow(clippy::cast_possible_truncation)]
+    let cycles = (duration.as_nanos() * CPU_HZ as u128 / 1_000_000_000) as u64;
+    cycles
+}
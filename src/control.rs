@@ -0,0 +1,472 @@
+//! Local control socket for driving a running emulator from other programs.
+//!
+//! Clients send one JSON object per line and get one JSON object back per command,
+//! either `{"ok":true}` (with extra fields for some commands) or `{"ok":false,"error":"..."}`.
+//!
+//! | Command | Arguments |
+//! |---------|-----------|
+//! | `load_rom` | `path`, loaded with its battery save and header overrides as at startup |
+//! | `pause`, `resume` | |
+//! | `save_state`, `load_state` | `path`, saving also writes the session journal next to the state |
+//! | `screenshot` | `path`, written as a grayscale PGM image |
+//! | `load_cheats` | `path` of a RetroArch `.cht` file, returns the number of `cheats` added |
+//! | `press`, `release` | `button`: one of `a`, `b`, `select`, `start`, `right`, `left`, `up`, `down` |
+//! | `status` | returns `paused`, `frame` and `frame_hash` |
+//! | `writers` | `address` (e.g. `"$C123"`), starts recording the code writing it and returns `writers` so far |
+//! | `unwatch_writers` | `address` |
+//! | `quit` | |
+
+use crate::{load_cartridge, new_gameboy};
+use gb_emulator::cheat::parse_cht;
+use gb_emulator::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gb_emulator::hardware::{Button, GameboyHardware};
+use gb_emulator::persistence::{read_payload, write_payload, Payload, PlainCodec};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+
+/// A command with the sender for its response line.
+pub type Request = (Command, Sender<String>);
+
+#[derive(Debug)]
+pub enum Command {
+    LoadRom(String),
+    Pause,
+    Resume,
+    SaveState(String),
+    LoadState(String),
+    Screenshot(String),
+    LoadCheats(String),
+    Button(Button, bool),
+    Status,
+    Writers(u16),
+    UnwatchWriters(u16),
+    Quit,
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        Self::from_object(&parse_object(line)?)
+    }
+
+    /// Parses the shorthand typed at a console, a command name followed by its
+    /// argument if any, e.g. `writers $C123` or `press start`.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn parse_words(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("empty command")?;
+        let key = match name {
+            "load_rom" | "save_state" | "load_state" | "screenshot" | "load_cheats" => "path",
+            "press" | "release" => "button",
+            "writers" | "unwatch_writers" => "address",
+            _ => "",
+        };
+        let mut object = BTreeMap::from([("command".to_string(), Value::String(name.to_string()))]);
+        let rest = words.collect::<Vec<_>>().join(" ");
+        if !key.is_empty() && !rest.is_empty() {
+            object.insert(key.to_string(), Value::String(rest));
+        }
+        Self::from_object(&object)
+    }
+
+    fn from_object(object: &BTreeMap<String, Value>) -> Result<Self, String> {
+        let string = |key: &str| match object.get(key) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            _ => Err(format!("missing string argument \"{key}\"")),
+        };
+        let address = || match object.get("address") {
+            Some(Value::String(value)) => {
+                parse_address(value).ok_or_else(|| format!("invalid address \"{value}\""))
+            }
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Some(Value::Number(value))
+                if value.fract() == 0.0 && (0.0..=65535.0).contains(value) =>
+            {
+                Ok(*value as u16)
+            }
+            _ => Err("missing address argument \"address\"".to_string()),
+        };
+        let button = || {
+            let name = string("button")?;
+            parse_button(&name).ok_or_else(|| format!("unknown button \"{name}\""))
+        };
+        match string("command")?.as_str() {
+            "load_rom" => Ok(Self::LoadRom(string("path")?)),
+            "pause" => Ok(Self::Pause),
+            "resume" => Ok(Self::Resume),
+            "save_state" => Ok(Self::SaveState(string("path")?)),
+            "load_state" => Ok(Self::LoadState(string("path")?)),
+            "screenshot" => Ok(Self::Screenshot(string("path")?)),
+            "load_cheats" => Ok(Self::LoadCheats(string("path")?)),
+            "press" => Ok(Self::Button(button()?, true)),
+            "release" => Ok(Self::Button(button()?, false)),
+            "status" => Ok(Self::Status),
+            "writers" => Ok(Self::Writers(address()?)),
+            "unwatch_writers" => Ok(Self::UnwatchWriters(address()?)),
+            "quit" => Ok(Self::Quit),
+            other => Err(format!("unknown command \"{other}\"")),
+        }
+    }
+}
+
+/// Parses an address in hex, written as `$C123`, `0xC123` or `C123`.
+fn parse_address(address: &str) -> Option<u16> {
+    let digits = address
+        .strip_prefix('$')
+        .or_else(|| address.strip_prefix("0x"))
+        .unwrap_or(address);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn parse_button(name: &str) -> Option<Button> {
+    match name.to_ascii_lowercase().as_str() {
+        "a" => Some(Button::A),
+        "b" => Some(Button::B),
+        "select" => Some(Button::Select),
+        "start" => Some(Button::Start),
+        "right" => Some(Button::Right),
+        "left" => Some(Button::Left),
+        "up" => Some(Button::Up),
+        "down" => Some(Button::Down),
+        _ => None,
+    }
+}
+
+/// State of the emulator the commands act on.
+pub struct Session {
+    pub gameboy: GameboyHardware,
+    pub paused: bool,
+    pub frame: u64,
+}
+
+impl Session {
+    /// Runs a command, returning the response line and whether to quit.
+    pub fn execute(&mut self, command: Command) -> (String, bool) {
+        let result = match command {
+            // Loaded like the ROM given at startup, keeping the journal and sink
+            Command::LoadRom(path) => load_cartridge(&path, None)
+                .map_err(|err| err.to_string())
+                .map(|cartridge| {
+                    let journal = self.gameboy.set_journal(None);
+                    let sink = self.gameboy.set_notification_sink(None);
+                    self.gameboy = new_gameboy(cartridge);
+                    self.gameboy.set_journal(journal);
+                    self.gameboy.set_notification_sink(sink);
+                    self.frame = 0;
+                    String::new()
+                }),
+            Command::Pause => {
+                self.paused = true;
+                Ok(String::new())
+            }
+            Command::Resume => {
+                self.paused = false;
+                Ok(String::new())
+            }
+            // The journal goes next to the state, for attaching both to bug reports
+            Command::SaveState(path) => write_payload(
+                &path,
+                Payload::Savestate,
+                self.gameboy.save_state(),
+                &PlainCodec,
+            )
+            .and_then(|()| {
+                self.gameboy
+                    .save_journal(Path::new(&path).with_extension("journal"))
+            })
+            .map(|()| String::new())
+            .map_err(|err| err.to_string()),
+            Command::LoadState(path) => read_payload(&path, Payload::Savestate, &PlainCodec)
+                .map_err(|err| err.to_string())
+                .and_then(|state| {
+                    self.gameboy
+                        .load_state(&state)
+                        .map_err(|err| err.to_string())
+                })
+                .map(|()| String::new()),
+            Command::Screenshot(path) => write_screenshot(&path, self.gameboy.frame())
+                .map(|()| String::new())
+                .map_err(|err| err.to_string()),
+            Command::LoadCheats(path) => fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| parse_cht(&text).map_err(|err| err.to_string()))
+                .map(|file| {
+                    for warning in &file.warnings {
+                        println!("Warning: {warning}");
+                    }
+                    let count = file.cheats.len();
+                    for cheat in file.cheats {
+                        self.gameboy.add_cheat(cheat);
+                    }
+                    format!(",\"cheats\":{count}")
+                }),
+            Command::Button(button, pressed) => {
+                self.gameboy.set_button(button, pressed);
+                Ok(String::new())
+            }
+            Command::Status => Ok(format!(
+                ",\"paused\":{},\"frame\":{},\"frame_hash\":\"{:016X}\"",
+                self.paused,
+                self.frame,
+                self.gameboy.frame_hash()
+            )),
+            Command::Writers(addr) => {
+                self.gameboy.watch_writers(addr);
+                let writers = self.gameboy.writers(addr).unwrap_or_default();
+                let entries: Vec<String> = writers
+                    .iter()
+                    .map(|writer| {
+                        format!(
+                            "{{\"pc\":\"{}\",\"count\":{},\"last_value\":{}}}",
+                            writer.location, writer.count, writer.last_value
+                        )
+                    })
+                    .collect();
+                Ok(format!(",\"writers\":[{}]", entries.join(",")))
+            }
+            Command::UnwatchWriters(addr) => {
+                self.gameboy.unwatch_writers(addr);
+                Ok(String::new())
+            }
+            Command::Quit => return ("{\"ok\":true}".to_string(), true),
+        };
+        let response = match result {
+            Ok(fields) => format!("{{\"ok\":true{fields}}}"),
+            Err(err) => format!("{{\"ok\":false,\"error\":{}}}", quote(&err)),
+        };
+        (response, false)
+    }
+}
+
+/// Writes shades as a binary PGM image, shade 0 being white.
+fn write_screenshot(path: impl AsRef<Path>, frame: &[u8]) -> io::Result<()> {
+    let mut image = format!("P5\n{SCREEN_WIDTH} {SCREEN_HEIGHT}\n255\n").into_bytes();
+    image.extend(frame.iter().map(|shade| 255 - shade * 85));
+    fs::write(path, image)
+}
+
+/// Listens on a Unix socket, forwarding commands to the returned receiver.
+///
+/// # Errors
+///
+/// Returns an error if the socket can't be bound.
+#[cfg(unix)]
+pub fn listen(path: &str) -> io::Result<Receiver<Request>> {
+    use std::io::{BufRead, BufReader, Write as _};
+    use std::os::unix::net::UnixListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // A socket left behind by a previous run would make binding fail
+    if Path::new(path).exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let Ok(mut writer) = stream.try_clone() else {
+                    return;
+                };
+                for line in BufReader::new(stream).lines() {
+                    let Ok(line) = line else { break };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let response = match Command::parse(&line) {
+                        Ok(command) => {
+                            let (reply, response) = mpsc::channel();
+                            if sender.send((command, reply)).is_err() {
+                                break;
+                            }
+                            response.recv().unwrap_or_default()
+                        }
+                        Err(err) => format!("{{\"ok\":false,\"error\":{}}}", quote(&err)),
+                    };
+                    if writeln!(writer, "{response}").is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(receiver)
+}
+
+#[cfg(not(unix))]
+pub fn listen(_path: &str) -> io::Result<Receiver<Request>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "control sockets are only supported on Unix",
+    ))
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+/// Parses a flat JSON object, which is all the commands need.
+fn parse_object(input: &str) -> Result<BTreeMap<String, Value>, String> {
+    let mut chars = input.trim().chars().peekable();
+    let mut object = BTreeMap::new();
+    let invalid = || "invalid JSON object".to_string();
+
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+
+    if chars.next() != Some('{') {
+        return Err(invalid());
+    }
+    skip_whitespace(&mut chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Ok(object);
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        let key = parse_string(&mut chars).ok_or_else(invalid)?;
+        skip_whitespace(&mut chars);
+        if chars.next() != Some(':') {
+            return Err(invalid());
+        }
+        skip_whitespace(&mut chars);
+        let value = match chars.peek() {
+            Some('"') => Value::String(parse_string(&mut chars).ok_or_else(invalid)?),
+            Some(_) => {
+                let mut literal = String::new();
+                while let Some(c) = chars.next_if(|c| !matches!(c, ',' | '}') && !c.is_whitespace())
+                {
+                    literal.push(c);
+                }
+                match literal.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    "null" => Value::Null,
+                    number => Value::Number(number.parse().map_err(|_| invalid())?),
+                }
+            }
+            None => return Err(invalid()),
+        };
+        object.insert(key, value);
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(',') => {}
+            Some('}') if chars.next().is_none() => return Ok(object),
+            _ => return Err(invalid()),
+        }
+    }
+}
+
+fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                'n' => string.push('\n'),
+                't' => string.push('\t'),
+                'r' => string.push('\r'),
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    string.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+                c => string.push(c),
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+pub fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::control::{parse_address, parse_object, quote, Command, Value};
+
+    #[test]
+    fn test_parse_object() {
+        let object =
+            parse_object(r#"{"command": "press", "button":"a", "n": 2, "x": true}"#).unwrap();
+        assert_eq!(object["command"], Value::String("press".to_string()));
+        assert_eq!(object["n"], Value::Number(2.0));
+        assert_eq!(object["x"], Value::Bool(true));
+        assert!(parse_object(r#"{"command": "pause""#).is_err());
+        assert!(parse_object("[]").is_err());
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert!(matches!(
+            Command::parse(r#"{"command":"save_state","path":"a \"b\".state"}"#),
+            Ok(Command::SaveState(path)) if path == "a \"b\".state"
+        ));
+        assert!(Command::parse(r#"{"command":"press","button":"turbo"}"#).is_err());
+        assert!(Command::parse(r#"{"command":"load_rom"}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_words() {
+        assert!(matches!(
+            Command::parse_words("writers $C123"),
+            Ok(Command::Writers(0xC123))
+        ));
+        assert!(matches!(
+            Command::parse_words("  save_state my game.state"),
+            Ok(Command::SaveState(path)) if path == "my game.state"
+        ));
+        assert!(matches!(
+            Command::parse_words("load_cheats Pokemon Red.cht"),
+            Ok(Command::LoadCheats(path)) if path == "Pokemon Red.cht"
+        ));
+        assert!(matches!(
+            Command::parse_words("status"),
+            Ok(Command::Status)
+        ));
+        assert!(Command::parse_words("press").is_err());
+        assert!(Command::parse_words("").is_err());
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(parse_address("$C123"), Some(0xC123));
+        assert_eq!(parse_address("0xff80"), Some(0xFF80));
+        assert_eq!(parse_address("A000"), Some(0xA000));
+        assert_eq!(parse_address("$10000"), None);
+        assert!(matches!(
+            Command::parse(r#"{"command":"writers","address":49443}"#),
+            Ok(Command::Writers(0xC123))
+        ));
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("a\"b\n"), r#""a\"b\n""#);
+    }
+}
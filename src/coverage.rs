@@ -0,0 +1,151 @@
+use std::fmt::{Display, Formatter};
+
+const UNDEFINED_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+const PREFIX_OPCODE: u8 = 0xCB;
+
+/// An instruction in the opcode space, either unprefixed or `0xCB` prefixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Unprefixed(u8),
+    Prefixed(u8),
+}
+
+impl Opcode {
+    /// Returns all 500 implemented opcodes, excluding undefined opcodes and the prefix itself.
+    pub fn all() -> impl Iterator<Item = Self> {
+        let unprefixed = (0..=u8::MAX)
+            .filter(|opcode| *opcode != PREFIX_OPCODE && !UNDEFINED_OPCODES.contains(opcode))
+            .map(Self::Unprefixed);
+        let prefixed = (0..=u8::MAX).map(Self::Prefixed);
+        unprefixed.chain(prefixed)
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unprefixed(opcode) => write!(f, "{opcode:#04X}"),
+            Self::Prefixed(opcode) => write!(f, "0xCB {opcode:#04X}"),
+        }
+    }
+}
+
+/// Records how many times each instruction was executed.
+///
+/// Used by test suites to find instructions that are never exercised.
+#[derive(Debug, Clone)]
+pub struct InstructionCoverage {
+    unprefixed: [u64; 256],
+    prefixed: [u64; 256],
+}
+
+impl InstructionCoverage {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            unprefixed: [0; 256],
+            prefixed: [0; 256],
+        }
+    }
+
+    pub(crate) fn record(&mut self, opcode: Opcode) {
+        match opcode {
+            Opcode::Unprefixed(opcode) => self.unprefixed[opcode as usize] += 1,
+            Opcode::Prefixed(opcode) => self.prefixed[opcode as usize] += 1,
+        }
+    }
+
+    /// Returns the number of times an instruction was executed.
+    #[must_use]
+    pub const fn count(&self, opcode: Opcode) -> u64 {
+        match opcode {
+            Opcode::Unprefixed(opcode) => self.unprefixed[opcode as usize],
+            Opcode::Prefixed(opcode) => self.prefixed[opcode as usize],
+        }
+    }
+
+    /// Returns the instructions that were executed at least once.
+    pub fn executed(&self) -> impl Iterator<Item = Opcode> + '_ {
+        Opcode::all().filter(|opcode| self.count(*opcode) > 0)
+    }
+
+    /// Returns the instructions that were never executed.
+    pub fn untested(&self) -> impl Iterator<Item = Opcode> + '_ {
+        Opcode::all().filter(|opcode| self.count(*opcode) == 0)
+    }
+
+    /// Returns the number of executed instructions and the total number of instructions.
+    #[must_use]
+    pub fn summary(&self) -> (usize, usize) {
+        (self.executed().count(), Opcode::all().count())
+    }
+
+    /// Combines the counts of another coverage run into this one.
+    pub fn merge(&mut self, other: &Self) {
+        for (count, other) in self.unprefixed.iter_mut().zip(other.unprefixed) {
+            *count += other;
+        }
+        for (count, other) in self.prefixed.iter_mut().zip(other.prefixed) {
+            *count += other;
+        }
+    }
+}
+
+impl Default for InstructionCoverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for InstructionCoverage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (executed, total) = self.summary();
+        writeln!(f, "Instruction coverage: {executed}/{total}")?;
+        let untested: Vec<Opcode> = self.untested().collect();
+        if !untested.is_empty() {
+            writeln!(f, "Untested instructions:")?;
+            for opcode in untested {
+                writeln!(f, "  {opcode}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::Cartridge;
+    use crate::coverage::{InstructionCoverage, Opcode};
+    use crate::hardware::GameboyHardware;
+
+    #[test]
+    fn test_opcode_space() {
+        assert_eq!(Opcode::all().count(), 500);
+        assert!(!Opcode::all().any(|opcode| opcode == Opcode::Unprefixed(0xCB)));
+    }
+
+    #[test]
+    fn test_coverage_records_instructions() {
+        let mut rom = vec![0; 0x8000];
+        // LD A, 0x42; SWAP A; NOP
+        rom[0x100..0x105].copy_from_slice(&[0x3E, 0x42, 0xCB, 0x37, 0x00]);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.enable_instruction_coverage();
+        for _ in 0..3 {
+            gameboy.step();
+        }
+
+        let coverage = gameboy.instruction_coverage().unwrap();
+        assert_eq!(coverage.count(Opcode::Unprefixed(0x3E)), 1);
+        assert_eq!(coverage.count(Opcode::Prefixed(0x37)), 1);
+        assert_eq!(coverage.count(Opcode::Unprefixed(0x00)), 1);
+        assert_eq!(coverage.summary(), (3, 500));
+
+        let mut total = InstructionCoverage::new();
+        total.merge(coverage);
+        total.merge(coverage);
+        assert_eq!(total.count(Opcode::Unprefixed(0x3E)), 2);
+    }
+}
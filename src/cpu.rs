@@ -2,8 +2,12 @@
 mod execute;
 mod instructions;
 
-use crate::hardware::AddressBus;
+use crate::coverage::{InstructionCoverage, Opcode};
+use crate::error::SavestateError;
 use crate::interrupts::InterruptFlags;
+use crate::savestate::{StateReader, StateWriter};
+use crate::trace::{TraceBuffer, TracedInstruction};
+use crate::watch::CodeAddress;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Registers {
@@ -106,6 +110,84 @@ impl Registers {
     }
 }
 
+/// Snapshot of the CPU registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl From<Registers> for CpuRegisters {
+    fn from(registers: Registers) -> Self {
+        Self {
+            a: registers.a,
+            f: registers.f.bits(),
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+            sp: registers.sp,
+            pc: registers.pc,
+        }
+    }
+}
+
+/// The memory and interrupt lines the CPU runs against, so the SM83 core can be used
+/// without the rest of the Game Boy, e.g. to test single instructions.
+///
+/// The CPU doesn't advance the bus itself: [`Cpu::step`] makes the reads and writes of a
+/// whole instruction in order and returns the T-cycles it took, and the caller then runs the
+/// rest of the system for those cycles before the next step. Interrupts requested in the
+/// meantime are seen by the next step.
+pub trait BusInterface {
+    /// Reads a byte, with any side effects a CPU read has.
+    fn read_byte(&mut self, addr: u16) -> u8;
+
+    fn write_byte(&mut self, addr: u16, value: u8);
+
+    /// Reads a byte without side effects, for the instructions recorded by a trace buffer.
+    fn peek_byte(&self, addr: u16) -> u8;
+
+    /// Returns the interrupts both requested and enabled (IF & IE), in IF's layout with the
+    /// highest priority in bit 0.
+    fn pending_interrupts(&self) -> u8;
+
+    /// Clears the request of the interrupt with bit `mask` when the CPU services it.
+    fn acknowledge_interrupt(&mut self, mask: u8);
+
+    /// Called with `true` before the CPU pushes PC to service an interrupt and with `false`
+    /// after, so those writes can be told apart from the ones made by instructions.
+    fn set_interrupt_dispatch(&mut self, _dispatching: bool) {}
+
+    /// Returns true if a joypad input line is low, which keeps STOP from stopping the clock.
+    fn is_any_button_pressed(&self) -> bool {
+        false
+    }
+
+    /// Resets the divider, done by STOP when it stops the clock.
+    fn reset_div(&mut self) {}
+
+    /// Called when the 16-bit incrementer puts `addr` on the bus outside of a write: on its
+    /// own for INC/DEC r16 and the first decrement of a push, and with `reading` set before
+    /// the reads it increments for (POP, LD A, [HL+]). Only matters for the DMG OAM bug.
+    fn increment_address(&mut self, _addr: u16, _reading: bool) {}
+
+    /// Returns the ROM bank mapped at `addr`, for the locations of traced instructions.
+    fn bank_at(&self, _addr: u16) -> usize {
+        0
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct FlagsRegister(u8);
 
@@ -153,15 +235,15 @@ impl FlagsRegister {
 }
 
 pub trait AccessReadByte<S> {
-    fn read_byte(&mut self, bus: &AddressBus, src: S) -> u8;
+    fn read_byte(&mut self, bus: &mut impl BusInterface, src: S) -> u8;
 }
 
 pub trait AccessWriteByte<D> {
-    fn write_byte(&mut self, bus: &mut AddressBus, dst: D, value: u8);
+    fn write_byte(&mut self, bus: &mut impl BusInterface, dst: D, value: u8);
 }
 
 pub trait AccessReadWord<S> {
-    fn read_word(&mut self, bus: &AddressBus, src: S) -> u16;
+    fn read_word(&mut self, bus: &mut impl BusInterface, src: S) -> u16;
 }
 
 pub trait AccessWriteWord<D> {
@@ -181,13 +263,13 @@ pub enum Register8 {
 }
 
 impl AccessReadByte<Register8> for Cpu {
-    fn read_byte(&mut self, _: &AddressBus, src: Register8) -> u8 {
+    fn read_byte(&mut self, _: &mut impl BusInterface, src: Register8) -> u8 {
         self.registers.read_byte(src)
     }
 }
 
 impl AccessWriteByte<Register8> for Cpu {
-    fn write_byte(&mut self, _: &mut AddressBus, dst: Register8, value: u8) {
+    fn write_byte(&mut self, _: &mut impl BusInterface, dst: Register8, value: u8) {
         self.registers.write_byte(dst, value);
     }
 }
@@ -204,7 +286,7 @@ pub enum Register16 {
 }
 
 impl AccessReadWord<Register16> for Cpu {
-    fn read_word(&mut self, _: &AddressBus, src: Register16) -> u16 {
+    fn read_word(&mut self, _: &mut impl BusInterface, src: Register16) -> u16 {
         self.registers.read_word(src)
     }
 }
@@ -221,13 +303,13 @@ impl AccessWriteWord<Register16> for Cpu {
 pub struct Immediate;
 
 impl AccessReadByte<Immediate> for Cpu {
-    fn read_byte(&mut self, bus: &AddressBus, _: Immediate) -> u8 {
+    fn read_byte(&mut self, bus: &mut impl BusInterface, _: Immediate) -> u8 {
         self.read_next_byte(bus)
     }
 }
 
 impl AccessReadWord<Immediate> for Cpu {
-    fn read_word(&mut self, bus: &AddressBus, _: Immediate) -> u16 {
+    fn read_word(&mut self, bus: &mut impl BusInterface, _: Immediate) -> u16 {
         self.read_next_word(bus)
     }
 }
@@ -241,7 +323,7 @@ impl<T> AccessReadByte<Direct<T>> for Cpu
 where
     Self: AccessReadWord<T>,
 {
-    fn read_byte(&mut self, bus: &AddressBus, src: Direct<T>) -> u8 {
+    fn read_byte(&mut self, bus: &mut impl BusInterface, src: Direct<T>) -> u8 {
         let addr = self.read_word(bus, src.0);
         bus.read_byte(addr)
     }
@@ -251,7 +333,7 @@ impl<T> AccessWriteByte<Direct<T>> for Cpu
 where
     Self: AccessReadWord<T>,
 {
-    fn write_byte(&mut self, bus: &mut AddressBus, dst: Direct<T>, value: u8) {
+    fn write_byte(&mut self, bus: &mut impl BusInterface, dst: Direct<T>, value: u8) {
         let addr = self.read_word(bus, dst.0);
         bus.write_byte(addr, value);
     }
@@ -267,7 +349,7 @@ where
     Self: AccessReadWord<T> + AccessWriteWord<T>,
     T: Copy,
 {
-    fn read_word(&mut self, bus: &AddressBus, src: Increment<T>) -> u16 {
+    fn read_word(&mut self, bus: &mut impl BusInterface, src: Increment<T>) -> u16 {
         let word = self.read_word(bus, src.0);
         let new_word = word.wrapping_add(1);
         self.write_word(src.0, new_word);
@@ -285,7 +367,7 @@ where
     Self: AccessReadWord<T> + AccessWriteWord<T>,
     T: Copy,
 {
-    fn read_word(&mut self, bus: &AddressBus, src: Decrement<T>) -> u16 {
+    fn read_word(&mut self, bus: &mut impl BusInterface, src: Decrement<T>) -> u16 {
         let word = self.read_word(bus, src.0);
         let new_word = word.wrapping_sub(1);
         self.write_word(src.0, new_word);
@@ -302,7 +384,7 @@ impl<T> AccessReadWord<HighIndexed<T>> for Cpu
 where
     Self: AccessReadByte<T>,
 {
-    fn read_word(&mut self, bus: &AddressBus, src: HighIndexed<T>) -> u16 {
+    fn read_word(&mut self, bus: &mut impl BusInterface, src: HighIndexed<T>) -> u16 {
         let byte = self.read_byte(bus, src.0) as u16;
         0xFF00 | byte
     }
@@ -321,10 +403,18 @@ pub enum JumpCondition {
 pub struct Cpu {
     registers: Registers,
     halted: bool,
+    // Set by undefined opcodes, which hang the CPU until reset
+    locked: bool,
+    // Set by STOP, the clock stays stopped until a button is pressed
+    stopped: bool,
     // IME: Interrupt Master Enable
     ime: bool,
     // Used to delay setting IME after calling EI
     ime_delay_counter: Option<u8>,
+    // Only recorded when enabled for test runs
+    coverage: Option<Box<InstructionCoverage>>,
+    // Only recorded when enabled, kept for post-mortem debugging
+    trace: Option<Box<TraceBuffer>>,
 }
 
 impl Cpu {
@@ -333,12 +423,33 @@ impl Cpu {
         Self {
             registers: Registers::new(),
             halted: false,
+            locked: false,
+            stopped: false,
             ime: false,
             ime_delay_counter: None,
+            coverage: None,
+            trace: None,
         }
     }
 
-    pub fn step(&mut self, bus: &mut AddressBus) -> usize {
+    /// Returns to the state after the boot ROM, keeping coverage counts and the trace.
+    pub fn reset(&mut self) {
+        *self = Self {
+            coverage: self.coverage.take(),
+            trace: self.trace.take(),
+            ..Self::new()
+        };
+    }
+
+    /// Runs one instruction or services an interrupt, returning the T-cycles taken. While
+    /// halted or stopped, a step takes one M-cycle without doing anything.
+    ///
+    /// The bus isn't advanced, see [`BusInterface`].
+    pub fn step(&mut self, bus: &mut impl BusInterface) -> usize {
+        if self.locked || self.stopped {
+            return 4;
+        }
+
         // Checks for next instruction after EI is called
         self.ime_delay_counter = self.ime_delay_counter.map(|n| n - 1);
         if self.ime_delay_counter.is_some_and(|n| n == 0) {
@@ -347,19 +458,14 @@ impl Cpu {
         }
 
         // Checks for pending interrupts
-        let interrupt_pending = bus.get_interrupts_pending();
-
-        for flag in InterruptFlags::flags() {
-            if interrupt_pending.contains(flag.bits()) {
-                self.halted = false;
-                if self.ime {
-                    // Calls interrupt handler
-                    self.ime = false;
-                    bus.interrupt_flag().set(flag.bits(), false);
-                    self.push(bus, Register16::PC);
-                    self.registers.pc = flag.handler_addr();
-                }
-                break;
+        if bus.pending_interrupts() != 0 {
+            // Interrupts requested during the previous step's bus ticks are sampled here,
+            // waking from HALT takes one more M-cycle before the dispatch can start
+            let wake_cycles = if self.halted { 4 } else { 0 };
+            self.halted = false;
+            if self.ime {
+                self.ime = false;
+                return self.dispatch_interrupt(bus) + wake_cycles;
             }
         }
 
@@ -367,22 +473,199 @@ impl Cpu {
             return 4;
         }
 
+        if self.trace.is_some() {
+            self.record_trace(bus);
+        }
         let opcode = self.read_next_byte(bus);
         self.execute(bus, opcode)
     }
 
-    fn read_next_byte(&mut self, bus: &AddressBus) -> u8 {
+    /// Calls the handler of the highest priority pending interrupt, taking 5 M-cycles: 2 idle,
+    /// 2 pushing PC and 1 jumping.
+    ///
+    /// The interrupt is picked after the high byte of PC is pushed, so a push overwriting IE
+    /// (SP at 0x0000) can pick a lower priority interrupt, or cancel the dispatch when none
+    /// is left enabled. A cancelled dispatch jumps to 0x0000 and leaves IF untouched.
+    fn dispatch_interrupt(&mut self, bus: &mut impl BusInterface) -> usize {
+        bus.set_interrupt_dispatch(true);
+        let [low, high] = self.registers.pc.to_le_bytes();
+        bus.increment_address(self.registers.sp, false);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        bus.write_byte(self.registers.sp, high);
+
+        // Only the highest priority interrupt is serviced, others stay requested in IF
+        let pending = InterruptFlags::from_bits(bus.pending_interrupts());
+        let highest = InterruptFlags::flags()
+            .into_iter()
+            .find(|flag| pending.contains(flag.bits()));
+        if let Some(flag) = highest {
+            bus.acknowledge_interrupt(flag.bits());
+        }
+
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        bus.write_byte(self.registers.sp, low);
+        bus.set_interrupt_dispatch(false);
+        self.registers.pc = highest.map_or(0x0000, InterruptFlags::handler_addr);
+        20
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        let r = &self.registers;
+        writer.component("CPU");
+        for (name, value) in [
+            ("A", r.a),
+            ("F", r.f.bits()),
+            ("B", r.b),
+            ("C", r.c),
+            ("D", r.d),
+            ("E", r.e),
+            ("H", r.h),
+            ("L", r.l),
+        ] {
+            writer.field(name);
+            writer.write_u8(value);
+        }
+        writer.field("SP");
+        writer.write_u16(r.sp);
+        writer.field("PC");
+        writer.write_u16(r.pc);
+        writer.field("halted");
+        writer.write_bool(self.halted);
+        writer.field("locked");
+        writer.write_bool(self.locked);
+        writer.field("stopped");
+        writer.write_bool(self.stopped);
+        writer.field("IME");
+        writer.write_bool(self.ime);
+        writer.field("IME delay");
+        writer.write_option_u8(self.ime_delay_counter);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        let r = &mut self.registers;
+        r.a = reader.read_u8()?;
+        r.f = FlagsRegister::from_bits(reader.read_u8()?);
+        r.b = reader.read_u8()?;
+        r.c = reader.read_u8()?;
+        r.d = reader.read_u8()?;
+        r.e = reader.read_u8()?;
+        r.h = reader.read_u8()?;
+        r.l = reader.read_u8()?;
+        r.sp = reader.read_u16()?;
+        r.pc = reader.read_u16()?;
+        self.halted = reader.read_bool()?;
+        self.locked = reader.read_bool()?;
+        self.stopped = reader.read_bool()?;
+        self.ime = reader.read_bool()?;
+        self.ime_delay_counter = reader.read_option_u8()?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn registers(&self) -> CpuRegisters {
+        self.registers.into()
+    }
+
+    /// Sets every register, the low nibble of F always reads 0.
+    pub fn set_registers(&mut self, registers: CpuRegisters) {
+        self.registers = Registers {
+            a: registers.a,
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            f: FlagsRegister::from_bits(registers.f),
+            h: registers.h,
+            l: registers.l,
+            sp: registers.sp,
+            pc: registers.pc,
+        };
+    }
+
+    #[must_use]
+    pub const fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    #[must_use]
+    pub const fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    #[must_use]
+    pub const fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Restarts the clock after STOP, to be called when a joypad input line goes low.
+    pub fn wake_from_stop(&mut self) {
+        self.stopped = false;
+    }
+
+    /// Returns IME, false until the instruction after EI has run.
+    #[must_use]
+    pub const fn ime(&self) -> bool {
+        self.ime
+    }
+
+    /// Sets IME, cancelling a pending EI.
+    pub fn set_ime(&mut self, enable: bool) {
+        self.ime = enable;
+        self.ime_delay_counter = None;
+    }
+
+    pub(crate) fn enable_coverage(&mut self) {
+        self.coverage.get_or_insert_with(Box::default);
+    }
+
+    pub(crate) fn coverage(&self) -> Option<&InstructionCoverage> {
+        self.coverage.as_deref()
+    }
+
+    fn record_coverage(&mut self, opcode: Opcode) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(opcode);
+        }
+    }
+
+    pub(crate) fn set_trace_capacity(&mut self, capacity: Option<usize>) {
+        self.trace = capacity.map(|capacity| Box::new(TraceBuffer::new(capacity)));
+    }
+
+    pub(crate) fn trace_tail(&self, n: usize) -> Vec<TracedInstruction> {
+        self.trace
+            .as_ref()
+            .map(|trace| trace.tail(n))
+            .unwrap_or_default()
+    }
+
+    fn record_trace(&mut self, bus: &impl BusInterface) {
+        let pc = self.registers.pc;
+        // Wraps around to 0x0000 for instructions at the end of the address space
+        let bytes = [0, 1, 2].map(|offset| bus.peek_byte(pc.wrapping_add(offset)));
+        if let Some(trace) = &mut self.trace {
+            trace.record(TracedInstruction {
+                location: CodeAddress {
+                    bank: bus.bank_at(pc),
+                    pc,
+                },
+                bytes,
+            });
+        }
+    }
+
+    fn read_next_byte(&mut self, bus: &mut impl BusInterface) -> u8 {
         let byte = bus.read_byte(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
         byte
     }
 
     #[allow(clippy::cast_possible_wrap)]
-    fn read_next_byte_signed(&mut self, bus: &AddressBus) -> i8 {
+    fn read_next_byte_signed(&mut self, bus: &mut impl BusInterface) -> i8 {
         self.read_next_byte(bus) as i8
     }
 
-    fn read_next_word(&mut self, bus: &AddressBus) -> u16 {
+    fn read_next_word(&mut self, bus: &mut impl BusInterface) -> u16 {
         // Game Boy is little endian, so read the second byte as the most significant byte
         // and the first as the least significant
         let low = bus.read_byte(self.registers.pc);
@@ -398,3 +681,344 @@ impl Default for Cpu {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::coverage::Opcode;
+    use crate::cpu::{BusInterface, Cpu, CpuRegisters, FlagsRegister, Register16};
+    use crate::hardware::{AddressBus, Button, GameboyHardware, Model};
+    use crate::opcodes;
+
+    #[test]
+    fn test_services_highest_priority_interrupt_only() {
+        let program = [
+            0x3E, 0x1F, // LD A, 0x1F
+            0xE0, 0xFF, // LDH (IE), A
+            0x3E, 0x05, // LD A, VBLANK | TIMER
+            0xE0, 0x0F, // LDH (IF), A
+            0xFB, // EI
+            0x00, // NOP
+            0x18, 0xFE, // JR -2
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+
+        let mut steps = 0;
+        while gameboy.registers().pc != 0x40 {
+            assert!(steps < 20, "VBlank interrupt was never serviced");
+            gameboy.step();
+            steps += 1;
+        }
+        // The timer interrupt is still requested
+        assert_eq!(gameboy.peek_byte(0xFF0F) & 0x1F, 0x04);
+    }
+
+    /// Steps until PC reaches `pc`, returning the cycles of each step.
+    fn step_until(gameboy: &mut GameboyHardware, pc: u16) -> Vec<usize> {
+        let mut cycles = Vec::new();
+        while gameboy.registers().pc != pc {
+            assert!(cycles.len() < 100_000, "never reached {pc:#06X}");
+            cycles.push(gameboy.step());
+        }
+        cycles
+    }
+
+    #[test]
+    fn test_halt_with_interrupt_pending() {
+        let program = [
+            0x3E, 0x01, // LD A, VBLANK
+            0xE0, 0xFF, // LDH (IE), A
+            0xE0, 0x0F, // LDH (IF), A
+            0xFB, // EI
+            0x76, // HALT
+            0x18, 0xFE, // JR -2
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        step_until(&mut gameboy, 0x157);
+
+        // HALT doesn't halt, the interrupt is dispatched right after without waking up
+        assert_eq!(step_until(&mut gameboy, 0x40), [4, 20]);
+        let sp = gameboy.registers().sp;
+        assert_eq!(gameboy.peek_word(sp), 0x158);
+    }
+
+    #[test]
+    fn test_halt_woken_by_interrupt() {
+        let program = [
+            0x3E, 0x01, // LD A, VBLANK
+            0xE0, 0xFF, // LDH (IE), A
+            0xAF, // XOR A
+            0xE0, 0x0F, // LDH (IF), A
+            0xFB, // EI
+            0x76, // HALT
+            0x18, 0xFE, // JR -2
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        step_until(&mut gameboy, 0x158);
+
+        // One M-cycle per halted step, then one to wake up before the 5 M-cycle dispatch
+        let cycles = step_until(&mut gameboy, 0x40);
+        let (dispatch, halted) = cycles.split_last().unwrap();
+        assert!(halted.iter().all(|cycles| *cycles == 4));
+        assert_eq!(*dispatch, 24);
+        let sp = gameboy.registers().sp;
+        assert_eq!(gameboy.peek_word(sp), 0x159);
+    }
+
+    #[test]
+    fn test_stop_until_button_pressed() {
+        let program = [
+            0x3E, 0x20, // LD A, 0x20
+            0xE0, 0x00, // LDH (P1), A
+            0x10, 0x00, // STOP
+            0x04, // INC B
+            0x18, 0xFE, // JR -2
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        step_until(&mut gameboy, 0x156);
+
+        // The clock is stopped, DIV stays reset and frames still complete
+        assert!(gameboy.run_frame());
+        assert_eq!(gameboy.peek_byte(0xFF04), 0);
+        assert_eq!(gameboy.registers().pc, 0x156);
+        // Only buttons in the selected group restart it
+        gameboy.set_button(Button::A, true);
+        gameboy.run_frame();
+        assert_eq!(gameboy.registers().pc, 0x156);
+        gameboy.set_button(Button::Down, true);
+        step_until(&mut gameboy, 0x157);
+        assert_eq!(gameboy.registers().b, 0x01);
+        assert_eq!(gameboy.peek_byte(0xFF0F) & 0x10, 0x10);
+    }
+
+    #[test]
+    fn test_stop_with_button_held() {
+        let program = [
+            0x3E, 0x20, // LD A, 0x20
+            0xE0, 0x00, // LDH (P1), A
+            0xE0, 0x04, // LDH (DIV), A
+            0x10, 0x00, // STOP
+            0x18, 0xFE, // JR -2
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_button(Button::Down, true);
+        step_until(&mut gameboy, 0x156);
+        gameboy.step();
+
+        // STOP halts instead, without resetting DIV
+        assert_eq!(gameboy.registers().pc, 0x158);
+        gameboy.run_frame();
+        gameboy.run_frame();
+        assert_eq!(gameboy.registers().pc, 0x158);
+        assert_ne!(gameboy.peek_byte(0xFF04), 0);
+
+        let program = [
+            0x3E, 0x10, // LD A, JOYPAD
+            0xE0, 0xFF, // LDH (IE), A
+            0x3E, 0x20, // LD A, 0x20
+            0xE0, 0x00, // LDH (P1), A
+            0x10, // STOP
+            0x04, // INC B
+            0x18, 0xFE, // JR -2
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_button(Button::Down, true);
+        step_until(&mut gameboy, 0x158);
+
+        // Selecting the D-pad requested the joypad interrupt, STOP does nothing and the
+        // next byte is an instruction
+        assert_eq!(gameboy.step(), 4);
+        assert_eq!(gameboy.registers().pc, 0x159);
+        gameboy.step();
+        assert_eq!(gameboy.registers().b, 0x01);
+    }
+
+    /// 64 KiB of RAM with IE and IF, and no other hardware.
+    struct FlatBus {
+        memory: Vec<u8>,
+        dispatch_writes: usize,
+        dispatching: bool,
+    }
+
+    impl BusInterface for FlatBus {
+        fn read_byte(&mut self, addr: u16) -> u8 {
+            self.memory[usize::from(addr)]
+        }
+
+        fn write_byte(&mut self, addr: u16, value: u8) {
+            self.dispatch_writes += usize::from(self.dispatching);
+            self.memory[usize::from(addr)] = value;
+        }
+
+        fn peek_byte(&self, addr: u16) -> u8 {
+            self.memory[usize::from(addr)]
+        }
+
+        fn pending_interrupts(&self) -> u8 {
+            self.memory[0xFFFF] & self.memory[0xFF0F] & 0x1F
+        }
+
+        fn acknowledge_interrupt(&mut self, mask: u8) {
+            self.memory[0xFF0F] &= !mask;
+        }
+
+        fn set_interrupt_dispatch(&mut self, dispatching: bool) {
+            self.dispatching = dispatching;
+        }
+    }
+
+    #[test]
+    fn test_custom_bus() {
+        let mut bus = FlatBus {
+            memory: vec![0; 0x10000],
+            dispatch_writes: 0,
+            dispatching: false,
+        };
+        // EI; ADD A, B; LD (0x8000), A; HALT
+        bus.memory[..7].copy_from_slice(&[0xFB, 0x80, 0xEA, 0x00, 0x80, 0x76, 0x00]);
+        let mut cpu = Cpu::new();
+        cpu.set_registers(CpuRegisters {
+            a: 0x3A,
+            b: 0xC6,
+            f: 0xFF,
+            sp: 0xD000,
+            pc: 0x0000,
+            ..cpu.registers()
+        });
+
+        let cycles: Vec<_> = (0..4).map(|_| cpu.step(&mut bus)).collect();
+        assert_eq!(cycles, [4, 4, 16, 4]);
+        assert_eq!(bus.memory[0x8000], 0x00);
+        // Zero, half carry and carry, the low nibble of F is never set
+        assert_eq!(cpu.registers().f, 0xB0);
+        assert_eq!(cpu.step(&mut bus), 4);
+
+        bus.memory[0xFFFF] = 0x04;
+        bus.memory[0xFF0F] = 0x04;
+        assert_eq!(cpu.step(&mut bus), 24);
+        assert_eq!(cpu.registers().pc, 0x50);
+        assert_eq!(bus.memory[0xFF0F], 0x00);
+        assert_eq!(bus.dispatch_writes, 2);
+        assert_eq!(bus.memory[0xCFFE..0xD000], [0x06, 0x00]);
+    }
+
+    #[test]
+    fn test_ie_push_cancels_dispatch() {
+        let mut bus = FlatBus {
+            memory: vec![0; 0x10000],
+            dispatch_writes: 0,
+            dispatching: false,
+        };
+        let mut cpu = Cpu::new();
+        // EI; NOP, with SP wrapping so the high byte of PC is pushed to IE
+        bus.memory[0x0412..0x0414].copy_from_slice(&[0xFB, 0x00]);
+        cpu.set_registers(CpuRegisters {
+            sp: 0x0000,
+            pc: 0x0412,
+            ..cpu.registers()
+        });
+        bus.memory[0xFFFF] = 0x05;
+        bus.memory[0xFF0F] = 0x05;
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+
+        // Pushing 0x04 leaves only the timer enabled, so it's serviced instead of VBlank
+        assert_eq!(cpu.step(&mut bus), 20);
+        assert_eq!(cpu.registers().pc, 0x50);
+        assert_eq!(bus.memory[0xFF0F], 0x01);
+        assert_eq!(bus.memory[0xFFFE], 0x14);
+
+        // Pushing 0x00 disables all interrupts, cancelling the dispatch
+        bus.memory[0x0012..0x0014].copy_from_slice(&[0xFB, 0x00]);
+        cpu.set_registers(CpuRegisters {
+            sp: 0x0000,
+            pc: 0x0012,
+            ..cpu.registers()
+        });
+        bus.memory[0xFFFF] = 0x05;
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+        assert_eq!(cpu.step(&mut bus), 20);
+        assert_eq!(cpu.registers().pc, 0x0000);
+        assert_eq!(bus.memory[0xFF0F], 0x01);
+        assert_eq!(bus.memory[0xFFFF], 0x00);
+    }
+
+    #[test]
+    fn test_trace_wraps_around_address_space() {
+        let program = [
+            0xAF, // XOR A
+            0xE0, 0xFD, // LDH (0xFFFD), A
+            0xE0, 0xFE, // LDH (0xFFFE), A
+            0xE0, 0xFF, // LDH (IE), A
+            0xC3, 0xFD, 0xFF, // JP 0xFFFD
+        ];
+        let mut rom = HeaderBuilder::new().build(&program);
+        rom[..2].copy_from_slice(&[0x3C, 0x04]);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_trace_buffer(Some(8));
+        step_until(&mut gameboy, 0xFFFD);
+        for _ in 0..3 {
+            gameboy.step();
+        }
+
+        // IE reads 0xE0 with its unused bits set, so 0xFFFF holds LDH (n), A with its
+        // operand at 0x0000
+        let trace = gameboy.trace_tail(3);
+        assert_eq!(trace[0].location.pc, 0xFFFD);
+        assert_eq!(trace[0].bytes, [0x00, 0x00, 0xE0]);
+        assert_eq!(trace[2].location.pc, 0xFFFF);
+        assert_eq!(trace[2].bytes, [0xE0, 0x3C, 0x04]);
+        assert_eq!(gameboy.registers().pc, 0x0001);
+    }
+
+    #[test]
+    fn test_opcode_table_matches_execution() {
+        const START: u16 = 0xC000;
+        for info in opcodes::table() {
+            let bytes = match info.opcode {
+                Opcode::Unprefixed(byte) => vec![byte],
+                Opcode::Prefixed(byte) => vec![0xCB, byte],
+            };
+            // Each condition holds with one of the flag values and not the other
+            let mut cycles: Vec<_> = [0x00, 0xF0]
+                .into_iter()
+                .map(|flags| {
+                    // MBC1, so writes to address 0 don't hit ROM without a controller
+                    let rom = HeaderBuilder::new().cartridge_type(0x01).build(&[]);
+                    let mut bus = AddressBus::new(Cartridge::new(rom), Model::Dmg);
+                    for (addr, byte) in (START..).zip(&bytes) {
+                        bus.write_byte(addr, *byte);
+                    }
+                    let mut cpu = Cpu::new();
+                    cpu.registers.pc = START;
+                    cpu.registers.sp = 0xDFF0;
+                    cpu.registers.f = FlagsRegister::from_bits(flags);
+                    cpu.registers.write_word(Register16::BC, 0xC100);
+                    cpu.registers.write_word(Register16::DE, 0xC100);
+                    cpu.registers.write_word(Register16::HL, 0xC100);
+                    let cycles = u8::try_from(cpu.step(&mut bus)).unwrap();
+
+                    let branched = info.branch_cycles == Some(cycles)
+                        || (info.branch_cycles.is_none()
+                            && ["JP", "CALL", "RET", "RETI", "RST"].contains(&info.mnemonic));
+                    if !branched {
+                        let length = cpu.registers.pc.wrapping_sub(START);
+                        assert_eq!(length, u16::from(info.length), "length of {info}");
+                    }
+                    cycles
+                })
+                .collect();
+            cycles.sort_unstable();
+            let mut expected = vec![info.cycles, info.branch_cycles.unwrap_or(info.cycles)];
+            expected.sort_unstable();
+            assert_eq!(cycles, expected, "cycles of {info} ({})", info.opcode);
+        }
+    }
+}
@@ -1,11 +1,14 @@
+use crate::coverage::Opcode;
 use crate::cpu::{
-    Cpu, Decrement, Direct, HighIndexed, Immediate, Increment, JumpCondition, Register16::*,
-    Register8::*,
+    BusInterface, Cpu, Decrement, Direct, HighIndexed, Immediate, Increment, JumpCondition,
+    Register16::*, Register8::*,
 };
-use crate::hardware::AddressBus;
 
 impl Cpu {
-    pub(crate) fn execute(&mut self, bus: &mut AddressBus, opcode: u8) -> usize {
+    pub(crate) fn execute(&mut self, bus: &mut impl BusInterface, opcode: u8) -> usize {
+        if opcode != 0xCB {
+            self.record_coverage(Opcode::Unprefixed(opcode));
+        }
         match opcode {
             // ---- 8-bit Arithmetic
             // ADD
@@ -414,36 +417,36 @@ impl Cpu {
             }
             // INC
             0x03 => {
-                self.increment16(BC);
+                self.increment16(bus, BC);
                 8
             }
             0x13 => {
-                self.increment16(DE);
+                self.increment16(bus, DE);
                 8
             }
             0x23 => {
-                self.increment16(HL);
+                self.increment16(bus, HL);
                 8
             }
             0x33 => {
-                self.increment16(SP);
+                self.increment16(bus, SP);
                 8
             }
             // DEC
             0x0B => {
-                self.decrement16(BC);
+                self.decrement16(bus, BC);
                 8
             }
             0x1B => {
-                self.decrement16(DE);
+                self.decrement16(bus, DE);
                 8
             }
             0x2B => {
-                self.decrement16(HL);
+                self.decrement16(bus, HL);
                 8
             }
             0x3B => {
-                self.decrement16(SP);
+                self.decrement16(bus, SP);
                 8
             }
             // ---- Bit Shift
@@ -778,10 +781,12 @@ impl Cpu {
                 8
             }
             0x2A => {
+                bus.increment_address(self.registers.read_word(HL), true);
                 self.load(bus, A, Direct(Increment(HL)));
                 8
             }
             0x3A => {
+                bus.increment_address(self.registers.read_word(HL), true);
                 self.load(bus, A, Direct(Decrement(HL)));
                 8
             }
@@ -957,7 +962,7 @@ impl Cpu {
             }
             // HALT
             0x76 => {
-                self.halt();
+                self.halt(bus);
                 4
             }
             // PREFIX
@@ -976,13 +981,17 @@ impl Cpu {
                 4
             }
             // ---- Undefined
-            byte @ (0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD) => {
-                panic!("Error: Trying to run undefined instruction {byte:#02X}");
+            // Hangs the CPU, PC is left on the opcode so debuggers show it
+            0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+                self.locked = true;
+                self.registers.pc = self.registers.pc.wrapping_sub(1);
+                4
             }
         }
     }
 
-    fn execute_prefixed(&mut self, bus: &mut AddressBus, opcode: u8) -> usize {
+    fn execute_prefixed(&mut self, bus: &mut impl BusInterface, opcode: u8) -> usize {
+        self.record_coverage(Opcode::Prefixed(opcode));
         match opcode {
             // ---- Bit Shift
             // RLC
@@ -1,7 +1,7 @@
 use crate::cpu::{
-    Cpu, FlagsRegister, JumpCondition, AccessReadByte, AccessReadWord, Register16, AccessWriteByte, AccessWriteWord,
+    AccessReadByte, AccessReadWord, AccessWriteByte, AccessWriteWord, BusInterface, Cpu,
+    FlagsRegister, JumpCondition, Register16,
 };
-use crate::hardware::AddressBus;
 
 impl Cpu {
     /// NOP
@@ -16,16 +16,21 @@ impl Cpu {
     /// - - - -
     ///
     /// Stop CPU & display until button pressed.
-    pub(crate) fn stop(&mut self, bus: &AddressBus) {
-        let _ = self.read_next_byte(bus);
-        loop {
-            // TODO: Add sleeping to save CPU usage
-            let joypad = bus.get_joypad();
-            if joypad.is_any_pressed() {
-                break;
-            }
+    ///
+    /// With a button already held, the CPU can't stop. It halts instead, or does nothing if
+    /// an interrupt is pending. The byte after STOP is skipped unless an interrupt is
+    /// pending, and DIV is reset when the clock stops.
+    pub(crate) fn stop(&mut self, bus: &mut impl BusInterface) {
+        let interrupt_pending = bus.pending_interrupts() != 0;
+        if !interrupt_pending {
+            let _ = self.read_next_byte(bus);
+        }
+        if bus.is_any_button_pressed() {
+            self.halted = !interrupt_pending;
+        } else {
+            bus.reset_div();
+            self.stopped = true;
         }
-        // TODO: look into strange stop behavior
     }
 
     /// HALT
@@ -33,8 +38,11 @@ impl Cpu {
     /// - - - -
     ///
     /// Halt CPU until an interrupt occurs.
-    pub(crate) fn halt(&mut self) {
-        self.halted = true;
+    ///
+    /// If an interrupt is already pending, the CPU doesn't halt and with IME set the
+    /// interrupt is serviced right after, without the cycle it takes to wake up.
+    pub(crate) fn halt(&mut self, bus: &impl BusInterface) {
+        self.halted = bus.pending_interrupts() == 0;
         // TODO: Look into halt bug
     }
 
@@ -43,7 +51,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Load src (right) and copy into dst (left).
-    pub(crate) fn load<D, S>(&mut self, bus: &mut AddressBus, dst: D, src: S)
+    pub(crate) fn load<D, S>(&mut self, bus: &mut impl BusInterface, dst: D, src: S)
     where
         Self: AccessReadByte<S> + AccessWriteByte<D>,
     {
@@ -56,7 +64,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Load src (right) and copy into dst (left).
-    pub(crate) fn load16<D, S>(&mut self, bus: &AddressBus, dst: D, src: S)
+    pub(crate) fn load16<D, S>(&mut self, bus: &mut impl BusInterface, dst: D, src: S)
     where
         Self: AccessReadWord<S> + AccessWriteWord<D>,
     {
@@ -69,7 +77,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Load SP at address a16.
-    pub(crate) fn load16_a16_sp(&mut self, bus: &mut AddressBus) {
+    pub(crate) fn load16_a16_sp(&mut self, bus: &mut impl BusInterface) {
         let value = self.registers.sp;
         let [low, high] = value.to_le_bytes();
         let addr = self.read_next_word(bus);
@@ -82,7 +90,7 @@ impl Cpu {
     /// 0 0 H C
     ///
     /// Add the signed value e8 to SP and store the result in HL.
-    pub(crate) fn load16_hl_sp(&mut self, bus: &AddressBus) {
+    pub(crate) fn load16_hl_sp(&mut self, bus: &mut impl BusInterface) {
         let sp = self.registers.sp;
         let offset = self.read_next_byte_signed(bus) as i16;
         self.registers.f.set(FlagsRegister::ZERO, false);
@@ -102,7 +110,7 @@ impl Cpu {
     /// Z 0 H C
     ///
     /// Add the value in r8 to register A.
-    pub(crate) fn add<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn add<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -125,7 +133,7 @@ impl Cpu {
     /// Z 0 H C
     ///
     /// Add the value in r8 plus the carry flag to register A.
-    pub(crate) fn add_with_carry<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn add_with_carry<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -147,7 +155,7 @@ impl Cpu {
     /// Z 1 H C
     ///
     /// Subtract the value in r8 from register A.
-    pub(crate) fn subtract<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn subtract<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -167,7 +175,7 @@ impl Cpu {
     /// Z 1 H C
     ///
     /// Subtract the value in r8 and the carry flag from register A.
-    pub(crate) fn subtract_with_carry<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn subtract_with_carry<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -189,7 +197,7 @@ impl Cpu {
     /// Z 0 1 0
     ///
     /// Bitwise AND between the value in r8 and register A.
-    pub(crate) fn and<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn and<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -207,7 +215,7 @@ impl Cpu {
     /// Z 0 0 0
     ///
     /// Bitwise XOR between the value in r8 and register A.
-    pub(crate) fn xor<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn xor<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -225,7 +233,7 @@ impl Cpu {
     /// Z 0 0 0
     ///
     /// Bitwise OR between the value in r8 and register A.
-    pub(crate) fn or<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn or<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -243,7 +251,7 @@ impl Cpu {
     /// Z 1 H C
     ///
     /// Subtract the value in r8 from register A and set flags accordingly, but don't store the result.
-    pub(crate) fn compare<S>(&mut self, bus: &AddressBus, src: S)
+    pub(crate) fn compare<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -262,7 +270,7 @@ impl Cpu {
     /// Z 0 H -
     ///
     /// Increment value in register r8 by 1.
-    pub(crate) fn increment<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn increment<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -282,7 +290,7 @@ impl Cpu {
     /// Z 1 H -
     ///
     /// Decrement value in register r8 by 1.
-    pub(crate) fn decrement<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn decrement<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -321,7 +329,7 @@ impl Cpu {
     /// 0 0 H C
     ///
     /// Add the signed value e8 to SP.
-    pub(crate) fn add16_sp(&mut self, bus: &AddressBus) {
+    pub(crate) fn add16_sp(&mut self, bus: &mut impl BusInterface) {
         let offset = self.read_next_byte_signed(bus) as i16;
         let sp = self.registers.sp;
         self.registers.f.set(FlagsRegister::ZERO, false);
@@ -341,8 +349,9 @@ impl Cpu {
     /// - - - -
     ///
     /// Increment value in register r16 by 1.
-    pub(crate) fn increment16(&mut self, src: Register16) {
+    pub(crate) fn increment16(&mut self, bus: &mut impl BusInterface, src: Register16) {
         let value = self.registers.read_word(src);
+        bus.increment_address(value, false);
         let new_value = value.wrapping_add(1);
         self.registers.write_word(src, new_value);
     }
@@ -352,8 +361,9 @@ impl Cpu {
     /// - - - -
     ///
     /// Decrement value in register r16 by 1.
-    pub(crate) fn decrement16(&mut self, src: Register16) {
+    pub(crate) fn decrement16(&mut self, bus: &mut impl BusInterface, src: Register16) {
         let value = self.registers.read_word(src);
+        bus.increment_address(value, false);
         let new_value = value.wrapping_sub(1);
         self.registers.write_word(src, new_value);
     }
@@ -507,7 +517,7 @@ impl Cpu {
     /// Z 0 0 C
     ///
     /// Rotate register r8 left.
-    pub(crate) fn rotate_left_circular<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn rotate_left_circular<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -527,7 +537,7 @@ impl Cpu {
     /// Z 0 0 C
     ///
     /// Rotate register r8 right.
-    pub(crate) fn rotate_right_circular<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn rotate_right_circular<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -547,7 +557,7 @@ impl Cpu {
     /// Z 0 0 C
     ///
     /// Rotate bits in register r8 left, through the carry flag.
-    pub(crate) fn rotate_left<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn rotate_left<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -568,7 +578,7 @@ impl Cpu {
     /// Z 0 0 C
     ///
     /// Rotate register r8 right, through the carry flag.
-    pub(crate) fn rotate_right<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn rotate_right<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -589,7 +599,7 @@ impl Cpu {
     /// Z 0 0 C
     ///
     /// Shift Left Arithmetically register r8.
-    pub(crate) fn shift_left_arithmetic<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn shift_left_arithmetic<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -609,7 +619,7 @@ impl Cpu {
     /// Z 0 0 C
     ///
     /// Shift Right Arithmetically register r8 (bit 7 of r8 is unchanged).
-    pub(crate) fn shift_right_arithmetic<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn shift_right_arithmetic<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -629,7 +639,7 @@ impl Cpu {
     /// Z 0 0 0
     ///
     /// Swap the upper 4 bits in register r8 and the lower 4 ones.
-    pub(crate) fn swap<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn swap<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -649,7 +659,7 @@ impl Cpu {
     /// Z 0 0 C
     ///
     /// Shift Right Logically register r8.
-    pub(crate) fn shift_right_logical<S>(&mut self, bus: &mut AddressBus, src: S)
+    pub(crate) fn shift_right_logical<S>(&mut self, bus: &mut impl BusInterface, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -669,7 +679,7 @@ impl Cpu {
     /// Z 0 1 -
     ///
     /// Test bit u3 in register r8, set the zero flag if bit not set.
-    pub(crate) fn bit_test<S>(&mut self, bus: &AddressBus, bit: u8, src: S)
+    pub(crate) fn bit_test<S>(&mut self, bus: &mut impl BusInterface, bit: u8, src: S)
     where
         Self: AccessReadByte<S>,
     {
@@ -686,7 +696,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Set bit u3 in register r8 to 0. Bit 0 is the rightmost one, bit 7 the leftmost one.
-    pub(crate) fn bit_reset<S>(&mut self, bus: &mut AddressBus, bit: u8, src: S)
+    pub(crate) fn bit_reset<S>(&mut self, bus: &mut impl BusInterface, bit: u8, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -702,7 +712,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Set bit u3 in register r8 to 1. Bit 0 is the rightmost one, bit 7 the leftmost one.
-    pub(crate) fn bit_set<S>(&mut self, bus: &mut AddressBus, bit: u8, src: S)
+    pub(crate) fn bit_set<S>(&mut self, bus: &mut impl BusInterface, bit: u8, src: S)
     where
         S: Copy,
         Self: AccessReadByte<S> + AccessWriteByte<S>,
@@ -727,7 +737,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Jump to address n16 if condition cc is met.
-    pub(crate) fn jump(&mut self, bus: &AddressBus, condition: JumpCondition) -> usize {
+    pub(crate) fn jump(&mut self, bus: &mut impl BusInterface, condition: JumpCondition) -> usize {
         let should_jump = self.registers.f.test(condition);
         let addr = self.read_next_word(bus);
         if should_jump {
@@ -742,7 +752,11 @@ impl Cpu {
     /// - - - -
     ///
     /// Relative Jump to current address plus e8 offset if condition cc is met.
-    pub(crate) fn jump_relative(&mut self, bus: &AddressBus, condition: JumpCondition) -> usize {
+    pub(crate) fn jump_relative(
+        &mut self,
+        bus: &mut impl BusInterface,
+        condition: JumpCondition,
+    ) -> usize {
         let should_jump = self.registers.f.test(condition);
         let offset = self.read_next_byte_signed(bus) as i16;
         if should_jump {
@@ -757,9 +771,10 @@ impl Cpu {
     /// - - - -
     ///
     /// Push register r16 into the stack.
-    pub(crate) fn push(&mut self, bus: &mut AddressBus, register: Register16) {
+    pub(crate) fn push(&mut self, bus: &mut impl BusInterface, register: Register16) {
         let value = self.registers.read_word(register);
         let [low, high] = value.to_le_bytes();
+        bus.increment_address(self.registers.sp, false);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
         bus.write_byte(self.registers.sp, high);
 
@@ -774,10 +789,12 @@ impl Cpu {
     /// Pop register r16 from the stack.
     ///
     /// NOTE: POP AF affects all flags.
-    pub(crate) fn pop(&mut self, bus: &AddressBus, register: Register16) {
+    pub(crate) fn pop(&mut self, bus: &mut impl BusInterface, register: Register16) {
+        bus.increment_address(self.registers.sp, true);
         let low = bus.read_byte(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
 
+        bus.increment_address(self.registers.sp, true);
         let high = bus.read_byte(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
 
@@ -790,7 +807,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Call address n16 if condition cc is met.
-    pub(crate) fn call(&mut self, bus: &mut AddressBus, condition: JumpCondition) -> usize {
+    pub(crate) fn call(&mut self, bus: &mut impl BusInterface, condition: JumpCondition) -> usize {
         let should_jump = self.registers.f.test(condition);
         let addr = self.read_next_word(bus);
         if should_jump {
@@ -806,7 +823,11 @@ impl Cpu {
     /// - - - -
     ///
     /// Return from subroutine if condition cc is met.
-    pub(crate) fn return_(&mut self, bus: &AddressBus, condition: JumpCondition) -> usize {
+    pub(crate) fn return_(
+        &mut self,
+        bus: &mut impl BusInterface,
+        condition: JumpCondition,
+    ) -> usize {
         let should_jump = self.registers.f.test(condition);
         if should_jump {
             self.pop(bus, Register16::PC);
@@ -821,7 +842,7 @@ impl Cpu {
     ///
     /// Return from subroutine and enable interrupts.
     /// This is basically equivalent to executing EI then RET, meaning that IME is set right after this instruction.
-    pub(crate) fn return_from_interrupt_handler(&mut self, bus: &AddressBus) {
+    pub(crate) fn return_from_interrupt_handler(&mut self, bus: &mut impl BusInterface) {
         self.return_(bus, JumpCondition::Always);
         self.ime = true;
     }
@@ -831,7 +852,7 @@ impl Cpu {
     /// - - - -
     ///
     /// Push current address onto stack, and jump to address u8.
-    pub(crate) fn restart(&mut self, bus: &mut AddressBus, addr: u16) {
+    pub(crate) fn restart(&mut self, bus: &mut impl BusInterface, addr: u16) {
         self.push(bus, Register16::PC);
         self.registers.pc = addr;
     }
@@ -0,0 +1,147 @@
+//! Heuristics telling when a game crashed or soft-locked, so frontends can tell the user
+//! instead of showing a frozen screen.
+
+use crate::consts::FRAME_CYCLES;
+use std::fmt::{Display, Formatter};
+
+// Longest loop that counts as stuck, longer ones are usually waiting on something
+const MAX_LOOP_INSTRUCTIONS: usize = 2;
+
+/// A failure detected by [`GameboyHardware::set_crash_detection`].
+///
+/// [`GameboyHardware::set_crash_detection`]: crate::hardware::GameboyHardware::set_crash_detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crash {
+    /// The CPU ran a loop of at most 2 instructions starting at `pc` for the configured
+    /// number of frames, with interrupts disabled and without reading I/O registers, so
+    /// nothing can break it.
+    SoftLock { pc: u16 },
+    /// The CPU jumped into the I/O registers (0xFF00-0xFF7F), usually through a corrupted
+    /// return address.
+    OpenBusExecution { pc: u16 },
+    /// An undefined opcode hung the CPU, only a reset recovers.
+    IllegalOpcode { pc: u16, opcode: u8 },
+}
+
+impl Display for Crash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SoftLock { pc } => write!(f, "soft-locked in a loop at {pc:04X}"),
+            Self::OpenBusExecution { pc } => write!(f, "executing I/O registers at {pc:04X}"),
+            Self::IllegalOpcode { pc, opcode } => {
+                write!(f, "undefined opcode {opcode:02X} at {pc:04X}")
+            }
+        }
+    }
+}
+
+/// Tracks the instructions run in each frame's worth of cycles to find soft-locks.
+#[derive(Debug, Clone)]
+pub(crate) struct CrashDetector {
+    soft_lock_frames: u32,
+    crash: Option<Crash>,
+    // Distinct instructions run in the current frame, stops growing past the longest loop
+    loop_pcs: Vec<u16>,
+    cycles: usize,
+    stuck_frames: u32,
+}
+
+impl CrashDetector {
+    pub(crate) fn new(soft_lock_frames: u32) -> Self {
+        Self {
+            soft_lock_frames: soft_lock_frames.max(1),
+            crash: None,
+            loop_pcs: Vec::with_capacity(MAX_LOOP_INSTRUCTIONS + 1),
+            cycles: 0,
+            stuck_frames: 0,
+        }
+    }
+
+    /// Forgets the crash and the frames counted so far, keeping the configuration.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.soft_lock_frames);
+    }
+
+    pub(crate) const fn crash(&self) -> Option<Crash> {
+        self.crash
+    }
+
+    /// Records the first crash, later ones are usually consequences of it.
+    pub(crate) fn report(&mut self, crash: Crash) {
+        if self.crash.is_none() {
+            self.crash = Some(crash);
+        }
+    }
+
+    /// Records an instruction run at `pc`, returning whether a frame's worth of cycles has
+    /// passed and [`Self::end_frame`] should be called.
+    pub(crate) fn record_step(&mut self, pc: u16, cycles: usize) -> bool {
+        if self.loop_pcs.len() <= MAX_LOOP_INSTRUCTIONS && !self.loop_pcs.contains(&pc) {
+            self.loop_pcs.push(pc);
+        }
+        self.cycles += cycles;
+        self.cycles >= FRAME_CYCLES as usize
+    }
+
+    /// Ends a frame's worth of cycles. `can_exit` is whether the loop could still end:
+    /// interrupts are enabled, or it read I/O registers that can change on their own.
+    pub(crate) fn end_frame(&mut self, can_exit: bool) {
+        let stuck = !can_exit && self.loop_pcs.len() <= MAX_LOOP_INSTRUCTIONS;
+        self.stuck_frames = if stuck { self.stuck_frames + 1 } else { 0 };
+        if self.stuck_frames >= self.soft_lock_frames {
+            if let Some(&pc) = self.loop_pcs.iter().min() {
+                self.report(Crash::SoftLock { pc });
+            }
+        }
+        self.loop_pcs.clear();
+        self.cycles -= FRAME_CYCLES as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::crash::Crash;
+    use crate::hardware::GameboyHardware;
+
+    fn run(code: &[u8], frames: usize) -> Option<Crash> {
+        let rom = HeaderBuilder::new().build(code);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_crash_detection(Some(10));
+        for _ in 0..frames {
+            gameboy.run_frame();
+        }
+        gameboy.crash()
+    }
+
+    #[test]
+    fn test_soft_lock() {
+        // DI; JR -2
+        let code = [0xF3, 0x18, 0xFE];
+        assert_eq!(run(&code, 5), None);
+        assert_eq!(run(&code, 15), Some(Crash::SoftLock { pc: 0x0151 }));
+        // HALT with no interrupts enabled never wakes up
+        assert_eq!(run(&[0x76], 15), Some(Crash::SoftLock { pc: 0x0151 }));
+        // Polling the joypad: LDH A,(0x00); JR -4
+        assert_eq!(run(&[0xF0, 0x00, 0x18, 0xFC], 15), None);
+    }
+
+    #[test]
+    fn test_illegal_opcode() {
+        let crash = run(&[0x00, 0xD3], 1);
+        assert_eq!(
+            crash,
+            Some(Crash::IllegalOpcode {
+                pc: 0x0151,
+                opcode: 0xD3
+            })
+        );
+    }
+
+    #[test]
+    fn test_open_bus_execution() {
+        // JP 0xFF00
+        let crash = run(&[0xC3, 0x00, 0xFF], 1);
+        assert_eq!(crash, Some(Crash::OpenBusExecution { pc: 0xFF00 }));
+    }
+}
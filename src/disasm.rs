@@ -0,0 +1,213 @@
+//! Disassembling instructions from memory, with the values of their operands read from the
+//! bytes following the opcode.
+//!
+//! Built on the table in [`crate::opcodes`], which describes each opcode without its
+//! operand values.
+
+use crate::coverage::Opcode;
+use crate::opcodes::{self, OpcodeInfo};
+use std::fmt::{Display, Formatter};
+
+/// An operand of a decoded instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// An 8-bit register, or `[HL]` for the byte HL points to.
+    Register8(&'static str),
+    Register16(&'static str),
+    /// The byte a register pair points to, `[HL+]` and `[HL-]` also change HL.
+    Indirect(&'static str),
+    /// The byte at 0xFF00 + C.
+    HighC,
+    Immediate8(u8),
+    Immediate16(u16),
+    /// The byte at an address.
+    Address16(u16),
+    /// The byte at an address in 0xFF00-0xFFFF, encoded as its low byte.
+    HighAddress8(u16),
+    /// Where a relative jump goes, resolved from its signed offset.
+    Relative(u16),
+    /// SP plus a signed offset.
+    StackOffset8(i8),
+    /// A signed value added to SP.
+    Offset8(i8),
+    Condition(&'static str),
+    Bit(u8),
+    /// Address called by RST.
+    Vector(u8),
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Register8(name) | Self::Register16(name) | Self::Condition(name) => {
+                write!(f, "{name}")
+            }
+            Self::Indirect(name) => write!(f, "[{name}]"),
+            Self::HighC => write!(f, "[C]"),
+            Self::Immediate8(value) => write!(f, "${value:02X}"),
+            Self::Immediate16(value) | Self::Relative(value) => write!(f, "${value:04X}"),
+            Self::Address16(addr) | Self::HighAddress8(addr) => write!(f, "[${addr:04X}]"),
+            Self::StackOffset8(offset) if *offset < 0 => {
+                write!(f, "SP - {}", offset.unsigned_abs())
+            }
+            Self::StackOffset8(offset) => write!(f, "SP + {offset}"),
+            Self::Offset8(offset) => write!(f, "{offset}"),
+            Self::Bit(bit) => write!(f, "{bit}"),
+            Self::Vector(addr) => write!(f, "${addr:02X}"),
+        }
+    }
+}
+
+/// An instruction decoded by [`decode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// Address of the first byte, the prefix for prefixed opcodes.
+    pub addr: u16,
+    pub opcode: Opcode,
+    /// `DB` for undefined opcodes, which lock up the CPU.
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    /// Bytes including the opcode and its prefix.
+    pub length: u8,
+    /// T-cycles taken, when not branching for conditional instructions.
+    pub cycles: u8,
+    /// T-cycles taken by conditional instructions when branching.
+    pub branch_cycles: Option<u8>,
+}
+
+impl Instruction {
+    /// Returns the address of the instruction after this one.
+    #[must_use]
+    pub const fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.length as u16)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (index, operand) in self.operands.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(f, "{separator}{operand}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the instruction at `addr`, reading its bytes with `read`.
+///
+/// `read` should not have side effects, e.g.
+/// [`GameboyHardware::peek_byte`](crate::hardware::GameboyHardware::peek_byte).
+#[must_use]
+pub fn decode(addr: u16, read: &dyn Fn(u16) -> u8) -> Instruction {
+    let byte = read(addr);
+    let opcode = if byte == 0xCB {
+        Opcode::Prefixed(read(addr.wrapping_add(1)))
+    } else {
+        Opcode::Unprefixed(byte)
+    };
+    let Some(info) = opcodes::info(opcode) else {
+        return Instruction {
+            addr,
+            opcode,
+            mnemonic: "DB",
+            operands: vec![Operand::Immediate8(byte)],
+            length: 1,
+            cycles: 0,
+            branch_cycles: None,
+        };
+    };
+
+    // At most one operand takes bytes, right after the opcode (prefixed opcodes have none)
+    let next_addr = addr.wrapping_add(u16::from(info.length));
+    let byte_operand = read(addr.wrapping_add(1));
+    let word_operand = || u16::from_le_bytes([byte_operand, read(addr.wrapping_add(2))]);
+    let operands = info
+        .operands
+        .iter()
+        .map(|operand| match *operand {
+            opcodes::Operand::Register8(name) => Operand::Register8(name),
+            opcodes::Operand::Register16(name) => Operand::Register16(name),
+            opcodes::Operand::Indirect(name) => Operand::Indirect(name),
+            opcodes::Operand::HighC => Operand::HighC,
+            opcodes::Operand::Immediate8 => Operand::Immediate8(byte_operand),
+            opcodes::Operand::Immediate16 => Operand::Immediate16(word_operand()),
+            opcodes::Operand::Address16 => Operand::Address16(word_operand()),
+            opcodes::Operand::HighAddress8 => {
+                Operand::HighAddress8(0xFF00 | u16::from(byte_operand))
+            }
+            opcodes::Operand::Offset8 if info.mnemonic == "JR" => {
+                Operand::Relative(next_addr.wrapping_add_signed(i16::from(byte_operand as i8)))
+            }
+            opcodes::Operand::Offset8 => Operand::Offset8(byte_operand as i8),
+            opcodes::Operand::StackOffset8 => Operand::StackOffset8(byte_operand as i8),
+            opcodes::Operand::Condition(name) => Operand::Condition(name),
+            opcodes::Operand::Bit(bit) => Operand::Bit(bit),
+            opcodes::Operand::Vector(vector) => Operand::Vector(vector),
+        })
+        .collect();
+    let OpcodeInfo {
+        mnemonic,
+        length,
+        cycles,
+        branch_cycles,
+        ..
+    } = info;
+    Instruction {
+        addr,
+        opcode,
+        mnemonic,
+        operands,
+        length,
+        cycles,
+        branch_cycles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::decode;
+
+    #[test]
+    fn test_decode() {
+        let program = [
+            0x3E, 0x42, // LD A, 0x42
+            0xEA, 0x34, 0xC1, // LD (0xC134), A
+            0xF0, 0x44, // LDH A, (0x44)
+            0xE8, 0xFE, // ADD SP, -2
+            0xF8, 0x05, // LD HL, SP + 5
+            0xCB, 0x7E, // BIT 7, (HL)
+            0xD3, // undefined
+            0x20, 0xF0, // JR NZ, -16
+        ];
+        let read = |addr: u16| program.get(usize::from(addr - 0x100)).copied().unwrap_or(0);
+        let mut addr = 0x100;
+        let mut lines = Vec::new();
+        while usize::from(addr - 0x100) < program.len() {
+            let instruction = decode(addr, &read);
+            lines.push(format!("{:04X} {instruction}", instruction.addr));
+            addr = instruction.next_addr();
+        }
+        assert_eq!(
+            lines,
+            [
+                "0100 LD A, $42",
+                "0102 LD [$C134], A",
+                "0105 LDH A, [$FF44]",
+                "0107 ADD SP, -2",
+                "0109 LD HL, SP + 5",
+                "010B BIT 7, [HL]",
+                "010D DB $D3",
+                "010E JR NZ, $0100",
+            ]
+        );
+
+        let jump = decode(0x10E, &read);
+        assert_eq!(
+            (jump.length, jump.cycles, jump.branch_cycles),
+            (2, 8, Some(12))
+        );
+        let bit = decode(0x10B, &read);
+        assert_eq!((bit.length, bit.cycles), (2, 12));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::error::TraceError;
+use crate::hardware::{CpuRegisters, GameboyHardware, MemoryWrite};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+
+const TRACE_MAGIC: &[u8; 4] = b"GBTR";
+const CONTEXT_STEPS: usize = 8;
+
+/// State observed for a single CPU step: the registers before the step and
+/// the memory writes it made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    pub registers: CpuRegisters,
+    pub writes: Vec<MemoryWrite>,
+    /// Up to 3 bytes at PC before the step, used to show the instruction.
+    pub instruction: [u8; 3],
+}
+
+impl TraceStep {
+    fn record(gameboy: &mut GameboyHardware) -> Self {
+        let registers = gameboy.registers();
+        let pc = registers.pc;
+        let instruction = [
+            gameboy.peek_byte(pc),
+            gameboy.peek_byte(pc.wrapping_add(1)),
+            gameboy.peek_byte(pc.wrapping_add(2)),
+        ];
+        gameboy.step();
+        Self {
+            registers,
+            writes: gameboy.take_writes(),
+            instruction,
+        }
+    }
+}
+
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let r = &self.registers;
+        let [b0, b1, b2] = self.instruction;
+        write!(
+            f,
+            "PC:{:04X} [{b0:02X} {b1:02X} {b2:02X}] AF:{:02X}{:02X} BC:{:02X}{:02X} DE:{:02X}{:02X} HL:{:02X}{:02X} SP:{:04X}",
+            r.pc, r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l, r.sp
+        )?;
+        for write in &self.writes {
+            write!(f, " [{:04X}]={:02X}", write.addr, write.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The first step where two executions disagree.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Number of steps executed before the divergent one.
+    pub step: u64,
+    pub expected: TraceStep,
+    pub actual: TraceStep,
+    /// Steps leading up to the divergence, oldest first.
+    pub context: Vec<TraceStep>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Divergence at step {}", self.step)?;
+        for step in &self.context {
+            writeln!(f, "           {step}")?;
+        }
+        writeln!(f, "expected:  {}", self.expected)?;
+        write!(f, "actual:    {}", self.actual)
+    }
+}
+
+/// A recorded execution, used to compare against a different build of the core.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    /// Records `steps` CPU steps.
+    pub fn record(gameboy: &mut GameboyHardware, steps: u64) -> Self {
+        gameboy.set_write_logging(true);
+        let steps = (0..steps).map(|_| TraceStep::record(gameboy)).collect();
+        gameboy.set_write_logging(false);
+        Self { steps }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = TRACE_MAGIC.to_vec();
+        for step in &self.steps {
+            let r = &step.registers;
+            bytes.extend_from_slice(&[r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l]);
+            bytes.extend_from_slice(&r.sp.to_le_bytes());
+            bytes.extend_from_slice(&r.pc.to_le_bytes());
+            bytes.extend_from_slice(&step.instruction);
+            #[allow(clippy::cast_possible_truncation)]
+            let write_count = step.writes.len() as u8;
+            bytes.push(write_count);
+            for write in &step.writes {
+                bytes.extend_from_slice(&write.addr.to_le_bytes());
+                bytes.push(write.value);
+            }
+        }
+        bytes
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the data isn't a trace or is truncated.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TraceError> {
+        let mut bytes = bytes
+            .strip_prefix(TRACE_MAGIC)
+            .ok_or(TraceError::InvalidFormat)?;
+        let mut steps = Vec::new();
+        while !bytes.is_empty() {
+            let (header, rest) = bytes.split_at_checked(16).ok_or(TraceError::Truncated)?;
+            let registers = CpuRegisters {
+                a: header[0],
+                f: header[1],
+                b: header[2],
+                c: header[3],
+                d: header[4],
+                e: header[5],
+                h: header[6],
+                l: header[7],
+                sp: u16::from_le_bytes([header[8], header[9]]),
+                pc: u16::from_le_bytes([header[10], header[11]]),
+            };
+            let instruction = [header[12], header[13], header[14]];
+            let (writes, rest) = rest
+                .split_at_checked(header[15] as usize * 3)
+                .ok_or(TraceError::Truncated)?;
+            let writes = writes
+                .chunks_exact(3)
+                .map(|write| MemoryWrite {
+                    addr: u16::from_le_bytes([write[0], write[1]]),
+                    value: write[2],
+                })
+                .collect();
+            steps.push(TraceStep {
+                registers,
+                writes,
+                instruction,
+            });
+            bytes = rest;
+        }
+        Ok(Self { steps })
+    }
+}
+
+/// Runs two instances in lockstep for up to `max_steps` CPU steps and returns the first step
+/// where their registers or memory writes differ.
+///
+/// `expected` is treated as the reference, e.g. the core before a refactor.
+pub fn find_divergence(
+    expected: &mut GameboyHardware,
+    actual: &mut GameboyHardware,
+    max_steps: u64,
+) -> Option<Divergence> {
+    expected.set_write_logging(true);
+    let mut reference = (0..max_steps).map(|_| TraceStep::record(expected));
+    let divergence = compare(actual, &mut reference);
+    expected.set_write_logging(false);
+    divergence
+}
+
+/// Runs an instance against a recorded trace and returns the first step that differs.
+pub fn find_trace_divergence(actual: &mut GameboyHardware, trace: &Trace) -> Option<Divergence> {
+    compare(actual, &mut trace.steps.iter().cloned())
+}
+
+fn compare(
+    actual: &mut GameboyHardware,
+    reference: &mut dyn Iterator<Item = TraceStep>,
+) -> Option<Divergence> {
+    actual.set_write_logging(true);
+    let mut context = VecDeque::with_capacity(CONTEXT_STEPS);
+    let mut divergence = None;
+    for (step, expected) in (0..).zip(reference) {
+        let observed = TraceStep::record(actual);
+        if observed.registers != expected.registers || observed.writes != expected.writes {
+            divergence = Some(Divergence {
+                step,
+                expected,
+                actual: observed,
+                context: context.into(),
+            });
+            break;
+        }
+        if context.len() == CONTEXT_STEPS {
+            context.pop_front();
+        }
+        context.push_back(observed);
+    }
+    actual.set_write_logging(false);
+    divergence
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::divergence::{find_divergence, find_trace_divergence, Trace};
+    use crate::hardware::GameboyHardware;
+
+    // LD HL, 0xC000; loop: LD (HL+), A; INC A; JR loop
+    const PROGRAM: [u8; 7] = [0x21, 0x00, 0xC0, 0x22, 0x3C, 0x18, 0xFC];
+
+    fn gameboy(program: &[u8]) -> GameboyHardware {
+        GameboyHardware::new(Cartridge::new(HeaderBuilder::new().build(program)))
+    }
+
+    #[test]
+    fn test_identical_runs_do_not_diverge() {
+        let divergence = find_divergence(&mut gameboy(&PROGRAM), &mut gameboy(&PROGRAM), 500);
+        assert!(divergence.is_none());
+    }
+
+    #[test]
+    fn test_finds_first_divergent_write() {
+        let mut modified = PROGRAM;
+        // INC A -> DEC A
+        modified[4] = 0x3D;
+        let divergence =
+            find_divergence(&mut gameboy(&PROGRAM), &mut gameboy(&modified), 500).unwrap();
+        // NOP, JP, LD HL, LD (HL+), INC/DEC A all match; A differs before the JR
+        assert_eq!(divergence.step, 5);
+        assert_eq!(divergence.expected.registers.pc, 0x155);
+        assert_ne!(
+            divergence.expected.registers.a,
+            divergence.actual.registers.a
+        );
+        assert_eq!(divergence.context.len(), 5);
+    }
+
+    #[test]
+    fn test_trace_round_trip() {
+        let trace = Trace::record(&mut gameboy(&PROGRAM), 100);
+        let trace = Trace::from_bytes(&trace.to_bytes()).unwrap();
+        assert!(find_trace_divergence(&mut gameboy(&PROGRAM), &trace).is_none());
+    }
+}
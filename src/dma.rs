@@ -0,0 +1,123 @@
+use crate::error::SavestateError;
+use crate::savestate::{StateReader, StateWriter};
+
+// Bytes copied by a transfer, the size of OAM
+const TRANSFER_LENGTH: u8 = 0xA0;
+// M-cycles between writing to the DMA register and the first byte being copied
+const START_DELAY: u8 = 1;
+
+/// OAM DMA, copying 160 bytes from `source << 8` to OAM, one byte per M-cycle.
+///
+/// The DMA register itself is kept by the PPU, this only tracks the transfer.
+#[derive(Debug, Clone, Copy)]
+pub struct OamDma {
+    // Upper byte of the source address of the running transfer
+    source: u8,
+    // Bytes copied by the running transfer, `None` when no transfer is running
+    copied: Option<u8>,
+    // Upper byte of the source address of a transfer waiting to start
+    requested_source: u8,
+    // M-cycles until the requested transfer starts, `None` when none was requested.
+    // A running transfer continues until then
+    start_delay: Option<u8>,
+}
+
+impl OamDma {
+    pub const fn new() -> Self {
+        Self {
+            source: 0,
+            copied: None,
+            requested_source: 0,
+            start_delay: None,
+        }
+    }
+
+    /// Requests a transfer from `source << 8`, like writing to the DMA register.
+    pub fn start(&mut self, source: u8) {
+        self.requested_source = source;
+        self.start_delay = Some(START_DELAY);
+    }
+
+    /// Returns whether a transfer is running, blocking CPU access to OAM.
+    pub const fn is_active(&self) -> bool {
+        self.copied.is_some()
+    }
+
+    /// Advances the transfer by one M-cycle, returning the source address and OAM offset of
+    /// the byte to copy during it.
+    pub fn tick(&mut self) -> Option<(u16, u16)> {
+        let copy = self.copied.map(|copied| {
+            (
+                u16::from(self.source) << 8 | u16::from(copied),
+                u16::from(copied),
+            )
+        });
+        self.copied = self
+            .copied
+            .map(|copied| copied + 1)
+            .filter(|&copied| copied < TRANSFER_LENGTH);
+
+        match self.start_delay {
+            Some(0) => {
+                self.source = self.requested_source;
+                self.copied = Some(0);
+                self.start_delay = None;
+            }
+            Some(delay) => self.start_delay = Some(delay - 1),
+            None => {}
+        }
+        copy
+    }
+
+    pub fn save_state(self, writer: &mut StateWriter) {
+        writer.component("OAM DMA");
+        writer.field("source");
+        writer.write_u8(self.source);
+        writer.field("bytes copied");
+        writer.write_option_u8(self.copied);
+        writer.field("requested source");
+        writer.write_u8(self.requested_source);
+        writer.field("start delay");
+        writer.write_option_u8(self.start_delay);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.source = reader.read_u8()?;
+        self.copied = reader.read_option_u8()?;
+        self.requested_source = reader.read_u8()?;
+        self.start_delay = reader.read_option_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dma::{OamDma, TRANSFER_LENGTH};
+
+    #[test]
+    fn test_transfer() {
+        let mut dma = OamDma::new();
+        dma.start(0xC1);
+        assert_eq!(dma.tick(), None);
+        assert_eq!(dma.tick(), None);
+        assert!(dma.is_active());
+        for offset in 0..u16::from(TRANSFER_LENGTH) {
+            assert_eq!(dma.tick(), Some((0xC100 | offset, offset)));
+        }
+        assert!(!dma.is_active());
+        assert_eq!(dma.tick(), None);
+    }
+
+    #[test]
+    fn test_restart_continues_until_started() {
+        let mut dma = OamDma::new();
+        dma.start(0xC1);
+        for _ in 0..12 {
+            dma.tick();
+        }
+        dma.start(0xD0);
+        assert_eq!(dma.tick(), Some((0xC10A, 0x0A)));
+        assert_eq!(dma.tick(), Some((0xC10B, 0x0B)));
+        assert_eq!(dma.tick(), Some((0xD000, 0x00)));
+    }
+}
@@ -12,3 +12,221 @@ impl Display for TryFromUintError {
 }
 
 impl Error for TryFromUintError {}
+
+/// Reasons a save file can be rejected when loading it into a cartridge.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFileError {
+    /// The cartridge has no battery-backed RAM to load the save into.
+    NoSaveRam,
+    /// The save file size does not match the RAM size in the cartridge header.
+    SizeMismatch { expected: usize, actual: usize },
+    /// The save file was written by a different ROM.
+    RomMismatch { expected: u64, actual: u64 },
+}
+
+impl Display for SaveFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSaveRam => "cartridge has no battery-backed RAM".fmt(f),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "save file is {actual} bytes but cartridge RAM is {expected} bytes"
+            ),
+            Self::RomMismatch { expected, actual } => write!(
+                f,
+                "save file belongs to ROM {actual:#018X} instead of {expected:#018X}"
+            ),
+        }
+    }
+}
+
+impl Error for SaveFileError {}
+
+/// Reasons an input movie can't be read or converted.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data isn't a movie in the expected format.
+    InvalidFormat,
+    /// The movie was written by an unsupported version of the format.
+    UnsupportedVersion(u16),
+    /// The movie starts from a savestate, SRAM, or resets, only power on is supported.
+    UnsupportedStart,
+    /// The number of frames doesn't match the header.
+    FrameCountMismatch { expected: usize, actual: usize },
+    /// The movie was recorded with another ROM.
+    RomMismatch { expected: u64, actual: u64 },
+    /// The savestate the movie starts from can't be loaded.
+    InvalidStartState(SavestateError),
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFormat => "data is not a supported movie format".fmt(f),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "movie format version {version} is not supported")
+            }
+            Self::UnsupportedStart => "movie does not start from power on".fmt(f),
+            Self::FrameCountMismatch { expected, actual } => {
+                write!(f, "movie has {actual} frames but header says {expected}")
+            }
+            Self::RomMismatch { expected, actual } => write!(
+                f,
+                "movie was recorded with ROM {expected:#018X}, loaded ROM is {actual:#018X}"
+            ),
+            Self::InvalidStartState(err) => write!(f, "movie start state can't be loaded: {err}"),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+/// Reasons an execution trace can't be read.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceError {
+    /// The data isn't an execution trace.
+    InvalidFormat,
+    /// The data ends in the middle of a step.
+    Truncated,
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFormat => "data is not an execution trace".fmt(f),
+            Self::Truncated => "execution trace is truncated".fmt(f),
+        }
+    }
+}
+
+impl Error for TraceError {}
+
+/// Reasons a savestate can't be loaded.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavestateError {
+    /// The data isn't a savestate.
+    InvalidFormat,
+    /// The savestate was written by an incompatible version.
+    UnsupportedVersion(u16),
+    /// The savestate belongs to another ROM.
+    RomMismatch { expected: u64, actual: u64 },
+    /// The savestate was made on another hardware model.
+    ModelMismatch,
+    /// The savestate ends before all components were read.
+    Truncated,
+}
+
+impl Display for SavestateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFormat => "data is not a savestate".fmt(f),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "savestate version {version} is not supported")
+            }
+            Self::RomMismatch { expected, actual } => write!(
+                f,
+                "savestate belongs to ROM {actual:#018X}, expected {expected:#018X}"
+            ),
+            Self::ModelMismatch => "savestate was made on another hardware model".fmt(f),
+            Self::Truncated => "savestate is truncated".fmt(f),
+        }
+    }
+}
+
+impl Error for SavestateError {}
+
+/// Reasons a [`PersistenceCodec`](crate::persistence::PersistenceCodec) rejects stored data.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    /// The data isn't in the codec's format.
+    Malformed,
+    /// The data's signature or authentication tag doesn't match.
+    Unauthenticated,
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed => "data is not in the codec's format".fmt(f),
+            Self::Unauthenticated => "data failed authentication".fmt(f),
+        }
+    }
+}
+
+impl Error for CodecError {}
+
+/// Reasons a [`OverrideTable`](crate::cartridge::OverrideTable) can't be parsed.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverrideError {
+    /// The line, counting from 1, isn't a ROM hash followed by overrides.
+    InvalidLine(usize),
+}
+
+impl Display for OverrideError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidLine(line) => write!(f, "invalid override on line {line}"),
+        }
+    }
+}
+
+impl Error for OverrideError {}
+
+/// Reasons cheats can't be imported.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheatError {
+    /// The code is neither a Game Genie nor a GameShark code.
+    InvalidCode(String),
+    /// The line, counting from 1, isn't `key = value`.
+    InvalidLine(usize),
+    /// The cheat file doesn't say how many cheats it has.
+    MissingCount,
+}
+
+impl Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCode(code) => write!(f, "invalid cheat code \"{code}\""),
+            Self::InvalidLine(line) => write!(f, "invalid cheat file line {line}"),
+            Self::MissingCount => "cheat file has no cheat count".fmt(f),
+        }
+    }
+}
+
+impl Error for CheatError {}
+
+/// Accesses a real console ignores, but that usually mean an emulation bug or a broken ROM,
+/// handled as set with [`GameboyHardware::set_strictness`].
+///
+/// [`GameboyHardware::set_strictness`]: crate::hardware::GameboyHardware::set_strictness
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// An access to an address in 0xFF00-0xFF7F without an I/O register.
+    UnmappedIo { addr: u16 },
+    /// A write to ROM on a cartridge without a memory bank controller.
+    RomWrite { addr: u16, value: u8 },
+}
+
+impl Display for EmulatorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnmappedIo { addr } => {
+                write!(f, "address {addr:#X} is not mapped to an I/O register")
+            }
+            Self::RomWrite { addr, value } => write!(
+                f,
+                "write of {value:#04X} to ROM at {addr:#06X} without a memory bank controller"
+            ),
+        }
+    }
+}
+
+impl Error for EmulatorError {}
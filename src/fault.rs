@@ -0,0 +1,100 @@
+//! Resetting single components and corrupting memory while the rest of the hardware keeps
+//! running, to test how game code copes with faults and to isolate interactions between
+//! components when debugging the emulator.
+
+use std::ops::RangeInclusive;
+
+/// A component that can be reset on its own with [`GameboyHardware::reset_subsystem`].
+///
+/// [`GameboyHardware::reset_subsystem`]: crate::hardware::GameboyHardware::reset_subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Registers return to their state after the boot ROM, wave RAM is kept.
+    Apu,
+    /// Registers and the current line return to their state after the boot ROM, VRAM and
+    /// OAM are kept.
+    Ppu,
+    /// DIV, TIMA, TMA and TAC return to their state after the boot ROM.
+    Timer,
+    /// Any transfer in progress is dropped.
+    SerialPort,
+    /// The memory bank controller returns to its first banks, cartridge RAM is kept.
+    Mbc,
+}
+
+/// Memory corruption applied with [`GameboyHardware::inject_fault`].
+///
+/// Faults only reach RAM: WRAM, HRAM, VRAM, OAM and the cartridge RAM bank currently
+/// mapped (even if disabled). Addresses elsewhere are left alone.
+///
+/// [`GameboyHardware::inject_fault`]: crate::hardware::GameboyHardware::inject_fault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Flips the bits set in `mask` of the byte at `addr`.
+    FlipBits { addr: u16, mask: u8 },
+    /// Overwrites every byte in `range` with pseudo-random values, the same for the same seed.
+    Scramble {
+        range: RangeInclusive<u16>,
+        seed: u64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::fault::{Fault, Subsystem};
+    use crate::hardware::GameboyHardware;
+
+    fn gameboy() -> GameboyHardware {
+        // JR -2
+        let rom = HeaderBuilder::new().build(&[0x18, 0xFE]);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.run_frame();
+        gameboy
+    }
+
+    #[test]
+    fn test_reset_subsystem() {
+        let mut gameboy = gameboy();
+        let div = gameboy.peek_byte(0xFF04);
+        assert_ne!(gameboy.peek_byte(0xFF44), 0);
+        gameboy.reset_subsystem(Subsystem::Ppu);
+        assert_eq!(gameboy.peek_byte(0xFF44), 0);
+        // The timer isn't affected
+        assert_eq!(gameboy.peek_byte(0xFF04), div);
+
+        let state = gameboy.save_state();
+        gameboy.reset_subsystem(Subsystem::Mbc);
+        assert_eq!(gameboy.save_state(), state);
+    }
+
+    #[test]
+    fn test_inject_fault() {
+        let mut gameboy = gameboy();
+        let byte = gameboy.peek_byte(0x9800);
+        assert!(gameboy.inject_fault(&Fault::FlipBits {
+            addr: 0x9800,
+            mask: 0x81
+        }));
+        assert_eq!(gameboy.peek_byte(0x9800), byte ^ 0x81);
+        // ROM and I/O registers can't be corrupted
+        assert!(!gameboy.inject_fault(&Fault::FlipBits {
+            addr: 0x0150,
+            mask: 0xFF
+        }));
+        assert!(!gameboy.inject_fault(&Fault::FlipBits {
+            addr: 0xFF40,
+            mask: 0xFF
+        }));
+
+        let scramble = Fault::Scramble {
+            range: 0xC000..=0xC0FF,
+            seed: 7,
+        };
+        assert!(gameboy.inject_fault(&scramble));
+        let scrambled = gameboy.hash_memory(&[0xC000..=0xC0FF]);
+        gameboy.inject_fault(&scramble);
+        assert_eq!(gameboy.hash_memory(&[0xC000..=0xC0FF]), scrambled);
+        assert!((0xC000..=0xC0FF).any(|addr| gameboy.peek_byte(addr) != 0));
+    }
+}
@@ -0,0 +1,282 @@
+//! C ABI for embedding the core in non-Rust frontends, enabled with the `ffi` feature.
+//!
+//! Build a shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`
+//! and declare the functions as in `include/gb_emulator.h`.
+//!
+//! An instance is created empty and runs nothing until a ROM is loaded. All functions
+//! accept a null instance and treat it like an instance without a ROM. Panics never
+//! cross the boundary, a ROM the core can't load is reported as a failure instead.
+
+use crate::cartridge::Cartridge;
+use crate::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::error::SavestateError;
+use crate::hardware::{Button, GameboyHardware};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+/// Size of the framebuffer returned by [`gb_framebuffer`] in bytes.
+pub const GB_FRAMEBUFFER_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+pub const GB_BUTTON_A: u8 = 0;
+pub const GB_BUTTON_B: u8 = 1;
+pub const GB_BUTTON_SELECT: u8 = 2;
+pub const GB_BUTTON_START: u8 = 3;
+pub const GB_BUTTON_RIGHT: u8 = 4;
+pub const GB_BUTTON_LEFT: u8 = 5;
+pub const GB_BUTTON_UP: u8 = 6;
+pub const GB_BUTTON_DOWN: u8 = 7;
+
+/// Results of [`gb_load_state`].
+pub const GB_OK: i32 = 0;
+pub const GB_ERROR_NO_ROM: i32 = -1;
+pub const GB_ERROR_INVALID_STATE: i32 = -2;
+pub const GB_ERROR_UNSUPPORTED_VERSION: i32 = -3;
+pub const GB_ERROR_ROM_MISMATCH: i32 = -4;
+pub const GB_ERROR_MODEL_MISMATCH: i32 = -5;
+
+/// An emulator instance, opaque to C.
+pub struct GbInstance {
+    gameboy: Option<GameboyHardware>,
+}
+
+const fn button(button: u8) -> Option<Button> {
+    match button {
+        GB_BUTTON_A => Some(Button::A),
+        GB_BUTTON_B => Some(Button::B),
+        GB_BUTTON_SELECT => Some(Button::Select),
+        GB_BUTTON_START => Some(Button::Start),
+        GB_BUTTON_RIGHT => Some(Button::Right),
+        GB_BUTTON_LEFT => Some(Button::Left),
+        GB_BUTTON_UP => Some(Button::Up),
+        GB_BUTTON_DOWN => Some(Button::Down),
+        _ => None,
+    }
+}
+
+/// Returns the bytes at `data`, treating null as empty.
+///
+/// # Safety
+///
+/// `data` must be null or valid for reads of `len` bytes.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() {
+        &[]
+    } else {
+        // SAFETY: guaranteed by the caller
+        unsafe { slice::from_raw_parts(data, len) }
+    }
+}
+
+/// Creates an instance without a ROM, free it with [`gb_destroy`].
+#[no_mangle]
+pub extern "C" fn gb_create() -> *mut GbInstance {
+    Box::into_raw(Box::new(GbInstance { gameboy: None }))
+}
+
+/// Frees an instance.
+///
+/// # Safety
+///
+/// `instance` must be null or returned by [`gb_create`] and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn gb_destroy(instance: *mut GbInstance) {
+    if !instance.is_null() {
+        // SAFETY: guaranteed by the caller
+        drop(unsafe { Box::from_raw(instance) });
+    }
+}
+
+/// Loads a ROM, replacing the running one. The bytes are copied.
+///
+/// Returns false and keeps the previous ROM if this one can't be loaded.
+///
+/// # Safety
+///
+/// `instance` must be null or a live instance, and `rom` valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gb_load_rom(
+    instance: *mut GbInstance,
+    rom: *const u8,
+    len: usize,
+) -> bool {
+    // SAFETY: guaranteed by the caller
+    let (Some(instance), rom) = (unsafe { instance.as_mut() }, unsafe { bytes(rom, len) }) else {
+        return false;
+    };
+    let rom = rom.to_vec();
+    match panic::catch_unwind(|| GameboyHardware::new(Cartridge::new(rom))) {
+        Ok(gameboy) => {
+            instance.gameboy = Some(gameboy);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Runs until the next frame is completed, returning false if no ROM is loaded
+/// or the emulation panicked, in which case the ROM is unloaded.
+///
+/// # Safety
+///
+/// `instance` must be null or a live instance.
+#[no_mangle]
+pub unsafe extern "C" fn gb_run_frame(instance: *mut GbInstance) -> bool {
+    // SAFETY: guaranteed by the caller
+    let Some(instance) = (unsafe { instance.as_mut() }) else {
+        return false;
+    };
+    let Some(gameboy) = &mut instance.gameboy else {
+        return false;
+    };
+    if panic::catch_unwind(AssertUnwindSafe(|| gameboy.run_frame())).is_err() {
+        // The state may be inconsistent after a panic
+        instance.gameboy = None;
+        return false;
+    }
+    true
+}
+
+/// Returns the last completed frame as [`GB_FRAMEBUFFER_SIZE`] shades (0-3, 0 being white),
+/// 160x144 in row-major order, or null if no ROM is loaded.
+///
+/// The pointer is valid until the next call taking the instance mutably.
+///
+/// # Safety
+///
+/// `instance` must be null or a live instance.
+#[no_mangle]
+pub unsafe extern "C" fn gb_framebuffer(instance: *const GbInstance) -> *const u8 {
+    // SAFETY: guaranteed by the caller
+    unsafe { instance.as_ref() }
+        .and_then(|instance| instance.gameboy.as_ref())
+        .map_or(ptr::null(), |gameboy| gameboy.frame().as_ptr())
+}
+
+/// Returns a counter incremented whenever the framebuffer changes, so frontends only upload
+/// new frames, or 0 if no ROM is loaded.
+///
+/// # Safety
+///
+/// `instance` must be null or a live instance.
+#[no_mangle]
+pub unsafe extern "C" fn gb_frame_generation(instance: *const GbInstance) -> u64 {
+    // SAFETY: guaranteed by the caller
+    unsafe { instance.as_ref() }
+        .and_then(|instance| instance.gameboy.as_ref())
+        .map_or(0, GameboyHardware::frame_generation)
+}
+
+/// Presses or releases one of the `GB_BUTTON_*` buttons. Unknown buttons are ignored.
+///
+/// # Safety
+///
+/// `instance` must be null or a live instance.
+#[no_mangle]
+pub unsafe extern "C" fn gb_set_button(instance: *mut GbInstance, button_id: u8, pressed: bool) {
+    // SAFETY: guaranteed by the caller
+    let gameboy = unsafe { instance.as_mut() }.and_then(|instance| instance.gameboy.as_mut());
+    if let (Some(gameboy), Some(button)) = (gameboy, button(button_id)) {
+        gameboy.set_button(button, pressed);
+    }
+}
+
+/// Writes a savestate into `buffer` if it fits in `capacity` bytes.
+///
+/// Returns the size of the savestate, so passing a null buffer queries the size needed.
+/// Returns 0 if no ROM is loaded.
+///
+/// # Safety
+///
+/// `instance` must be null or a live instance, and `buffer` null or valid for writes
+/// of `capacity` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gb_save_state(
+    instance: *const GbInstance,
+    buffer: *mut u8,
+    capacity: usize,
+) -> usize {
+    // SAFETY: guaranteed by the caller
+    let Some(gameboy) = unsafe { instance.as_ref() }.and_then(|instance| instance.gameboy.as_ref())
+    else {
+        return 0;
+    };
+    let state = gameboy.save_state();
+    if !buffer.is_null() && state.len() <= capacity {
+        // SAFETY: guaranteed by the caller, the state fits
+        unsafe { ptr::copy_nonoverlapping(state.as_ptr(), buffer, state.len()) };
+    }
+    state.len()
+}
+
+/// Restores a savestate made by [`gb_save_state`] with the same ROM.
+///
+/// Returns [`GB_OK`] or one of the `GB_ERROR_*` codes, leaving the state untouched on error.
+///
+/// # Safety
+///
+/// `instance` must be null or a live instance, and `state` valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gb_load_state(
+    instance: *mut GbInstance,
+    state: *const u8,
+    len: usize,
+) -> i32 {
+    // SAFETY: guaranteed by the caller
+    let Some(gameboy) = unsafe { instance.as_mut() }.and_then(|instance| instance.gameboy.as_mut())
+    else {
+        return GB_ERROR_NO_ROM;
+    };
+    // SAFETY: guaranteed by the caller
+    match gameboy.load_state(unsafe { bytes(state, len) }) {
+        Ok(()) => GB_OK,
+        Err(SavestateError::InvalidFormat | SavestateError::Truncated) => GB_ERROR_INVALID_STATE,
+        Err(SavestateError::UnsupportedVersion(_)) => GB_ERROR_UNSUPPORTED_VERSION,
+        Err(SavestateError::RomMismatch { .. }) => GB_ERROR_ROM_MISMATCH,
+        Err(SavestateError::ModelMismatch) => GB_ERROR_MODEL_MISMATCH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::HeaderBuilder;
+    use crate::ffi::{
+        gb_create, gb_destroy, gb_frame_generation, gb_framebuffer, gb_load_rom, gb_load_state,
+        gb_run_frame, gb_save_state, gb_set_button, GB_BUTTON_START, GB_ERROR_INVALID_STATE,
+        GB_ERROR_NO_ROM, GB_OK,
+    };
+    use std::ptr;
+
+    #[test]
+    fn test_instance_lifecycle() {
+        let rom = HeaderBuilder::new().build(&[0x18, 0xFE]);
+        unsafe {
+            let instance = gb_create();
+            assert!(!gb_run_frame(instance));
+            assert!(gb_framebuffer(instance).is_null());
+            assert_eq!(gb_save_state(instance, ptr::null_mut(), 0), 0);
+            assert_eq!(gb_load_state(instance, ptr::null(), 0), GB_ERROR_NO_ROM);
+
+            assert!(gb_load_rom(instance, rom.as_ptr(), rom.len()));
+            let generation = gb_frame_generation(instance);
+            assert!(gb_run_frame(instance));
+            assert!(!gb_framebuffer(instance).is_null());
+            assert_ne!(gb_frame_generation(instance), generation);
+            gb_set_button(instance, GB_BUTTON_START, true);
+            gb_set_button(instance, 42, true);
+
+            let size = gb_save_state(instance, ptr::null_mut(), 0);
+            let mut state = vec![0; size];
+            assert_eq!(gb_save_state(instance, state.as_mut_ptr(), size), size);
+            assert!(gb_run_frame(instance));
+            assert_eq!(gb_load_state(instance, state.as_ptr(), size), GB_OK);
+            assert_eq!(
+                gb_load_state(instance, state.as_ptr(), size - 1),
+                GB_ERROR_INVALID_STATE
+            );
+
+            gb_destroy(instance);
+            gb_destroy(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Copying frames into buffers laid out for uploading as GPU textures.
+//!
+//! A [`Framebuffer`] allocates its buffers once and converts each new frame in place, with
+//! rows padded to the pitch graphics APIs require (e.g. 256 bytes for wgpu buffer to
+//! texture copies), so frontends can upload without reallocating or repacking every frame.
+//!
+//! Colors come from the [`Palette`] set with [`GameboyHardware::set_palette`].
+
+use crate::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::hardware::GameboyHardware;
+
+/// Colors for shades 0-3 as RGBA bytes, white to black.
+pub const GRAYSCALE: [[u8; 4]; 4] = [
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+    [0x00, 0x00, 0x00, 0xFF],
+];
+
+/// Colors for shades 0-3 as RGBA bytes, from the lightest shade to the darkest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 4]; 4]);
+
+impl Palette {
+    pub const GRAYSCALE: Self = Self(GRAYSCALE);
+    /// The green tint of the original Game Boy screen.
+    pub const CLASSIC_GREEN: Self = Self([
+        [0x9B, 0xBC, 0x0F, 0xFF],
+        [0x8B, 0xAC, 0x0F, 0xFF],
+        [0x30, 0x62, 0x30, 0xFF],
+        [0x0F, 0x38, 0x0F, 0xFF],
+    ]);
+    /// The gray screen of the Game Boy Pocket.
+    pub const POCKET_GRAY: Self = Self([
+        [0xC4, 0xCF, 0xA1, 0xFF],
+        [0x8B, 0x95, 0x6D, 0xFF],
+        [0x4D, 0x53, 0x3C, 0xFF],
+        [0x1F, 0x1F, 0x1F, 0xFF],
+    ]);
+
+    /// Returns the RGBA color of `shade` (0-3).
+    #[must_use]
+    pub const fn rgba(self, shade: u8) -> [u8; 4] {
+        self.0[shade as usize & 3]
+    }
+
+    /// Returns the color of `shade` (0-3) as RGB565, red in the high bits.
+    #[must_use]
+    pub const fn rgb565(self, shade: u8) -> u16 {
+        let [red, green, blue, _] = self.rgba(shade);
+        (red as u16 >> 3) << 11 | (green as u16 >> 2) << 5 | blue as u16 >> 3
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::GRAYSCALE
+    }
+}
+
+/// Format of the pixels in a [`Framebuffer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// One byte per pixel, the raw 2-bit shade (0-3) as returned by
+    /// [`GameboyHardware::frame`], for frontends applying colors in a shader.
+    Shades,
+    /// Four bytes per pixel, RGBA in the palette's colors.
+    Rgba8888,
+    /// Two bytes per pixel, little-endian RGB565 in the palette's colors.
+    Rgb565,
+}
+
+impl PixelFormat {
+    const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Shades => 1,
+            Self::Rgb565 => 2,
+            Self::Rgba8888 => 4,
+        }
+    }
+}
+
+/// Layout of the buffers of a [`Framebuffer`], by default tightly packed RGBA with a single
+/// buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferLayout {
+    format: PixelFormat,
+    row_alignment: usize,
+    double_buffered: bool,
+}
+
+impl Default for FramebufferLayout {
+    fn default() -> Self {
+        Self {
+            format: PixelFormat::Rgba8888,
+            row_alignment: 1,
+            double_buffered: false,
+        }
+    }
+}
+
+impl FramebufferLayout {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn format(mut self, format: PixelFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Pads each row to a multiple of `alignment` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alignment` is not a power of two.
+    #[must_use]
+    pub const fn row_alignment(mut self, alignment: usize) -> Self {
+        assert!(
+            alignment.is_power_of_two(),
+            "Row alignment must be a power of two."
+        );
+        self.row_alignment = alignment;
+        self
+    }
+
+    /// Alternates between two buffers, so the buffer of the previous frame isn't written
+    /// while it may still be uploading.
+    #[must_use]
+    pub const fn double_buffered(mut self, enable: bool) -> Self {
+        self.double_buffered = enable;
+        self
+    }
+}
+
+/// Persistent buffers holding the last frame of a [`GameboyHardware`] in a
+/// [`FramebufferLayout`].
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    format: PixelFormat,
+    pitch: usize,
+    buffers: Vec<Box<[u8]>>,
+    // Index of the buffer holding the last frame copied
+    front: usize,
+    // Frame generation copied last, `None` before the first copy
+    generation: Option<u64>,
+    // Palette of the last copy
+    palette: Palette,
+}
+
+impl Framebuffer {
+    #[must_use]
+    pub fn new(layout: FramebufferLayout) -> Self {
+        let pitch =
+            (SCREEN_WIDTH * layout.format.bytes_per_pixel()).next_multiple_of(layout.row_alignment);
+        let count = if layout.double_buffered { 2 } else { 1 };
+        Self {
+            format: layout.format,
+            pitch,
+            buffers: vec![vec![0; pitch * SCREEN_HEIGHT].into_boxed_slice(); count],
+            front: 0,
+            generation: None,
+            palette: Palette::default(),
+        }
+    }
+
+    /// Copies the frame of `gameboy` if it or the palette changed since the last update
+    /// (see [`GameboyHardware::frame_generation`]), returning whether it did.
+    ///
+    /// With double buffering, the copy goes to the other buffer and then becomes the front.
+    pub fn update(&mut self, gameboy: &GameboyHardware) -> bool {
+        let generation = gameboy.frame_generation();
+        let palette = gameboy.palette();
+        if self.generation == Some(generation) && self.palette == palette {
+            return false;
+        }
+        self.generation = Some(generation);
+        self.palette = palette;
+        self.front = (self.front + 1) % self.buffers.len();
+
+        let format = self.format;
+        let buffer = &mut self.buffers[self.front];
+        let rows = gameboy.frame().chunks_exact(SCREEN_WIDTH);
+        for (row, shades) in buffer.chunks_exact_mut(self.pitch).zip(rows) {
+            match format {
+                PixelFormat::Shades => row[..SCREEN_WIDTH].copy_from_slice(shades),
+                PixelFormat::Rgba8888 => {
+                    for (pixel, shade) in row.chunks_exact_mut(4).zip(shades) {
+                        pixel.copy_from_slice(&palette.rgba(*shade));
+                    }
+                }
+                PixelFormat::Rgb565 => {
+                    for (pixel, shade) in row.chunks_exact_mut(2).zip(shades) {
+                        pixel.copy_from_slice(&palette.rgb565(*shade).to_le_bytes());
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the buffer holding the last frame copied, [`Self::pitch`] bytes per row.
+    /// Padding bytes are always zero.
+    #[must_use]
+    pub fn front(&self) -> &[u8] {
+        &self.buffers[self.front]
+    }
+
+    /// Returns the number of bytes per row, including padding.
+    #[must_use]
+    pub const fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// Returns the frame generation of the front buffer, `None` before the first update.
+    #[must_use]
+    pub const fn generation(&self) -> Option<u64> {
+        self.generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::consts::SCREEN_HEIGHT;
+    use crate::framebuffer::{Framebuffer, FramebufferLayout, Palette, PixelFormat, GRAYSCALE};
+    use crate::hardware::GameboyHardware;
+
+    fn gameboy() -> GameboyHardware {
+        // JR -2
+        let rom = HeaderBuilder::new().build(&[0x18, 0xFE]);
+        GameboyHardware::new(Cartridge::new(rom))
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut gameboy = gameboy();
+        let layout = FramebufferLayout::new().row_alignment(256);
+        let mut framebuffer = Framebuffer::new(layout);
+        assert_eq!(framebuffer.pitch(), 768);
+        assert_eq!(framebuffer.front().len(), 768 * SCREEN_HEIGHT);
+
+        gameboy.run_frame();
+        assert!(framebuffer.update(&gameboy));
+        assert_eq!(framebuffer.generation(), Some(gameboy.frame_generation()));
+        let row = &framebuffer.front()[768..768 * 2];
+        assert_eq!(row[..4], GRAYSCALE[gameboy.frame()[160] as usize]);
+        assert!(row[640..].iter().all(|&byte| byte == 0));
+
+        let shades = FramebufferLayout::new().format(PixelFormat::Shades);
+        let mut framebuffer = Framebuffer::new(shades);
+        assert_eq!(framebuffer.pitch(), 160);
+        framebuffer.update(&gameboy);
+        assert_eq!(framebuffer.front(), gameboy.frame());
+    }
+
+    #[test]
+    fn test_palette_and_rgb565() {
+        let mut gameboy = gameboy();
+        gameboy.run_frame();
+        let layout = FramebufferLayout::new().format(PixelFormat::Rgb565);
+        let mut framebuffer = Framebuffer::new(layout);
+        assert_eq!(framebuffer.pitch(), 320);
+        framebuffer.update(&gameboy);
+        let shade = gameboy.frame()[0];
+        assert_eq!(
+            framebuffer.front()[..2],
+            Palette::GRAYSCALE.rgb565(shade).to_le_bytes()
+        );
+        assert_eq!(Palette::GRAYSCALE.rgb565(0), 0xFFFF);
+        assert_eq!(Palette::CLASSIC_GREEN.rgb565(0), 0x9DE1);
+
+        // Changing the palette redraws the same frame
+        assert!(!framebuffer.update(&gameboy));
+        gameboy.set_palette(Palette::CLASSIC_GREEN);
+        assert!(framebuffer.update(&gameboy));
+        assert_eq!(
+            framebuffer.front()[..2],
+            Palette::CLASSIC_GREEN.rgb565(shade).to_le_bytes()
+        );
+        assert!(!framebuffer.update(&gameboy));
+    }
+
+    #[test]
+    fn test_double_buffering() {
+        let mut gameboy = gameboy();
+        let layout = FramebufferLayout::new().double_buffered(true);
+        let mut framebuffer = Framebuffer::new(layout);
+        assert!(framebuffer.update(&gameboy));
+        let first = framebuffer.front().as_ptr();
+        // Nothing new to copy
+        assert!(!framebuffer.update(&gameboy));
+        assert_eq!(framebuffer.front().as_ptr(), first);
+
+        gameboy.run_frame();
+        assert!(framebuffer.update(&gameboy));
+        assert_ne!(framebuffer.front().as_ptr(), first);
+        gameboy.run_frame();
+        assert!(framebuffer.update(&gameboy));
+        assert_eq!(framebuffer.front().as_ptr(), first);
+    }
+}
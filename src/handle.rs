@@ -0,0 +1,143 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::consts::FRAMES_PER_SECOND;
+
+/// Slowest speed multiplier, lower ones are raised to it so a frame's duration stays
+/// representable.
+pub const MIN_SPEED: f32 = 0.01;
+
+#[derive(Debug)]
+struct Controls {
+    // f32 bits of the speed multiplier
+    speed: AtomicU32,
+    paused: AtomicBool,
+    step_requested: AtomicBool,
+}
+
+/// Emulation speed, see [`GameboyHardware::set_speed`](crate::hardware::GameboyHardware::set_speed).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    /// As fast as the host can run frames.
+    Unlocked,
+    /// Real time scaled by a positive factor, 1.0 being real time.
+    Multiplier(f32),
+}
+
+/// Controls emulation speed and pausing from other threads.
+///
+/// The handle is cheap to clone and never blocks. Changes take effect at the next
+/// frame boundary, see [`GameboyHardware::run_frame`](crate::hardware::GameboyHardware::run_frame).
+#[derive(Debug, Clone)]
+#[allow(clippy::module_name_repetitions)]
+pub struct EmulatorHandle {
+    controls: Arc<Controls>,
+}
+
+impl EmulatorHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            controls: Arc::new(Controls {
+                speed: AtomicU32::new(1.0_f32.to_bits()),
+                paused: AtomicBool::new(false),
+                step_requested: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Returns the speed multiplier, 1.0 being real time and infinity when unlocked.
+    #[must_use]
+    pub fn speed(&self) -> f32 {
+        f32::from_bits(self.controls.speed.load(Ordering::Relaxed))
+    }
+
+    /// Sets the speed multiplier, 1.0 being real time, raised to [`MIN_SPEED`] if lower.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` isn't a positive finite number.
+    pub fn set_speed(&self, speed: f32) {
+        assert!(
+            speed.is_finite() && speed > 0.0,
+            "speed must be positive, got {speed}"
+        );
+        self.controls
+            .speed
+            .store(speed.max(MIN_SPEED).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Runs frames as fast as the host can, until the next [`Self::set_speed`].
+    pub fn unlock_speed(&self) {
+        self.controls
+            .speed
+            .store(f32::INFINITY.to_bits(), Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_speed_unlocked(&self) -> bool {
+        self.speed().is_infinite()
+    }
+
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.controls.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.controls.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Toggles pausing, returning whether emulation is now paused.
+    pub fn toggle_paused(&self) -> bool {
+        !self.controls.paused.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Runs a single frame while paused.
+    pub fn request_frame_step(&self) {
+        self.controls.step_requested.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns how long a frame should take in real time at the current speed, zero when
+    /// unlocked.
+    #[must_use]
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / (FRAMES_PER_SECOND * f64::from(self.speed())))
+    }
+
+    /// Returns whether the next frame should run, consuming a pending frame step.
+    pub(crate) fn should_run_frame(&self) -> bool {
+        let step_requested = self.controls.step_requested.swap(false, Ordering::Relaxed);
+        !self.is_paused() || step_requested
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handle::{EmulatorHandle, MIN_SPEED};
+
+    #[test]
+    fn test_frame_step_while_paused() {
+        let handle = EmulatorHandle::new();
+        assert!(handle.should_run_frame());
+        assert!(handle.toggle_paused());
+        assert!(!handle.should_run_frame());
+        handle.clone().request_frame_step();
+        assert!(handle.should_run_frame());
+        assert!(!handle.should_run_frame());
+    }
+
+    #[test]
+    fn test_speed_scales_frame_duration() {
+        let handle = EmulatorHandle::new();
+        let normal = handle.frame_duration();
+        handle.set_speed(2.0);
+        assert_eq!(handle.speed(), 2.0);
+        assert!(handle.frame_duration() < normal);
+
+        // Tiny multipliers would make frames last longer than a `Duration` can hold
+        handle.set_speed(f32::MIN_POSITIVE / 2.0);
+        assert_eq!(handle.speed(), MIN_SPEED);
+        assert!(handle.frame_duration() > normal * 99);
+    }
+}
@@ -1,23 +1,1471 @@
 use crate::apu::Apu;
-use crate::cartridge::Cartridge;
-use crate::cpu::Cpu;
+use crate::audio::{AudioChannel, AudioSample, AudioSink};
+use crate::bus_log::{Access, BusLogger, Component};
+use crate::capabilities::Capabilities;
+use crate::cartridge::{Cartridge, MbcWrite};
+use crate::cheat::{Cheat, Cheats};
+use crate::consts::{cycles_to_duration, FRAME_CYCLES, SCREEN_HEIGHT};
+use crate::coverage::InstructionCoverage;
+pub use crate::cpu::{BusInterface, Cpu, CpuRegisters};
+use crate::crash::{Crash, CrashDetector};
+use crate::dma::OamDma;
+use crate::error::{EmulatorError, SavestateError};
+use crate::fault::{Fault, Subsystem};
+use crate::framebuffer::Palette;
+use crate::handle::{EmulatorHandle, Speed};
 use crate::interrupts::InterruptFlags;
+use crate::journal::{Journal, JournalEntry, JournalEvent};
+pub use crate::joypad::Button;
 use crate::joypad::Joypad;
-use crate::ppu::Ppu;
+use crate::link::SerialLink;
+use crate::mmio::{BusDevice, BusDevices};
+use crate::movie::Input;
+use crate::notification::{Notification, NotificationSink};
+use crate::overlay::{InputDisplay, ScanlineMetrics, VramWriteStats};
+use crate::persistence::{write_payload, Payload, PersistenceCodec};
+use crate::ppu::{OamCorruption, Ppu};
+use crate::rewind::Rewind;
+use crate::rng::{RandomSource, SplitMix64};
+use crate::savestate::{StateDiff, StateReader, StateWriter, SAVESTATE_MAGIC, SAVESTATE_VERSION};
 use crate::serial_port::SerialPort;
+use crate::sgb::Sgb;
 use crate::timer::Timer;
+use crate::trace::TracedInstruction;
+use crate::util::{fnv1a_64, fnv1a_64_iter};
+use crate::watch::{CodeAddress, WatchHit, Watchpoint, Watchpoints, WriteWatches, Writer};
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// The hardware model being emulated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// The original Game Boy.
+    #[default]
+    Dmg,
+    /// The Game Boy Color.
+    Cgb,
+}
+
+/// Frames skipped after each frame drawn while the speed is unlocked with fast-forward
+/// skipping, see [`GameboyHardware::set_fast_forward_skip`].
+pub const UNLOCKED_FRAME_SKIP: u8 = 9;
+
+/// How [`GameboyHardware`] handles accesses a real console ignores, see [`EmulatorError`].
+///
+/// Both raise a diagnostic, see [`GameboyHardware::set_notification_sink`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// Stops emulation after the instruction making the first one, until it is taken with
+    /// [`GameboyHardware::take_error`].
+    Strict,
+    /// Ignores them like a real console: reads return 0xFF and writes do nothing.
+    #[default]
+    Permissive,
+}
+
+/// Contents of RAM after a power cycle.
+///
+/// Applies to WRAM, HRAM, VRAM, OAM and cartridge RAM without a battery. Real hardware
+/// powers on with mostly random RAM, which some games use as a source of randomness.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RamInit {
+    /// All zeros, the same as a new instance.
+    #[default]
+    Zero,
+    /// Every byte set to a value.
+    Fill(u8),
+    /// Pseudo-random bytes from the random source, see
+    /// [`GameboyHardware::set_random_source`].
+    Random,
+}
+
+impl RamInit {
+    fn fill(self, regions: [&mut [u8]; 5], random: &mut dyn RandomSource) {
+        for byte in regions.into_iter().flatten() {
+            *byte = match self {
+                Self::Zero => 0,
+                Self::Fill(value) => value,
+                Self::Random => random.next_u8(),
+            };
+        }
+    }
+}
+
+/// Set of screen lines, one bit per visible scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyLines([u64; 3]);
+
+impl DirtyLines {
+    pub(crate) const fn none() -> Self {
+        Self([0; 3])
+    }
+
+    pub(crate) const fn all() -> Self {
+        // Bits past the last line stay clear
+        Self([u64::MAX, u64::MAX, (1 << (SCREEN_HEIGHT - 128)) - 1])
+    }
+
+    pub(crate) fn insert(&mut self, line: usize) {
+        self.0[line / 64] |= 1 << (line % 64);
+    }
+
+    /// Returns whether `line` (0-143) changed.
+    #[must_use]
+    pub const fn contains(&self, line: usize) -> bool {
+        line < SCREEN_HEIGHT && self.0[line / 64] & (1 << (line % 64)) != 0
+    }
+
+    /// Returns whether no line changed.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0[0] == 0 && self.0[1] == 0 && self.0[2] == 0
+    }
+
+    /// Returns the changed lines in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..SCREEN_HEIGHT).filter(|line| self.contains(*line))
+    }
+
+    /// Returns the underlying bitmap, line `n` being bit `n % 64` of word `n / 64`.
+    #[must_use]
+    pub const fn bits(&self) -> [u64; 3] {
+        self.0
+    }
+}
+
+/// A write to the address space made by the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub addr: u16,
+    pub value: u8,
+}
+
+/// Output of one frame, see [`GameboyHardware::run_frame_output`].
+#[derive(Debug, Clone, Copy)]
+pub struct FrameOutput<'a> {
+    /// Shades of the frame, see [`GameboyHardware::frame`].
+    pub framebuffer: &'a [u8],
+    /// Samples generated during the frame, the same ones the audio sink received. Empty
+    /// without a sink, see [`GameboyHardware::set_audio_sink`].
+    pub audio_samples: &'a [AudioSample],
+    /// Bytes sent by serial transfers completed during the frame.
+    pub serial_bytes: &'a [u8],
+}
 
 const WORK_RAM_SIZE: usize = 8 * 1024;
-const WAVE_PATTERN_RAM_SIZE: usize = 0xFF3F - 0xFF30 + 1;
 const HIGH_RAM_SIZE: usize = 0xFFFE - 0xFF80 + 1;
 
-#[allow(clippy::module_name_repetitions)]
-pub struct GameboyHardware {
-    cpu: Cpu,
+#[allow(clippy::module_name_repetitions)]
+pub struct GameboyHardware {
+    cpu: Cpu,
+    bus: AddressBus,
+    handle: Option<EmulatorHandle>,
+    hash_regions: Vec<RangeInclusive<u16>>,
+    region_hash: Option<u64>,
+    crash_detector: Option<Box<CrashDetector>>,
+    // Buttons held at any point since the last frame completed
+    frame_input: u8,
+    input_display: InputDisplay,
+    // Behind a lock so saving a state can be recorded through `&self`
+    journal: Option<Mutex<Journal>>,
+    rewind: Option<Box<Rewind>>,
+    palette: Palette,
+    // Length of a savestate, which only depends on the ROM and model, once one was made
+    state_len: OnceLock<usize>,
+}
+
+/// Host-side state of a [`GameboyHardware`] that isn't part of any component: the
+/// [`EmulatorHandle`], hashed regions, crash detection, the input display, the journal,
+/// the rewind history and the palette.
+#[derive(Default)]
+pub struct HostState {
+    handle: Option<EmulatorHandle>,
+    hash_regions: Vec<RangeInclusive<u16>>,
+    crash_detector: Option<Box<CrashDetector>>,
+    frame_input: u8,
+    input_display: InputDisplay,
+    journal: Option<Mutex<Journal>>,
+    rewind: Option<Box<Rewind>>,
+    palette: Palette,
+}
+
+/// A [`GameboyHardware`] taken apart by [`GameboyHardware::into_parts`].
+pub struct HardwareParts {
+    /// The CPU, with its instruction coverage and trace if enabled.
+    pub cpu: Cpu,
+    /// Everything on the other side of the CPU: the cartridge, memory and I/O, with bus
+    /// debugging (write log, bus logger, watches) if enabled.
+    pub bus: AddressBus,
+    pub host: HostState,
+}
+
+// Pure reads only need `&self`, so the hardware can be shared with other threads
+// (e.g. a debugger UI inspecting memory while emulation is paused).
+const _: () = {
+    const fn assert_sync<T: Sync>() {}
+    assert_sync::<GameboyHardware>();
+};
+
+impl GameboyHardware {
+    #[must_use]
+    pub const fn new(cartridge: Cartridge) -> Self {
+        Self::with_model(cartridge, Model::Dmg)
+    }
+
+    #[must_use]
+    pub const fn with_model(cartridge: Cartridge, model: Model) -> Self {
+        Self {
+            cpu: Cpu::new(),
+            bus: AddressBus::new(cartridge, model),
+            handle: None,
+            hash_regions: Vec::new(),
+            region_hash: None,
+            crash_detector: None,
+            frame_input: 0,
+            input_display: InputDisplay::new(Input::empty(), Input::empty()),
+            journal: None,
+            rewind: None,
+            palette: Palette::GRAYSCALE,
+            state_len: OnceLock::new(),
+        }
+    }
+
+    /// Creates a console without a cartridge, for running code loaded with
+    /// [`Self::load_raw`] without building a ROM with a valid header.
+    #[must_use]
+    pub fn new_headless() -> Self {
+        Self::new(Cartridge::empty())
+    }
+
+    /// Writes `bytes` to memory from `load_addr` and jumps there, e.g. to run instructions
+    /// from work RAM. Writes go through the bus like the CPU's, so bytes in the cartridge
+    /// area reach its memory bank controller instead of the ROM.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bytes run past 0xFFFF.
+    pub fn load_raw(&mut self, bytes: &[u8], load_addr: u16) {
+        assert!(
+            usize::from(load_addr) + bytes.len() <= 0x10000,
+            "bytes don't fit in the address space"
+        );
+        for (addr, &byte) in (load_addr..=0xFFFF).zip(bytes) {
+            self.bus.write_byte(addr, byte);
+        }
+        let mut registers = self.cpu.registers();
+        registers.pc = load_addr;
+        self.cpu.set_registers(registers);
+    }
+
+    /// Takes the hardware apart, for embedders that need to inspect or replace components
+    /// (e.g. attaching devices to the cartridge of a running console, or moving a CPU to
+    /// another bus). The PPU is caught up first if it was lagging.
+    #[must_use]
+    pub fn into_parts(mut self) -> HardwareParts {
+        self.bus.sync_ppu();
+        HardwareParts {
+            cpu: self.cpu,
+            bus: self.bus,
+            host: HostState {
+                handle: self.handle,
+                hash_regions: self.hash_regions,
+                crash_detector: self.crash_detector,
+                frame_input: self.frame_input,
+                input_display: self.input_display,
+                journal: self.journal,
+                rewind: self.rewind,
+                palette: self.palette,
+            },
+        }
+    }
+
+    /// Reassembles hardware taken apart by [`Self::into_parts`].
+    ///
+    /// Parts are always at an instruction boundary, so any combination is a working console.
+    /// Parts from the same call resume exactly where they stopped. Mixing parts of different
+    /// consoles is allowed but gives a console in the state of neither: the CPU registers of
+    /// one facing the memory and interrupt flags of the other, as if the chips were swapped.
+    /// Save states, movies and detected crashes from before no longer apply to it, and
+    /// crash detection should be reset with [`Self::set_crash_detection`]. Either way,
+    /// [`Self::region_hash`] is `None` until the end of the next frame.
+    #[must_use]
+    pub fn from_parts(parts: HardwareParts) -> Self {
+        let HardwareParts { cpu, bus, host } = parts;
+        Self {
+            cpu,
+            bus,
+            handle: host.handle,
+            hash_regions: host.hash_regions,
+            region_hash: None,
+            crash_detector: host.crash_detector,
+            frame_input: host.frame_input,
+            input_display: host.input_display,
+            journal: host.journal,
+            rewind: host.rewind,
+            palette: host.palette,
+            state_len: OnceLock::new(),
+        }
+    }
+
+    /// Runs one instruction (or services an interrupt), returning the T-cycles it took.
+    pub fn step(&mut self) -> usize {
+        let cycles = self.step_cycles();
+        self.bus.sync_ppu();
+        cycles
+    }
+
+    fn step_cycles(&mut self) -> usize {
+        let was_halted = self.cpu.is_halted();
+        let was_stopped = self.cpu.is_stopped();
+        if !self.bus.write_watches.is_empty() || !self.bus.watchpoints.is_empty() {
+            let pc = self.cpu.registers().pc;
+            self.bus.instruction = CodeAddress {
+                bank: self.bus.cartridge.bank_at(pc),
+                pc,
+            };
+        }
+        let pc = self.cpu.registers().pc;
+        let cycles = self.cpu.step(&mut self.bus);
+        if was_stopped && self.cpu.is_stopped() {
+            self.bus.tick_stopped(cycles);
+        } else {
+            let cpu_active = !(was_halted && self.cpu.is_halted());
+            self.bus.tick(cycles, cpu_active);
+        }
+        if self.crash_detector.is_some() {
+            self.detect_crash(pc, cycles);
+        }
+        if self.bus.cartridge.has_warnings() {
+            self.bus.raise_cartridge_warnings();
+        }
+        cycles
+    }
+
+    fn detect_crash(&mut self, pc: u16, cycles: usize) {
+        let Some(detector) = self.crash_detector.as_deref_mut() else {
+            return;
+        };
+        if detector.crash().is_some() {
+            return;
+        }
+        if self.cpu.is_locked() {
+            let pc = self.cpu.registers().pc;
+            let opcode = self.bus.peek_byte(pc);
+            detector.report(Crash::IllegalOpcode { pc, opcode });
+        } else if (0xFF00..=0xFF7F).contains(&pc) {
+            detector.report(Crash::OpenBusExecution { pc });
+        } else if detector.record_step(pc, cycles) {
+            // A halted CPU wakes up for any pending interrupt, even with IME off
+            let can_exit = std::mem::take(&mut self.bus.io_read)
+                || self.cpu.ime()
+                || self.cpu.is_stopped()
+                || (self.cpu.is_halted() && self.bus.get_interrupts_pending().bits() != 0);
+            detector.end_frame(can_exit);
+        }
+        if let Some(crash) = detector.crash() {
+            self.bus.raise(|| format!("The game crashed: {crash}."));
+        }
+    }
+
+    /// Enables detecting crashes and soft-locks, see [`Crash`]. A soft-lock is reported after
+    /// `soft_lock_frames` frames stuck in a loop, `None` disables detection.
+    ///
+    /// Detected crashes are raised as a [`Notification::DiagnosticRaised`] and returned by
+    /// [`Self::crash`].
+    pub fn set_crash_detection(&mut self, soft_lock_frames: Option<u32>) {
+        self.bus.io_read = false;
+        self.crash_detector = soft_lock_frames.map(|frames| Box::new(CrashDetector::new(frames)));
+    }
+
+    /// Sets how accesses a real console ignores are handled, [`Strictness::Permissive`] by
+    /// default. Kept across resets and not part of savestates.
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.bus.strictness = strictness;
+    }
+
+    #[must_use]
+    pub const fn strictness(&self) -> Strictness {
+        self.bus.strictness
+    }
+
+    /// Returns the error that stopped emulation with [`Strictness::Strict`], letting it run
+    /// again. Resets also clear it.
+    pub fn take_error(&mut self) -> Option<EmulatorError> {
+        self.bus.error.take()
+    }
+
+    /// Returns the first crash detected since detection was enabled or the last reset.
+    #[must_use]
+    pub fn crash(&self) -> Option<Crash> {
+        self.crash_detector
+            .as_ref()
+            .and_then(|detector| detector.crash())
+    }
+
+    /// Runs until the next frame is completed, returning false without running if paused
+    /// through an [`EmulatorHandle`]. Also returns false if stopped by an error with
+    /// [`Strictness::Strict`], see [`Self::take_error`].
+    ///
+    /// While the LCD is off or STOP has stopped the clock, a frame's worth of cycles counts
+    /// as a frame.
+    pub fn run_frame(&mut self) -> bool {
+        if self
+            .handle
+            .as_ref()
+            .is_some_and(|handle| !handle.should_run_frame())
+        {
+            return false;
+        }
+        self.bus.frame_audio.clear();
+        self.bus.frame_serial.clear();
+        self.bus.recording_frame = true;
+        loop {
+            if self.bus.error.is_some() {
+                return false;
+            }
+            self.bus.frame_cycles += self.step_cycles();
+            if self.is_frame_complete() {
+                self.end_frame();
+                return true;
+            }
+        }
+    }
+
+    /// Runs for at least `cycles` T-cycles, returning how many ran, or 0 without running if
+    /// paused through an [`EmulatorHandle`].
+    ///
+    /// Instructions run to completion, so this can run a few cycles more than asked, to be
+    /// taken off the next call when pacing. Frames completed along the way end like in
+    /// [`Self::run_frame`]. Stops early if stopped by an error with [`Strictness::Strict`].
+    pub fn run_cycles(&mut self, cycles: u64) -> u64 {
+        if self.handle.as_ref().is_some_and(EmulatorHandle::is_paused) {
+            return 0;
+        }
+        let start = self.bus.cycles;
+        while self.bus.cycles - start < cycles && self.bus.error.is_none() {
+            self.bus.frame_cycles += self.step_cycles();
+            if self.is_frame_complete() {
+                self.end_frame();
+            }
+        }
+        self.bus.sync_ppu();
+        self.bus.cycles - start
+    }
+
+    /// Returns whether the PPU completed a frame, or a frame's worth of cycles ran while the
+    /// LCD is off or STOP has stopped the clock.
+    fn is_frame_complete(&mut self) -> bool {
+        self.bus.ppu.take_frame_ready()
+            || ((!self.bus.ppu.is_enabled() || self.cpu.is_stopped())
+                && self.bus.frame_cycles >= FRAME_CYCLES as usize)
+    }
+
+    fn end_frame(&mut self) {
+        self.bus.frame_cycles = 0;
+        self.bus.sync_ppu();
+        self.bus.apply_cheats();
+        self.bus.ppu.end_vram_write_frame();
+        self.bus.cartridge.end_frame();
+        let held = Input::from_bits(self.frame_input);
+        self.input_display = InputDisplay::new(self.input_display.held, held);
+        self.frame_input = self.bus.joypad.pressed_bits();
+        if !self.hash_regions.is_empty() {
+            self.region_hash = Some(self.hash_memory(&self.hash_regions));
+        }
+        if let Some(mut rewind) = self.rewind.take() {
+            rewind.end_frame(|| self.serialize_state());
+            self.rewind = Some(rewind);
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.get_mut().unwrap().end_frame();
+        }
+        if self.bus.fast_forward_skip {
+            let frames = fast_forward_frame_skip(self.speed());
+            self.bus.ppu.set_fast_forward_skip(frames);
+        }
+        self.bus.recording_frame = false;
+        self.bus.notify(|| Notification::FrameCompleted);
+    }
+
+    /// Runs one frame like [`Self::run_frame`], returning what it produced, for frontends
+    /// driving the core a frame at a time. Returns `None` without running if paused through
+    /// an [`EmulatorHandle`].
+    ///
+    /// Frames end when the PPU completes one, so with the LCD on they are
+    /// [`FRAME_CYCLES`] apart on average. The instruction running when a frame ends
+    /// finishes first, which can make a frame a few cycles longer than the next.
+    pub fn run_frame_output(&mut self) -> Option<FrameOutput<'_>> {
+        if !self.run_frame() {
+            return None;
+        }
+        Some(FrameOutput {
+            framebuffer: self.bus.ppu.frame(),
+            audio_samples: &self.bus.frame_audio,
+            serial_bytes: &self.bus.frame_serial,
+        })
+    }
+
+    /// Returns the T-cycles run since the instance was created.
+    ///
+    /// The count only measures how long the emulator has run, so it isn't affected by resets
+    /// or save states.
+    #[must_use]
+    pub const fn cycles(&self) -> u64 {
+        self.bus.cycles
+    }
+
+    /// Returns how long the emulated console has run, [`Self::cycles`] at
+    /// [`CPU_HZ`](crate::consts::CPU_HZ).
+    #[must_use]
+    pub const fn emulated_time(&self) -> Duration {
+        cycles_to_duration(self.bus.cycles)
+    }
+
+    /// Enables the DMG bug where writing to STAT during HBlank, VBlank or while LY=LYC
+    /// requests a STAT interrupt, whatever sources are enabled. On by default on the DMG and
+    /// off on the CGB, which doesn't have it. Kept across resets and not part of savestates.
+    pub fn set_stat_write_bug(&mut self, enable: bool) {
+        self.bus.ppu.set_stat_write_bug(enable);
+    }
+
+    #[must_use]
+    pub const fn has_stat_write_bug(&self) -> bool {
+        self.bus.ppu.has_stat_write_bug()
+    }
+
+    /// Enables the DMG OAM bug, where reads, writes and 16-bit increments and decrements of
+    /// addresses in 0xFE00-0xFEFF during OAM scan corrupt the row of OAM being scanned. On
+    /// by default on the DMG and off on the CGB, which doesn't have it. Kept across resets
+    /// and not part of savestates.
+    pub fn set_oam_bug(&mut self, enable: bool) {
+        self.bus.ppu.set_oam_bug(enable);
+    }
+
+    #[must_use]
+    pub const fn has_oam_bug(&self) -> bool {
+        self.bus.ppu.has_oam_bug()
+    }
+
+    /// Runs as in a Super Game Boy, receiving the commands games send through P1 and
+    /// exposing the border and palettes they set, see [`crate::sgb`]. Frontends usually
+    /// enable it for cartridges that [support it](Cartridge::supports_sgb). Off by default.
+    /// Kept across resets and not part of savestates, and turning it off forgets the border
+    /// and palettes.
+    pub fn set_sgb(&mut self, enable: bool) {
+        if enable != self.bus.sgb.is_some() {
+            self.bus.sgb = enable.then(|| Box::new(Sgb::new()));
+        }
+    }
+
+    /// Returns the Super Game Boy state, `None` unless enabled with [`Self::set_sgb`].
+    #[must_use]
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.bus.sgb.as_deref()
+    }
+
+    /// Lets the PPU lag behind the CPU, only catching up when the CPU accesses VRAM, OAM or
+    /// the LCD registers, and before it would request an interrupt or complete a frame.
+    ///
+    /// Results are the same as running it every M-cycle, which stays the default so timing
+    /// tests run the PPU eagerly. State is always caught up after [`Self::step`] and
+    /// [`Self::run_frame`] return.
+    pub fn set_lazy_ppu(&mut self, enable: bool) {
+        self.bus.sync_ppu();
+        self.bus.ppu_lag = enable.then(PpuLag::default);
+    }
+
+    #[must_use]
+    pub const fn is_lazy_ppu(&self) -> bool {
+        self.bus.ppu_lag.is_some()
+    }
+
+    /// Skips drawing `frames` frames after each frame drawn, for hosts too slow to emulate
+    /// at full speed otherwise. 0 draws every frame, the default.
+    ///
+    /// Skipped frames are still emulated in full, only pixels aren't written: timing,
+    /// interrupts, registers and [`Self::scanline_metrics`] are identical whatever the
+    /// setting. [`Self::frame`] keeps the last frame drawn and [`Self::frame_drawn`] tells
+    /// whether it is the frame that just completed. Save states taken after a drawn frame
+    /// are byte-identical to those taken without frame skipping, after a skipped frame
+    /// they differ only in the pixels of the frame in progress.
+    pub fn set_frame_skip(&mut self, frames: u8) {
+        self.bus.sync_ppu();
+        self.bus.ppu.set_frame_skip(frames);
+    }
+
+    #[must_use]
+    pub const fn frame_skip(&self) -> u8 {
+        self.bus.ppu.frame_skip()
+    }
+
+    /// Sets the emulation speed through the [`EmulatorHandle`] pacing frames, see
+    /// [`Self::handle`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a multiplier isn't a positive finite number.
+    pub fn set_speed(&mut self, speed: Speed) {
+        let handle = self.handle();
+        match speed {
+            Speed::Unlocked => handle.unlock_speed(),
+            Speed::Multiplier(multiplier) => handle.set_speed(multiplier),
+        }
+    }
+
+    /// Returns the emulation speed, real time until changed with [`Self::set_speed`] or
+    /// through the handle.
+    #[must_use]
+    pub fn speed(&self) -> Speed {
+        match &self.handle {
+            Some(handle) if handle.is_speed_unlocked() => Speed::Unlocked,
+            Some(handle) => Speed::Multiplier(handle.speed()),
+            None => Speed::Multiplier(1.0),
+        }
+    }
+
+    /// Skips drawing frames while running faster than real time, so fast-forwarding doesn't
+    /// spend time drawing frames that are never shown. Off by default.
+    ///
+    /// At a speed multiplier of `n`, only about one frame in `n` is drawn, and one in
+    /// [`UNLOCKED_FRAME_SKIP`] + 1 when unlocked. Like [`Self::set_frame_skip`], which
+    /// still applies when it skips more, skipped frames keep their timing and interrupts.
+    /// The speed is checked at the end of each frame.
+    pub fn set_fast_forward_skip(&mut self, enable: bool) {
+        self.bus.sync_ppu();
+        self.bus.fast_forward_skip = enable;
+        let frames = if enable {
+            fast_forward_frame_skip(self.speed())
+        } else {
+            0
+        };
+        self.bus.ppu.set_fast_forward_skip(frames);
+    }
+
+    #[must_use]
+    pub const fn is_fast_forward_skip(&self) -> bool {
+        self.bus.fast_forward_skip
+    }
+
+    /// Returns whether the last frame completed was drawn, see [`Self::set_frame_skip`].
+    #[must_use]
+    pub const fn frame_drawn(&self) -> bool {
+        self.bus.ppu.frame_drawn()
+    }
+
+    /// Starts recording resets, power cycles and save states in `journal`, returning the
+    /// journal recorded so far, if any. A [`JournalEvent::RomLoaded`] entry for this
+    /// cartridge is recorded first, so a frontend loading another ROM can move its journal
+    /// over to the new hardware.
+    ///
+    /// Frontends can add their own actions with [`Self::record_event`].
+    pub fn set_journal(&mut self, journal: Option<Journal>) -> Option<Journal> {
+        let previous = std::mem::replace(&mut self.journal, journal.map(Mutex::new));
+        self.record_event(JournalEvent::RomLoaded {
+            title: self
+                .bus
+                .cartridge
+                .get_title()
+                .trim_end_matches('\0')
+                .to_string(),
+            rom_hash: self.bus.cartridge.rom_hash(),
+        });
+        previous.map(|journal| journal.into_inner().unwrap())
+    }
+
+    /// Records `event` in the journal, if one is set with [`Self::set_journal`].
+    pub fn record_event(&self, event: JournalEvent) {
+        if let Some(journal) = &self.journal {
+            journal.lock().unwrap().record(event);
+        }
+    }
+
+    /// Returns the entries recorded in the journal, empty without one.
+    #[must_use]
+    pub fn journal_entries(&self) -> Vec<JournalEntry> {
+        self.journal
+            .as_ref()
+            .map(|journal| journal.lock().unwrap().entries().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Writes the journal as text, see [`Journal::save`]. Does nothing without one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn save_journal(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        match &self.journal {
+            Some(journal) => journal.lock().unwrap().save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a handle for controlling speed and pausing from other threads.
+    pub fn handle(&mut self) -> EmulatorHandle {
+        self.handle.get_or_insert_with(EmulatorHandle::new).clone()
+    }
+
+    /// Presses the reset button: the CPU and I/O registers return to their state after
+    /// the boot ROM, while WRAM, HRAM, VRAM, OAM, wave RAM and cartridge RAM keep their
+    /// contents and the memory bank controller goes back to its first banks.
+    ///
+    /// The DMG has no reset button, this behaves like the reset line on later models and
+    /// flash carts. Buttons held and host-side settings (handle, audio sink, logging,
+    /// watches, coverage, trace) are kept.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.bus.reset();
+        self.region_hash = None;
+        self.reset_crash_detector();
+        self.record_event(JournalEvent::Reset);
+    }
+
+    /// Turns the console off and on: like [`Self::reset`], but RAM is initialized as
+    /// configured with [`Self::set_ram_init`], except battery-backed cartridge RAM which
+    /// keeps its contents. Wave RAM returns to its power on pattern.
+    pub fn power_cycle(&mut self) {
+        self.cpu.reset();
+        self.bus.power_cycle();
+        self.region_hash = None;
+        self.reset_crash_detector();
+        self.record_event(JournalEvent::PowerCycle);
+    }
+
+    fn reset_crash_detector(&mut self) {
+        if let Some(detector) = &mut self.crash_detector {
+            detector.reset();
+        }
+    }
+
+    /// Resets one component to its state after the boot ROM while the others keep running.
+    pub fn reset_subsystem(&mut self, subsystem: Subsystem) {
+        match subsystem {
+            Subsystem::Apu => self.bus.apu.reset(),
+            Subsystem::Ppu => {
+                self.bus.sync_ppu();
+                self.bus.ppu.reset();
+                if let Some(lag) = &mut self.bus.ppu_lag {
+                    lag.event_free_dots = 0;
+                }
+            }
+            Subsystem::Timer => self.bus.timer = Timer::new(),
+            Subsystem::SerialPort => self.bus.serial_port = SerialPort::new(),
+            Subsystem::Mbc => self.bus.cartridge.reset(),
+        }
+        self.record_event(JournalEvent::SubsystemReset(subsystem));
+    }
+
+    /// Corrupts memory as described by `fault`, returning false if it targets no RAM.
+    pub fn inject_fault(&mut self, fault: &Fault) -> bool {
+        self.bus.sync_ppu();
+        match fault {
+            Fault::FlipBits { addr, mask } => self
+                .bus
+                .ram_byte_mut(*addr)
+                .map(|byte| *byte ^= mask)
+                .is_some(),
+            Fault::Scramble { range, seed } => {
+                let mut random = SplitMix64::new(*seed);
+                let mut corrupted = false;
+                for addr in range.clone() {
+                    let value = random.next_u8();
+                    if let Some(byte) = self.bus.ram_byte_mut(addr) {
+                        *byte = value;
+                        corrupted = true;
+                    }
+                }
+                corrupted
+            }
+        }
+    }
+
+    /// Sets how RAM is initialized by [`Self::power_cycle`].
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.bus.ram_init = ram_init;
+    }
+
+    /// Adds a cheat, returning its index for [`Self::set_cheat_enabled`].
+    ///
+    /// Cheats are a host setting, kept across resets.
+    pub fn add_cheat(&mut self, cheat: Cheat) -> usize {
+        let enabled = cheat.enabled;
+        let index = self.bus.cheats.push(cheat);
+        if enabled {
+            self.bus.notify_cheat_applied(index);
+        }
+        index
+    }
+
+    #[must_use]
+    pub fn cheats(&self) -> &[Cheat] {
+        self.bus.cheats.cheats()
+    }
+
+    /// Turns the cheat at `index` on or off, returning false if there is no such cheat.
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        let Some(cheat) = self.bus.cheats.set_enabled(index, enabled) else {
+            return false;
+        };
+        let state = if enabled { "enabled" } else { "disabled" };
+        let note = format!("Cheat {state}: {}", cheat.description);
+        self.record_event(JournalEvent::Note(note));
+        if enabled {
+            self.bus.notify_cheat_applied(index);
+        }
+        true
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.bus.cheats.clear();
+    }
+
+    /// Sets where random values come from, by default a [`SplitMix64`] seeded with 0.
+    ///
+    /// Every random behavior (e.g. [`RamInit::Random`]) draws from this source, so runs
+    /// with the same seed are identical. The source is a host setting: it is kept across
+    /// resets and isn't part of savestates.
+    pub fn set_random_source(&mut self, source: Box<dyn RandomSource>) {
+        self.bus.random = Some(source);
+    }
+
+    /// Replaces the random source with a [`SplitMix64`] seeded with `seed`.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.set_random_source(Box::new(SplitMix64::new(seed)));
+    }
+
+    /// Returns the hash of the cartridge ROM, see [`Cartridge::rom_hash`].
+    #[must_use]
+    pub fn rom_hash(&self) -> u64 {
+        self.bus.cartridge.rom_hash()
+    }
+
+    /// Returns which features and quirks this core emulates.
+    #[must_use]
+    pub const fn capabilities(&self) -> Capabilities {
+        Capabilities::new(self.bus.model)
+    }
+
+    /// Sets where audio output goes, replacing and returning the previous sink.
+    ///
+    /// Samples are produced at the sink's [`AudioSink::sample_rate`]. No audio is
+    /// generated while there's no sink.
+    pub fn set_audio_sink(
+        &mut self,
+        sink: Option<Box<dyn AudioSink>>,
+    ) -> Option<Box<dyn AudioSink>> {
+        self.bus
+            .apu
+            .set_sample_rate(sink.as_ref().map(|sink| sink.sample_rate()));
+        std::mem::replace(&mut self.bus.audio_sink, sink)
+    }
+
+    /// Sets where [`Notification`]s go, replacing and returning the previous sink.
+    ///
+    /// Without a sink notifications are dropped, the core never prints them. Warnings about
+    /// the cartridge found before a sink was set (e.g. guessed header fields) are raised
+    /// right away.
+    pub fn set_notification_sink(
+        &mut self,
+        sink: Option<Box<dyn NotificationSink>>,
+    ) -> Option<Box<dyn NotificationSink>> {
+        let previous = std::mem::replace(&mut self.bus.notification_sink, sink);
+        self.bus.raise_cartridge_warnings();
+        previous
+    }
+
+    /// Enables emulating the high-pass filter on the audio output, off by default.
+    ///
+    /// The DACs output a DC offset that real hardware blocks with a capacitor, which is
+    /// what makes channels click when they are turned on or off. Without the filter, samples
+    /// are the raw DAC levels.
+    pub fn set_high_pass_filter(&mut self, enable: bool) {
+        self.bus.apu.set_high_pass_filter(enable);
+    }
+
+    #[must_use]
+    pub const fn is_high_pass_filter_enabled(&self) -> bool {
+        self.bus.apu.is_high_pass_filter_enabled()
+    }
+
+    /// Enables or mutes a sound channel in the audio output, independently of what the game
+    /// writes to NR51/NR52. Soloing a channel is muting the other three.
+    ///
+    /// A muted channel keeps running and is silent in both the mix and its own entry of
+    /// [`AudioSample::channels`]. All channels are enabled by default, this is a host
+    /// setting kept across resets and not part of savestates.
+    pub fn set_channel_enabled(&mut self, channel: AudioChannel, enable: bool) {
+        self.bus.apu.set_channel_muted(channel, !enable);
+    }
+
+    #[must_use]
+    pub const fn is_channel_enabled(&self, channel: AudioChannel) -> bool {
+        !self.bus.apu.is_channel_muted(channel)
+    }
+
+    /// Presses or releases a button, updating P1.
+    ///
+    /// The joypad interrupt is requested when this pulls an input line of P1 from high to
+    /// low, i.e. on a press of a button in a selected group with no other button on the
+    /// same line held. This also restarts the clock after STOP.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.bus.joypad.set_pressed(button, pressed) {
+            self.bus.interrupt_flag.set(InterruptFlags::JOYPAD, true);
+            self.cpu.wake_from_stop();
+        }
+        self.frame_input |= self.bus.joypad.pressed_bits();
+    }
+
+    /// Holds the buttons in `input` and releases the others, see [`Self::set_button`].
+    pub fn set_input(&mut self, input: Input) {
+        // In the order of the Input bits
+        let buttons = [
+            Button::A,
+            Button::B,
+            Button::Select,
+            Button::Start,
+            Button::Right,
+            Button::Left,
+            Button::Up,
+            Button::Down,
+        ];
+        for (bit, button) in buttons.into_iter().enumerate() {
+            self.set_button(button, input.contains(1 << bit));
+        }
+    }
+
+    /// Returns the buttons to show in an input display for the last frame run by
+    /// [`Self::run_frame`].
+    ///
+    /// A button counts as held if it was held at any point since the frame before completed,
+    /// so presses shorter than a frame still show up.
+    #[must_use]
+    pub const fn input_display(&self) -> InputDisplay {
+        self.input_display
+    }
+
+    #[must_use]
+    pub const fn is_button_pressed(&self, button: Button) -> bool {
+        self.bus.joypad.is_pressed(button)
+    }
+
+    /// Sets the image the Game Boy Camera sensor sees, see [`Cartridge::set_camera_image`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image` is not the size of a camera image.
+    pub fn set_camera_image(&mut self, image: &[u8]) {
+        self.bus.cartridge.set_camera_image(image);
+    }
+
+    /// Returns the shades (0-3, 0 being white) of the last completed frame,
+    /// 160x144 in row-major order.
+    #[must_use]
+    pub const fn frame(&self) -> &[u8] {
+        self.bus.ppu.frame()
+    }
+
+    /// Sets the colors frontends show the shades of [`Self::frame`] in, used by
+    /// [`Framebuffer`]. The palette is a host setting, kept on reset and not saved in
+    /// savestates.
+    ///
+    /// [`Framebuffer`]: crate::framebuffer::Framebuffer
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    #[must_use]
+    pub const fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    /// Returns a counter incremented whenever [`Self::frame`] changes: when a frame is drawn,
+    /// on resets and when loading a savestate. Frontends can compare it to the value at
+    /// their last upload to know whether a new frame is ready, see [`Framebuffer`].
+    ///
+    /// [`Framebuffer`]: crate::framebuffer::Framebuffer
+    #[must_use]
+    pub const fn frame_generation(&self) -> u64 {
+        self.bus.ppu.frame_generation()
+    }
+
+    /// Returns the lines of [`Self::frame`] that changed since the last call, so slow
+    /// frontends can redraw only those rows.
+    ///
+    /// Changes accumulate over frames until taken. Every line is reported after creating
+    /// the instance or loading a savestate.
+    pub fn take_dirty_lines(&mut self) -> DirtyLines {
+        self.bus.ppu.take_dirty_lines()
+    }
+
+    /// Returns a 64-bit hash of the last completed frame.
+    ///
+    /// Intended for cheaply comparing video output, e.g. in regression tests or to detect
+    /// desyncs in netplay. The hash is FNV-1a over the 160x144 shades (0-3, one byte each)
+    /// in row-major order. It only changes between versions if the emulated output changes,
+    /// never because of the hashing itself.
+    #[must_use]
+    pub fn frame_hash(&self) -> u64 {
+        fnv1a_64(self.bus.ppu.frame())
+    }
+
+    /// Adds a memory region to hash at the end of every frame, see [`Self::region_hash`].
+    ///
+    /// Hashing the variables that matter to a game (e.g. RNG state and player positions)
+    /// catches a desync in a movie or netplay session at the frame it happens, often long
+    /// before it shows on screen.
+    pub fn add_hash_region(&mut self, region: RangeInclusive<u16>) {
+        self.hash_regions.push(region);
+        self.region_hash = None;
+    }
+
+    pub fn clear_hash_regions(&mut self) {
+        self.hash_regions.clear();
+        self.region_hash = None;
+    }
+
+    #[must_use]
+    pub fn hash_regions(&self) -> &[RangeInclusive<u16>] {
+        &self.hash_regions
+    }
+
+    /// Returns the hash of the regions added with [`Self::add_hash_region`] as they were at
+    /// the end of the last completed frame, or `None` if no frame completed since adding them.
+    ///
+    /// Like [`Self::frame_hash`], this is FNV-1a over the bytes of each region in the order
+    /// they were added, read without side effects.
+    #[must_use]
+    pub const fn region_hash(&self) -> Option<u64> {
+        self.region_hash
+    }
+
+    /// Hashes memory regions as they are now, see [`Self::region_hash`].
+    #[must_use]
+    pub fn hash_memory(&self, regions: &[RangeInclusive<u16>]) -> u64 {
+        fnv1a_64_iter(
+            regions
+                .iter()
+                .flat_map(|region| region.clone().map(|addr| self.bus.peek_byte(addr))),
+        )
+    }
+
+    /// Returns whether the STAT interrupt line is high, i.e. any enabled STAT source is active.
+    ///
+    /// A new STAT interrupt is only requested when the line goes from low to high.
+    #[must_use]
+    pub const fn stat_line(&self) -> bool {
+        self.bus.ppu.stat_line()
+    }
+
+    /// Returns timing metrics for each visible scanline of the last completed frame,
+    /// for frontends drawing a debug overlay.
+    #[must_use]
+    pub const fn scanline_metrics(&self) -> &[ScanlineMetrics] {
+        self.bus.ppu.scanline_metrics()
+    }
+
+    /// Returns the VRAM writes made during the last completed frame, since the frame before
+    /// it completed (so including the VBlank before its first line).
+    #[must_use]
+    pub const fn vram_writes(&self) -> &VramWriteStats {
+        self.bus.ppu.completed_vram_writes()
+    }
+
+    /// Starts counting executed instructions, see [`InstructionCoverage`].
+    pub fn enable_instruction_coverage(&mut self) {
+        self.cpu.enable_coverage();
+    }
+
+    /// Returns the instruction coverage if it was enabled.
+    #[must_use]
+    pub fn instruction_coverage(&self) -> Option<&InstructionCoverage> {
+        self.cpu.coverage()
+    }
+
+    /// Drives one serial clock pulse from an external device (e.g. a link cable partner
+    /// acting as master or a printer).
+    ///
+    /// `in_bit` is shifted into the serial port and the bit shifted out is returned.
+    /// Only has an effect while a transfer using the external clock is in progress.
+    pub fn serial_external_clock(&mut self, in_bit: bool) -> bool {
+        self.bus.external_clock_pulse(in_bit)
+    }
+
+    /// Connects the serial port to another device through `link`, e.g. one end of a
+    /// [`link_cable`](crate::link::link_cable), replacing and returning the previous link.
+    ///
+    /// While nothing is connected, transfers using the internal clock read 1 bits. The link
+    /// is a host setting, kept across resets and not part of savestates.
+    pub fn set_serial_link(
+        &mut self,
+        link: Option<Box<dyn SerialLink>>,
+    ) -> Option<Box<dyn SerialLink>> {
+        let mut link = std::mem::replace(&mut self.bus.serial_link, link);
+        // Passed on to the new link on the next M-cycle
+        self.bus.link_line = None;
+        if let Some(previous) = &mut link {
+            // The line floats high once disconnected
+            previous.set_line(true);
+        }
+        link
+    }
+
+    /// Maps a device into the address space on top of everything else, see [`BusDevice`].
+    /// Devices attached later take precedence when ranges overlap. Devices are kept across
+    /// resets and aren't part of savestates.
+    ///
+    /// For the cartridge address space, [`Cartridge::attach_device`] keeps the device with
+    /// the cartridge.
+    pub fn attach_bus_device(&mut self, range: RangeInclusive<u16>, device: Box<dyn BusDevice>) {
+        self.bus.bus_devices.attach(range, device);
+    }
+
+    /// Removes every device attached with [`Self::attach_bus_device`].
+    pub fn clear_bus_devices(&mut self) {
+        self.bus.bus_devices.clear();
+    }
+
+    /// Returns the bytes sent over the serial port since the last call, e.g. the results
+    /// blargg's test ROMs print.
+    ///
+    /// Every completed transfer counts, linked or not and whichever end drives the clock.
+    /// Bytes are kept until taken, at most about a kilobyte per emulated second, across
+    /// resets and save states.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bus.serial_output)
+    }
+
+    /// Returns a snapshot of the CPU registers.
+    #[must_use]
+    pub fn registers(&self) -> CpuRegisters {
+        self.cpu.registers()
+    }
+
+    /// Returns IE, the interrupts enabled, in the layout of [`Self::interrupt_flags`].
+    #[must_use]
+    pub const fn interrupt_enable(&self) -> u8 {
+        self.bus.interrupt_enable.bits()
+    }
+
+    /// Returns IF, the interrupts requested: VBlank in bit 0, then STAT, timer, serial and
+    /// joypad.
+    #[must_use]
+    pub const fn interrupt_flags(&self) -> u8 {
+        self.bus.interrupt_flag.bits()
+    }
+
+    /// Returns the interrupt master enable, set by EI one instruction late and by RETI.
+    #[must_use]
+    pub const fn ime(&self) -> bool {
+        self.cpu.ime()
+    }
+
+    /// Returns whether the CPU is in HALT, waiting for an interrupt both requested and
+    /// enabled, see [`Self::interrupt_enable`] and [`Self::interrupt_flags`]. It wakes up
+    /// even when IME is off.
+    #[must_use]
+    pub const fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    /// Returns whether the CPU is in STOP, waiting for a button press.
+    #[must_use]
+    pub const fn is_stopped(&self) -> bool {
+        self.cpu.is_stopped()
+    }
+
+    /// Returns whether the CPU locked up running an undefined opcode, until reset.
+    #[must_use]
+    pub const fn is_locked(&self) -> bool {
+        self.cpu.is_locked()
+    }
+
+    /// Keeps the last `capacity` instructions executed, to be read with [`Self::trace_tail`]
+    /// after a crash. `None` stops recording and forgets them.
+    ///
+    /// Recording is cheap enough to leave on: each instruction is packed in 8 bytes.
+    pub fn set_trace_buffer(&mut self, capacity: Option<usize>) {
+        self.cpu.set_trace_capacity(capacity);
+    }
+
+    /// Returns the last `n` instructions executed, oldest first, or nothing if the trace
+    /// buffer isn't enabled.
+    ///
+    /// Instructions that were interrupted before running aren't included, and the trace
+    /// survives resets.
+    #[must_use]
+    pub fn trace_tail(&self, n: usize) -> Vec<TracedInstruction> {
+        self.cpu.trace_tail(n)
+    }
+
+    /// Starts or stops recording CPU writes to the address space.
+    pub fn set_write_logging(&mut self, enable: bool) {
+        self.bus.write_log = enable.then(Vec::new);
+    }
+
+    /// Returns the writes recorded since the last call, if write logging is enabled.
+    pub fn take_writes(&mut self) -> Vec<MemoryWrite> {
+        self.bus
+            .write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Starts logging bus transactions to `logger`, or stops with `None`, returning the
+    /// previous logger so it can be finished.
+    ///
+    /// Transactions are checked against the logger's filter as they happen, nothing is
+    /// recorded while no logger is set.
+    pub fn set_bus_logger(&mut self, logger: Option<BusLogger>) -> Option<BusLogger> {
+        std::mem::replace(&mut self.bus.bus_logger, logger.map(Box::new)).map(|logger| *logger)
+    }
+
+    /// Starts or stops recording writes to the memory bank controller registers.
+    ///
+    /// Independently of this, frames with thousands of bank switches are reported as warnings.
+    pub fn set_mbc_write_logging(&mut self, enable: bool) {
+        self.bus.cartridge.set_mbc_write_logging(enable);
+    }
+
+    /// Returns the MBC register writes recorded since the last call, if logging is enabled.
+    pub fn take_mbc_writes(&mut self) -> Vec<MbcWrite> {
+        self.bus.cartridge.take_mbc_writes()
+    }
+
+    /// Starts recording every instruction that writes to `addr`, see [`Self::writers`].
+    ///
+    /// Writers already recorded for the address are kept.
+    pub fn watch_writers(&mut self, addr: u16) {
+        self.bus.write_watches.watch(addr);
+    }
+
+    /// Stops recording writers of `addr` and forgets the ones recorded.
+    pub fn unwatch_writers(&mut self, addr: u16) {
+        self.bus.write_watches.unwatch(addr);
+    }
+
+    /// Returns the instructions that wrote to `addr` since it was watched, with how often
+    /// and the last value written, or `None` if the address isn't watched.
+    ///
+    /// Writes made while dispatching an interrupt are attributed to the interrupted instruction.
+    #[must_use]
+    pub fn writers(&self, addr: u16) -> Option<Vec<Writer>> {
+        self.bus.write_watches.writers(addr)
+    }
+
+    /// Starts queueing CPU accesses to the watchpoint's addresses, see
+    /// [`Self::take_watch_hits`]. Instruction fetches and interrupt dispatch are accesses
+    /// too. Adding a watchpoint twice has no effect.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.bus.watchpoints.add(watchpoint);
+    }
+
+    /// Removes a watchpoint, returning false if it wasn't added. Hits already queued are kept.
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        self.bus.watchpoints.remove(watchpoint)
+    }
+
+    #[must_use]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        self.bus.watchpoints.watchpoints()
+    }
+
+    /// Returns the accesses that hit a watchpoint since the last call, in order, each with
+    /// the instruction making it.
+    ///
+    /// Hits are queued during emulation, so none are missed between calls. Taking them
+    /// after every [`Self::step`] stops right after the instruction that triggered them.
+    /// Accesses made while dispatching an interrupt are attributed to the interrupted
+    /// instruction.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.bus.watchpoints.take_hits()
+    }
+
+    /// Serializes the emulation state, see [`crate::savestate`].
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        self.record_event(JournalEvent::StateSaved);
+        self.serialize_state()
+    }
+
+    fn serialize_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        self.write_state(&mut writer);
+        let state = writer.into_bytes();
+        let _ = self.state_len.set(state.len());
+        state
+    }
+
+    /// Returns the length of a savestate of this hardware, serializing one only the first
+    /// time.
+    fn state_len(&self) -> usize {
+        match self.state_len.get() {
+            Some(&len) => len,
+            None => self.serialize_state().len(),
+        }
+    }
+
+    fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(SAVESTATE_MAGIC);
+        writer.write_u16(SAVESTATE_VERSION);
+        writer.write_u64(self.bus.cartridge.rom_hash());
+        writer.write_u8(self.bus.model as u8);
+        self.cpu.save_state(writer);
+        self.bus.save_state(writer);
+    }
+
+    /// Checks that `state` is a savestate for this ROM and model, returning a reader past
+    /// its header.
+    fn read_state_header<'a>(&self, state: &'a [u8]) -> Result<StateReader<'a>, SavestateError> {
+        let mut reader = StateReader::new(state);
+        let mut magic = [0; SAVESTATE_MAGIC.len()];
+        reader
+            .read_bytes(&mut magic)
+            .map_err(|_| SavestateError::InvalidFormat)?;
+        if &magic != SAVESTATE_MAGIC {
+            return Err(SavestateError::InvalidFormat);
+        }
+        let version = reader.read_u16()?;
+        if version != SAVESTATE_VERSION {
+            return Err(SavestateError::UnsupportedVersion(version));
+        }
+        let actual = reader.read_u64()?;
+        let expected = self.bus.cartridge.rom_hash();
+        if actual != expected {
+            return Err(SavestateError::RomMismatch { expected, actual });
+        }
+        if reader.read_u8()? != self.bus.model as u8 {
+            return Err(SavestateError::ModelMismatch);
+        }
+        // The layout only depends on the ROM and model, so checking the size up front
+        // means loading can't fail halfway through
+        if state.len() != self.state_len() {
+            return Err(SavestateError::Truncated);
+        }
+        Ok(reader)
+    }
+
+    /// Compares two savestates of this ROM and model field by field, for tracking down
+    /// where two runs that should be identical diverged (e.g. netplay desyncs, or output
+    /// changing after a refactor). The current state of the hardware doesn't matter, it is
+    /// only used for the layout of the savestates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either savestate can't be loaded by [`Self::load_state`].
+    pub fn diff_states(&self, first: &[u8], second: &[u8]) -> Result<StateDiff, SavestateError> {
+        self.read_state_header(first)?;
+        self.read_state_header(second)?;
+        let mut writer = StateWriter::with_layout();
+        self.write_state(&mut writer);
+        Ok(StateDiff::new(writer, first, second))
+    }
+
+    /// Restores the emulation state from [`Self::save_state`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error and leaves the state untouched if the savestate is malformed,
+    /// from an incompatible version, or made with another ROM or model.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SavestateError> {
+        self.restore_state(state)?;
+        self.record_event(JournalEvent::StateLoaded);
+        self.bus.notify(|| Notification::StateLoaded);
+        Ok(())
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SavestateError> {
+        let mut reader = self.read_state_header(state)?;
+        self.cpu.load_state(&mut reader)?;
+        self.bus.load_state(&mut reader)?;
+        debug_assert!(reader.is_empty());
+        self.region_hash = None;
+        self.reset_crash_detector();
+        Ok(())
+    }
+
+    /// Starts keeping a history of snapshots to go back to with [`Self::step_back`],
+    /// returning the previous history, if any. `None` stops taking snapshots.
+    ///
+    /// Snapshots are taken at the end of [`Self::run_frame`], the history survives resets
+    /// and loading states.
+    pub fn set_rewind(&mut self, rewind: Option<Rewind>) -> Option<Rewind> {
+        std::mem::replace(&mut self.rewind, rewind.map(Box::new)).map(|rewind| *rewind)
+    }
+
+    #[must_use]
+    pub fn rewind_history(&self) -> Option<&Rewind> {
+        self.rewind.as_deref()
+    }
+
+    /// Goes back to the latest snapshot taken before the current frame, returning false if
+    /// rewinding isn't enabled or the history is exhausted. Calling it repeatedly goes
+    /// further back, one snapshot at a time.
+    ///
+    /// Snapshots are checked like savestates: once [`Self::from_parts`] has put a cartridge
+    /// or model they weren't taken with in place, this returns false.
+    ///
+    /// Unlike [`Self::load_state`], this isn't recorded in the journal or notified, as
+    /// frontends usually step back every frame while rewinding.
+    pub fn step_back(&mut self) -> bool {
+        let Some(mut rewind) = self.rewind.take() else {
+            return false;
+        };
+        let stepped = rewind
+            .step_back()
+            .is_some_and(|state| self.restore_state(state).is_ok());
+        self.rewind = Some(rewind);
+        stepped
+    }
+
+    /// Writes battery-backed RAM to `path`, see [`Cartridge::save_data`], returning false
+    /// without writing if the cartridge has no battery.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn persist_save_ram(
+        &mut self,
+        path: impl AsRef<Path>,
+        codec: &dyn PersistenceCodec,
+    ) -> io::Result<bool> {
+        let Some(save) = self.bus.cartridge.save_data() else {
+            return Ok(false);
+        };
+        write_payload(path, Payload::SaveRam, save, codec)?;
+        self.bus.notify(|| Notification::SaveRamPersisted);
+        Ok(true)
+    }
+
+    /// Reads a byte from the address space without side effects.
+    ///
+    /// No time passes and no hardware state changes, which makes this safe to call
+    /// from debugging tools at any point.
+    #[must_use]
+    pub fn peek_byte(&self, addr: u16) -> u8 {
+        self.bus.peek_byte(addr)
+    }
+
+    /// Reads a little-endian word from the address space without side effects.
+    #[must_use]
+    pub fn peek_word(&self, addr: u16) -> u16 {
+        let low = self.bus.peek_byte(addr);
+        let high = self.bus.peek_byte(addr.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+}
+
+/// The cartridge, memory and I/O the CPU accesses, see [`GameboyHardware::into_parts`].
+pub struct AddressBus {
+    model: Model,
     // ROM and External RAM
     cartridge: Cartridge,
     // Picture Processing Unit
     ppu: Ppu,
+    oam_dma: OamDma,
     // WRAM
     work_ram: [u8; WORK_RAM_SIZE],
     // P1/JOYP
@@ -29,151 +1477,522 @@ pub struct GameboyHardware {
     interrupt_flag: InterruptFlags,
     // Audio Processing Unit
     apu: Apu,
-    wave_pattern_ram: [u8; WAVE_PATTERN_RAM_SIZE],
+    // Receives the APU output when set
+    audio_sink: Option<Box<dyn AudioSink>>,
+    // Receives notifications and diagnostics when set
+    notification_sink: Option<Box<dyn NotificationSink>>,
+    // Set once a device on the other end of the link cable clocks the serial port
+    link_connected: bool,
+    serial_link: Option<Box<dyn SerialLink>>,
+    // Output line level last given to the serial link
+    link_line: Option<bool>,
+    // Set during `GameboyHardware::run_frame`, which collects its output below
+    recording_frame: bool,
+    // Audio samples and serial bytes sent during the last frame run
+    frame_audio: Vec<AudioSample>,
+    frame_serial: Vec<u8>,
+    // Serial bytes sent since last taken, whatever runs the console
+    serial_output: Vec<u8>,
     // HRAM
     high_ram: [u8; HIGH_RAM_SIZE],
     // IE
     interrupt_enable: InterruptFlags,
+    // Only recorded when enabled for debugging
+    write_log: Option<Vec<MemoryWrite>>,
+    bus_logger: Option<Box<BusLogger>>,
+    // Component making the bus transactions, for the bus logger
+    component: Component,
+    write_watches: WriteWatches,
+    watchpoints: Watchpoints,
+    // Host handlers checked before everything else
+    bus_devices: BusDevices,
+    // Applied to RAM on power cycles
+    ram_init: RamInit,
+    // Source of random values, a SplitMix64 seeded with 0 until set
+    random: Option<Box<dyn RandomSource>>,
+    cheats: Cheats,
+    // Instruction being executed, for attributing watched accesses
+    instruction: CodeAddress,
+    // Set by CPU reads of I/O registers, for telling polling loops from soft-locks
+    io_read: bool,
+    // Set while the PPU is allowed to lag behind the CPU
+    ppu_lag: Option<PpuLag>,
+    // Skips drawing frames when running faster than real time, a host setting
+    fast_forward_skip: bool,
+    // Handling of accesses a real console ignores, a host setting
+    strictness: Strictness,
+    // Error that stopped emulation when strict
+    error: Option<EmulatorError>,
+    // Super Game Boy receiving packets through P1, a host setting
+    sgb: Option<Box<Sgb>>,
+    // T-cycles run since the bus was created, kept across resets and save states
+    cycles: u64,
+    // T-cycles run by `GameboyHardware::run_frame` or `run_cycles` since the last frame ended
+    frame_cycles: usize,
 }
 
-impl GameboyHardware {
+/// PPU cycles owed while it lags behind the CPU, see [`GameboyHardware::set_lazy_ppu`].
+#[derive(Debug, Default, Clone, Copy)]
+struct PpuLag {
+    // M-cycles not run yet
+    cycles: usize,
+    // Whether the CPU was running during them
+    cpu_active: bool,
+    // Dots the PPU can run before it may request an interrupt or complete a frame
+    event_free_dots: usize,
+}
+
+/// Returns the frames to skip after each frame drawn at `speed`, drawing about as many
+/// frames per second as in real time.
+fn fast_forward_frame_skip(speed: Speed) -> u8 {
+    match speed {
+        Speed::Unlocked => UNLOCKED_FRAME_SKIP,
+        // Float casts saturate, so huge multipliers skip as many frames as possible
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Speed::Multiplier(multiplier) => (multiplier.ceil() as u8).saturating_sub(1),
+    }
+}
+
+/// Returns whether the CPU accessing `addr` needs the PPU to be up to date.
+const fn is_ppu_visible(addr: u16) -> bool {
+    matches!(
+        addr,
+        0x8000..=0x9FFF | 0xFE00..=0xFEFF | 0xFF40..=0xFF4B | 0xFF68..=0xFF6B
+    )
+}
+
+impl AddressBus {
+    /// Creates a bus as it is after the boot ROM, see [`GameboyHardware::with_model`].
     #[must_use]
-    pub const fn new(cartridge: Cartridge) -> Self {
+    pub const fn new(cartridge: Cartridge, model: Model) -> Self {
         Self {
-            cpu: Cpu::new(),
+            model,
             cartridge,
-            ppu: Ppu::new(),
+            ppu: Ppu::new(model),
+            oam_dma: OamDma::new(),
             work_ram: [0; WORK_RAM_SIZE],
             joypad: Joypad::new(),
             serial_port: SerialPort::new(),
             timer: Timer::new(),
             interrupt_flag: InterruptFlags::from_bits(InterruptFlags::VBLANK),
-            apu: Apu::new(),
-            wave_pattern_ram: [0xFF; WAVE_PATTERN_RAM_SIZE],
+            apu: Apu::new(model),
+            audio_sink: None,
+            notification_sink: None,
+            link_connected: false,
+            serial_link: None,
+            link_line: None,
+            recording_frame: false,
+            frame_audio: Vec::new(),
+            frame_serial: Vec::new(),
+            serial_output: Vec::new(),
             high_ram: [0; HIGH_RAM_SIZE],
             interrupt_enable: InterruptFlags::empty(),
+            write_log: None,
+            bus_logger: None,
+            component: Component::Cpu,
+            write_watches: WriteWatches::new(),
+            watchpoints: Watchpoints::new(),
+            bus_devices: BusDevices::new(),
+            ram_init: RamInit::Zero,
+            random: None,
+            cheats: Cheats::new(),
+            instruction: CodeAddress { bank: 0, pc: 0 },
+            io_read: false,
+            ppu_lag: None,
+            fast_forward_skip: false,
+            strictness: Strictness::Permissive,
+            error: None,
+            sgb: None,
+            cycles: 0,
+            frame_cycles: 0,
         }
     }
 
-    pub fn step(&mut self) {
-        let mut bus = AddressBus {
-            cartridge: &mut self.cartridge,
-            ppu: &mut self.ppu,
-            work_ram: &mut self.work_ram,
-            joypad: &mut self.joypad,
-            serial_port: &mut self.serial_port,
-            timer: &mut self.timer,
-            interrupt_flag: &mut self.interrupt_flag,
-            apu: &mut self.apu,
-            wave_pattern_ram: &mut self.wave_pattern_ram,
-            high_ram: &mut self.high_ram,
-            interrupt_enable: &mut self.interrupt_enable,
-        };
+    #[must_use]
+    pub const fn model(&self) -> Model {
+        self.model
+    }
+
+    #[must_use]
+    pub const fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    /// Resets everything but memory contents, like the reset line does.
+    fn reset(&mut self) {
+        self.cartridge.reset();
+        self.ppu.reset();
+        self.oam_dma = OamDma::new();
+        self.joypad.reset();
+        self.serial_port = SerialPort::new();
+        self.timer = Timer::new();
+        self.interrupt_flag = InterruptFlags::from_bits(InterruptFlags::VBLANK);
+        self.apu.reset();
+        self.interrupt_enable = InterruptFlags::empty();
+        self.ppu_lag = self.ppu_lag.map(|_| PpuLag::default());
+        self.error = None;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.reset();
+        }
+    }
+
+    /// Resets everything and initializes RAM as configured, keeping battery-backed RAM.
+    fn power_cycle(&mut self) {
+        self.reset();
+        let high_pass = self.apu.is_high_pass_filter_enabled();
+        let muted = AudioChannel::ALL.map(|channel| self.apu.is_channel_muted(channel));
+        self.apu = Apu::new(self.model);
+        self.apu
+            .set_sample_rate(self.audio_sink.as_ref().map(|sink| sink.sample_rate()));
+        self.apu.set_high_pass_filter(high_pass);
+        for (channel, muted) in AudioChannel::ALL.into_iter().zip(muted) {
+            self.apu.set_channel_muted(channel, muted);
+        }
+        let [video_ram, sprite_ram] = self.ppu.memory_mut();
+        let cartridge_ram = self.cartridge.volatile_ram_mut().unwrap_or_default();
+        let random = self
+            .random
+            .get_or_insert_with(|| Box::new(SplitMix64::new(0)));
+        self.ram_init.fill(
+            [
+                &mut self.work_ram,
+                &mut self.high_ram,
+                video_ram,
+                sprite_ram,
+                cartridge_ram,
+            ],
+            random.as_mut(),
+        );
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.cartridge.save_state(writer);
+        self.ppu.save_state(writer);
+        self.oam_dma.save_state(writer);
+        writer.component("Memory");
+        writer.memory("WRAM", 0xC000);
+        writer.write_bytes(&self.work_ram);
+        self.joypad.save_state(writer);
+        self.serial_port.save_state(writer);
+        self.timer.save_state(writer);
+        writer.component("Interrupts");
+        writer.field("IF");
+        writer.write_u8(self.interrupt_flag.bits());
+        self.apu.save_state(writer);
+        writer.component("Memory");
+        writer.memory("HRAM", 0xFF80);
+        writer.write_bytes(&self.high_ram);
+        writer.component("Interrupts");
+        writer.field("IE");
+        writer.write_u8(self.interrupt_enable.bits());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.cartridge.load_state(reader)?;
+        self.ppu.load_state(reader)?;
+        self.oam_dma.load_state(reader)?;
+        reader.read_bytes(&mut self.work_ram)?;
+        self.joypad.load_state(reader)?;
+        self.serial_port.load_state(reader)?;
+        self.timer.load_state(reader)?;
+        self.interrupt_flag = InterruptFlags::from_bits(reader.read_u8()?);
+        self.apu.load_state(reader)?;
+        reader.read_bytes(&mut self.high_ram)?;
+        self.interrupt_enable = InterruptFlags::from_bits(reader.read_u8()?);
+        self.ppu_lag = self.ppu_lag.map(|_| PpuLag::default());
+        Ok(())
+    }
 
-        let cycles = self.cpu.step(&mut bus);
+    fn tick(&mut self, cycles: usize, cpu_active: bool) {
+        if let Some(logger) = &mut self.bus_logger {
+            logger.advance(cycles);
+        }
+        self.cartridge.tick(cycles);
+        self.cycles += cycles as u64;
         for _ in 0..(cycles / 4) {
-            self.timer.tick(&mut self.interrupt_flag);
+            // Everything is clocked from the timer's system counter, see `crate::clock`
+            let edges = self.timer.tick(&mut self.interrupt_flag);
+            self.serial_port
+                .tick(edges, &mut self.interrupt_flag, self.serial_link.as_mut());
+            self.record_serial_byte();
+            self.tick_serial_link();
+            self.tick_ppu(cpu_active);
+            self.tick_oam_dma();
+            if let Some(sample) = self.apu.tick(edges) {
+                if let Some(sink) = &mut self.audio_sink {
+                    sink.push_sample(&sample);
+                }
+                if self.recording_frame {
+                    self.frame_audio.push(sample);
+                }
+            }
         }
-        self.serial_port.step();
     }
-}
 
-pub(crate) struct AddressBus<'a> {
-    // ROM and External RAM
-    cartridge: &'a mut Cartridge,
-    // Picture Processing Unit
-    ppu: &'a mut Ppu,
-    // WRAM
-    work_ram: &'a mut [u8],
-    // P1/JOYP
-    joypad: &'a mut Joypad,
-    // Link Cable
-    serial_port: &'a mut SerialPort,
-    timer: &'a mut Timer,
-    // IF
-    interrupt_flag: &'a mut InterruptFlags,
-    // Audio Processing Unit
-    apu: &'a mut Apu,
-    wave_pattern_ram: &'a mut [u8],
-    // HRAM
-    high_ram: &'a mut [u8],
-    // IE
-    interrupt_enable: &'a mut InterruptFlags,
-}
+    /// Lets time pass while STOP has stopped the clock: only the cartridge (e.g. its
+    /// real-time clock) keeps running.
+    fn tick_stopped(&mut self, cycles: usize) {
+        if let Some(logger) = &mut self.bus_logger {
+            logger.advance(cycles);
+        }
+        self.cartridge.tick(cycles);
+        self.cycles += cycles as u64;
+    }
 
-impl AddressBus<'_> {
-    pub(crate) fn read_byte(&self, addr: u16) -> u8 {
+    /// Receives a bit clocked in over the serial link, if any, and passes on changes to the
+    /// output line.
+    fn tick_serial_link(&mut self) {
+        let Some(link) = &mut self.serial_link else {
+            return;
+        };
+        if let Some(in_bit) = link.clock_in() {
+            self.external_clock_pulse(in_bit);
+        }
+        let line = self.serial_port.out_line();
+        if self.link_line != Some(line) {
+            self.link_line = Some(line);
+            if let Some(link) = &mut self.serial_link {
+                link.set_line(line);
+            }
+        }
+    }
+
+    fn external_clock_pulse(&mut self, in_bit: bool) -> bool {
+        if !self.link_connected {
+            self.link_connected = true;
+            self.notify(|| Notification::LinkConnected);
+        }
+        let out_bit = self
+            .serial_port
+            .external_clock_pulse(in_bit, &mut self.interrupt_flag);
+        self.record_serial_byte();
+        out_bit
+    }
+
+    fn record_serial_byte(&mut self) {
+        if let Some(byte) = self.serial_port.take_sent() {
+            if self.recording_frame {
+                self.frame_serial.push(byte);
+            }
+            self.serial_output.push(byte);
+        }
+    }
+
+    /// Runs the PPU for one M-cycle, or adds the M-cycle to those owed while it lags behind.
+    fn tick_ppu(&mut self, cpu_active: bool) {
+        let Some(lag) = &mut self.ppu_lag else {
+            self.ppu.tick(&mut self.interrupt_flag, cpu_active);
+            return;
+        };
+        if lag.cycles > 0 && lag.cpu_active != cpu_active {
+            self.sync_ppu();
+        }
+        let Some(lag) = &mut self.ppu_lag else {
+            return;
+        };
+        lag.cycles += 1;
+        lag.cpu_active = cpu_active;
+        // Interrupts are requested right away, so the CPU sees them on its next step
+        if 4 * lag.cycles > lag.event_free_dots {
+            self.sync_ppu();
+        }
+    }
+
+    /// Copies the byte OAM DMA transfers during this M-cycle, if any.
+    fn tick_oam_dma(&mut self) {
+        let Some((source, offset)) = self.oam_dma.tick() else {
+            return;
+        };
+        // Sources past WRAM read its echo
+        let source = if source >= 0xE000 {
+            source - 0x2000
+        } else {
+            source
+        };
+        let value = self.peek_byte(source);
+        self.sync_ppu();
+        self.ppu.write_sprite(offset, value);
+    }
+
+    /// Runs the M-cycles the PPU owes, if it lags behind.
+    fn sync_ppu(&mut self) {
+        if let Some(lag) = &mut self.ppu_lag {
+            let dots = 4 * std::mem::take(&mut lag.cycles);
+            self.ppu
+                .run_dots(dots, &mut self.interrupt_flag, lag.cpu_active);
+            lag.event_free_dots = self.ppu.dots_until_event();
+        }
+    }
+
+    /// Reads a byte on behalf of the CPU.
+    ///
+    /// Unlike [`Self::peek_byte`], this access is allowed to affect the hardware.
+    pub(crate) fn read_byte(&mut self, addr: u16) -> u8 {
+        if is_ppu_visible(addr) {
+            self.sync_ppu();
+        }
+        let value = match self.bus_devices.read(addr) {
+            Some(value) => value,
+            None => self.read_console(addr),
+        };
+        self.log_transaction(addr, value, Access::Read);
+        if !self.watchpoints.is_empty() {
+            self.watchpoints
+                .record(addr, Access::Read, value, self.instruction);
+        }
+        value
+    }
+
+    /// Reads a byte from the console's own memory and registers, for [`Self::read_byte`].
+    fn read_console(&mut self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x3FFF => self.cartridge.read_rom_bank0(addr),
-            0x4000..=0x7FFF => {
-                let offset = addr - 0x4000;
-                self.cartridge.read_rom_bank1(offset)
+            0x0000..=0x7FFF => {
+                let value = self.cartridge.read(addr);
+                self.cheats.patch_rom(addr, value)
+            }
+            0xA000..=0xBFFF => self.cartridge.read(addr),
+            0xFE00..=0xFEFF => {
+                self.ppu.corrupt_oam(OamCorruption::Read);
+                match addr {
+                    // OAM is busy during OAM DMA
+                    0xFE00..=0xFE9F if self.oam_dma.is_active() => 0xFF,
+                    _ => self.peek_byte(addr),
+                }
             }
+            0xFF00..=0xFF7F => {
+                self.io_read = true;
+                let value = self.read_io(addr);
+                value.unwrap_or_else(|| {
+                    self.raise_unmapped_io(addr);
+                    0xFF
+                })
+            }
+            _ => self.peek_byte(addr),
+        }
+    }
+
+    /// Reads a byte without any side effects.
+    pub(crate) fn peek_byte(&self, addr: u16) -> u8 {
+        if let Some(value) = self.bus_devices.peek(addr) {
+            return value;
+        }
+        match addr {
+            0x0000..=0x7FFF => self.cheats.patch_rom(addr, self.cartridge.peek(addr)),
+            0xA000..=0xBFFF => self.cartridge.peek(addr),
             0x8000..=0x9FFF => {
                 let offset = addr - 0x8000;
                 self.ppu.read_vram(offset)
             }
-            0xA000..=0xBFFF => {
-                let offset = addr - 0xA000;
-                self.cartridge.read_ram(offset)
-            }
             0xC000..=0xDFFF => {
                 let offset = (addr - 0xC000) as usize;
                 self.work_ram[offset]
             }
+            // Echo of 0xC000-0xDDFF
+            0xE000..=0xFDFF => {
+                let offset = (addr - 0xE000) as usize;
+                self.work_ram[offset]
+            }
             0xFE00..=0xFE9F => {
                 let offset = addr - 0xFE00;
                 self.ppu.read_sprite(offset)
             }
-            0xFF00..=0xFF7F => self.read_io(addr),
+            // Unusable, reads 0xFF while OAM is blocked
+            0xFEA0..=0xFEFF if self.oam_dma.is_active() || self.ppu.is_oam_blocked() => 0xFF,
+            0xFEA0..=0xFEFF => 0x00,
+            0xFF00..=0xFF7F => self.read_io(addr).unwrap_or(0xFF),
             0xFF80..=0xFFFE => {
                 let offset = (addr - 0xFF80) as usize;
                 self.high_ram[offset]
             }
             0xFFFF => self.interrupt_enable.bits(),
-            0xE000..=0xFDFF | 0xFEA0..=0xFEFF => {
-                panic!("Use of this area is prohibited {addr:#X}")
-            }
         }
     }
 
-    fn read_io(&self, addr: u16) -> u8 {
-        match addr {
-            0xFF00 => self.joypad.bits(),
+    /// Reads an I/O register, `None` if `addr` isn't mapped to one.
+    fn read_io(&self, addr: u16) -> Option<u8> {
+        let value = match addr {
+            0xFF00 => match &self.sgb {
+                Some(sgb) => sgb.joypad_bits(self.joypad.bits()),
+                None => self.joypad.bits(),
+            },
             0xFF01..=0xFF02 => self.serial_port.read_byte(addr),
             0xFF04..=0xFF07 => self.timer.read_byte(addr),
             0xFF0F => self.interrupt_flag.bits(),
-            0xFF10..=0xFF26 => self.apu.read_audio(addr),
-            0xFF30..=0xFF3F => {
-                let offset = (addr - 0xFF30) as usize;
-                self.wave_pattern_ram[offset]
-            }
+            0xFF10..=0xFF14 | 0xFF16..=0xFF1E | 0xFF20..=0xFF26 => self.apu.read_audio(addr),
+            0xFF30..=0xFF3F => self.apu.read_wave_ram(addr - 0xFF30),
             0xFF40..=0xFF4B => self.ppu.read_display(addr),
-            _ => {
-                println!("Warning: Address {addr:#X} is not mapped to an I/O register.");
-                0xFF
-            }
+            0xFF68..=0xFF6B => self.ppu.read_color_palette(addr),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn raise_unmapped_io(&mut self, addr: u16) {
+        self.report_error(EmulatorError::UnmappedIo { addr });
+    }
+
+    /// Raises an access a real console ignores, keeping the first one to stop emulation
+    /// when strict.
+    fn report_error(&mut self, err: EmulatorError) {
+        if self.strictness == Strictness::Strict && self.error.is_none() {
+            self.error = Some(err);
         }
+        self.raise(|| format!("Stray access: {err}."));
     }
 
     pub(crate) fn write_byte(&mut self, addr: u16, value: u8) {
+        if let Some(write_log) = &mut self.write_log {
+            write_log.push(MemoryWrite { addr, value });
+        }
+        self.log_transaction(addr, value, Access::Write);
+        if !self.write_watches.is_empty() {
+            self.write_watches.record(addr, self.instruction, value);
+        }
+        if !self.watchpoints.is_empty() {
+            self.watchpoints
+                .record(addr, Access::Write, value, self.instruction);
+        }
+        if self.bus_devices.write(addr, value) {
+            return;
+        }
+        if is_ppu_visible(addr) {
+            self.sync_ppu();
+            // Writes can change when the next interrupt is, e.g. LYC or turning the LCD on
+            if let Some(lag) = &mut self.ppu_lag {
+                lag.event_free_dots = 0;
+            }
+        }
         match addr {
-            0x0000..=0x7FFF => self.cartridge.write_rom(addr, value),
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => {
+                if let Err(err) = self.cartridge.write(addr, value) {
+                    self.report_error(err);
+                }
+            }
             0x8000..=0x9FFF => {
                 let offset = addr - 0x8000;
                 self.ppu.write_vram(offset, value);
             }
-            0xA000..=0xBFFF => {
-                let offset = addr - 0xA000;
-                self.cartridge.write_ram(offset, value);
-            }
             0xC000..=0xDFFF => {
                 let offset = (addr - 0xC000) as usize;
                 self.work_ram[offset] = value;
             }
-            0xFE00..=0xFE9F => {
-                let offset = addr - 0xFE00;
-                self.ppu.write_sprite(offset, value);
+            0xE000..=0xFDFF => {
+                let offset = (addr - 0xE000) as usize;
+                self.work_ram[offset] = value;
+            }
+            0xFE00..=0xFEFF => {
+                self.ppu.corrupt_oam(OamCorruption::Write);
+                match addr {
+                    0xFE00..=0xFE9F if !self.oam_dma.is_active() => {
+                        self.ppu.write_sprite(addr - 0xFE00, value);
+                    }
+                    // Writes to the unusable area are ignored
+                    _ => {}
+                }
             }
             0xFF00..=0xFF7F => {
                 self.write_io(addr, value);
@@ -183,39 +2002,660 @@ impl AddressBus<'_> {
                 self.high_ram[offset] = value;
             }
             0xFFFF => {
-                *self.interrupt_enable = InterruptFlags::from_bits(value);
-            }
-            0xE000..=0xFDFF | 0xFEA0..=0xFEFF => {
-                panic!("Use of this area is prohibited {addr:#X}")
+                self.interrupt_enable = InterruptFlags::from_bits(value);
             }
         }
     }
 
     fn write_io(&mut self, addr: u16, value: u8) {
         match addr {
-            0xFF00 => *self.joypad = Joypad::from_bits(value),
+            0xFF00 => {
+                if self.joypad.write(value) {
+                    self.interrupt_flag.set(InterruptFlags::JOYPAD, true);
+                }
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value, self.ppu.background_tile_data(), self.ppu.frame());
+                }
+            }
             0xFF01..=0xFF02 => self.serial_port.write_byte(addr, value),
             0xFF04..=0xFF07 => self.timer.write_byte(addr, value),
-            0xFF0F => *self.interrupt_flag = InterruptFlags::from_bits(value),
-            0xFF10..=0xFF26 => self.apu.write_audio(addr, value),
-            0xFF30..=0xFF3F => {
-                let offset = (addr - 0xFF30) as usize;
-                self.wave_pattern_ram[offset] = value;
+            0xFF0F => self.interrupt_flag = InterruptFlags::from_bits(value),
+            0xFF10..=0xFF14 | 0xFF16..=0xFF1E | 0xFF20..=0xFF26 => {
+                self.apu.write_audio(addr, value);
+            }
+            0xFF30..=0xFF3F => self.apu.write_wave_ram(addr - 0xFF30, value),
+            0xFF46 => {
+                self.ppu.write_display(addr, value);
+                self.oam_dma.start(value);
             }
+            0xFF41 => self.ppu.write_status(value, &mut self.interrupt_flag),
             0xFF40..=0xFF4B => self.ppu.write_display(addr, value),
-            _ => println!("Warning: Address {addr:#X} is not mapped to an I/O register."),
+            0xFF68..=0xFF6B => self.ppu.write_color_palette(addr, value),
+            _ => self.raise_unmapped_io(addr),
+        }
+    }
+
+    fn log_transaction(&mut self, addr: u16, value: u8, access: Access) {
+        let Some(logger) = &mut self.bus_logger else {
+            return;
+        };
+        if let Some(err) = logger.record(addr, value, access, self.component) {
+            let message = format!("Bus logging stopped: {err}.");
+            self.raise(|| message);
+        }
+    }
+
+    /// Sends a notification to the sink, only creating it if there is a sink.
+    fn notify(&mut self, notification: impl FnOnce() -> Notification) {
+        if let Some(sink) = &mut self.notification_sink {
+            sink.notify(&notification());
+        }
+    }
+
+    /// Raises a diagnostic, see [`Self::notify`].
+    fn raise(&mut self, message: impl FnOnce() -> String) {
+        self.notify(|| Notification::DiagnosticRaised(message()));
+    }
+
+    /// Raises the cartridge's warnings, keeping them until there is a sink to receive them.
+    fn raise_cartridge_warnings(&mut self) {
+        if self.notification_sink.is_some() {
+            for warning in self.cartridge.take_warnings() {
+                self.raise(|| warning);
+            }
+        }
+    }
+
+    fn notify_cheat_applied(&mut self, index: usize) {
+        let cheats = &self.cheats;
+        if let Some(sink) = &mut self.notification_sink {
+            sink.notify(&Notification::CheatApplied {
+                index,
+                description: cheats.cheats()[index].description.clone(),
+            });
         }
     }
 
-    pub(crate) const fn get_joypad(&self) -> Joypad {
-        *self.joypad
+    /// Returns the bank mapped at `addr`, see [`Cartridge::bank_at`].
+    pub(crate) fn bank_at(&self, addr: u16) -> usize {
+        self.cartridge.bank_at(addr)
+    }
+
+    /// Returns the RAM byte at `addr`, or `None` for ROM, I/O registers and unusable areas.
+    fn ram_byte_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        let [video_ram, sprite_ram] = self.ppu.memory_mut();
+        match addr {
+            0x8000..=0x9FFF => video_ram.get_mut((addr - 0x8000) as usize),
+            0xA000..=0xBFFF => self.cartridge.ram_byte_mut(addr),
+            0xC000..=0xDFFF => self.work_ram.get_mut((addr - 0xC000) as usize),
+            0xE000..=0xFDFF => self.work_ram.get_mut((addr - 0xE000) as usize),
+            0xFE00..=0xFE9F => sprite_ram.get_mut((addr - 0xFE00) as usize),
+            0xFF80..=0xFFFE => self.high_ram.get_mut((addr - 0xFF80) as usize),
+            _ => None,
+        }
     }
 
-    pub(crate) const fn interrupt_flag(&mut self) -> &mut InterruptFlags {
-        self.interrupt_flag
+    /// Makes the RAM writes of GameShark codes, at the start of VBlank.
+    fn apply_cheats(&mut self) {
+        for index in 0..self.cheats.ram_writes().len() {
+            let (addr, value) = self.cheats.ram_writes()[index];
+            if let Some(byte) = self.ram_byte_mut(addr) {
+                *byte = value;
+            }
+        }
     }
 
+    /// Returns the interrupts both requested in IF and enabled in IE.
     pub(crate) fn get_interrupts_pending(&self) -> InterruptFlags {
-        (*self.interrupt_enable & *self.interrupt_flag) & !InterruptFlags::empty()
+        (self.interrupt_enable & self.interrupt_flag).without_unused()
+    }
+}
+
+impl BusInterface for AddressBus {
+    fn read_byte(&mut self, addr: u16) -> u8 {
+        Self::read_byte(self, addr)
+    }
+
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        Self::write_byte(self, addr, value);
+    }
+
+    fn peek_byte(&self, addr: u16) -> u8 {
+        Self::peek_byte(self, addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.get_interrupts_pending().bits()
+    }
+
+    fn acknowledge_interrupt(&mut self, mask: u8) {
+        self.interrupt_flag.set(mask, false);
+    }
+
+    fn set_interrupt_dispatch(&mut self, dispatching: bool) {
+        self.component = if dispatching {
+            Component::InterruptDispatch
+        } else {
+            Component::Cpu
+        };
+    }
+
+    fn is_any_button_pressed(&self) -> bool {
+        self.joypad.is_any_pressed()
+    }
+
+    fn reset_div(&mut self) {
+        self.timer.write_byte(0xFF04, 0);
+    }
+
+    fn increment_address(&mut self, addr: u16, reading: bool) {
+        if !matches!(addr, 0xFE00..=0xFEFF) {
+            return;
+        }
+        self.sync_ppu();
+        self.ppu.corrupt_oam(if reading {
+            OamCorruption::IncreaseDuringRead
+        } else {
+            OamCorruption::Write
+        });
+    }
+
+    fn bank_at(&self, addr: u16) -> usize {
+        Self::bank_at(self, addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::AudioRingBuffer;
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::consts::{
+        cycles_to_duration, duration_to_cycles, CPU_HZ, FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH,
+    };
+    use crate::error::EmulatorError;
+    use crate::handle::Speed;
+    use crate::hardware::{AddressBus, Button, Cpu, GameboyHardware, Model, RamInit, Strictness};
+    use crate::interrupts::InterruptFlags;
+    use crate::movie::Input;
+    use crate::notification::Notification;
+    use crate::rng::{RandomSource, SplitMix64};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    // Enables cartridge RAM, writes 0x42 to it, selects ROM bank 2, then fills WRAM
+    const PROGRAM: [u8; 23] = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, 0x3E, 0x42, 0xEA, 0x00, 0xA0, 0x3E, 0x02, 0xEA, 0x00, 0x20,
+        0x21, 0x00, 0xC0, 0x77, 0x2C, 0x3C, 0x18, 0xFB,
+    ];
+    const BANK_2_MARKER: u8 = 0x99;
+
+    fn rom(cartridge_type: u8) -> Vec<u8> {
+        let mut rom = HeaderBuilder::new()
+            .cartridge_type(cartridge_type)
+            .rom_banks(4)
+            .ram_banks(1)
+            .build(&PROGRAM);
+        rom[2 * 0x4000] = BANK_2_MARKER;
+        rom
+    }
+
+    fn run(cartridge_type: u8) -> GameboyHardware {
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom(cartridge_type)));
+        gameboy.run_frame();
+        assert_eq!(gameboy.peek_byte(0x4000), BANK_2_MARKER);
+        gameboy
+    }
+
+    /// Runs the entry point and the instructions enabling cartridge RAM, then reads it.
+    fn cartridge_ram(gameboy: &mut GameboyHardware) -> u8 {
+        for _ in 0..4 {
+            gameboy.step();
+        }
+        gameboy.peek_byte(0xA000)
+    }
+
+    #[test]
+    fn test_reset_keeps_memory() {
+        let mut gameboy = run(0x03);
+        let work_ram = gameboy.peek_word(0xC010);
+        assert_ne!(work_ram, 0);
+
+        gameboy.reset();
+        assert_eq!(gameboy.registers().pc, 0x100);
+        assert_eq!(gameboy.peek_byte(0x4000), 0);
+        assert_eq!(gameboy.peek_word(0xC010), work_ram);
+        assert_eq!(cartridge_ram(&mut gameboy), 0x42);
+    }
+
+    #[test]
+    fn test_power_cycle_matches_new_instance() {
+        let mut gameboy = run(0x02);
+        gameboy.power_cycle();
+        let fresh = GameboyHardware::new(Cartridge::new(rom(0x02)));
+        assert_eq!(gameboy.save_state(), fresh.save_state());
+        assert_eq!(cartridge_ram(&mut gameboy), 0);
+    }
+
+    #[test]
+    fn test_power_cycle_ram_init() {
+        let mut first = run(0x03);
+        let mut second = run(0x03);
+        for gameboy in [&mut first, &mut second] {
+            gameboy.set_ram_init(RamInit::Random);
+            gameboy.set_random_seed(7);
+            gameboy.power_cycle();
+        }
+        assert_eq!(first.save_state(), second.save_state());
+        assert_ne!(first.peek_word(0xC010), second.peek_word(0xC012));
+        // The source keeps going, so the next power cycle gives other values
+        first.power_cycle();
+        assert_ne!(first.save_state(), second.save_state());
+        second.set_random_seed(7);
+        second.power_cycle();
+        assert_ne!(first.save_state(), second.save_state());
+        // Battery-backed RAM survives
+        assert_eq!(cartridge_ram(&mut first), 0x42);
+
+        first.set_ram_init(RamInit::Fill(0xAA));
+        first.power_cycle();
+        assert_eq!(first.peek_word(0xC010), 0xAAAA);
+        assert_eq!(first.peek_byte(0xFF80), 0xAA);
+    }
+
+    #[test]
+    fn test_lazy_ppu_matches_eager() {
+        #[rustfmt::skip]
+        let program = [
+            // LD HL, 0xC000; LYC = 0x44; STAT = LYC interrupt; IE = VBLANK | STAT; EI
+            0x21, 0x00, 0xC0, 0x3E, 0x44, 0xE0, 0x45, 0x3E, 0x40, 0xE0, 0x41,
+            0x3E, 0x03, 0xE0, 0xFF, 0xFB,
+            // loop: HALT; LD A, (LY); LD (HL+), A; LD A, (STAT); LD (HL+), A; JR loop
+            0x76, 0xF0, 0x44, 0x22, 0xF0, 0x41, 0x22, 0x18, 0xF7,
+        ];
+        let mut rom = HeaderBuilder::new().build(&program);
+        // RETI from the VBlank and STAT handlers
+        rom[0x40] = 0xD9;
+        rom[0x48] = 0xD9;
+
+        let mut eager = GameboyHardware::new(Cartridge::new(rom.clone()));
+        let mut lazy = GameboyHardware::new(Cartridge::new(rom));
+        lazy.set_lazy_ppu(true);
+        for _ in 0..5 {
+            eager.run_frame();
+            lazy.run_frame();
+            assert_eq!(lazy.save_state(), eager.save_state());
+            assert_eq!(lazy.scanline_metrics(), eager.scanline_metrics());
+        }
+        assert_eq!(lazy.peek_word(0xC000), eager.peek_word(0xC000));
+        for _ in 0..1000 {
+            assert_eq!(lazy.step(), eager.step());
+        }
+        assert_eq!(lazy.save_state(), eager.save_state());
+    }
+
+    #[test]
+    fn test_joypad_interrupt_on_p1_write() {
+        let mut bus = AddressBus::new(Cartridge::new(rom(0x00)), Model::Dmg);
+        bus.write_byte(0xFF00, 0x30);
+        bus.joypad.set_pressed(Button::B, true);
+        bus.write_byte(0xFF0F, 0x00);
+        bus.write_byte(0xFF00, 0x20);
+        assert_eq!(bus.read_byte(0xFF0F) & InterruptFlags::JOYPAD, 0);
+        bus.write_byte(0xFF00, 0x10);
+        assert_eq!(
+            bus.read_byte(0xFF0F) & InterruptFlags::JOYPAD,
+            InterruptFlags::JOYPAD
+        );
+        assert_eq!(bus.read_byte(0xFF00), 0xDD);
+    }
+
+    #[test]
+    fn test_parts_round_trip() {
+        let mut gameboy = run(0x03);
+        let mut expected = run(0x03);
+        gameboy.set_lazy_ppu(true);
+        gameboy.step();
+        expected.step();
+
+        let gameboy = GameboyHardware::from_parts(gameboy.into_parts());
+        assert!(gameboy.is_lazy_ppu());
+        assert_eq!(gameboy.save_state(), expected.save_state());
+
+        // A fresh CPU on the same bus starts over from the entry point, memory is kept
+        let mut parts = gameboy.into_parts();
+        assert_eq!(parts.bus.model(), Model::Dmg);
+        parts.cpu = Cpu::new();
+        let mut gameboy = GameboyHardware::from_parts(parts);
+        assert_eq!(gameboy.registers().pc, 0x0100);
+        assert_eq!(cartridge_ram(&mut gameboy), 0x42);
+    }
+
+    #[test]
+    fn test_input_display() {
+        let mut gameboy = run(0x03);
+        gameboy.set_button(Button::A, true);
+        gameboy.run_frame();
+        assert_eq!(gameboy.input_display().pressed, Input::from_bits(Input::A));
+
+        // Tapped between frames
+        gameboy.set_button(Button::B, true);
+        gameboy.set_button(Button::B, false);
+        gameboy.run_frame();
+        let display = gameboy.input_display();
+        assert_eq!(display.held, Input::from_bits(Input::A | Input::B));
+        assert_eq!(display.pressed, Input::from_bits(Input::B));
+
+        gameboy.set_button(Button::A, false);
+        gameboy.run_frame();
+        assert_eq!(gameboy.input_display().held, Input::from_bits(Input::A));
+        gameboy.run_frame();
+        let display = gameboy.input_display();
+        assert_eq!(display.held, Input::empty());
+        assert_eq!(display.released, Input::from_bits(Input::A));
+    }
+
+    #[test]
+    fn test_frame_output() {
+        // LD A, 0x41; LDH (SB), A; LD A, 0x81; LDH (SC), A; loop: JR loop
+        let program = [0x3E, 0x41, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_audio_sink(Some(Box::new(AudioRingBuffer::new(48_000, 4096))));
+
+        // The first frame ends early, the transfer completes in one of the first two
+        let first = gameboy.run_frame_output().unwrap().serial_bytes.to_vec();
+        let output = gameboy.run_frame_output().unwrap();
+        assert_eq!([first, output.serial_bytes.to_vec()].concat(), [0x41]);
+        assert!((803..=804).contains(&output.audio_samples.len()));
+
+        let output = gameboy.run_frame_output().unwrap();
+        assert!(output.serial_bytes.is_empty());
+        assert_eq!(output.framebuffer.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn test_run_cycles() {
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom(0x03)));
+        let (sender, receiver) = mpsc::channel();
+        gameboy.set_notification_sink(Some(Box::new(sender)));
+
+        let ran = gameboy.run_cycles(u64::from(10 * FRAME_CYCLES));
+        // Overshoots by less than the longest instruction
+        assert!((0..24).contains(&(ran - u64::from(10 * FRAME_CYCLES))));
+        assert_eq!(gameboy.cycles(), ran);
+        let frames = receiver
+            .try_iter()
+            .filter(|notification| *notification == Notification::FrameCompleted)
+            .count();
+        assert!((9..=10).contains(&frames));
+
+        let second = u64::from(CPU_HZ);
+        assert_eq!(cycles_to_duration(second), Duration::from_secs(1));
+        assert_eq!(cycles_to_duration(second / 4), Duration::from_millis(250));
+        assert_eq!(duration_to_cycles(Duration::from_millis(500)), second / 2);
+        assert_eq!(gameboy.emulated_time(), cycles_to_duration(ran));
+    }
+
+    #[test]
+    fn test_headless() {
+        let mut gameboy = GameboyHardware::new_headless();
+        assert_eq!(gameboy.peek_byte(0x0100), 0xFF);
+
+        // LD A, 0x42; LD (0xC100), A; loop: JR loop
+        gameboy.load_raw(&[0x3E, 0x42, 0xEA, 0x00, 0xC1, 0x18, 0xFE], 0xC000);
+        assert_eq!(gameboy.registers().pc, 0xC000);
+        gameboy.step();
+        gameboy.step();
+        assert_eq!(gameboy.peek_byte(0xC100), 0x42);
+        assert_eq!(gameboy.registers().pc, 0xC005);
+    }
+
+    #[test]
+    fn test_echo_and_unusable_memory() {
+        let mut gameboy = GameboyHardware::new_headless();
+        // LCD off, so OAM isn't blocked
+        gameboy.load_raw(&[0x00], 0xFF40);
+        gameboy.load_raw(&[0x42], 0xE123);
+        assert_eq!(gameboy.peek_byte(0xC123), 0x42);
+        gameboy.load_raw(&[0x24], 0xDD00);
+        assert_eq!(gameboy.peek_byte(0xFD00), 0x24);
+
+        gameboy.load_raw(&[0x99], 0xFEA0);
+        assert_eq!(gameboy.peek_byte(0xFEA0), 0x00);
+    }
+
+    #[test]
+    fn test_oam_bug() {
+        let run = |oam_bug| {
+            let mut gameboy = GameboyHardware::new_headless();
+            assert!(gameboy.has_oam_bug());
+            gameboy.set_oam_bug(oam_bug);
+            gameboy.load_raw(&[0x00], 0xFF40);
+            let pattern: Vec<u8> = (0..160u8).map(|offset| offset.wrapping_mul(0x35)).collect();
+            gameboy.load_raw(&pattern, 0xFE00);
+            let program = [
+                0x3E, 0x91, 0xE0, 0x40, // LD A, 0x91; LDH (LCDC), A
+                0x21, 0x40, 0xFE, // LD HL, 0xFE40
+                0x23, 0x2B, 0x18, 0xFC, // loop: INC HL; DEC HL; JR loop
+            ];
+            gameboy.load_raw(&program, 0xC000);
+            gameboy.run_cycles(FRAME_CYCLES.into());
+            (0xFE00..0xFEA0)
+                .map(|addr| gameboy.peek_byte(addr))
+                .ne(pattern)
+        };
+        assert!(run(true));
+        assert!(!run(false));
+        assert!(!GameboyHardware::with_model(Cartridge::empty(), Model::Cgb).has_oam_bug());
+    }
+
+    #[test]
+    fn test_strictness() {
+        let program = [
+            0x3E, 0x01, 0xEA, 0x00, 0x20, // LD A, 0x01; LD (0x2000), A
+            0xF0, 0x03, // LDH A, (0x03)
+            0xEA, 0x00, 0xC0, // LD (0xC000), A
+            0x18, 0xFE, // loop: JR loop
+        ];
+        // No memory bank controller
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom.clone()));
+        assert_eq!(gameboy.strictness(), Strictness::Permissive);
+        assert!(gameboy.run_frame());
+        assert_eq!(gameboy.take_error(), None);
+        assert_eq!(gameboy.peek_byte(0xC000), 0xFF);
+
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_strictness(Strictness::Strict);
+        assert!(!gameboy.run_frame());
+        assert_eq!(gameboy.registers().pc, 0x155);
+        assert!(!gameboy.run_frame());
+        assert_eq!(
+            gameboy.take_error(),
+            Some(EmulatorError::RomWrite {
+                addr: 0x2000,
+                value: 0x01
+            })
+        );
+        assert_eq!(gameboy.run_cycles(FRAME_CYCLES.into()), 12);
+        assert_eq!(
+            gameboy.take_error(),
+            Some(EmulatorError::UnmappedIo { addr: 0xFF03 })
+        );
+        assert!(gameboy.run_frame());
+        assert_eq!(gameboy.peek_byte(0xC000), 0xFF);
+    }
+
+    #[test]
+    fn test_fast_forward_skip() {
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom(0x03)));
+        assert_eq!(gameboy.speed(), Speed::Multiplier(1.0));
+        gameboy.set_fast_forward_skip(true);
+        gameboy.set_speed(Speed::Multiplier(3.0));
+        let drawn: Vec<_> = (0..8)
+            .map(|_| {
+                gameboy.run_frame();
+                gameboy.frame_drawn()
+            })
+            .collect();
+        // The speed is picked up at the end of the first frame
+        assert_eq!(drawn, [true, true, false, false, true, false, false, true]);
+
+        gameboy.set_speed(Speed::Unlocked);
+        assert_eq!(gameboy.speed(), Speed::Unlocked);
+        assert_eq!(gameboy.handle().frame_duration(), Duration::ZERO);
+        gameboy.set_fast_forward_skip(false);
+        gameboy.run_frame();
+        gameboy.run_frame();
+        assert!(gameboy.frame_drawn());
+    }
+
+    #[test]
+    fn test_serial_output() {
+        let mut gameboy = GameboyHardware::new_headless();
+        // For "OK": LD A, byte; LDH (SB), A; LD A, 0x81; LDH (SC), A; wait: LDH A, (SC);
+        // BIT 7, A; JR NZ, wait
+        let mut program = Vec::new();
+        for byte in b"OK" {
+            program.extend_from_slice(&[0x3E, *byte, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02]);
+            program.extend_from_slice(&[0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA]);
+        }
+        program.extend_from_slice(&[0x18, 0xFE]);
+        gameboy.load_raw(&program, 0xC000);
+
+        // A byte takes 4096 T-cycles at 8192 Hz
+        gameboy.run_cycles(6_000);
+        assert_eq!(gameboy.take_serial_output(), b"O");
+        gameboy.run_frame();
+        gameboy.run_frame();
+        assert_eq!(gameboy.take_serial_output(), b"K");
+        assert!(gameboy.take_serial_output().is_empty());
+    }
+
+    #[test]
+    fn test_interrupt_state() {
+        let mut gameboy = GameboyHardware::new_headless();
+        // LD A, TIMER; LDH (IE), A; EI; HALT
+        gameboy.load_raw(&[0x3E, 0x04, 0xE0, 0xFF, 0xFB, 0x76], 0xC000);
+        for _ in 0..4 {
+            gameboy.step();
+        }
+        // Only VBlank is requested, which isn't enabled
+        assert!(gameboy.is_halted());
+        // EI sets IME once the instruction after it has run, at the start of the next step
+        assert!(!gameboy.ime());
+        gameboy.step();
+        assert!(gameboy.ime());
+        assert_eq!(gameboy.interrupt_enable() & 0x1F, 0x04);
+        assert_eq!(gameboy.interrupt_flags() & 0x1F, 0x01);
+        assert!(!gameboy.is_stopped());
+        assert!(!gameboy.is_locked());
+    }
+
+    #[test]
+    fn test_region_hash() {
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom(0x03)));
+        gameboy.add_hash_region(0xC000..=0xC0FF);
+        // DIV changes every frame
+        gameboy.add_hash_region(0xFF04..=0xFF04);
+        assert_eq!(gameboy.region_hash(), None);
+
+        gameboy.run_frame();
+        let first = gameboy.region_hash().unwrap();
+        assert_eq!(first, gameboy.hash_memory(gameboy.hash_regions()));
+        gameboy.run_frame();
+        assert_ne!(gameboy.region_hash(), Some(first));
+
+        gameboy.clear_hash_regions();
+        gameboy.run_frame();
+        assert_eq!(gameboy.region_hash(), None);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut bus = AddressBus::new(Cartridge::new(rom(0x00)), Model::Dmg);
+        for offset in 0..0xA0 {
+            bus.write_byte(0xC100 + offset, offset as u8 ^ 0x55);
+        }
+        bus.write_byte(0xFF46, 0xC1);
+        assert_eq!(bus.read_byte(0xFF46), 0xC1);
+        bus.tick(8, true);
+        // OAM is busy until the last byte is copied
+        bus.write_byte(0xFE00, 0x00);
+        assert_eq!(bus.read_byte(0xFE00), 0xFF);
+        bus.tick(4 * 159, true);
+        assert_eq!(bus.read_byte(0xFE00), 0xFF);
+        bus.tick(4, true);
+        for offset in 0..0xA0 {
+            assert_eq!(bus.read_byte(0xFE00 + offset), offset as u8 ^ 0x55);
+        }
+    }
+
+    // Keeps OAM DMA, serial transfers, timer reloads and the EI delay in flight
+    #[rustfmt::skip]
+    const TRANSFERS_PROGRAM: [u8; 41] = [
+        0x3E, 0x05, 0xE0, 0x07,             // LD A, 0x05; LDH (TAC), A
+        0x3E, 0xF0, 0xE0, 0x06,             // LD A, 0xF0; LDH (TMA), A
+        0x3E, 0x0C, 0xE0, 0xFF,             // LD A, TIMER | SERIAL; LDH (IE), A
+        0x21, 0x00, 0xC0,                   // LD HL, 0xC000
+        // loop: change the DMA source, start a DMA
+        0x34, 0x2C,                         // INC (HL); INC L
+        0x3E, 0xC0, 0xE0, 0x46,             // LD A, 0xC0; LDH (DMA), A
+        // Start a serial transfer unless one is running
+        0xF0, 0x02, 0xCB, 0x7F, 0x20, 0x04, // LDH A, (SC); BIT 7, A; JR NZ, +4
+        0x3E, 0x81, 0xE0, 0x02,             // LD A, 0x81; LDH (SC), A
+        // Take pending interrupts, then wait for the DMA
+        0xFB, 0x00, 0xF3,                   // EI; NOP; DI
+        0x06, 0x30,                         // LD B, 0x30
+        0x05, 0x20, 0xFD,                   // wait: DEC B; JR NZ, wait
+        0x18, 0xE6,                         // JR loop
+    ];
+
+    #[test]
+    fn test_savestate_round_trip_mid_transfer() {
+        // Instruction boundaries to save at, and instructions to check after loading
+        const SAVE_POINTS: usize = 160;
+        const STEPS: usize = 110;
+        let transfers = || {
+            // Interrupt handlers return right away
+            let mut rom = HeaderBuilder::new().build(&TRANSFERS_PROGRAM);
+            rom[0x50] = 0xD9;
+            rom[0x58] = 0xD9;
+            GameboyHardware::new(Cartridge::new(rom))
+        };
+        let mut gameboy = transfers();
+        // Start partway into a frame, picked by a seeded random source
+        let start = SplitMix64::new(1).next_u64() % 10_000;
+        for _ in 0..start {
+            gameboy.step();
+        }
+        let mut states = Vec::new();
+        let mut dma_active = 0;
+        for _ in 0..SAVE_POINTS + STEPS {
+            dma_active += usize::from(gameboy.bus.oam_dma.is_active());
+            states.push((gameboy.save_state(), gameboy.cycles()));
+            gameboy.step();
+        }
+        assert!(dma_active > SAVE_POINTS / 2);
+
+        // Loading into a new instance, so fields that aren't loaded show up
+        for index in 0..SAVE_POINTS {
+            let mut resumed = transfers();
+            resumed.load_state(&states[index].0).unwrap();
+            let loaded_cycles = resumed.cycles();
+            for step in 1..=STEPS {
+                resumed.step();
+                let (expected, expected_cycles) = &states[index + step];
+                let state = resumed.save_state();
+                if state != *expected {
+                    let diff = resumed.diff_states(expected, &state).unwrap();
+                    panic!("Resumed from save point {index}, {step} steps later:\n{diff}");
+                }
+                assert_eq!(
+                    resumed.cycles() - loaded_cycles,
+                    expected_cycles - states[index].1
+                );
+            }
+        }
     }
 }
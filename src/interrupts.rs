@@ -26,13 +26,14 @@ impl InterruptFlags {
     }
 
     pub const fn flags() -> [Self; 5] {
-        // Ordered from highest to lowest priority
+        // Ordered from highest to lowest priority, without the unused bits so each
+        // flag only matches its own interrupt
         [
-            Self::from_bits(Self::VBLANK),
-            Self::from_bits(Self::STAT),
-            Self::from_bits(Self::TIMER),
-            Self::from_bits(Self::SERIAL),
-            Self::from_bits(Self::JOYPAD),
+            Self(Self::VBLANK),
+            Self(Self::STAT),
+            Self(Self::TIMER),
+            Self(Self::SERIAL),
+            Self(Self::JOYPAD),
         ]
     }
 
@@ -40,6 +41,11 @@ impl InterruptFlags {
         self.0
     }
 
+    /// Clears the unused bits, which are always set otherwise, leaving only interrupts.
+    pub const fn without_unused(self) -> Self {
+        Self(self.0 & !Self::UNUSED)
+    }
+
     pub fn set(&mut self, bits: u8, enable: bool) {
         if enable {
             self.0 |= bits;
@@ -0,0 +1,188 @@
+//! A journal of what happened to the emulator during a session (ROM loads, resets, save
+//! states), so a bug report can show the sequence of actions that led to an issue.
+
+use crate::fault::Subsystem;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An action recorded in a [`Journal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalEvent {
+    /// Recorded when the journal is attached to hardware, see
+    /// [`GameboyHardware::set_journal`].
+    ///
+    /// [`GameboyHardware::set_journal`]: crate::hardware::GameboyHardware::set_journal
+    RomLoaded {
+        title: String,
+        rom_hash: u64,
+    },
+    Reset,
+    PowerCycle,
+    SubsystemReset(Subsystem),
+    StateSaved,
+    StateLoaded,
+    /// An action the core doesn't know about, recorded by the frontend (e.g. a cheat
+    /// toggled or a setting changed).
+    Note(String),
+}
+
+impl Display for JournalEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RomLoaded { title, rom_hash } => {
+                write!(f, "ROM loaded: {title} ({rom_hash:016X})")
+            }
+            Self::Reset => write!(f, "Reset"),
+            Self::PowerCycle => write!(f, "Power cycle"),
+            Self::SubsystemReset(subsystem) => write!(f, "{subsystem:?} reset"),
+            Self::StateSaved => write!(f, "State saved"),
+            Self::StateLoaded => write!(f, "State loaded"),
+            Self::Note(note) => write!(f, "{note}"),
+        }
+    }
+}
+
+/// A [`JournalEvent`] with when it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub time: SystemTime,
+    /// Frames completed since the journal was created.
+    pub frame: u64,
+    pub event: JournalEvent,
+}
+
+impl Display for JournalEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let since_epoch = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "{}.{:03} frame {}: {}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_millis(),
+            self.frame,
+            self.event
+        )
+    }
+}
+
+/// The actions taken during a session, see [`GameboyHardware::set_journal`].
+///
+/// [`GameboyHardware::set_journal`]: crate::hardware::GameboyHardware::set_journal
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    frame: u64,
+}
+
+impl Journal {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `event` as happening now.
+    pub fn record(&mut self, event: JournalEvent) {
+        self.entries.push(JournalEntry {
+            time: SystemTime::now(),
+            frame: self.frame,
+            event,
+        });
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Writes the entries as text, one per line: seconds since the Unix epoch, frame and
+    /// event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    pub fn write_text(&self, mut output: impl Write) -> io::Result<()> {
+        for entry in &self.entries {
+            writeln!(output, "{entry}")?;
+        }
+        output.flush()
+    }
+
+    /// Writes the entries as text to a new file at `path`, see [`Self::write_text`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_text(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::fault::Subsystem;
+    use crate::hardware::GameboyHardware;
+    use crate::journal::{Journal, JournalEvent};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_records_session() {
+        // JR -2
+        let rom = HeaderBuilder::new().title("JOURNAL").build(&[0x18, 0xFE]);
+        let rom_hash = Cartridge::new(rom.clone()).rom_hash();
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_journal(Some(Journal::new()));
+        gameboy.run_frame();
+        let state = gameboy.save_state();
+        gameboy.run_frame();
+        gameboy.reset();
+        gameboy.reset_subsystem(Subsystem::Timer);
+        gameboy.load_state(&state).unwrap();
+        assert!(gameboy.load_state(b"not a savestate").is_err());
+        gameboy.record_event(JournalEvent::Note("Cheat enabled".to_string()));
+
+        let journal = gameboy.set_journal(None).unwrap();
+        let events: Vec<_> = journal
+            .entries()
+            .iter()
+            .map(|entry| (entry.frame, entry.event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (
+                    0,
+                    JournalEvent::RomLoaded {
+                        title: "JOURNAL".to_string(),
+                        rom_hash
+                    }
+                ),
+                (1, JournalEvent::StateSaved),
+                (2, JournalEvent::Reset),
+                (2, JournalEvent::SubsystemReset(Subsystem::Timer)),
+                (2, JournalEvent::StateLoaded),
+                (2, JournalEvent::Note("Cheat enabled".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_text() {
+        let mut journal = Journal::new();
+        journal.record(JournalEvent::PowerCycle);
+        journal.entries[0].time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_250);
+        let mut text = Vec::new();
+        journal.write_text(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "1700000000.250 frame 0: Power cycle\n"
+        );
+    }
+}
@@ -1,4 +1,9 @@
-#[derive(Debug, Clone, Copy)]
+use crate::error::SavestateError;
+use crate::savestate::{StateReader, StateWriter};
+
+/// A button on the console, see
+/// [`GameboyHardware::set_button`](crate::hardware::GameboyHardware::set_button).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Button {
     A,
     B,
@@ -10,44 +15,208 @@ pub enum Button {
     Down,
 }
 
+impl Button {
+    // Action buttons use the low nibble and the D-pad the high nibble,
+    // in the same order as their P1 bits
+    const fn mask(self) -> u8 {
+        match self {
+            Self::A => 0b0000_0001,
+            Self::B => 0b0000_0010,
+            Self::Select => 0b0000_0100,
+            Self::Start => 0b0000_1000,
+            Self::Right => 0b0001_0000,
+            Self::Left => 0b0010_0000,
+            Self::Up => 0b0100_0000,
+            Self::Down => 0b1000_0000,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
-pub struct Joypad(u8);
+pub struct Joypad {
+    // Select lines written to P1, other bits are ignored
+    select: u8,
+    // Buttons currently held, see Button::mask
+    pressed: u8,
+}
 
 impl Joypad {
     const SELECT_BUTTONS: u8 = 0b0010_0000;
     const SELECT_D_PAD: u8 = 0b0001_0000;
-    const START_DOWN: u8 = 0b0000_1000;
-    const SELECT_UP: u8 = 0b0000_0100;
-    const B_LEFT: u8 = 0b0000_0010;
-    const A_RIGHT: u8 = 0b0000_0001;
+    const SELECT: u8 = Self::SELECT_BUTTONS | Self::SELECT_D_PAD;
+    const INPUT: u8 = 0b0000_1111;
     const UNUSED: u8 = 0b1100_0000;
 
     pub const fn new() -> Self {
-        Self::from_bits(0xCF)
+        Self {
+            select: 0,
+            pressed: 0,
+        }
     }
 
-    pub const fn from_bits(byte: u8) -> Self {
-        Self(byte | Self::UNUSED)
+    /// Resets the select lines, buttons held by the player stay held.
+    pub fn reset(&mut self) {
+        self.select = 0;
     }
 
+    /// Returns the value of P1.
+    ///
+    /// Select lines and inputs are active low. Reading with both groups selected
+    /// combines their inputs, and with neither selected all inputs read high.
     pub const fn bits(self) -> u8 {
-        self.0
+        let mut input = Self::INPUT;
+        if self.select & Self::SELECT_BUTTONS == 0 {
+            input &= !self.pressed & Self::INPUT;
+        }
+        if self.select & Self::SELECT_D_PAD == 0 {
+            input &= !(self.pressed >> 4) & Self::INPUT;
+        }
+        Self::UNUSED | self.select | input
+    }
+
+    /// Writes P1, only the select lines are writable.
+    ///
+    /// Returns whether the joypad interrupt is requested: selecting a group with a button
+    /// already held pulls its input line low, just like pressing it.
+    pub fn write(&mut self, value: u8) -> bool {
+        self.update(|joypad| joypad.select = value & Self::SELECT)
+    }
+
+    /// Presses or releases a button, returning whether the joypad interrupt is requested.
+    pub fn set_pressed(&mut self, button: Button, pressed: bool) -> bool {
+        self.update(|joypad| {
+            if pressed {
+                joypad.pressed |= button.mask();
+            } else {
+                joypad.pressed &= !button.mask();
+            }
+        })
     }
 
+    /// Applies `change`, returning whether an input line of P1 went from high to low,
+    /// which is what requests the joypad interrupt.
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> bool {
+        let before = self.bits();
+        change(self);
+        before & !self.bits() & Self::INPUT != 0
+    }
+
+    /// Returns true if any button in the selected groups is held.
     pub const fn is_any_pressed(self) -> bool {
-        self.0 & 0xF != 0xF
+        self.bits() & Self::INPUT != Self::INPUT
     }
 
     pub const fn is_pressed(self, button: Button) -> bool {
-        match button {
-            Button::A => self.0 & (Self::SELECT_BUTTONS | Self::A_RIGHT) == 0x00,
-            Button::B => self.0 & (Self::SELECT_BUTTONS | Self::B_LEFT) == 0x00,
-            Button::Select => self.0 & (Self::SELECT_BUTTONS | Self::SELECT_UP) == 0x00,
-            Button::Start => self.0 & (Self::SELECT_BUTTONS | Self::START_DOWN) == 0x00,
-            Button::Right => self.0 & (Self::SELECT_D_PAD | Self::A_RIGHT) == 0x00,
-            Button::Left => self.0 & (Self::SELECT_D_PAD | Self::B_LEFT) == 0x00,
-            Button::Up => self.0 & (Self::SELECT_D_PAD | Self::SELECT_UP) == 0x00,
-            Button::Down => self.0 & (Self::SELECT_D_PAD | Self::START_DOWN) == 0x00,
+        self.pressed & button.mask() != 0
+    }
+
+    /// Returns the buttons held, in the same bits as `movie::Input`.
+    pub const fn pressed_bits(self) -> u8 {
+        self.pressed
+    }
+
+    pub fn save_state(self, writer: &mut StateWriter) {
+        writer.component("Joypad");
+        writer.field("select");
+        writer.write_u8(self.select);
+        writer.field("pressed");
+        writer.write_u8(self.pressed);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.select = reader.read_u8()? & Self::SELECT;
+        self.pressed = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::joypad::{Button, Joypad};
+
+    fn joypad_with(buttons: &[Button]) -> Joypad {
+        let mut joypad = Joypad::new();
+        for button in buttons {
+            joypad.set_pressed(*button, true);
+        }
+        joypad
+    }
+
+    #[test]
+    fn test_interrupt_on_press() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x20);
+        // The buttons group isn't selected
+        assert!(!joypad.set_pressed(Button::A, true));
+        assert!(joypad.set_pressed(Button::Down, true));
+        // The line is already low
+        assert!(!joypad.set_pressed(Button::Down, true));
+        assert!(!joypad.set_pressed(Button::Down, false));
+    }
+
+    #[test]
+    fn test_interrupt_on_select_write() {
+        let mut joypad = joypad_with(&[Button::Start]);
+        assert!(!joypad.write(0x30));
+        // Start isn't on the D-pad
+        assert!(!joypad.write(0x20));
+        assert!(joypad.write(0x10));
+        assert!(!joypad.write(0x00));
+        assert!(!joypad.write(0x30));
+    }
+
+    #[test]
+    fn test_sgb_detection_reads_one_controller() {
+        // SGB detection sends MLT_REQ, then deselects both groups and checks whether the
+        // low nibble changes to the next controller's ID, which never happens on a DMG
+        let mut joypad = joypad_with(&[Button::A, Button::Right]);
+        for _ in 0..4 {
+            joypad.write(0x30);
+            assert_eq!(joypad.bits() & 0x0F, 0x0F);
+            joypad.write(0x20);
+            joypad.write(0x30);
         }
     }
+
+    #[test]
+    fn test_no_group_selected() {
+        let mut joypad = joypad_with(&[Button::A, Button::Down]);
+        joypad.write(0x30);
+        assert_eq!(joypad.bits(), 0xFF);
+        assert!(!joypad.is_any_pressed());
+    }
+
+    #[test]
+    fn test_buttons_selected() {
+        let mut joypad = joypad_with(&[Button::A, Button::Start, Button::Left]);
+        joypad.write(0x10);
+        assert_eq!(joypad.bits(), 0xD6);
+    }
+
+    #[test]
+    fn test_d_pad_selected() {
+        let mut joypad = joypad_with(&[Button::A, Button::Start, Button::Left]);
+        joypad.write(0x20);
+        assert_eq!(joypad.bits(), 0xED);
+    }
+
+    #[test]
+    fn test_both_groups_selected() {
+        let mut joypad = Joypad::new();
+        assert_eq!(joypad.bits(), 0xCF);
+        joypad.set_pressed(Button::B, true);
+        joypad.set_pressed(Button::Up, true);
+        assert_eq!(joypad.bits(), 0xC9);
+        joypad.set_pressed(Button::B, false);
+        assert_eq!(joypad.bits(), 0xCB);
+    }
+
+    #[test]
+    fn test_only_select_lines_writable() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x0F);
+        assert_eq!(joypad.bits(), 0xCF);
+        joypad.write(0xFF);
+        assert_eq!(joypad.bits(), 0xFF);
+    }
 }
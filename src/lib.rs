@@ -5,13 +5,48 @@
 )]
 
 mod apu;
+pub mod audio;
+pub mod bus_log;
+pub mod capabilities;
 pub mod cartridge;
+pub mod cheat;
+mod clock;
+pub mod consts;
+pub mod coverage;
 mod cpu;
-mod error;
+pub mod crash;
+pub mod disasm;
+pub mod divergence;
+mod dma;
+pub mod error;
+pub mod fault;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod framebuffer;
+pub mod handle;
 pub mod hardware;
 mod interrupts;
+pub mod journal;
 mod joypad;
+pub mod link;
+pub mod mmio;
+pub mod movie;
+pub mod notification;
+pub mod opcodes;
+pub mod overlay;
+pub mod pacer;
+pub mod persistence;
 mod ppu;
+pub mod ppu_debug;
+pub mod printer;
+pub mod rewind;
+pub mod rng;
+pub mod runner;
+pub mod savestate;
 mod serial_port;
+pub mod sgb;
+pub mod tile;
 mod timer;
+pub mod trace;
 mod util;
+pub mod watch;
@@ -0,0 +1,266 @@
+//! Link cable connections between consoles, see
+//! [`GameboyHardware::set_serial_link`](crate::hardware::GameboyHardware::set_serial_link).
+//!
+//! The console driving the clock (internal clock) exchanges a bit with the other end on
+//! every pulse: it sends its outgoing bit and receives the level of the other end's output
+//! line. The other end (external clock) shifts the pulses in as they arrive.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A connection to the device on the other end of the link cable.
+///
+/// [`CableEnd`] connects two consoles in the same process, other implementations could
+/// connect to a console over the network.
+pub trait SerialLink: Send + Sync {
+    /// Clocks one bit out with this console's internal clock, returning the bit shifted in
+    /// from the other end's output line at the same time.
+    fn clock_out(&mut self, out_bit: bool) -> bool;
+
+    /// Returns the next bit clocked in by the other end, if it sent one. Polled every
+    /// M-cycle.
+    fn clock_in(&mut self) -> Option<bool>;
+
+    /// Sets the level of this console's output line, received by the other end when it
+    /// clocks a bit. Only called when the level changes.
+    fn set_line(&mut self, level: bool);
+}
+
+/// Nothing on the other end of the cable: the line floats high, so transfers clocked by the
+/// console read 0xFF, and transfers waiting for an external clock never finish. The same
+/// as having no link.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Disconnected;
+
+impl SerialLink for Disconnected {
+    fn clock_out(&mut self, _out_bit: bool) -> bool {
+        true
+    }
+
+    fn clock_in(&mut self) -> Option<bool> {
+        None
+    }
+
+    fn set_line(&mut self, _level: bool) {}
+}
+
+/// A cable plugged back into the console's own port: transfers it clocks receive the byte
+/// sent.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Loopback;
+
+impl SerialLink for Loopback {
+    fn clock_out(&mut self, out_bit: bool) -> bool {
+        out_bit
+    }
+
+    fn clock_in(&mut self) -> Option<bool> {
+        None
+    }
+
+    fn set_line(&mut self, _level: bool) {}
+}
+
+/// Bytes received by a [`ByteSink`], shared with the console it's plugged into.
+#[derive(Debug, Clone, Default)]
+pub struct SerialOutput {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialOutput {
+    /// Returns the bytes received since the last call.
+    #[must_use]
+    pub fn take_bytes(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.bytes.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// Captures the bytes a console sends with its internal clock, e.g. the results test ROMs
+/// print over the serial port. Transfers read 0xFF, as if nothing was connected.
+#[derive(Debug, Default)]
+pub struct ByteSink {
+    output: SerialOutput,
+    // Bits of the byte being received, most significant first
+    byte: u8,
+    bits: u8,
+}
+
+impl ByteSink {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a handle to the bytes received, kept after the sink is given to a console.
+    #[must_use]
+    pub fn output(&self) -> SerialOutput {
+        self.output.clone()
+    }
+}
+
+impl SerialLink for ByteSink {
+    fn clock_out(&mut self, out_bit: bool) -> bool {
+        self.byte = (self.byte << 1) | u8::from(out_bit);
+        self.bits += 1;
+        if self.bits == 8 {
+            self.output
+                .bytes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(self.byte);
+            self.bits = 0;
+        }
+        true
+    }
+
+    fn clock_in(&mut self) -> Option<bool> {
+        None
+    }
+
+    fn set_line(&mut self, _level: bool) {}
+}
+
+/// The lines going into one console.
+#[derive(Debug)]
+struct Wire {
+    // Output line of the other end
+    line: AtomicBool,
+    // Bits clocked in by the other end and not received yet
+    pulses: Mutex<VecDeque<bool>>,
+}
+
+impl Wire {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            // Pulled high while nothing drives it
+            line: AtomicBool::new(true),
+            pulses: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn pulses(&self) -> std::sync::MutexGuard<'_, VecDeque<bool>> {
+        self.pulses.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// One end of a link cable between two consoles in the same process, see [`link_cable`].
+#[derive(Debug)]
+pub struct CableEnd {
+    incoming: Arc<Wire>,
+    outgoing: Arc<Wire>,
+}
+
+/// Returns the two ends of a link cable, to be given to two consoles.
+///
+/// Bits sent are queued until the other console runs, so consoles should be run in short
+/// alternating slices (e.g. always stepping the one with fewer [`cycles`]) to stay in sync.
+///
+/// [`cycles`]: crate::hardware::GameboyHardware::cycles
+#[must_use]
+pub fn link_cable() -> (CableEnd, CableEnd) {
+    let first = Wire::new();
+    let second = Wire::new();
+    (
+        CableEnd {
+            incoming: Arc::clone(&first),
+            outgoing: Arc::clone(&second),
+        },
+        CableEnd {
+            incoming: second,
+            outgoing: first,
+        },
+    )
+}
+
+impl SerialLink for CableEnd {
+    fn clock_out(&mut self, out_bit: bool) -> bool {
+        self.outgoing.pulses().push_back(out_bit);
+        self.incoming.line.load(Ordering::Relaxed)
+    }
+
+    fn clock_in(&mut self) -> Option<bool> {
+        self.incoming.pulses().pop_front()
+    }
+
+    fn set_line(&mut self, level: bool) {
+        self.outgoing.line.store(level, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use crate::link::{link_cable, ByteSink, Disconnected, Loopback, SerialLink};
+
+    /// Returns a console sending `data` with SC set to `control`, then looping.
+    fn console(data: u8, control: u8) -> GameboyHardware {
+        // LD A, data; LDH (SB), A; LD A, control; LDH (SC), A; loop: JR loop
+        let program = [
+            0x3E, data, 0xE0, 0x01, 0x3E, control, 0xE0, 0x02, 0x18, 0xFE,
+        ];
+        GameboyHardware::new(Cartridge::new(HeaderBuilder::new().build(&program)))
+    }
+
+    #[test]
+    fn test_link_cable_transfer() {
+        let mut master = console(0x5A, 0x81);
+        let mut slave = console(0xC3, 0x80);
+        let (first, second) = link_cable();
+        master.set_serial_link(Some(Box::new(first)));
+        slave.set_serial_link(Some(Box::new(second)));
+        // The slave is ready before the master starts clocking
+        for _ in 0..4 {
+            slave.step();
+        }
+
+        while master.cycles().min(slave.cycles()) < 20_000 {
+            if master.cycles() <= slave.cycles() {
+                master.step();
+            } else {
+                slave.step();
+            }
+        }
+        assert_eq!(master.peek_byte(0xFF01), 0xC3);
+        assert_eq!(slave.peek_byte(0xFF01), 0x5A);
+        for gameboy in [&master, &slave] {
+            // Transfer done and serial interrupt requested
+            assert_eq!(gameboy.peek_byte(0xFF02) & 0x80, 0);
+            assert_eq!(gameboy.peek_byte(0xFF0F) & 0x08, 0x08);
+        }
+    }
+
+    /// Runs a console sending 0x5A with its internal clock through `link`, returning SB.
+    fn transfer(link: Box<dyn SerialLink>) -> u8 {
+        let mut gameboy = console(0x5A, 0x81);
+        gameboy.set_serial_link(Some(link));
+        while gameboy.cycles() < 20_000 {
+            gameboy.step();
+        }
+        gameboy.peek_byte(0xFF01)
+    }
+
+    #[test]
+    fn test_builtin_devices() {
+        assert_eq!(transfer(Box::new(Disconnected)), 0xFF);
+        assert_eq!(transfer(Box::new(Loopback)), 0x5A);
+
+        let sink = ByteSink::new();
+        let output = sink.output();
+        assert_eq!(transfer(Box::new(sink)), 0xFF);
+        assert_eq!(output.take_bytes(), [0x5A]);
+        assert!(output.take_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_unlinked_reads_high() {
+        let mut master = console(0x5A, 0x81);
+        let (first, _second) = link_cable();
+        master.set_serial_link(Some(Box::new(first)));
+        while master.cycles() < 20_000 {
+            master.step();
+        }
+        assert_eq!(master.peek_byte(0xFF01), 0xFF);
+    }
+}
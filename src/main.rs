@@ -1,15 +1,232 @@
-use gb_emulator::cartridge::Cartridge;
+mod control;
+mod sm83_tests;
+mod test_roms;
+#[cfg(feature = "tui")]
+mod tui;
+
+use crate::control::Session;
+use gb_emulator::bus_log::{convert_to_text, BusLogFilter, BusLogger};
+use gb_emulator::cartridge::{find_sub_roms, Cartridge, OverrideTable};
 use gb_emulator::hardware::GameboyHardware;
-use std::{env, fs, io};
+use gb_emulator::journal::Journal;
+use gb_emulator::notification::{Notification, NotificationSink};
+use gb_emulator::opcodes;
+use gb_emulator::pacer::Pacer;
+use gb_emulator::persistence::{read_payload, Payload, PlainCodec};
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::{env, fs, io, process};
+
+const USAGE: &str = "Usage: gb-emulator [run] <rom> [--control-socket <path>]
+       gb-emulator run <multi-rom image> --sub-rom <n>
+       gb-emulator run <rom> --bus-log <path> [<first address>-<last address>]...
+       gb-emulator bus-log <path>
+       gb-emulator tui <rom> [--braille]
+       gb-emulator info <rom>
+       gb-emulator state-diff <rom> <savestate> <savestate>
+       gb-emulator opcodes
+       gb-emulator test-roms <rom or directory>... [--jobs <n>] [--timeout <seconds>] [--json <path>] [--junit <path>] [--coverage]
+       gb-emulator sm83-tests <json file or directory>...
+
+Set GB_EMULATOR_OVERRIDES to a file of header overrides to fix carts with a wrong header.";
+
+// Path of a file with header overrides for ROMs, see `OverrideTable::parse`
+const OVERRIDES_VAR: &str = "GB_EMULATOR_OVERRIDES";
+
+// Long enough for games waiting on a button with interrupts disabled to poll the joypad
+const SOFT_LOCK_FRAMES: u32 = 300;
+// Instructions kept for post-mortem debugging, and how many of them are printed
+const TRACE_CAPACITY: usize = 1024;
+const TRACE_DUMP_LENGTH: usize = 32;
 
 fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let rom = fs::read(&args[1])?;
-    let cartridge = Cartridge::new(rom);
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["info", path] => info(path),
+        ["state-diff", path, first, second] => state_diff(path, first, second),
+        ["opcodes"] => io::stdout().write_all(opcodes::table_json().as_bytes()),
+        ["bus-log", path] => convert_to_text(fs::File::open(path)?, io::stdout().lock()),
+        ["run", path, "--bus-log", log, ranges @ ..] => {
+            let Some(filter) = parse_bus_log_filter(ranges) else {
+                eprintln!("{USAGE}");
+                process::exit(2);
+            };
+            run_with_bus_log(path, BusLogger::create(log, filter)?)
+        }
+        ["test-roms", ..] => {
+            if !test_roms::main(&args[1..])? {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        ["sm83-tests", ..] => {
+            if !sm83_tests::main(&args[1..])? {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        #[cfg(feature = "tui")]
+        ["tui", path] => run_tui(path, tui::Renderer::HalfBlock),
+        #[cfg(feature = "tui")]
+        ["tui", path, "--braille"] => run_tui(path, tui::Renderer::Braille),
+        ["run", path] => run(path, None, None),
+        ["run", path, "--control-socket", socket] | [path, "--control-socket", socket]
+            if *path != "info" =>
+        {
+            run(path, None, Some(socket))
+        }
+        ["run", path, "--sub-rom", index] if index.parse::<usize>().is_ok() => {
+            run(path, index.parse().ok(), None)
+        }
+        [path]
+            if ![
+                "info",
+                "test-roms",
+                "sm83-tests",
+                "tui",
+                "bus-log",
+                "state-diff",
+                "opcodes",
+            ]
+            .contains(path) =>
+        {
+            run(path, None, None)
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    }
+}
+
+fn info(path: &str) -> io::Result<()> {
+    let rom = fs::read(path)?;
+    let sub_roms = find_sub_roms(&rom);
+    let cartridge = Cartridge::with_overrides(rom, &read_overrides()?);
+    for warning in cartridge.warnings() {
+        println!("Warning: {warning}");
+    }
+
+    let mut features = vec![cartridge.get_mbc_name()];
+    if cartridge.has_ram() {
+        features.push("RAM");
+    }
+    if cartridge.has_battery() {
+        features.push("BATTERY");
+    }
+    if cartridge.has_timer() {
+        features.push("TIMER");
+    }
+    if cartridge.has_rumble() {
+        features.push("RUMBLE");
+    }
 
     println!("Title: {}", cartridge.get_title());
+    println!("Licensee: {}", cartridge.get_licensee());
+    println!("Cartridge Type: {}", features.join("+"));
+    println!("CGB Support: {:?}", cartridge.cgb_support());
+    println!("SGB Support: {}", cartridge.supports_sgb());
+    println!("Destination: {:?}", cartridge.destination());
     println!("ROM Size: {}", cartridge.get_rom_size());
     println!("RAM Size: {}", cartridge.get_ram_size());
+    println!("ROM Hash: {:016X}", cartridge.rom_hash());
+    println!("Logo: {}", check_status(cartridge.passed_logo_check()));
+    println!(
+        "Header Checksum: {}",
+        check_status(cartridge.passed_header_check())
+    );
+    println!(
+        "Global Checksum: {}",
+        check_status(cartridge.passed_global_check())
+    );
+    if sub_roms.len() > 1 {
+        println!("Contained ROMs (run with --sub-rom <n>):");
+        for (index, sub_rom) in sub_roms.iter().enumerate() {
+            println!(
+                "  {index}: {} at {:#X}, {} bytes",
+                sub_rom.title, sub_rom.offset, sub_rom.size
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Prints the fields that differ between two savestates of a ROM.
+fn state_diff(path: &str, first: &str, second: &str) -> io::Result<()> {
+    let gameboy = GameboyHardware::new(load_cartridge(path, None)?);
+    let first = read_payload(first, Payload::Savestate, &PlainCodec)?;
+    let second = read_payload(second, Payload::Savestate, &PlainCodec)?;
+    let diff = gameboy
+        .diff_states(&first, &second)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    print!("{diff}");
+    Ok(())
+}
+
+const fn check_status(passed: bool) -> &'static str {
+    if passed {
+        "OK"
+    } else {
+        "FAILED"
+    }
+}
+
+/// Reads the header overrides from the file named by the environment variable, if set.
+fn read_overrides() -> io::Result<OverrideTable> {
+    let Some(path) = env::var_os(OVERRIDES_VAR) else {
+        return Ok(OverrideTable::new());
+    };
+    OverrideTable::parse(&fs::read_to_string(path)?).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{OVERRIDES_VAR}: {err}"),
+        )
+    })
+}
+
+/// Reads a ROM, or one of the games in a multi-ROM image, and its battery save,
+/// warning about failed checksums.
+fn load_cartridge(path: &str, sub_rom: Option<usize>) -> io::Result<Cartridge> {
+    let rom = fs::read(path)?;
+    let overrides = read_overrides()?;
+    let (mut cartridge, save_path) = match sub_rom {
+        Some(index) => {
+            let sub_roms = find_sub_roms(&rom);
+            let sub_rom = sub_roms.get(index).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{path} contains {} ROMs, no ROM {index}", sub_roms.len()),
+                )
+            })?;
+            (
+                Cartridge::with_overrides(sub_rom.extract(&rom), &overrides),
+                Path::new(path).with_extension(format!("{index}.sav")),
+            )
+        }
+        None => (
+            Cartridge::with_overrides(rom, &overrides),
+            Path::new(path).with_extension("sav"),
+        ),
+    };
+    if cartridge.has_battery() && save_path.exists() {
+        let save = read_payload(&save_path, Payload::SaveRam, &PlainCodec)?;
+        if let Err(err) = cartridge.load_save(&save) {
+            println!(
+                "Warning: Ignoring save file {}: {err}.",
+                save_path.display()
+            );
+        }
+    }
+
+    if !cartridge.passed_logo_check() {
+        println!("Warning: Cartridge header has no Nintendo logo. Real hardware would refuse to boot it.");
+    }
 
     if !cartridge.passed_header_check() {
         println!(
@@ -23,8 +240,124 @@ fn main() -> io::Result<()> {
         );
     }
 
+    Ok(cartridge)
+}
+
+/// Prints diagnostics raised by the emulator as warnings.
+struct PrintDiagnostics;
+
+impl NotificationSink for PrintDiagnostics {
+    fn notify(&mut self, notification: &Notification) {
+        if let Notification::DiagnosticRaised(message) = notification {
+            println!("Warning: {message}");
+        }
+    }
+}
+
+/// Creates the hardware with diagnostics printed, and crash detection, the trace buffer
+/// and the journal enabled.
+fn new_gameboy(cartridge: Cartridge) -> GameboyHardware {
     let mut gameboy = GameboyHardware::new(cartridge);
-    loop {
-        gameboy.step();
+    gameboy.set_notification_sink(Some(Box::new(PrintDiagnostics)));
+    gameboy.set_crash_detection(Some(SOFT_LOCK_FRAMES));
+    gameboy.set_trace_buffer(Some(TRACE_CAPACITY));
+    gameboy.set_journal(Some(Journal::new()));
+    gameboy
+}
+
+/// Prints the last instructions executed, for context after a crash.
+fn print_trace(gameboy: &GameboyHardware) {
+    println!("Last instructions executed:");
+    for instruction in gameboy.trace_tail(TRACE_DUMP_LENGTH) {
+        println!("  {instruction}");
+    }
+}
+
+/// Runs `f`, printing the trace if the emulator panics.
+fn print_trace_on_panic<T>(session: &mut Session, f: impl FnOnce(&mut Session) -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(|| f(session))) {
+        Ok(value) => value,
+        Err(payload) => {
+            print_trace(&session.gameboy);
+            panic::resume_unwind(payload)
+        }
     }
 }
+
+/// Runs `f`, printing the trace if it made the game crash.
+fn print_trace_on_crash<T>(
+    gameboy: &mut GameboyHardware,
+    f: impl FnOnce(&mut GameboyHardware) -> T,
+) -> T {
+    let crashed = gameboy.crash().is_some();
+    let value = f(gameboy);
+    if !crashed && gameboy.crash().is_some() {
+        print_trace(gameboy);
+    }
+    value
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(path: &str, renderer: tui::Renderer) -> io::Result<()> {
+    let gameboy = new_gameboy(load_cartridge(path, None)?);
+    let session = Session {
+        gameboy,
+        paused: false,
+        frame: 0,
+    };
+    tui::run(session, renderer)
+}
+
+/// Parses address ranges written as hexadecimal `C000-DFFF`, `None` if one is malformed.
+fn parse_bus_log_filter(ranges: &[&str]) -> Option<BusLogFilter> {
+    ranges
+        .iter()
+        .try_fold(BusLogFilter::new(), |filter, range| {
+            let (first, last) = range.split_once('-')?;
+            let first = u16::from_str_radix(first, 16).ok()?;
+            let last = u16::from_str_radix(last, 16).ok()?;
+            Some(filter.range(first..=last))
+        })
+}
+
+/// Runs until a crash is detected, logging bus transactions.
+fn run_with_bus_log(path: &str, logger: BusLogger) -> io::Result<()> {
+    let mut gameboy = new_gameboy(load_cartridge(path, None)?);
+    gameboy.set_bus_logger(Some(logger));
+    while gameboy.crash().is_none() {
+        print_trace_on_crash(&mut gameboy, GameboyHardware::run_frame);
+    }
+    gameboy
+        .set_bus_logger(None)
+        .map_or(Ok(()), BusLogger::finish)
+}
+
+fn run(path: &str, sub_rom: Option<usize>, control_socket: Option<&str>) -> io::Result<()> {
+    let mut session = Session {
+        gameboy: new_gameboy(load_cartridge(path, sub_rom)?),
+        paused: false,
+        frame: 0,
+    };
+    let requests = control_socket.map(control::listen).transpose()?;
+
+    // Runs in real time, so games play at their speed and commands line up with what a
+    // player would see
+    let mut pacer = Pacer::new();
+    print_trace_on_panic(&mut session, |session| loop {
+        for (command, reply) in requests.iter().flat_map(|requests| requests.try_iter()) {
+            let (response, quit) = session.execute(command);
+            let _ = reply.send(response);
+            if quit {
+                if let Some(control_socket) = control_socket {
+                    let _ = fs::remove_file(control_socket);
+                }
+                return Ok(());
+            }
+        }
+        if !session.paused {
+            print_trace_on_crash(&mut session.gameboy, GameboyHardware::run_frame);
+            session.frame += 1;
+        }
+        pacer.wait(session.gameboy.cycles());
+    })
+}
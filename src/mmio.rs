@@ -0,0 +1,135 @@
+//! Handlers supplied by the host for address ranges anywhere on the bus, for host-backed
+//! RAM, logging devices or custom peripherals for homebrew. See
+//! [`GameboyHardware::attach_bus_device`](crate::hardware::GameboyHardware::attach_bus_device).
+
+use std::ops::RangeInclusive;
+
+/// Custom hardware intercepting CPU accesses to an address range.
+///
+/// Devices are checked before the console's own memory and registers, including the
+/// unusable areas (0xE000-0xFDFF and 0xFEA0-0xFEFF) which otherwise can't be accessed.
+/// Addresses passed to a device are absolute. Like
+/// [`CartridgeDevice`](crate::cartridge::CartridgeDevice), but not limited to the cartridge
+/// address space.
+pub trait BusDevice: Send + Sync {
+    /// Reads a byte without side effects, returns `None` to let the console handle the read.
+    fn peek(&self, addr: u16) -> Option<u8>;
+
+    /// Reads a byte on behalf of the CPU, returns `None` to let the console handle the read.
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    /// Writes a byte, returns `false` to let the console handle the write.
+    fn write(&mut self, addr: u16, value: u8) -> bool;
+}
+
+struct MappedDevice {
+    range: RangeInclusive<u16>,
+    device: Box<dyn BusDevice>,
+}
+
+/// Devices attached to the bus, later ones taking precedence where ranges overlap.
+pub(crate) struct BusDevices {
+    devices: Vec<MappedDevice>,
+}
+
+impl BusDevices {
+    pub const fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn attach(&mut self, range: RangeInclusive<u16>, device: Box<dyn BusDevice>) {
+        self.devices.push(MappedDevice { range, device });
+    }
+
+    pub fn clear(&mut self) {
+        self.devices.clear();
+    }
+
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        self.devices
+            .iter()
+            .rev()
+            .filter(|mapped| mapped.range.contains(&addr))
+            .find_map(|mapped| mapped.device.peek(addr))
+    }
+
+    pub fn read(&mut self, addr: u16) -> Option<u8> {
+        self.devices
+            .iter_mut()
+            .rev()
+            .filter(|mapped| mapped.range.contains(&addr))
+            .find_map(|mapped| mapped.device.read(addr))
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) -> bool {
+        self.devices
+            .iter_mut()
+            .rev()
+            .filter(|mapped| mapped.range.contains(&addr))
+            .any(|mapped| mapped.device.write(addr, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::GameboyHardware;
+    use crate::mmio::BusDevice;
+    use std::sync::atomic::{AtomicU8, Ordering};
+    use std::sync::Arc;
+
+    /// RAM in the unusable area, and a port at 0xFF7F logging writes.
+    struct Peripheral {
+        ram: [u8; 0x60],
+        port: Arc<AtomicU8>,
+    }
+
+    impl BusDevice for Peripheral {
+        fn peek(&self, addr: u16) -> Option<u8> {
+            match addr {
+                0xFEA0..=0xFEFF => Some(self.ram[usize::from(addr - 0xFEA0)]),
+                _ => None,
+            }
+        }
+
+        fn write(&mut self, addr: u16, value: u8) -> bool {
+            match addr {
+                0xFEA0..=0xFEFF => self.ram[usize::from(addr - 0xFEA0)] = value,
+                0xFF7F => self.port.store(value, Ordering::Relaxed),
+                _ => return false,
+            }
+            true
+        }
+    }
+
+    #[test]
+    fn test_bus_device() {
+        let port = Arc::new(AtomicU8::new(0));
+        let mut gameboy = GameboyHardware::new_headless();
+        gameboy.attach_bus_device(
+            0xFEA0..=0xFF7F,
+            Box::new(Peripheral {
+                ram: [0; 0x60],
+                port: Arc::clone(&port),
+            }),
+        );
+        gameboy.load_raw(
+            &[
+                0x3E, 0x42, 0xEA, 0xA0, 0xFE, // LD A, 0x42; LD (0xFEA0), A
+                0x3C, 0xE0, 0x7F, // INC A; LDH (0xFF7F), A
+                0xF0, 0x40, 0xEA, 0x00, 0xC1, // LDH A, (LCDC); LD (0xC100), A
+            ],
+            0xC000,
+        );
+        for _ in 0..6 {
+            gameboy.step();
+        }
+        assert_eq!(gameboy.peek_byte(0xFEA0), 0x42);
+        assert_eq!(port.load(Ordering::Relaxed), 0x43);
+        // Registers the device doesn't handle are left to the console
+        assert_eq!(gameboy.peek_byte(0xC100), gameboy.peek_byte(0xFF40));
+    }
+}
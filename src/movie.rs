@@ -0,0 +1,597 @@
+mod bk2;
+mod vbm;
+
+use crate::error::MovieError;
+use crate::hardware::GameboyHardware;
+use crate::overlay::InputDisplay;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+
+const MOVIE_MAGIC: &[u8; 4] = b"GBMV";
+const MOVIE_VERSION: u16 = 3;
+const MOVIE_HEADER_SIZE: usize = 22;
+
+/// Buttons held during a single frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Input(u8);
+
+impl Input {
+    pub const A: u8 = 0b0000_0001;
+    pub const B: u8 = 0b0000_0010;
+    pub const SELECT: u8 = 0b0000_0100;
+    pub const START: u8 = 0b0000_1000;
+    pub const RIGHT: u8 = 0b0001_0000;
+    pub const LEFT: u8 = 0b0010_0000;
+    pub const UP: u8 = 0b0100_0000;
+    pub const DOWN: u8 = 0b1000_0000;
+
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self::from_bits(0)
+    }
+
+    #[must_use]
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    #[must_use]
+    pub const fn contains(self, bits: u8) -> bool {
+        (self.0 & bits) == bits
+    }
+
+    pub fn set(&mut self, bits: u8, enable: bool) {
+        if enable {
+            self.0 |= bits;
+        } else {
+            self.0 &= !bits;
+        }
+    }
+}
+
+/// The first frame where the hashed memory regions differ from the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl Display for Desync {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "desync at frame {}: region hash {:016X}, expected {:016X}",
+            self.frame, self.actual, self.expected
+        )
+    }
+}
+
+/// A recording of the input for every frame, starting from power on or a savestate.
+///
+/// The native format is a small header followed by one byte of [`Input`] per frame,
+/// then the hashed memory regions and the hash after each frame if any, and the savestate
+/// the movie starts from if any. Movies can also be converted to and from formats used by
+/// other emulators' TAS tools, which drop the hashes and the savestate.
+///
+/// See [`MovieRecorder`] and [`MoviePlayer`] for running a console with a movie.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Movie {
+    /// Hash of the ROM the movie was recorded with, see `Cartridge::rom_hash`.
+    pub rom_hash: u64,
+    /// Number of times a savestate was loaded while recording.
+    pub rerecords: u32,
+    /// Memory regions hashed after each frame, see `GameboyHardware::add_hash_region`.
+    pub hash_regions: Vec<RangeInclusive<u16>>,
+    /// Savestate the movie starts from, `None` to start from power on.
+    pub start_state: Option<Vec<u8>>,
+    frames: Vec<Input>,
+    hashes: Vec<u64>,
+}
+
+impl Movie {
+    #[must_use]
+    pub const fn new(rom_hash: u64) -> Self {
+        Self {
+            rom_hash,
+            rerecords: 0,
+            hash_regions: Vec::new(),
+            start_state: None,
+            frames: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn frames(&self) -> &[Input] {
+        &self.frames
+    }
+
+    #[must_use]
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn push_frame(&mut self, input: Input) {
+        self.frames.push(input);
+    }
+
+    /// Returns the input display for `frame`, the movie starting with no buttons held.
+    #[must_use]
+    pub fn input_display(&self, frame: usize) -> Option<InputDisplay> {
+        let current = *self.frames.get(frame)?;
+        let previous = frame
+            .checked_sub(1)
+            .map_or(Input::empty(), |previous| self.frames[previous]);
+        Some(InputDisplay::new(previous, current))
+    }
+
+    /// Adds a frame along with `GameboyHardware::region_hash` after running it.
+    ///
+    /// Hashes are only kept if every frame has one.
+    pub fn push_frame_with_hash(&mut self, input: Input, hash: u64) {
+        if self.hashes.len() == self.frames.len() {
+            self.hashes.push(hash);
+        }
+        self.frames.push(input);
+    }
+
+    /// Returns the region hash recorded after `frame`.
+    #[must_use]
+    pub fn frame_hash(&self, frame: usize) -> Option<u64> {
+        if self.hashes.len() == self.frames.len() {
+            self.hashes.get(frame).copied()
+        } else {
+            None
+        }
+    }
+
+    /// Compares the region hash after playing back `frame` with the recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns the desync if the movie has a different hash for the frame.
+    pub fn check_frame_hash(&self, frame: usize, actual: u64) -> Result<(), Desync> {
+        match self.frame_hash(frame) {
+            Some(expected) if expected != actual => Err(Desync {
+                frame,
+                expected,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Discards every frame after `frame_count`, e.g. when rerecording from a savestate.
+    pub fn truncate(&mut self, frame_count: usize) {
+        self.frames.truncate(frame_count);
+        self.hashes.truncate(frame_count);
+    }
+
+    /// Serializes the movie to the native format.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MOVIE_HEADER_SIZE + self.frames.len());
+        bytes.extend_from_slice(MOVIE_MAGIC);
+        bytes.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.rerecords.to_le_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        let frame_count = self.frames.len() as u32;
+        bytes.extend_from_slice(&frame_count.to_le_bytes());
+        bytes.extend(self.frames.iter().map(|input| input.bits()));
+
+        #[allow(clippy::cast_possible_truncation)]
+        let region_count = self.hash_regions.len() as u16;
+        bytes.extend_from_slice(&region_count.to_le_bytes());
+        for region in &self.hash_regions {
+            bytes.extend_from_slice(&region.start().to_le_bytes());
+            bytes.extend_from_slice(&region.end().to_le_bytes());
+        }
+        let has_hashes = self.hashes.len() == self.frames.len() && !self.hashes.is_empty();
+        bytes.push(u8::from(has_hashes));
+        if has_hashes {
+            for hash in &self.hashes {
+                bytes.extend_from_slice(&hash.to_le_bytes());
+            }
+        }
+        // An empty state for power on
+        let start_state = self.start_state.as_deref().unwrap_or_default();
+        #[allow(clippy::cast_possible_truncation)]
+        let start_state_len = start_state.len() as u32;
+        bytes.extend_from_slice(&start_state_len.to_le_bytes());
+        bytes.extend_from_slice(start_state);
+        bytes
+    }
+
+    /// Deserializes a movie in the native format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data isn't a movie, uses an unsupported version, or is truncated.
+    /// Version 1 movies, without hashes, and version 2 movies, without a start state, are
+    /// still accepted.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        if bytes.len() < MOVIE_HEADER_SIZE || !bytes.starts_with(MOVIE_MAGIC) {
+            return Err(MovieError::InvalidFormat);
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if !(1..=MOVIE_VERSION).contains(&version) {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = u64::from_le_bytes(bytes[6..14].try_into().unwrap());
+        let rerecords = u32::from_le_bytes(bytes[14..18].try_into().unwrap());
+        let frame_count = u32::from_le_bytes(bytes[18..22].try_into().unwrap()) as usize;
+
+        let rest = &bytes[MOVIE_HEADER_SIZE..];
+        let (frames, mut rest) = if version == 1 {
+            (rest, &[][..])
+        } else {
+            rest.split_at_checked(frame_count)
+                .ok_or(MovieError::FrameCountMismatch {
+                    expected: frame_count,
+                    actual: rest.len(),
+                })?
+        };
+        if frames.len() != frame_count {
+            return Err(MovieError::FrameCountMismatch {
+                expected: frame_count,
+                actual: frames.len(),
+            });
+        }
+
+        let mut movie = Self::new(rom_hash);
+        movie.rerecords = rerecords;
+        movie.frames = frames.iter().copied().map(Input::from_bits).collect();
+        if version == 1 {
+            return Ok(movie);
+        }
+
+        let mut take = |len: usize| {
+            let (taken, remaining) = rest
+                .split_at_checked(len)
+                .ok_or(MovieError::InvalidFormat)?;
+            rest = remaining;
+            Ok(taken)
+        };
+        let region_count = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        for _ in 0..region_count {
+            let region = take(4)?;
+            let start = u16::from_le_bytes([region[0], region[1]]);
+            let end = u16::from_le_bytes([region[2], region[3]]);
+            movie.hash_regions.push(start..=end);
+        }
+        if take(1)?[0] != 0 {
+            movie.hashes = take(8 * frame_count)?
+                .chunks_exact(8)
+                .map(|hash| u64::from_le_bytes(hash.try_into().unwrap()))
+                .collect();
+        }
+        if version >= 3 {
+            let start_state_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            if start_state_len > 0 {
+                movie.start_state = Some(take(start_state_len)?.to_vec());
+            }
+        }
+        if !rest.is_empty() {
+            return Err(MovieError::InvalidFormat);
+        }
+        Ok(movie)
+    }
+}
+
+/// Records a movie while running a console, one frame at a time.
+///
+/// Frames are recorded with the region hash after each one when the console hashes memory
+/// regions (see [`GameboyHardware::add_hash_region`]), so playback can detect desyncs.
+#[derive(Debug)]
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Power cycles `gameboy` and starts recording from there.
+    ///
+    /// Playback is only deterministic if the console is set up the same way, e.g. with the
+    /// same RAM initialization and random seed, and the same battery-backed RAM.
+    pub fn from_power_on(gameboy: &mut GameboyHardware) -> Self {
+        gameboy.power_cycle();
+        Self::start(gameboy, None)
+    }
+
+    /// Starts recording from the current state of `gameboy`, saved in the movie.
+    #[must_use]
+    pub fn from_current_state(gameboy: &GameboyHardware) -> Self {
+        Self::start(gameboy, Some(gameboy.save_state()))
+    }
+
+    fn start(gameboy: &GameboyHardware, start_state: Option<Vec<u8>>) -> Self {
+        let mut movie = Movie::new(gameboy.rom_hash());
+        movie.hash_regions = gameboy.hash_regions().to_vec();
+        movie.start_state = start_state;
+        Self { movie }
+    }
+
+    /// Runs a frame with the buttons in `input` held, see [`GameboyHardware::run_frame`].
+    /// Nothing is recorded if the frame didn't run.
+    pub fn run_frame(&mut self, gameboy: &mut GameboyHardware, input: Input) -> bool {
+        gameboy.set_input(input);
+        if !gameboy.run_frame() {
+            return false;
+        }
+        match gameboy.region_hash() {
+            Some(hash) => self.movie.push_frame_with_hash(input, hash),
+            None => self.movie.push_frame(input),
+        }
+        true
+    }
+
+    #[must_use]
+    pub const fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Plays a movie back on a console, one frame at a time, checking the region hash after
+/// each frame against the recording.
+#[derive(Debug)]
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    /// Sets up `gameboy` at the start of `movie`: loads its start state or power cycles, and
+    /// hashes the movie's memory regions instead of its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the movie was recorded with another ROM, or if its start state
+    /// can't be loaded. Movies converted from other formats don't have a ROM hash to check.
+    pub fn new(movie: Movie, gameboy: &mut GameboyHardware) -> Result<Self, MovieError> {
+        let actual = gameboy.rom_hash();
+        if movie.rom_hash != 0 && movie.rom_hash != actual {
+            return Err(MovieError::RomMismatch {
+                expected: movie.rom_hash,
+                actual,
+            });
+        }
+        match &movie.start_state {
+            Some(state) => gameboy
+                .load_state(state)
+                .map_err(MovieError::InvalidStartState)?,
+            None => gameboy.power_cycle(),
+        }
+        gameboy.clear_hash_regions();
+        for region in &movie.hash_regions {
+            gameboy.add_hash_region(region.clone());
+        }
+        Ok(Self { movie, frame: 0 })
+    }
+
+    /// Runs the next frame with its recorded input, returning `None` once every frame has
+    /// been played. A frame that didn't run (see [`GameboyHardware::run_frame`]) is played
+    /// again on the next call.
+    ///
+    /// # Errors
+    ///
+    /// Returns the desync if the region hash after the frame differs from the recording.
+    /// Playback can go on after a desync, but later frames are unlikely to match.
+    pub fn run_frame(&mut self, gameboy: &mut GameboyHardware) -> Option<Result<(), Desync>> {
+        let input = *self.movie.frames().get(self.frame)?;
+        gameboy.set_input(input);
+        if !gameboy.run_frame() {
+            return Some(Ok(()));
+        }
+        let frame = self.frame;
+        self.frame += 1;
+        Some(match gameboy.region_hash() {
+            Some(hash) => self.movie.check_frame_hash(frame, hash),
+            None => Ok(()),
+        })
+    }
+
+    /// Returns the number of frames played.
+    #[must_use]
+    pub const fn frame(&self) -> usize {
+        self.frame
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.frame == self.movie.frame_count()
+    }
+
+    #[must_use]
+    pub const fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::error::MovieError;
+    use crate::hardware::GameboyHardware;
+    use crate::movie::{Desync, Input, Movie, MoviePlayer, MovieRecorder};
+
+    fn sample_movie() -> Movie {
+        let mut movie = Movie::new(0x0123_4567_89AB_CDEF);
+        movie.rerecords = 42;
+        movie.push_frame(Input::empty());
+        movie.push_frame(Input::from_bits(Input::A | Input::RIGHT));
+        movie.push_frame(Input::from_bits(Input::START | Input::DOWN | Input::SELECT));
+        movie
+    }
+
+    #[test]
+    fn test_native_round_trip() {
+        let movie = sample_movie();
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()), Ok(movie));
+    }
+
+    #[test]
+    fn test_native_round_trip_with_hashes() {
+        let mut movie = Movie::new(1);
+        movie.hash_regions = vec![0xC000..=0xC0FF, 0xFF80..=0xFF8F];
+        movie.push_frame_with_hash(Input::empty(), 10);
+        movie.push_frame_with_hash(Input::from_bits(Input::B), 20);
+        let bytes = movie.to_bytes();
+        assert_eq!(Movie::from_bytes(&bytes), Ok(movie.clone()));
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MovieError::InvalidFormat)
+        );
+
+        assert_eq!(movie.check_frame_hash(1, 20), Ok(()));
+        assert_eq!(
+            movie.check_frame_hash(1, 21),
+            Err(Desync {
+                frame: 1,
+                expected: 20,
+                actual: 21
+            })
+        );
+        // Without a hash for every frame there is nothing to check against
+        movie.push_frame(Input::empty());
+        assert_eq!(movie.check_frame_hash(1, 21), Ok(()));
+    }
+
+    #[test]
+    fn test_native_round_trip_with_start_state() {
+        let mut movie = sample_movie();
+        movie.start_state = Some(vec![1, 2, 3]);
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()), Ok(movie));
+    }
+
+    fn gameboy() -> GameboyHardware {
+        let program = [
+            0x3E, 0x10, 0xE0, 0x00, // LD A, 0x10; LDH (P1), A: select the action buttons
+            0xF0, 0x00, // LDH A, (P1)
+            0x21, 0x00, 0xC0, 0x86, 0x77, // LD HL, 0xC000; ADD A, (HL); LD (HL), A
+            0x18, 0xF3, // JR to the start
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.add_hash_region(0xC000..=0xC000);
+        gameboy
+    }
+
+    fn play(movie: Movie, gameboy: &mut GameboyHardware) -> Result<(), Desync> {
+        let mut player = MoviePlayer::new(movie, gameboy).unwrap();
+        while let Some(result) = player.run_frame(gameboy) {
+            result?;
+        }
+        assert!(player.is_finished());
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_and_play_back() {
+        let mut gameboy = gameboy();
+        gameboy.run_frame();
+        let mut recorder = MovieRecorder::from_power_on(&mut gameboy);
+        for frame in 0..20 {
+            let input = Input::from_bits(if frame % 3 == 0 { Input::A } else { 0 });
+            assert!(recorder.run_frame(&mut gameboy, input));
+        }
+        let movie = recorder.finish();
+        assert!(movie.frame_hash(19).is_some());
+        assert_eq!(play(movie.clone(), &mut gameboy), Ok(()));
+
+        // Pressing B instead of A on frame 6 changes the sum from there
+        let mut changed = Movie::new(movie.rom_hash);
+        changed.hash_regions.clone_from(&movie.hash_regions);
+        for (frame, input) in movie.frames().iter().enumerate() {
+            let input = if frame == 6 {
+                Input::from_bits(Input::B)
+            } else {
+                *input
+            };
+            changed.push_frame_with_hash(input, movie.frame_hash(frame).unwrap());
+        }
+        assert!(matches!(
+            play(changed, &mut gameboy),
+            Err(Desync { frame: 6, .. })
+        ));
+
+        let mut other = GameboyHardware::new(Cartridge::new(HeaderBuilder::new().build(&[])));
+        assert!(matches!(
+            MoviePlayer::new(movie, &mut other),
+            Err(MovieError::RomMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_record_from_state() {
+        let mut gameboy = gameboy();
+        gameboy.set_input(Input::from_bits(Input::B));
+        for _ in 0..5 {
+            gameboy.run_frame();
+        }
+        let mut recorder = MovieRecorder::from_current_state(&gameboy);
+        for _ in 0..10 {
+            recorder.run_frame(&mut gameboy, Input::from_bits(Input::START));
+        }
+        let movie = Movie::from_bytes(&recorder.finish().to_bytes()).unwrap();
+        assert!(movie.start_state.is_some());
+
+        gameboy.power_cycle();
+        assert_eq!(play(movie, &mut gameboy), Ok(()));
+    }
+
+    #[test]
+    fn test_input_display() {
+        let movie = sample_movie();
+        let display = movie.input_display(2).unwrap();
+        assert_eq!(display.held, movie.frames()[2]);
+        assert_eq!(display.released, Input::from_bits(Input::A | Input::RIGHT));
+        assert_eq!(
+            movie.input_display(1).unwrap().pressed,
+            Input::from_bits(Input::A | Input::RIGHT)
+        );
+        assert_eq!(movie.input_display(3), None);
+    }
+
+    #[test]
+    fn test_reads_older_versions() {
+        let movie = sample_movie();
+        let mut bytes = movie.to_bytes();
+        // Without the start state
+        bytes[4] = 2;
+        bytes.truncate(bytes.len() - 4);
+        assert_eq!(Movie::from_bytes(&bytes), Ok(movie.clone()));
+        // Without the regions and hashes
+        bytes[4] = 1;
+        bytes.truncate(bytes.len() - 3);
+        assert_eq!(Movie::from_bytes(&bytes), Ok(movie));
+    }
+
+    #[test]
+    fn test_vbm_round_trip() {
+        let mut movie = sample_movie();
+        let converted = Movie::from_vbm(&movie.to_vbm("author")).unwrap();
+        // VBM doesn't store the ROM hash
+        movie.rom_hash = 0;
+        assert_eq!(converted, movie);
+    }
+
+    #[test]
+    fn test_bk2_round_trip() {
+        let mut movie = sample_movie();
+        let converted =
+            Movie::from_bk2(&movie.to_bk2_input_log(), Some(&movie.to_bk2_header())).unwrap();
+        movie.rom_hash = 0;
+        assert_eq!(converted, movie);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::error::MovieError;
+use crate::movie::{Input, Movie};
+
+// BizHawk movies (.bk2) are zip archives, these handle the "Input Log.txt" and
+// "Header.txt" files inside them
+const LOG_KEY: &str = "LogKey:#Up|Down|Left|Right|Start|Select|B|A|Power|";
+const BUTTONS: [(u8, char); 8] = [
+    (Input::UP, 'U'),
+    (Input::DOWN, 'D'),
+    (Input::LEFT, 'L'),
+    (Input::RIGHT, 'R'),
+    (Input::START, 'S'),
+    (Input::SELECT, 's'),
+    (Input::B, 'B'),
+    (Input::A, 'A'),
+];
+const POWER: usize = BUTTONS.len();
+const RERECORD_COUNT: &str = "RerecordCount";
+
+impl Movie {
+    /// Converts the movie to the contents of a BizHawk "Input Log.txt".
+    #[must_use]
+    pub fn to_bk2_input_log(&self) -> String {
+        let mut log = format!("[Input]\n{LOG_KEY}\n");
+        for input in self.frames() {
+            log.push('|');
+            for (button, mnemonic) in BUTTONS {
+                log.push(if input.contains(button) {
+                    mnemonic
+                } else {
+                    '.'
+                });
+            }
+            log.push_str(".|\n");
+        }
+        log.push_str("[/Input]\n");
+        log
+    }
+
+    /// Converts the movie metadata to the contents of a BizHawk "Header.txt".
+    #[must_use]
+    pub fn to_bk2_header(&self) -> String {
+        format!(
+            "MovieVersion BizHawk v2.0\nPlatform GB\n{RERECORD_COUNT} {}\n",
+            self.rerecords
+        )
+    }
+
+    /// Converts the contents of a BizHawk "Input Log.txt" and optionally "Header.txt".
+    ///
+    /// Buttons are matched by their position in the log key, so any mnemonic is accepted.
+    /// The ROM hash isn't stored in BK2 files and is left as 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a frame has the wrong number of buttons or presses power (reset).
+    pub fn from_bk2(input_log: &str, header: Option<&str>) -> Result<Self, MovieError> {
+        let mut movie = Self::new(0);
+
+        if let Some(header) = header {
+            let rerecords = header
+                .lines()
+                .filter_map(|line| line.strip_prefix(RERECORD_COUNT))
+                .find_map(|count| count.trim().parse().ok());
+            movie.rerecords = rerecords.unwrap_or_default();
+        }
+
+        for line in input_log.lines().filter(|line| line.starts_with('|')) {
+            let buttons: Vec<char> = line.chars().filter(|c| *c != '|').collect();
+            if buttons.len() != BUTTONS.len() + 1 {
+                return Err(MovieError::InvalidFormat);
+            }
+            if is_pressed(buttons[POWER]) && movie.frame_count() > 0 {
+                return Err(MovieError::UnsupportedStart);
+            }
+            let mut input = Input::empty();
+            for ((button, _), mnemonic) in BUTTONS.iter().zip(&buttons) {
+                input.set(*button, is_pressed(*mnemonic));
+            }
+            movie.push_frame(input);
+        }
+        Ok(movie)
+    }
+}
+
+const fn is_pressed(mnemonic: char) -> bool {
+    mnemonic != '.' && mnemonic != ' '
+}
@@ -0,0 +1,103 @@
+use crate::error::MovieError;
+use crate::movie::{Input, Movie};
+
+// VisualBoyAdvance movie (.vbm) layout
+const VBM_MAGIC: &[u8; 4] = b"VBM\x1A";
+const VBM_VERSION: u32 = 1;
+const VBM_FRAME_COUNT: usize = 0x0C;
+const VBM_RERECORD_COUNT: usize = 0x10;
+const VBM_START_FLAGS: usize = 0x14;
+const VBM_CONTROLLER_FLAGS: usize = 0x15;
+const VBM_SAVESTATE_OFFSET: usize = 0x38;
+const VBM_CONTROLLER_DATA_OFFSET: usize = 0x3C;
+const VBM_AUTHOR_INFO: usize = 0x40;
+const VBM_AUTHOR_INFO_SIZE: usize = 192;
+const VBM_HEADER_SIZE: usize = VBM_AUTHOR_INFO + VBM_AUTHOR_INFO_SIZE;
+
+const START_FROM_SAVESTATE: u8 = 0b0000_0001;
+const START_FROM_SRAM: u8 = 0b0000_0010;
+const CONTROLLER_1: u8 = 0b0000_0001;
+const RESET: u16 = 0b0000_1000_0000_0000;
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+impl Movie {
+    /// Converts the movie to a VisualBoyAdvance movie (.vbm) recorded from power on.
+    #[must_use]
+    pub fn to_vbm(&self, author: &str) -> Vec<u8> {
+        let mut bytes = vec![0; VBM_HEADER_SIZE];
+        bytes[..4].copy_from_slice(VBM_MAGIC);
+        bytes[4..8].copy_from_slice(&VBM_VERSION.to_le_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        let frame_count = self.frame_count() as u32;
+        bytes[VBM_FRAME_COUNT..VBM_FRAME_COUNT + 4].copy_from_slice(&frame_count.to_le_bytes());
+        bytes[VBM_RERECORD_COUNT..VBM_RERECORD_COUNT + 4]
+            .copy_from_slice(&self.rerecords.to_le_bytes());
+        bytes[VBM_CONTROLLER_FLAGS] = CONTROLLER_1;
+        #[allow(clippy::cast_possible_truncation)]
+        let data_offset = VBM_HEADER_SIZE as u32;
+        bytes[VBM_CONTROLLER_DATA_OFFSET..VBM_CONTROLLER_DATA_OFFSET + 4]
+            .copy_from_slice(&data_offset.to_le_bytes());
+
+        let author = author.as_bytes();
+        let length = author.len().min(VBM_AUTHOR_INFO_SIZE - 1);
+        bytes[VBM_AUTHOR_INFO..VBM_AUTHOR_INFO + length].copy_from_slice(&author[..length]);
+
+        for input in self.frames() {
+            // VBM uses the same bit order as Input for the Game Boy buttons
+            bytes.extend_from_slice(&(input.bits() as u16).to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Converts a VisualBoyAdvance movie (.vbm) using the first controller.
+    ///
+    /// The ROM hash isn't stored in VBM files and is left as 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data isn't a VBM movie, starts from a savestate or SRAM
+    /// (only power on is supported), or has fewer frames than its header claims.
+    pub fn from_vbm(bytes: &[u8]) -> Result<Self, MovieError> {
+        if bytes.len() < VBM_HEADER_SIZE || !bytes.starts_with(VBM_MAGIC) {
+            return Err(MovieError::InvalidFormat);
+        }
+        let version = read_u32(bytes, 4);
+        if version != VBM_VERSION {
+            #[allow(clippy::cast_possible_truncation)]
+            return Err(MovieError::UnsupportedVersion(version as u16));
+        }
+        let start_flags = bytes[VBM_START_FLAGS];
+        if start_flags & (START_FROM_SAVESTATE | START_FROM_SRAM) != 0
+            || read_u32(bytes, VBM_SAVESTATE_OFFSET) != 0
+        {
+            return Err(MovieError::UnsupportedStart);
+        }
+
+        let frame_count = read_u32(bytes, VBM_FRAME_COUNT) as usize;
+        let controllers = bytes[VBM_CONTROLLER_FLAGS].count_ones().max(1) as usize;
+        let data_offset = read_u32(bytes, VBM_CONTROLLER_DATA_OFFSET) as usize;
+        let data = bytes.get(data_offset..).unwrap_or_default();
+        let frame_size = controllers * 2;
+        if data.len() / frame_size < frame_count {
+            return Err(MovieError::FrameCountMismatch {
+                expected: frame_count,
+                actual: data.len() / frame_size,
+            });
+        }
+
+        let mut movie = Self::new(0);
+        movie.rerecords = read_u32(bytes, VBM_RERECORD_COUNT);
+        for frame in data.chunks_exact(frame_size).take(frame_count) {
+            let buttons = u16::from_le_bytes([frame[0], frame[1]]);
+            if buttons & RESET != 0 {
+                return Err(MovieError::UnsupportedStart);
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            movie.push_frame(Input::from_bits(buttons as u8));
+        }
+        Ok(movie)
+    }
+}
@@ -0,0 +1,126 @@
+//! Notifications about emulation milestones, for frontends showing them to the user (e.g.
+//! as on-screen messages) without the core printing anything itself.
+
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc::Sender;
+
+/// Something that happened to the emulator, see [`GameboyHardware::set_notification_sink`].
+///
+/// [`GameboyHardware::set_notification_sink`]: crate::hardware::GameboyHardware::set_notification_sink
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    /// A frame was completed by [`GameboyHardware::run_frame`] or
+    /// [`GameboyHardware::run_cycles`].
+    ///
+    /// [`GameboyHardware::run_frame`]: crate::hardware::GameboyHardware::run_frame
+    /// [`GameboyHardware::run_cycles`]: crate::hardware::GameboyHardware::run_cycles
+    FrameCompleted,
+    /// Battery-backed RAM was written to storage by
+    /// [`GameboyHardware::persist_save_ram`].
+    ///
+    /// [`GameboyHardware::persist_save_ram`]: crate::hardware::GameboyHardware::persist_save_ram
+    SaveRamPersisted,
+    /// A savestate was restored by [`GameboyHardware::load_state`].
+    ///
+    /// [`GameboyHardware::load_state`]: crate::hardware::GameboyHardware::load_state
+    StateLoaded,
+    /// A cheat was turned on, either added enabled or enabled later.
+    CheatApplied { index: usize, description: String },
+    /// A device on the other end of the link cable started clocking the serial port.
+    LinkConnected,
+    /// A problem the user may want to know about, e.g. a malformed ROM header or a crash.
+    DiagnosticRaised(String),
+}
+
+impl Display for Notification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FrameCompleted => write!(f, "Frame completed"),
+            Self::SaveRamPersisted => write!(f, "Save RAM persisted"),
+            Self::StateLoaded => write!(f, "State loaded"),
+            Self::CheatApplied { description, .. } => write!(f, "Cheat applied: {description}"),
+            Self::LinkConnected => write!(f, "Link cable connected"),
+            Self::DiagnosticRaised(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+/// Receiver of [`Notification`]s.
+///
+/// Notifications are sent as they happen, during emulation, so sinks should return quickly.
+/// A [`Sender`] is a sink forwarding them to another thread.
+pub trait NotificationSink: Send + Sync {
+    fn notify(&mut self, notification: &Notification);
+}
+
+impl NotificationSink for Sender<Notification> {
+    fn notify(&mut self, notification: &Notification) {
+        // Nothing to do once the receiver is gone
+        let _ = self.send(notification.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::cheat::Cheat;
+    use crate::hardware::GameboyHardware;
+    use crate::notification::Notification;
+    use crate::persistence::PlainCodec;
+    use std::fs;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_notifications() {
+        // LD A, 0x00; LDH (0x03), A; loop: JR loop
+        let program = [0x3E, 0x00, 0xE0, 0x03, 0x18, 0xFE];
+        // Battery-backed RAM, but no RAM size
+        let rom = HeaderBuilder::new().cartridge_type(0x03).build(&program);
+        let cartridge = Cartridge::new(rom);
+        assert_eq!(cartridge.warnings().len(), 1);
+        let mut gameboy = GameboyHardware::new(cartridge);
+        let (sender, receiver) = mpsc::channel();
+        gameboy.set_notification_sink(Some(Box::new(sender)));
+        let diagnostic = |message: &str| Notification::DiagnosticRaised(message.to_string());
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [diagnostic(
+                "Cartridge type has RAM but the header gives no RAM size. Assuming 32 KiB."
+            )]
+        );
+
+        gameboy.add_cheat(Cheat::new("Lives", "010099C1", true).unwrap());
+        let index = gameboy.add_cheat(Cheat::new("Moon Jump", "01FF10C1", false).unwrap());
+        gameboy.set_cheat_enabled(index, true);
+        gameboy.run_frame();
+        // Peeking has no side effects, diagnostics included
+        assert_eq!(gameboy.peek_byte(0xFF03), 0xFF);
+        let state = gameboy.save_state();
+        gameboy.load_state(&state).unwrap();
+        gameboy.serial_external_clock(true);
+        gameboy.serial_external_clock(true);
+        let path = std::env::temp_dir().join(format!(
+            "gb-emulator-notifications-{}.sav",
+            std::process::id()
+        ));
+        assert!(gameboy.persist_save_ram(&path, &PlainCodec).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let cheat_applied = |index, description: &str| Notification::CheatApplied {
+            index,
+            description: description.to_string(),
+        };
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [
+                cheat_applied(0, "Lives"),
+                cheat_applied(1, "Moon Jump"),
+                diagnostic("Stray access: address 0xFF03 is not mapped to an I/O register."),
+                Notification::FrameCompleted,
+                Notification::StateLoaded,
+                Notification::LinkConnected,
+                Notification::SaveRamPersisted,
+            ]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! A machine-readable description of every instruction (mnemonic, operands, length and
+//! cycles), for documentation, disassemblers and other tooling. [`disasm`](crate::disasm)
+//! decodes instructions from memory with it.
+//!
+//! The table is decoded from the regular layout of the opcode space and checked against
+//! what the CPU actually does for every opcode, so the two can't disagree.
+
+use crate::coverage::Opcode;
+use std::fmt::{Display, Formatter, Write};
+
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "[HL]", "A"];
+const REGISTER_PAIRS: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const STACK_PAIRS: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CONDITIONS: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU: [&str; 8] = ["ADD", "ADC", "SUB", "SBC", "AND", "XOR", "OR", "CP"];
+const SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const ACCUMULATOR_OPS: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+
+/// An operand of an instruction, by addressing mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// An 8-bit register, or `[HL]` for the byte HL points to.
+    Register8(&'static str),
+    Register16(&'static str),
+    /// The byte a register pair points to, `[HL+]` and `[HL-]` also change HL.
+    Indirect(&'static str),
+    /// The byte at 0xFF00 + C.
+    HighC,
+    /// An 8-bit value following the opcode.
+    Immediate8,
+    /// A 16-bit value following the opcode, also used for jump targets.
+    Immediate16,
+    /// The byte at the 16-bit address following the opcode.
+    Address16,
+    /// The byte at 0xFF00 + the 8-bit value following the opcode.
+    HighAddress8,
+    /// A signed 8-bit value following the opcode.
+    Offset8,
+    /// SP plus a signed 8-bit value following the opcode.
+    StackOffset8,
+    Condition(&'static str),
+    Bit(u8),
+    /// Address called by RST.
+    Vector(u8),
+}
+
+impl Operand {
+    /// Returns the number of bytes the operand takes after the opcode.
+    #[must_use]
+    pub const fn size(self) -> u8 {
+        match self {
+            Self::Immediate8 | Self::HighAddress8 | Self::Offset8 | Self::StackOffset8 => 1,
+            Self::Immediate16 | Self::Address16 => 2,
+            _ => 0,
+        }
+    }
+
+    /// Returns the name of the addressing mode, as used in [`table_json`].
+    #[must_use]
+    pub const fn mode(self) -> &'static str {
+        match self {
+            Self::Register8(_) => "register8",
+            Self::Register16(_) => "register16",
+            Self::Indirect(_) => "indirect",
+            Self::HighC => "high_c",
+            Self::Immediate8 => "immediate8",
+            Self::Immediate16 => "immediate16",
+            Self::Address16 => "address16",
+            Self::HighAddress8 => "high_address8",
+            Self::Offset8 => "offset8",
+            Self::StackOffset8 => "stack_offset8",
+            Self::Condition(_) => "condition",
+            Self::Bit(_) => "bit",
+            Self::Vector(_) => "vector",
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Register8(name) | Self::Register16(name) | Self::Condition(name) => {
+                write!(f, "{name}")
+            }
+            Self::Indirect(name) => write!(f, "[{name}]"),
+            Self::HighC => write!(f, "[C]"),
+            Self::Immediate8 => write!(f, "n8"),
+            Self::Immediate16 => write!(f, "n16"),
+            Self::Address16 => write!(f, "[a16]"),
+            Self::HighAddress8 => write!(f, "[a8]"),
+            Self::Offset8 => write!(f, "e8"),
+            Self::StackOffset8 => write!(f, "SP + e8"),
+            Self::Bit(bit) => write!(f, "{bit}"),
+            Self::Vector(addr) => write!(f, "${addr:02X}"),
+        }
+    }
+}
+
+/// Description of an instruction, see [`info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: Opcode,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    /// Bytes including the opcode and its prefix.
+    pub length: u8,
+    /// T-cycles taken, when not branching for conditional instructions.
+    pub cycles: u8,
+    /// T-cycles taken by conditional instructions when branching.
+    pub branch_cycles: Option<u8>,
+}
+
+impl OpcodeInfo {
+    fn new(opcode: Opcode, mnemonic: &'static str, operands: &[Operand], cycles: u8) -> Self {
+        let prefix = u8::from(matches!(opcode, Opcode::Prefixed(_)));
+        Self {
+            opcode,
+            mnemonic,
+            operands: operands.to_vec(),
+            length: 1 + prefix + operands.iter().map(|operand| operand.size()).sum::<u8>(),
+            cycles,
+            branch_cycles: None,
+        }
+    }
+
+    const fn branching(mut self, cycles: u8) -> Self {
+        self.branch_cycles = Some(cycles);
+        self
+    }
+}
+
+impl Display for OpcodeInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (index, operand) in self.operands.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(f, "{separator}{operand}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the description of `opcode`, `None` for undefined opcodes and the 0xCB prefix.
+#[must_use]
+pub fn info(opcode: Opcode) -> Option<OpcodeInfo> {
+    match opcode {
+        Opcode::Unprefixed(byte) => unprefixed(byte),
+        Opcode::Prefixed(byte) => Some(prefixed(byte)),
+    }
+}
+
+/// Returns the description of every implemented instruction, in [`Opcode::all`] order.
+#[must_use]
+pub fn table() -> Vec<OpcodeInfo> {
+    Opcode::all().filter_map(info).collect()
+}
+
+/// Returns [`table`] as a JSON object with an `opcodes` array.
+#[must_use]
+pub fn table_json() -> String {
+    let mut json = String::from("{\"opcodes\":[");
+    for (index, info) in table().iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        let (byte, prefixed) = match info.opcode {
+            Opcode::Unprefixed(byte) => (byte, false),
+            Opcode::Prefixed(byte) => (byte, true),
+        };
+        let _ = write!(
+            json,
+            "{{\"opcode\":{byte},\"prefixed\":{prefixed},\"text\":\"{info}\",\"mnemonic\":\"{}\",\"operands\":[",
+            info.mnemonic
+        );
+        for (index, operand) in info.operands.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"mode\":\"{}\",\"text\":\"{operand}\"}}",
+                operand.mode()
+            );
+        }
+        let _ = write!(
+            json,
+            "],\"length\":{},\"cycles\":{}",
+            info.length, info.cycles
+        );
+        if let Some(cycles) = info.branch_cycles {
+            let _ = write!(json, ",\"branch_cycles\":{cycles}");
+        }
+        json.push('}');
+    }
+    json.push_str("]}\n");
+    json
+}
+
+/// Returns the register operand encoded in 3 bits, and the extra cycles when it's `[HL]`.
+fn register(index: u8) -> (Operand, u8) {
+    let extra = if index == 6 { 4 } else { 0 };
+    (Operand::Register8(REGISTERS[index as usize]), extra)
+}
+
+fn unprefixed(byte: u8) -> Option<OpcodeInfo> {
+    use Operand::*;
+
+    let opcode = Opcode::Unprefixed(byte);
+    let op = |mnemonic, operands: &[Operand], cycles| {
+        OpcodeInfo::new(opcode, mnemonic, operands, cycles)
+    };
+    // Opcodes split into xx yyy zzz, with yyy as pp q
+    let (x, y, z) = (byte >> 6, (byte >> 3) & 7, byte & 7);
+    let (p, q) = ((y >> 1) as usize, y & 1);
+    let condition = || Condition(CONDITIONS[y as usize & 3]);
+
+    let info = match (x, z) {
+        (0, 0) => match y {
+            0 => op("NOP", &[], 4),
+            1 => op("LD", &[Address16, Register16("SP")], 20),
+            2 => op("STOP", &[Immediate8], 4),
+            3 => op("JR", &[Offset8], 12),
+            _ => op("JR", &[condition(), Offset8], 8).branching(12),
+        },
+        (0, 1) if q == 0 => op("LD", &[Register16(REGISTER_PAIRS[p]), Immediate16], 12),
+        (0, 1) => op("ADD", &[Register16("HL"), Register16(REGISTER_PAIRS[p])], 8),
+        (0, 2) => {
+            let memory = Indirect(["BC", "DE", "HL+", "HL-"][p]);
+            if q == 0 {
+                op("LD", &[memory, Register8("A")], 8)
+            } else {
+                op("LD", &[Register8("A"), memory], 8)
+            }
+        }
+        (0, 3) => {
+            let mnemonic = if q == 0 { "INC" } else { "DEC" };
+            op(mnemonic, &[Register16(REGISTER_PAIRS[p])], 8)
+        }
+        (0, 4 | 5) => {
+            let mnemonic = if z == 4 { "INC" } else { "DEC" };
+            let (register, extra) = register(y);
+            op(mnemonic, &[register], 4 + 2 * extra)
+        }
+        (0, 6) => {
+            let (register, extra) = register(y);
+            op("LD", &[register, Immediate8], 8 + extra)
+        }
+        (0, _) => op(ACCUMULATOR_OPS[y as usize], &[], 4),
+        (1, 6) if y == 6 => op("HALT", &[], 4),
+        (1, _) => {
+            let (dst, dst_extra) = register(y);
+            let (src, src_extra) = register(z);
+            op("LD", &[dst, src], 4 + dst_extra + src_extra)
+        }
+        (2, _) => {
+            let (src, extra) = register(z);
+            op(ALU[y as usize], &[Register8("A"), src], 4 + extra)
+        }
+        (3, 0) => match y {
+            0..=3 => op("RET", &[condition()], 8).branching(20),
+            4 => op("LDH", &[HighAddress8, Register8("A")], 12),
+            5 => op("ADD", &[Register16("SP"), Offset8], 16),
+            6 => op("LDH", &[Register8("A"), HighAddress8], 12),
+            _ => op("LD", &[Register16("HL"), StackOffset8], 12),
+        },
+        (3, 1) if q == 0 => op("POP", &[Register16(STACK_PAIRS[p])], 12),
+        (3, 1) => match p {
+            0 => op("RET", &[], 16),
+            1 => op("RETI", &[], 16),
+            2 => op("JP", &[Register16("HL")], 4),
+            _ => op("LD", &[Register16("SP"), Register16("HL")], 8),
+        },
+        (3, 2) => match y {
+            0..=3 => op("JP", &[condition(), Immediate16], 12).branching(16),
+            4 => op("LDH", &[HighC, Register8("A")], 8),
+            5 => op("LD", &[Address16, Register8("A")], 16),
+            6 => op("LDH", &[Register8("A"), HighC], 8),
+            _ => op("LD", &[Register8("A"), Address16], 16),
+        },
+        (3, 3) => match y {
+            0 => op("JP", &[Immediate16], 16),
+            6 => op("DI", &[], 4),
+            7 => op("EI", &[], 4),
+            _ => return None,
+        },
+        (3, 4) if y < 4 => op("CALL", &[condition(), Immediate16], 12).branching(24),
+        (3, 5) if q == 0 => op("PUSH", &[Register16(STACK_PAIRS[p])], 16),
+        (3, 5) if p == 0 => op("CALL", &[Immediate16], 24),
+        (3, 6) => op(ALU[y as usize], &[Register8("A"), Immediate8], 8),
+        (3, 7) => op("RST", &[Vector(y * 8)], 16),
+        _ => return None,
+    };
+    Some(info)
+}
+
+fn prefixed(byte: u8) -> OpcodeInfo {
+    let opcode = Opcode::Prefixed(byte);
+    let (x, y) = (byte >> 6, (byte >> 3) & 7);
+    let (register, extra) = register(byte & 7);
+    match x {
+        0 => OpcodeInfo::new(opcode, SHIFTS[y as usize], &[register], 8 + 2 * extra),
+        // BIT only reads [HL]
+        1 => OpcodeInfo::new(opcode, "BIT", &[Operand::Bit(y), register], 8 + extra),
+        2 => OpcodeInfo::new(opcode, "RES", &[Operand::Bit(y), register], 8 + 2 * extra),
+        _ => OpcodeInfo::new(opcode, "SET", &[Operand::Bit(y), register], 8 + 2 * extra),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coverage::Opcode;
+    use crate::opcodes::{info, table, table_json};
+
+    #[test]
+    fn test_decoding() {
+        let text = |opcode| info(opcode).unwrap().to_string();
+        assert_eq!(text(Opcode::Unprefixed(0x00)), "NOP");
+        assert_eq!(text(Opcode::Unprefixed(0x22)), "LD [HL+], A");
+        assert_eq!(text(Opcode::Unprefixed(0x7E)), "LD A, [HL]");
+        assert_eq!(text(Opcode::Unprefixed(0xC4)), "CALL NZ, n16");
+        assert_eq!(text(Opcode::Unprefixed(0xE2)), "LDH [C], A");
+        assert_eq!(text(Opcode::Unprefixed(0xF8)), "LD HL, SP + e8");
+        assert_eq!(text(Opcode::Unprefixed(0xFF)), "RST $38");
+        assert_eq!(text(Opcode::Prefixed(0x7E)), "BIT 7, [HL]");
+        assert!(info(Opcode::Unprefixed(0xCB)).is_none());
+        assert!(info(Opcode::Unprefixed(0xD3)).is_none());
+
+        let call = info(Opcode::Unprefixed(0xCD)).unwrap();
+        assert_eq!((call.length, call.cycles), (3, 24));
+        assert_eq!(table().len(), Opcode::all().count());
+    }
+
+    #[test]
+    fn test_json() {
+        let json = table_json();
+        assert!(json.starts_with("{\"opcodes\":[{\"opcode\":0,\"prefixed\":false,\"text\":\"NOP\""));
+        assert!(json.contains(
+            "\"text\":\"JR NZ, e8\",\"mnemonic\":\"JR\",\"operands\":[{\"mode\":\"condition\",\"text\":\"NZ\"},{\"mode\":\"offset8\",\"text\":\"e8\"}],\"length\":2,\"cycles\":8,\"branch_cycles\":12}"
+        ));
+    }
+}
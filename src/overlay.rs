@@ -0,0 +1,201 @@
+use crate::movie::Input;
+use std::fmt::{Display, Formatter};
+
+/// Timing information about a single visible scanline.
+///
+/// Intended for frontends drawing profiling bars beside the screen, e.g. to show
+/// homebrew developers how much of the HBlank budget their code uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanlineMetrics {
+    /// Length of mode 3 (drawing pixels) in dots.
+    pub mode3_length: u16,
+    /// Number of sprites selected during the OAM scan, at most 10.
+    pub sprite_count: u8,
+    /// T-cycles the CPU spent running (not halted) during HBlank.
+    pub hblank_cpu_cycles: u16,
+}
+
+impl ScanlineMetrics {
+    pub(crate) const fn new() -> Self {
+        Self {
+            mode3_length: 0,
+            sprite_count: 0,
+            hblank_cpu_cycles: 0,
+        }
+    }
+}
+
+/// VRAM writes made during one frame, by region and by the PPU mode (0-3) they were made in.
+///
+/// Intended for homebrew developers checking how much of the VBlank and HBlank budget their
+/// updates use. Writes during mode 3 deserve attention: a console drops them, while the
+/// emulator still makes them, so graphics that look right here can be corrupted on
+/// hardware.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VramWriteStats {
+    /// Writes to tile data (0x8000-0x97FF) with the LCD on, indexed by mode.
+    pub tile_data: [u32; 4],
+    /// Writes to the tile maps (0x9800-0x9FFF) with the LCD on, indexed by mode.
+    pub tile_maps: [u32; 4],
+    /// Writes while the LCD was off, which hardware always accepts.
+    pub lcd_off: u32,
+}
+
+// Mode 3, when the PPU reads VRAM to draw pixels
+const DRAWING_MODE: usize = 3;
+const MODE_NAMES: [&str; 4] = ["HBlank", "VBlank", "OAM scan", "drawing"];
+
+impl VramWriteStats {
+    pub(crate) const fn new() -> Self {
+        Self {
+            tile_data: [0; 4],
+            tile_maps: [0; 4],
+            lcd_off: 0,
+        }
+    }
+
+    /// Counts a write at `offset` into VRAM, `mode` is `None` while the LCD is off.
+    pub(crate) fn record(&mut self, offset: u16, mode: Option<usize>) {
+        match mode {
+            None => self.lcd_off += 1,
+            Some(mode) if offset < 0x1800 => self.tile_data[mode] += 1,
+            Some(mode) => self.tile_maps[mode] += 1,
+        }
+    }
+
+    #[must_use]
+    pub fn total(&self) -> u32 {
+        self.tile_data.iter().chain(&self.tile_maps).sum::<u32>() + self.lcd_off
+    }
+
+    /// Returns the number of writes made during mode 3, which hardware drops but the
+    /// emulator still makes.
+    #[must_use]
+    pub const fn mode3_writes(&self) -> u32 {
+        self.tile_data[DRAWING_MODE] + self.tile_maps[DRAWING_MODE]
+    }
+}
+
+/// Lists the writes to each region by mode, then the writes with the LCD off and in mode 3.
+impl Display for VramWriteStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, counts) in [("Tile data", self.tile_data), ("Tile maps", self.tile_maps)] {
+            write!(f, "{name}: {} (", counts.iter().sum::<u32>())?;
+            for (mode, (count, mode_name)) in counts.iter().zip(MODE_NAMES).enumerate() {
+                let separator = if mode == 0 { "" } else { ", " };
+                write!(f, "{separator}{mode_name} {count}")?;
+            }
+            write!(f, "), ")?;
+        }
+        let mode3 = self.mode3_writes();
+        write!(f, "LCD off: {}, mode 3: {mode3}", self.lcd_off)
+    }
+}
+
+/// Buttons for an input display drawn over one frame, e.g. when streaming or verifying a
+/// movie.
+///
+/// Built from the buttons held in consecutive frames, either by
+/// [`GameboyHardware::input_display`] while running or by [`Movie::input_display`] from
+/// a recording.
+///
+/// [`GameboyHardware::input_display`]: crate::hardware::GameboyHardware::input_display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputDisplay {
+    /// Buttons held during the frame.
+    pub held: Input,
+    /// Buttons held during the frame but not the one before, to be highlighted.
+    pub pressed: Input,
+    /// Buttons held during the frame before but not this one.
+    pub released: Input,
+}
+
+// Names in the order they are displayed
+const BUTTON_NAMES: [(u8, &str); 8] = [
+    (Input::LEFT, "Left"),
+    (Input::UP, "Up"),
+    (Input::DOWN, "Down"),
+    (Input::RIGHT, "Right"),
+    (Input::SELECT, "Select"),
+    (Input::START, "Start"),
+    (Input::B, "B"),
+    (Input::A, "A"),
+];
+
+impl InputDisplay {
+    #[must_use]
+    pub const fn new(previous: Input, current: Input) -> Self {
+        Self {
+            held: current,
+            pressed: Input::from_bits(current.bits() & !previous.bits()),
+            released: Input::from_bits(previous.bits() & !current.bits()),
+        }
+    }
+}
+
+/// Lists the held buttons, marking the ones pressed this frame with `*`.
+impl Display for InputDisplay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut separator = "";
+        for (bits, name) in BUTTON_NAMES {
+            if self.held.contains(bits) {
+                let edge = if self.pressed.contains(bits) { "*" } else { "" };
+                write!(f, "{separator}{name}{edge}")?;
+                separator = " ";
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use crate::movie::Input;
+    use crate::overlay::{InputDisplay, VramWriteStats};
+
+    #[test]
+    fn test_input_display() {
+        let previous = Input::from_bits(Input::A | Input::LEFT);
+        let current = Input::from_bits(Input::A | Input::UP | Input::START);
+        let display = InputDisplay::new(previous, current);
+        assert_eq!(display.pressed, Input::from_bits(Input::UP | Input::START));
+        assert_eq!(display.released, Input::from_bits(Input::LEFT));
+        assert_eq!(display.to_string(), "Up* Start* A");
+        assert_eq!(InputDisplay::default().to_string(), "");
+    }
+
+    #[test]
+    fn test_vram_write_stats() {
+        #[rustfmt::skip]
+        let program = [
+            // Wait for line 0x10 (mode 2), then write 0x9800 until the line is done
+            0xF0, 0x44, 0xFE, 0x10, 0x20, 0xFA, // loop: LDH A, (LY); CP 0x10; JR NZ, loop
+            0x21, 0x00, 0x98, 0x06, 0x40,       // LD HL, 0x9800; LD B, 0x40
+            0x70, 0x05, 0x20, 0xFC,             // write: LD (HL), B; DEC B; JR NZ, write
+            // Wait for VBlank, then write one tile row
+            0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // LDH A, (LY); CP 0x90; JR NZ, -6
+            0x21, 0x00, 0x80, 0x22, 0x22,       // LD HL, 0x8000; LD (HL+), A; LD (HL+), A
+            0x18, 0xFE,                         // JR -2
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.run_frame();
+        let stats = *gameboy.vram_writes();
+        assert_eq!(stats.tile_maps.iter().sum::<u32>(), 0x40);
+        assert!(stats.mode3_writes() > 0);
+        assert_eq!(stats.tile_data, [0; 4]);
+
+        gameboy.run_frame();
+        let stats = *gameboy.vram_writes();
+        assert_eq!(stats.tile_data, [0, 2, 0, 0]);
+        assert_eq!(stats.total(), 2);
+        assert_eq!(
+            stats.to_string(),
+            "Tile data: 2 (HBlank 0, VBlank 2, OAM scan 0, drawing 0), \
+             Tile maps: 0 (HBlank 0, VBlank 0, OAM scan 0, drawing 0), LCD off: 0, mode 3: 0"
+        );
+        assert_eq!(VramWriteStats::default().total(), 0);
+    }
+}
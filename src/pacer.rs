@@ -0,0 +1,188 @@
+//! Holding emulation to real time (about 59.73 frames per second) from the core's cycle
+//! counter, for frontends running frames on their own loop.
+
+use crate::audio::{AudioRingBuffer, AudioSink};
+use crate::consts::{cycles_to_duration, FRAMES_PER_SECOND};
+use crate::handle::MIN_SPEED;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Sleeps end this early and the rest is spun, since they can overshoot by about as much
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+// Falling further behind restarts pacing from now instead of running frames to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+// Polling interval while waiting for the audio buffer to drain
+const AUDIO_POLL: Duration = Duration::from_millis(1);
+
+/// What a [`Pacer`] holds emulation to.
+#[derive(Debug, Clone)]
+pub enum PacingClock {
+    /// The host's clock, emulated time following real time.
+    Host,
+    /// Playback of an audio buffer, waiting while it holds more than `latency` of samples.
+    /// Emulation follows the audio device's clock, so the buffer neither fills up nor runs
+    /// dry when it drifts from the host's. Waits are cut short after 100 ms, in case
+    /// playback stalls.
+    Audio {
+        buffer: AudioRingBuffer,
+        latency: Duration,
+    },
+}
+
+/// Waits between frames to hold emulation to real time, scaled by a speed multiplier.
+///
+/// Waits are measured from where pacing started rather than from the previous wait, so
+/// sleeps that overshoot don't add up. Pacing restarts from now after falling more than
+/// 100 ms behind, e.g. after a slow frame or while paused, instead of rushing to catch up.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    clock: PacingClock,
+    speed: f32,
+    // Real time and cycle count pacing is measured from, set by the first wait
+    anchor: Option<(Instant, u64)>,
+}
+
+impl Pacer {
+    /// Creates a pacer following the host's clock at normal speed.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_clock(PacingClock::Host)
+    }
+
+    #[must_use]
+    pub const fn with_clock(clock: PacingClock) -> Self {
+        Self {
+            clock,
+            speed: 1.0,
+            anchor: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn clock(&self) -> &PacingClock {
+        &self.clock
+    }
+
+    /// Returns the speed multiplier, 1.0 being real time and infinity when unlocked.
+    #[must_use]
+    pub const fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Sets the speed multiplier, infinity to never wait, e.g. from
+    /// [`EmulatorHandle::speed`](crate::handle::EmulatorHandle::speed). Raised to
+    /// [`MIN_SPEED`] if lower.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` isn't positive.
+    pub fn set_speed(&mut self, speed: f32) {
+        assert!(speed > 0.0, "speed must be positive, got {speed}");
+        let speed = speed.max(MIN_SPEED);
+        if speed != self.speed {
+            self.speed = speed;
+            self.anchor = None;
+        }
+    }
+
+    /// Restarts pacing from the next wait, e.g. after loading a state.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+    }
+
+    /// Waits until real time catches up with `cycles`, the console's
+    /// [`GameboyHardware::cycles`](crate::hardware::GameboyHardware::cycles) after running.
+    ///
+    /// If no cycles ran since the last wait, e.g. while paused, waits for a frame so loops
+    /// polling for input don't spin.
+    pub fn wait(&mut self, cycles: u64) {
+        let Some((start, start_cycles)) = self.anchor else {
+            self.anchor = Some((Instant::now(), cycles));
+            return;
+        };
+        if self.speed.is_infinite() {
+            self.anchor = Some((Instant::now(), cycles));
+            return;
+        }
+        let target = if cycles <= start_cycles {
+            // Nothing ran, e.g. while paused
+            let frame = Duration::from_secs_f64(1.0 / (FRAMES_PER_SECOND * f64::from(self.speed)));
+            let target = Instant::now() + frame;
+            self.anchor = Some((target, cycles));
+            target
+        } else {
+            match &self.clock {
+                PacingClock::Host => {
+                    start + cycles_to_duration(cycles - start_cycles).div_f32(self.speed)
+                }
+                PacingClock::Audio { buffer, latency } => {
+                    wait_for_audio(buffer, *latency);
+                    Instant::now()
+                }
+            }
+        };
+        let now = Instant::now();
+        if now > target + MAX_LAG {
+            self.anchor = Some((now, cycles));
+        }
+        sleep_until(target);
+    }
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Waits until `buffer` holds at most `latency` of samples, for at most [`MAX_LAG`].
+fn wait_for_audio(buffer: &AudioRingBuffer, latency: Duration) {
+    let samples = latency.as_secs_f64() * f64::from(buffer.sample_rate());
+    let deadline = Instant::now() + MAX_LAG;
+    #[allow(clippy::cast_precision_loss)]
+    while buffer.len() as f64 > samples && Instant::now() < deadline {
+        thread::sleep(AUDIO_POLL);
+    }
+}
+
+/// Sleeps until shortly before `target`, then spins until it.
+fn sleep_until(target: Instant) {
+    let now = Instant::now();
+    if target > now + SPIN_MARGIN {
+        thread::sleep(target - now - SPIN_MARGIN);
+    }
+    while Instant::now() < target {
+        std::hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::FRAME_CYCLES;
+    use crate::pacer::Pacer;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_paces_frames() {
+        let mut pacer = Pacer::new();
+        pacer.set_speed(4.0);
+        let frame_cycles = u64::from(FRAME_CYCLES);
+        pacer.wait(0);
+        let start = Instant::now();
+        for frame in 1..=8 {
+            pacer.wait(frame * frame_cycles);
+        }
+        // 8 frames at 4x speed take about 33 ms
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(33), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+
+        // Unlocked speed never waits
+        pacer.set_speed(f32::INFINITY);
+        let start = Instant::now();
+        for frame in 9..=100 {
+            pacer.wait(frame * frame_cycles);
+        }
+        assert!(start.elapsed() < Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,127 @@
+//! Reading and writing save files and savestates, with a pluggable codec applied to
+//! everything that goes to storage (e.g. to sign TAS submissions or encrypt saves synced
+//! to the cloud).
+
+use crate::error::CodecError;
+use std::path::Path;
+use std::{fs, io};
+
+/// Kind of data being persisted, so codecs can treat them differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payload {
+    /// Battery-backed cartridge RAM, see [`Cartridge::save_data`].
+    ///
+    /// [`Cartridge::save_data`]: crate::cartridge::Cartridge::save_data
+    SaveRam,
+    /// A savestate, see [`GameboyHardware::save_state`].
+    ///
+    /// [`GameboyHardware::save_state`]: crate::hardware::GameboyHardware::save_state
+    Savestate,
+}
+
+/// Transforms payloads on their way to and from storage. Both methods default to passing
+/// the data through unchanged.
+pub trait PersistenceCodec: Send + Sync {
+    /// Encodes data before it's written.
+    fn encode(&self, _payload: Payload, data: Vec<u8>) -> Vec<u8> {
+        data
+    }
+
+    /// Decodes data after it's read, undoing [`Self::encode`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data wasn't encoded by this codec, or was tampered with.
+    fn decode(&self, _payload: Payload, data: Vec<u8>) -> Result<Vec<u8>, CodecError> {
+        Ok(data)
+    }
+}
+
+/// Codec storing payloads as they are, the format other emulators use for save files.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PlainCodec;
+
+impl PersistenceCodec for PlainCodec {}
+
+/// Encodes `data` with `codec` and writes it to `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be written.
+pub fn write_payload(
+    path: impl AsRef<Path>,
+    payload: Payload,
+    data: Vec<u8>,
+    codec: &dyn PersistenceCodec,
+) -> io::Result<()> {
+    fs::write(path, codec.encode(payload, data))
+}
+
+/// Reads `path` and decodes it with `codec`.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read, or an [`io::ErrorKind::InvalidData`] error
+/// if the codec rejects it.
+pub fn read_payload(
+    path: impl AsRef<Path>,
+    payload: Payload,
+    codec: &dyn PersistenceCodec,
+) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    codec
+        .decode(payload, data)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CodecError;
+    use crate::persistence::{read_payload, write_payload, Payload, PersistenceCodec, PlainCodec};
+    use std::{fs, io};
+
+    // Appends a checksum of the data and the payload kind
+    struct Signed;
+
+    impl Signed {
+        fn signature(payload: Payload, data: &[u8]) -> u8 {
+            data.iter()
+                .fold(payload as u8, |sum, byte| sum.wrapping_add(*byte))
+        }
+    }
+
+    impl PersistenceCodec for Signed {
+        fn encode(&self, payload: Payload, mut data: Vec<u8>) -> Vec<u8> {
+            data.push(Self::signature(payload, &data));
+            data
+        }
+
+        fn decode(&self, payload: Payload, mut data: Vec<u8>) -> Result<Vec<u8>, CodecError> {
+            let signature = data.pop().ok_or(CodecError::Malformed)?;
+            if signature != Self::signature(payload, &data) {
+                return Err(CodecError::Unauthenticated);
+            }
+            Ok(data)
+        }
+    }
+
+    #[test]
+    fn test_codecs() {
+        let dir = std::env::temp_dir().join(format!("gb-emulator-codec-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.sav");
+
+        write_payload(&path, Payload::SaveRam, vec![1, 2, 3], &PlainCodec).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [1, 2, 3]);
+
+        write_payload(&path, Payload::SaveRam, vec![1, 2, 3], &Signed).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [1, 2, 3, 6]);
+        let data = read_payload(&path, Payload::SaveRam, &Signed).unwrap();
+        assert_eq!(data, [1, 2, 3]);
+
+        // A save presented as a savestate fails the signature
+        let err = read_payload(&path, Payload::Savestate, &Signed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,32 @@
-use crate::error::TryFromUintError;
+mod fifo;
+
+use crate::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::error::{SavestateError, TryFromUintError};
+use crate::hardware::{DirtyLines, Model};
+use crate::interrupts::InterruptFlags;
+use crate::overlay::{ScanlineMetrics, VramWriteStats};
+use crate::ppu::fifo::Pipeline;
+use crate::savestate::{StateReader, StateWriter};
+use crate::tile::{decode_row, palette_shades, TILE_PIXELS, TILE_SIZE};
 
 const VIDEO_RAM_SIZE: usize = 8 * 1024;
 const SPRITE_RAM_SIZE: usize = 0xFE9F - 0xFE00 + 1;
 
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+#[allow(clippy::cast_possible_truncation)]
+const VISIBLE_LINES: u8 = SCREEN_HEIGHT as u8;
+const FRAME_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+const LINES_PER_FRAME: u8 = 154;
+const MAX_SPRITES_PER_LINE: usize = 10;
+const SPRITE_SIZE: usize = 4;
+const TILE_MAP_WIDTH: usize = 32;
+
+const SPRITE_PRIORITY: u8 = 0b1000_0000;
+const SPRITE_Y_FLIP: u8 = 0b0100_0000;
+const SPRITE_X_FLIP: u8 = 0b0010_0000;
+const SPRITE_PALETTE: u8 = 0b0001_0000;
+
 const MEM_DISPLAY_CONTROL: u16 = 0xFF40;
 const MEM_DISPLAY_STATUS: u16 = 0xFF41;
 const MEM_SCROLL_Y: u16 = 0xFF42;
@@ -15,6 +39,15 @@ const MEM_OBJECT_PALETTE_0_DATA: u16 = 0xFF48;
 const MEM_OBJECT_PALETTE_1_DATA: u16 = 0xFF49;
 const MEM_WINDOW_Y: u16 = 0xFF4A;
 const MEM_WINDOW_X: u16 = 0xFF4B;
+const MEM_BACKGROUND_COLOR_PALETTE_SPECIFICATION: u16 = 0xFF68;
+const MEM_BACKGROUND_COLOR_PALETTE_DATA: u16 = 0xFF69;
+const MEM_OBJECT_COLOR_PALETTE_SPECIFICATION: u16 = 0xFF6A;
+const MEM_OBJECT_COLOR_PALETTE_DATA: u16 = 0xFF6B;
+
+const PALETTE_RAM_SIZE: usize = 64;
+// OAM is scanned in rows of 8 bytes, corrupted by the OAM bug
+const OAM_ROW_SIZE: usize = 8;
+const OAM_ROWS: usize = SPRITE_RAM_SIZE / OAM_ROW_SIZE;
 
 #[derive(Debug, Clone, Copy)]
 struct DisplayControl(u8);
@@ -44,6 +77,18 @@ impl DisplayControl {
     const fn bits(self) -> u8 {
         self.0
     }
+
+    const fn contains(self, bits: u8) -> bool {
+        (self.0 & bits) == bits
+    }
+
+    const fn sprite_height(self) -> u8 {
+        if self.contains(Self::SPRITE_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -69,6 +114,113 @@ impl DisplayStatus {
     const fn bits(self) -> u8 {
         self.0
     }
+
+    const fn contains(self, bits: u8) -> bool {
+        (self.0 & bits) == bits
+    }
+
+    fn set(&mut self, bits: u8, enable: bool) {
+        if enable {
+            self.0 |= bits;
+        } else {
+            self.0 &= !bits;
+        }
+    }
+
+    const fn mode(self) -> Mode {
+        match self.0 & Self::PPU_MODE {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            _ => Mode::Drawing,
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.0 = (self.0 & !Self::PPU_MODE) | mode as u8;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PaletteSpecification(u8);
+
+impl PaletteSpecification {
+    const AUTO_INCREMENT: u8 = 0b1000_0000;
+    const ADDRESS: u8 = 0b0011_1111;
+    const UNUSED: u8 = 0b0100_0000;
+
+    const fn empty() -> Self {
+        Self::from_bits(0)
+    }
+
+    const fn from_bits(bits: u8) -> Self {
+        Self(bits | Self::UNUSED)
+    }
+
+    const fn bits(self) -> u8 {
+        self.0
+    }
+
+    const fn address(self) -> usize {
+        (self.0 & Self::ADDRESS) as usize
+    }
+
+    /// Advances the address after a data write if auto-increment is on, wrapping at 64.
+    fn increment(&mut self) {
+        if self.0 & Self::AUTO_INCREMENT != 0 {
+            self.0 = (self.0 & !Self::ADDRESS) | ((self.0 & Self::ADDRESS) + 1) & Self::ADDRESS;
+        }
+    }
+}
+
+/// CGB palette RAM for either background or objects: 8 palettes of 4 colors, each a
+/// little-endian RGB555 value, accessed a byte at a time through a specification register.
+#[derive(Debug, Clone, Copy)]
+struct ColorPalettes {
+    // BCPS/OCPS
+    specification: PaletteSpecification,
+    // Accessed through BCPD/OCPD
+    data: [u8; PALETTE_RAM_SIZE],
+}
+
+impl ColorPalettes {
+    const fn new() -> Self {
+        Self {
+            specification: PaletteSpecification::empty(),
+            data: [0; PALETTE_RAM_SIZE],
+        }
+    }
+
+    const fn read_data(&self) -> u8 {
+        self.data[self.specification.address()]
+    }
+
+    /// Writes the byte at the current address, which still increments when `blocked`.
+    fn write_data(&mut self, value: u8, blocked: bool) {
+        if !blocked {
+            self.data[self.specification.address()] = value;
+        }
+        self.specification.increment();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+/// An access to 0xFE00-0xFEFF that corrupts OAM with the DMG OAM bug, see
+/// [`Ppu::corrupt_oam`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OamCorruption {
+    /// A write, or the CPU's 16-bit incrementer alone (INC/DEC r16, PUSH).
+    Write,
+    Read,
+    /// The incrementer during a read (POP, LD A, [HL+]), before that read's own corruption.
+    IncreaseDuringRead,
 }
 
 enum MonochromePalette {
@@ -105,6 +257,7 @@ impl TryFrom<u8> for MonochromePalette {
 
 #[derive(Debug, Clone)]
 pub struct Ppu {
+    model: Model,
     // VRAM
     video_ram: [u8; VIDEO_RAM_SIZE],
     // OAM
@@ -129,15 +282,64 @@ pub struct Ppu {
     object_palette_0_data: u8,
     // OBP1
     object_palette_1_data: u8,
+    // Shade of each color index under BGP, OBP0 and OBP1, updated when they are written
+    background_shades: [u8; 4],
+    object_shades: [[u8; 4]; 2],
     // WY
     window_y: u8,
     // WX
     window_x: u8,
+    // BCPS/BCPD, CGB only
+    background_palettes: ColorPalettes,
+    // OCPS/OCPD, CGB only
+    object_palettes: ColorPalettes,
+    // Dot within the current scanline (0-455)
+    dot: u16,
+    // Fetcher and FIFOs drawing the current scanline during mode 3
+    pipeline: Pipeline,
+    // OAM indexes of sprites selected for the current scanline
+    line_sprites: [u8; MAX_SPRITES_PER_LINE],
+    line_sprite_count: usize,
+    // Line of the window to draw next, only advances on lines showing the window
+    window_line: u8,
+    // Shades (0-3) for the frame being drawn and the last completed frame
+    frame: [u8; FRAME_SIZE],
+    completed_frame: [u8; FRAME_SIZE],
+    // Metrics for the frame being drawn and the last completed frame
+    metrics: [ScanlineMetrics; SCREEN_HEIGHT],
+    completed_metrics: [ScanlineMetrics; SCREEN_HEIGHT],
+    // Set when a frame completes, cleared when the hardware observes it
+    frame_ready: bool,
+    // Lines of the completed frame that changed since the frontend last asked
+    dirty_lines: DirtyLines,
+    // OR of all enabled STAT interrupt sources, interrupts are requested on its rising edge
+    stat_line: bool,
+    // Writes to STAT request an interrupt in HBlank, VBlank or on LY=LYC, the DMG bug by
+    // default, a setting that isn't saved
+    stat_write_bug: bool,
+    // Accesses to 0xFE00-0xFEFF during OAM scan corrupt OAM, the DMG bug by default, a
+    // setting that isn't saved
+    oam_bug: bool,
+    // Frames skipped after each drawn frame, a host setting that isn't saved
+    frame_skip: u8,
+    // Frames skipped after each drawn frame while fast-forwarding, when more than
+    // frame_skip, not saved
+    fast_forward_skip: u8,
+    // Frames left to skip before drawing the next one
+    frames_to_skip: u8,
+    // Whether the last completed frame was drawn
+    frame_drawn: bool,
+    // Incremented whenever the completed frame changes, not saved
+    frame_generation: u64,
+    // VRAM writes since the last completed frame and during it, not saved
+    vram_writes: VramWriteStats,
+    completed_vram_writes: VramWriteStats,
 }
 
 impl Ppu {
-    pub const fn new() -> Self {
+    pub const fn new(model: Model) -> Self {
         Self {
+            model,
             video_ram: [0; VIDEO_RAM_SIZE],
             sprite_ram: [0; SPRITE_RAM_SIZE],
             control: DisplayControl::new(),
@@ -150,16 +352,468 @@ impl Ppu {
             background_palette_data: 0xFC,
             object_palette_0_data: 0xFF,
             object_palette_1_data: 0xFF,
+            background_shades: palette_shades(0xFC),
+            object_shades: [palette_shades(0xFF); 2],
             window_y: 0,
             window_x: 0,
+            background_palettes: ColorPalettes::new(),
+            object_palettes: ColorPalettes::new(),
+            dot: 0,
+            pipeline: Pipeline::new(),
+            line_sprites: [0; MAX_SPRITES_PER_LINE],
+            line_sprite_count: 0,
+            window_line: 0,
+            frame: [0; FRAME_SIZE],
+            completed_frame: [0; FRAME_SIZE],
+            metrics: [ScanlineMetrics::new(); SCREEN_HEIGHT],
+            completed_metrics: [ScanlineMetrics::new(); SCREEN_HEIGHT],
+            frame_ready: false,
+            dirty_lines: DirtyLines::all(),
+            stat_line: false,
+            stat_write_bug: matches!(model, Model::Dmg),
+            oam_bug: matches!(model, Model::Dmg),
+            frame_skip: 0,
+            fast_forward_skip: 0,
+            frames_to_skip: 0,
+            frame_drawn: true,
+            frame_generation: 0,
+            vram_writes: VramWriteStats::new(),
+            completed_vram_writes: VramWriteStats::new(),
         }
     }
 
+    /// Resets the registers, keeping VRAM and OAM.
+    pub fn reset(&mut self) {
+        *self = Self {
+            video_ram: self.video_ram,
+            sprite_ram: self.sprite_ram,
+            stat_write_bug: self.stat_write_bug,
+            oam_bug: self.oam_bug,
+            frame_skip: self.frame_skip,
+            fast_forward_skip: self.fast_forward_skip,
+            frame_generation: self.frame_generation + 1,
+            ..Self::new(self.model)
+        };
+    }
+
+    /// Returns VRAM and OAM.
+    pub fn memory_mut(&mut self) -> [&mut [u8]; 2] {
+        [&mut self.video_ram, &mut self.sprite_ram]
+    }
+
+    /// Advances the PPU by one M-cycle (4 dots).
+    ///
+    /// `cpu_active` is false while the CPU is halted, and is only used for metrics.
+    pub fn tick(&mut self, interrupt_flag: &mut InterruptFlags, cpu_active: bool) {
+        if !self.is_enabled() {
+            return;
+        }
+        for _ in 0..4 {
+            self.tick_dot(interrupt_flag, cpu_active);
+        }
+    }
+
+    /// Runs `dots` dots at once, for catching up after lagging behind the CPU.
+    ///
+    /// Dots between mode and line changes only advance the dot counter, so they're skipped
+    /// over. The first dot always runs in full, as a register written since the previous
+    /// dot can request a STAT interrupt.
+    pub fn run_dots(
+        &mut self,
+        mut dots: usize,
+        interrupt_flag: &mut InterruptFlags,
+        cpu_active: bool,
+    ) {
+        if !self.is_enabled() || dots == 0 {
+            return;
+        }
+        self.tick_dot(interrupt_flag, cpu_active);
+        dots -= 1;
+        while dots > 0 {
+            let skipped = self.dots_until_event().min(dots);
+            if skipped == 0 {
+                self.tick_dot(interrupt_flag, cpu_active);
+                dots -= 1;
+                continue;
+            }
+            // Less than a line
+            #[allow(clippy::cast_possible_truncation)]
+            let skipped_dots = skipped as u16;
+            if cpu_active && self.ly < VISIBLE_LINES && self.status.mode() == Mode::HBlank {
+                self.metrics[self.ly as usize].hblank_cpu_cycles += skipped_dots;
+            }
+            self.dot += skipped_dots;
+            dots -= skipped;
+        }
+    }
+
+    /// Returns how many dots can run before one that changes the mode or line, the only
+    /// dots that can request an interrupt or complete a frame. Writes to the registers can
+    /// also request a STAT interrupt, so this only holds until the next write.
+    pub fn dots_until_event(&self) -> usize {
+        if !self.is_enabled() {
+            return usize::MAX;
+        }
+        let mut event = DOTS_PER_LINE - 1;
+        if self.ly < VISIBLE_LINES {
+            // Mode 3 ends whenever the last pixel is out, so it runs a dot at a time
+            if self.status.mode() == Mode::Drawing {
+                return 0;
+            }
+            for dot in [0, OAM_SCAN_DOTS] {
+                if dot >= self.dot {
+                    event = event.min(dot);
+                }
+            }
+        }
+        (event - self.dot) as usize
+    }
+
+    fn tick_dot(&mut self, interrupt_flag: &mut InterruptFlags, cpu_active: bool) {
+        if self.ly < VISIBLE_LINES {
+            if self.dot == 0 {
+                self.status.set_mode(Mode::OamScan);
+            } else if self.dot == OAM_SCAN_DOTS {
+                self.scan_oam();
+                self.start_drawing();
+                self.status.set_mode(Mode::Drawing);
+            } else if self.status.mode() == Mode::Drawing && self.pipeline.is_line_done() {
+                let metrics = &mut self.metrics[self.ly as usize];
+                metrics.mode3_length = self.dot - OAM_SCAN_DOTS;
+                #[allow(clippy::cast_possible_truncation)]
+                let sprite_count = self.line_sprite_count as u8;
+                metrics.sprite_count = sprite_count;
+                metrics.hblank_cpu_cycles = 0;
+                if self.pipeline.showed_window() {
+                    self.window_line += 1;
+                }
+                self.status.set_mode(Mode::HBlank);
+            }
+
+            if self.status.mode() == Mode::Drawing {
+                self.tick_drawing();
+            }
+
+            if cpu_active && self.status.mode() == Mode::HBlank {
+                self.metrics[self.ly as usize].hblank_cpu_cycles += 1;
+            }
+        }
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.ly = (self.ly + 1) % LINES_PER_FRAME;
+
+            if self.ly == 0 {
+                self.window_line = 0;
+            } else if self.ly == VISIBLE_LINES {
+                self.frame_drawn = self.frames_to_skip == 0;
+                if self.frame_drawn {
+                    self.mark_dirty_lines();
+                    self.completed_frame = self.frame;
+                    self.frame_generation += 1;
+                    self.frames_to_skip = self.frame_skip.max(self.fast_forward_skip);
+                } else {
+                    self.frames_to_skip -= 1;
+                }
+                self.completed_metrics = self.metrics;
+                self.frame_ready = true;
+                interrupt_flag.set(InterruptFlags::VBLANK, true);
+                self.status.set_mode(Mode::VBlank);
+            }
+        }
+
+        self.update_stat_line(interrupt_flag);
+    }
+
+    /// Combines all enabled STAT sources into the single interrupt line, requesting
+    /// an interrupt only when the line goes from low to high.
+    ///
+    /// A source becoming active while another one already holds the line high
+    /// doesn't request another interrupt.
+    fn update_stat_line(&mut self, interrupt_flag: &mut InterruptFlags) {
+        self.status
+            .set(DisplayStatus::LYC_EQ_LY, self.ly == self.lyc);
+        let mode_source = match self.status.mode() {
+            Mode::HBlank => DisplayStatus::MODE_0,
+            Mode::VBlank => DisplayStatus::MODE_1,
+            Mode::OamScan => DisplayStatus::MODE_2,
+            Mode::Drawing => 0,
+        };
+        let line = (mode_source != 0 && self.status.contains(mode_source))
+            || self
+                .status
+                .contains(DisplayStatus::LYC | DisplayStatus::LYC_EQ_LY);
+        if line && !self.stat_line {
+            interrupt_flag.set(InterruptFlags::STAT, true);
+        }
+        self.stat_line = line;
+    }
+
+    /// Selects the first 10 sprites in OAM overlapping the current scanline.
+    ///
+    /// Only Y is checked, so sprites hidden horizontally (X 0 or X 168 and beyond) still
+    /// use up the limit. Sprites partially above the screen (Y below 16) are selected for
+    /// the lines they overlap, and Y 0 or Y 160 and beyond never overlap a line.
+    fn scan_oam(&mut self) {
+        let height = self.control.sprite_height();
+        let line = self.ly + 16;
+        self.line_sprite_count = 0;
+        for index in 0..(SPRITE_RAM_SIZE / SPRITE_SIZE) {
+            if self.line_sprite_count == MAX_SPRITES_PER_LINE {
+                break;
+            }
+            let y = self.sprite_ram[index * SPRITE_SIZE];
+            // Wraps for sprites below the line, leaving them out like those above
+            if line.wrapping_sub(y) < height {
+                #[allow(clippy::cast_possible_truncation)]
+                let index = index as u8;
+                self.line_sprites[self.line_sprite_count] = index;
+                self.line_sprite_count += 1;
+            }
+        }
+    }
+
+    /// Returns the 4 KiB of tile data the background uses, from 0x8000 or 0x8800 as LCDC
+    /// selects.
+    pub fn background_tile_data(&self) -> &[u8] {
+        let start = if self
+            .control
+            .contains(DisplayControl::BACKGROUND_AND_WINDOW_TILE_DATA_AREA)
+        {
+            0x0000
+        } else {
+            0x0800
+        };
+        &self.video_ram[start..start + 0x1000]
+    }
+
+    /// Returns the VRAM offset of a background or window tile.
+    fn background_tile(&self, tile_number: u8) -> usize {
+        if self
+            .control
+            .contains(DisplayControl::BACKGROUND_AND_WINDOW_TILE_DATA_AREA)
+        {
+            tile_number as usize * TILE_SIZE
+        } else {
+            // Signed addressing from 0x9000
+            #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+            let offset = (0x1000 + (tile_number as i8 as isize) * TILE_SIZE as isize) as usize;
+            offset
+        }
+    }
+
+    /// Returns the color indices of row `y` of the tile starting at `tile` in VRAM.
+    fn tile_row(&self, tile: usize, y: usize) -> [u8; TILE_PIXELS] {
+        let row = tile + y * 2;
+        decode_row(self.video_ram[row], self.video_ram[row + 1])
+    }
+
+    fn update_palette_shades(&mut self) {
+        self.background_shades = palette_shades(self.background_palette_data);
+        self.object_shades = [
+            palette_shades(self.object_palette_0_data),
+            palette_shades(self.object_palette_1_data),
+        ];
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        self.control
+            .contains(DisplayControl::DISPLAY_AND_PPU_ENABLE)
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.component("PPU");
+        writer.memory("VRAM", 0x8000);
+        writer.write_bytes(&self.video_ram);
+        writer.memory("OAM", 0xFE00);
+        writer.write_bytes(&self.sprite_ram);
+        // In register order, from LCDC to WX
+        writer.memory("registers", 0xFF40);
+        for value in [
+            self.control.bits(),
+            self.status.bits(),
+            self.scroll_y,
+            self.scroll_x,
+            self.ly,
+            self.lyc,
+            self.transfer_and_start_address,
+            self.background_palette_data,
+            self.object_palette_0_data,
+            self.object_palette_1_data,
+            self.window_y,
+            self.window_x,
+        ] {
+            writer.write_u8(value);
+        }
+        for (name, palettes) in [
+            ("background palettes", &self.background_palettes),
+            ("object palettes", &self.object_palettes),
+        ] {
+            writer.field(name);
+            writer.write_u8(palettes.specification.bits());
+            writer.write_bytes(&palettes.data);
+        }
+        writer.field("dot");
+        writer.write_u16(self.dot);
+        writer.field("line sprites");
+        writer.write_bytes(&self.line_sprites);
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_u8(self.line_sprite_count as u8);
+        writer.field("window line");
+        writer.write_u8(self.window_line);
+        self.pipeline.save_state(writer);
+        writer.field("frame in progress");
+        writer.write_bytes(&self.frame);
+        writer.field("completed frame");
+        writer.write_bytes(&self.completed_frame);
+        writer.field("frame ready");
+        writer.write_bool(self.frame_ready);
+        writer.field("STAT line");
+        writer.write_bool(self.stat_line);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        reader.read_bytes(&mut self.video_ram)?;
+        reader.read_bytes(&mut self.sprite_ram)?;
+        self.control = DisplayControl::from_bits(reader.read_u8()?);
+        self.status = DisplayStatus::from_bits(reader.read_u8()?);
+        self.scroll_y = reader.read_u8()?;
+        self.scroll_x = reader.read_u8()?;
+        self.ly = reader.read_u8()?;
+        self.lyc = reader.read_u8()?;
+        self.transfer_and_start_address = reader.read_u8()?;
+        self.background_palette_data = reader.read_u8()?;
+        self.object_palette_0_data = reader.read_u8()?;
+        self.object_palette_1_data = reader.read_u8()?;
+        self.update_palette_shades();
+        self.window_y = reader.read_u8()?;
+        self.window_x = reader.read_u8()?;
+        for palettes in [&mut self.background_palettes, &mut self.object_palettes] {
+            palettes.specification = PaletteSpecification::from_bits(reader.read_u8()?);
+            reader.read_bytes(&mut palettes.data)?;
+        }
+        self.dot = reader.read_u16()?;
+        reader.read_bytes(&mut self.line_sprites)?;
+        self.line_sprite_count = (reader.read_u8()? as usize).min(MAX_SPRITES_PER_LINE);
+        self.window_line = reader.read_u8()?;
+        self.pipeline.load_state(reader)?;
+        reader.read_bytes(&mut self.frame)?;
+        reader.read_bytes(&mut self.completed_frame)?;
+        self.frame_ready = reader.read_bool()?;
+        self.stat_line = reader.read_bool()?;
+        self.dirty_lines = DirtyLines::all();
+        self.frame_generation += 1;
+        Ok(())
+    }
+
+    pub fn set_stat_write_bug(&mut self, enable: bool) {
+        self.stat_write_bug = enable;
+    }
+
+    pub const fn has_stat_write_bug(&self) -> bool {
+        self.stat_write_bug
+    }
+
+    /// Writes STAT. With the DMG bug, every source is enabled for the cycle of the write, so
+    /// writing during HBlank, VBlank or while LY=LYC requests an interrupt if the line was
+    /// low, whatever the sources written.
+    pub fn write_status(&mut self, value: u8, interrupt_flag: &mut InterruptFlags) {
+        if self.stat_write_bug && self.is_enabled() {
+            let line = matches!(self.status.mode(), Mode::HBlank | Mode::VBlank)
+                || self.status.contains(DisplayStatus::LYC_EQ_LY);
+            if line && !self.stat_line {
+                interrupt_flag.set(InterruptFlags::STAT, true);
+                self.stat_line = true;
+            }
+        }
+        self.write_display(MEM_DISPLAY_STATUS, value);
+    }
+
+    /// Returns the state of the STAT interrupt line.
+    pub const fn stat_line(&self) -> bool {
+        self.stat_line
+    }
+
+    fn mark_dirty_lines(&mut self) {
+        let lines = self.frame.chunks_exact(SCREEN_WIDTH);
+        let completed = self.completed_frame.chunks_exact(SCREEN_WIDTH);
+        for (line, (new, old)) in lines.zip(completed).enumerate() {
+            if new != old {
+                self.dirty_lines.insert(line);
+            }
+        }
+    }
+
+    pub fn take_dirty_lines(&mut self) -> DirtyLines {
+        std::mem::replace(&mut self.dirty_lines, DirtyLines::none())
+    }
+
+    /// Returns whether a frame completed since the last call.
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    /// Returns the shades (0-3) of the last completed frame in row-major order.
+    pub const fn frame(&self) -> &[u8; FRAME_SIZE] {
+        &self.completed_frame
+    }
+
+    /// Skips drawing `frames` frames after each frame drawn, see
+    /// [`GameboyHardware::set_frame_skip`].
+    ///
+    /// [`GameboyHardware::set_frame_skip`]: crate::hardware::GameboyHardware::set_frame_skip
+    pub fn set_frame_skip(&mut self, frames: u8) {
+        self.frame_skip = frames;
+        self.frames_to_skip = self.frames_to_skip.min(frames);
+    }
+
+    pub const fn frame_skip(&self) -> u8 {
+        self.frame_skip
+    }
+
+    /// Skips drawing at least `frames` frames after each frame drawn, on top of
+    /// [`Self::set_frame_skip`], see [`GameboyHardware::set_fast_forward_skip`].
+    ///
+    /// [`GameboyHardware::set_fast_forward_skip`]: crate::hardware::GameboyHardware::set_fast_forward_skip
+    pub fn set_fast_forward_skip(&mut self, frames: u8) {
+        self.fast_forward_skip = frames;
+        self.frames_to_skip = self
+            .frames_to_skip
+            .min(self.frame_skip.max(self.fast_forward_skip));
+    }
+
+    pub const fn frame_generation(&self) -> u64 {
+        self.frame_generation
+    }
+
+    /// Returns whether the last completed frame was drawn, or skipped by frame skipping.
+    pub const fn frame_drawn(&self) -> bool {
+        self.frame_drawn
+    }
+
+    /// Returns metrics for each visible scanline of the last completed frame.
+    pub const fn scanline_metrics(&self) -> &[ScanlineMetrics; SCREEN_HEIGHT] {
+        &self.completed_metrics
+    }
+
+    /// Starts counting VRAM writes for the next frame, keeping those of the frame that
+    /// just completed.
+    pub fn end_vram_write_frame(&mut self) {
+        self.completed_vram_writes = std::mem::take(&mut self.vram_writes);
+    }
+
+    pub const fn completed_vram_writes(&self) -> &VramWriteStats {
+        &self.completed_vram_writes
+    }
+
     pub const fn read_vram(&self, addr: u16) -> u8 {
         self.video_ram[addr as usize]
     }
 
     pub fn write_vram(&mut self, addr: u16, data: u8) {
+        let mode = self.is_enabled().then(|| self.status.mode() as usize);
+        self.vram_writes.record(addr, mode);
         self.video_ram[addr as usize] = data;
     }
 
@@ -171,6 +825,126 @@ impl Ppu {
         self.sprite_ram[addr as usize] = data;
     }
 
+    /// Returns whether the PPU is reading OAM, during OAM scan and drawing.
+    pub const fn is_oam_blocked(&self) -> bool {
+        self.is_enabled() && matches!(self.status.mode(), Mode::OamScan | Mode::Drawing)
+    }
+
+    pub fn set_oam_bug(&mut self, enable: bool) {
+        self.oam_bug = enable;
+    }
+
+    pub const fn has_oam_bug(&self) -> bool {
+        self.oam_bug
+    }
+
+    /// Corrupts OAM with the DMG OAM bug, if the PPU is scanning OAM. The row of 8 bytes
+    /// being scanned is overwritten with a mix of its first word and the preceding row's,
+    /// the first row is never corrupted.
+    pub fn corrupt_oam(&mut self, corruption: OamCorruption) {
+        if !self.oam_bug || !self.is_enabled() || self.status.mode() != Mode::OamScan {
+            return;
+        }
+        // One row is scanned per M-cycle
+        let row = usize::from(self.dot / 4).min(OAM_ROWS - 1);
+        if row == 0 {
+            return;
+        }
+        match corruption {
+            OamCorruption::Write => {
+                self.corrupt_oam_row(row, |a, b, c| ((a ^ c) & (b ^ c)) ^ c);
+            }
+            OamCorruption::Read => self.corrupt_oam_row(row, |a, b, c| b | (a & c)),
+            OamCorruption::IncreaseDuringRead => {
+                if (4..OAM_ROWS - 1).contains(&row) {
+                    let a = self.oam_word(row - 2, 0);
+                    let b = self.oam_word(row - 1, 0);
+                    let c = self.oam_word(row, 0);
+                    let d = self.oam_word(row - 1, 2);
+                    self.set_oam_word(row - 1, 0, (b & (a | c | d)) | (a & c & d));
+                    let preceding = (row - 1) * OAM_ROW_SIZE;
+                    for target in [row, row - 2] {
+                        self.sprite_ram.copy_within(
+                            preceding..preceding + OAM_ROW_SIZE,
+                            target * OAM_ROW_SIZE,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets the first word of `row` to `pattern(a, b, c)`, with `a` that word and `b` and `c`
+    /// the first and third words of the preceding row, then copies the rest of the
+    /// preceding row.
+    fn corrupt_oam_row(&mut self, row: usize, pattern: impl Fn(u16, u16, u16) -> u16) {
+        let a = self.oam_word(row, 0);
+        let b = self.oam_word(row - 1, 0);
+        let c = self.oam_word(row - 1, 2);
+        self.set_oam_word(row, 0, pattern(a, b, c));
+        let preceding = (row - 1) * OAM_ROW_SIZE;
+        self.sprite_ram.copy_within(
+            preceding + 2..preceding + OAM_ROW_SIZE,
+            row * OAM_ROW_SIZE + 2,
+        );
+    }
+
+    fn oam_word(&self, row: usize, word: usize) -> u16 {
+        let index = row * OAM_ROW_SIZE + word * 2;
+        u16::from_le_bytes([self.sprite_ram[index], self.sprite_ram[index + 1]])
+    }
+
+    fn set_oam_word(&mut self, row: usize, word: usize, value: u16) {
+        let index = row * OAM_ROW_SIZE + word * 2;
+        self.sprite_ram[index..index + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Returns whether the PPU is reading palette RAM for the current line, blocking the CPU.
+    fn is_palette_ram_blocked(&self) -> bool {
+        self.is_enabled() && self.status.mode() == Mode::Drawing
+    }
+
+    /// Reads the CGB palette registers, which read 0xFF on DMG and their data during mode 3.
+    pub fn read_color_palette(&self, addr: u16) -> u8 {
+        if self.model != Model::Cgb {
+            return 0xFF;
+        }
+        match addr {
+            MEM_BACKGROUND_COLOR_PALETTE_SPECIFICATION => {
+                self.background_palettes.specification.bits()
+            }
+            MEM_OBJECT_COLOR_PALETTE_SPECIFICATION => self.object_palettes.specification.bits(),
+            _ if self.is_palette_ram_blocked() => 0xFF,
+            MEM_BACKGROUND_COLOR_PALETTE_DATA => self.background_palettes.read_data(),
+            MEM_OBJECT_COLOR_PALETTE_DATA => self.object_palettes.read_data(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes the CGB palette registers, ignoring writes on DMG.
+    ///
+    /// Data writes during mode 3 are dropped, but still advance the address when
+    /// auto-increment is on.
+    pub fn write_color_palette(&mut self, addr: u16, value: u8) {
+        if self.model != Model::Cgb {
+            return;
+        }
+        let blocked = self.is_palette_ram_blocked();
+        match addr {
+            MEM_BACKGROUND_COLOR_PALETTE_SPECIFICATION => {
+                self.background_palettes.specification = PaletteSpecification::from_bits(value);
+            }
+            MEM_OBJECT_COLOR_PALETTE_SPECIFICATION => {
+                self.object_palettes.specification = PaletteSpecification::from_bits(value);
+            }
+            MEM_BACKGROUND_COLOR_PALETTE_DATA => {
+                self.background_palettes.write_data(value, blocked)
+            }
+            MEM_OBJECT_COLOR_PALETTE_DATA => self.object_palettes.write_data(value, blocked),
+            _ => unreachable!(),
+        }
+    }
+
     pub const fn read_display(&self, addr: u16) -> u8 {
         match addr {
             MEM_DISPLAY_CONTROL => self.control.bits(),
@@ -191,19 +965,446 @@ impl Ppu {
 
     pub fn write_display(&mut self, addr: u16, value: u8) {
         match addr {
-            MEM_DISPLAY_CONTROL => self.control = DisplayControl::from_bits(value),
-            MEM_DISPLAY_STATUS => self.status = DisplayStatus::from_bits(value),
+            MEM_DISPLAY_CONTROL => {
+                let was_enabled = self
+                    .control
+                    .contains(DisplayControl::DISPLAY_AND_PPU_ENABLE);
+                self.control = DisplayControl::from_bits(value);
+                if was_enabled
+                    && !self
+                        .control
+                        .contains(DisplayControl::DISPLAY_AND_PPU_ENABLE)
+                {
+                    // Turning off the LCD resets it to the start of the frame
+                    self.ly = 0;
+                    self.dot = 0;
+                    self.status.set_mode(Mode::HBlank);
+                    self.stat_line = false;
+                }
+            }
+            MEM_DISPLAY_STATUS => {
+                // Mode and LYC == LY bits are read-only
+                let read_only = DisplayStatus::LYC_EQ_LY | DisplayStatus::PPU_MODE;
+                self.status = DisplayStatus::from_bits(
+                    (value & !read_only) | (self.status.bits() & read_only),
+                );
+            }
             MEM_SCROLL_Y => self.scroll_y = value,
             MEM_SCROLL_X => self.scroll_x = value,
-            MEM_LY => self.ly = value,
+            // LY is read-only
+            MEM_LY => {}
             MEM_LYC => self.lyc = value,
             MEM_TRANSFER_AND_START_ADDRESS => self.transfer_and_start_address = value,
-            MEM_BACKGROUND_PALETTE_DATA => self.background_palette_data = value,
-            MEM_OBJECT_PALETTE_0_DATA => self.object_palette_0_data = value,
-            MEM_OBJECT_PALETTE_1_DATA => self.object_palette_1_data = value,
+            MEM_BACKGROUND_PALETTE_DATA => {
+                self.background_palette_data = value;
+                self.update_palette_shades();
+            }
+            MEM_OBJECT_PALETTE_0_DATA => {
+                self.object_palette_0_data = value;
+                self.update_palette_shades();
+            }
+            MEM_OBJECT_PALETTE_1_DATA => {
+                self.object_palette_1_data = value;
+                self.update_palette_shades();
+            }
             MEM_WINDOW_Y => self.window_y = value,
             MEM_WINDOW_X => self.window_x = value,
             _ => unreachable!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::SCREEN_WIDTH;
+    use crate::hardware::Model;
+    use crate::interrupts::InterruptFlags;
+    use crate::ppu::{
+        OamCorruption, Ppu, DOTS_PER_LINE, LINES_PER_FRAME, OAM_SCAN_DOTS, SPRITE_PRIORITY,
+    };
+
+    /// Draws a solid background of color 3 with a behind-background sprite of
+    /// color 1 at the left of line 0, then renders the first line.
+    fn render_first_line(model: Model, lcdc: u8) -> Vec<u8> {
+        let mut ppu = Ppu::new(model);
+        // Tile 0: all pixels color 3, tile 1: all pixels color 1
+        for row in 0..8 {
+            ppu.write_vram(row * 2, 0xFF);
+            ppu.write_vram(row * 2 + 1, 0xFF);
+            ppu.write_vram(16 + row * 2, 0xFF);
+        }
+        // Sprite at the top left using tile 1, drawn behind background colors 1-3
+        ppu.write_sprite(0, 16);
+        ppu.write_sprite(1, 8);
+        ppu.write_sprite(2, 1);
+        ppu.write_sprite(3, SPRITE_PRIORITY);
+        ppu.write_display(0xFF47, 0b1110_0100);
+        ppu.write_display(0xFF48, 0b1110_0100);
+        ppu.write_display(0xFF40, lcdc);
+
+        let mut interrupt_flag = InterruptFlags::empty();
+        for _ in 0..LINES_PER_FRAME {
+            for _ in 0..DOTS_PER_LINE / 4 {
+                ppu.tick(&mut interrupt_flag, true);
+            }
+        }
+        ppu.frame()[..SCREEN_WIDTH].to_vec()
+    }
+
+    // LCD on, unsigned tile data, sprites on
+    const LCDC: u8 = 0b1001_0010;
+
+    #[test]
+    fn test_stat_interrupt_on_rising_edge_only() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        // HBlank and OAM scan sources, LYC matches line 1
+        ppu.write_display(0xFF41, 0b0110_1000);
+        ppu.write_display(0xFF45, 1);
+
+        let mut requests = 0;
+        // Up to the start of mode 3 on line 2
+        for _ in 0..(2 * DOTS_PER_LINE + OAM_SCAN_DOTS + 4) / 4 {
+            let mut interrupt_flag = InterruptFlags::empty();
+            ppu.tick(&mut interrupt_flag, true);
+            if interrupt_flag.contains(InterruptFlags::STAT) {
+                requests += 1;
+            }
+        }
+        // OAM scan on line 0, then the line stays high from HBlank on line 0 through
+        // LYC and OAM scan on line 1 until mode 3 on line 2
+        assert_eq!(requests, 2);
+        assert!(!ppu.stat_line());
+    }
+
+    #[test]
+    fn test_oam_bug() {
+        let corrupted_row = |model, corruption| {
+            let mut ppu = Ppu::new(model);
+            for offset in 0..160 {
+                ppu.write_sprite(offset, (offset as u8).wrapping_mul(0x35));
+            }
+            ppu.write_display(0xFF40, LCDC);
+            // Into OAM scan of row 2 on line 1
+            let mut interrupt_flag = InterruptFlags::empty();
+            while ppu.ly != 1 || ppu.dot != 8 {
+                ppu.tick(&mut interrupt_flag, true);
+            }
+            ppu.corrupt_oam(corruption);
+            (16..24)
+                .map(|offset| ppu.read_sprite(offset))
+                .collect::<Vec<_>>()
+        };
+        let untouched = [0x50, 0x85, 0xBA, 0xEF, 0x24, 0x59, 0x8E, 0xC3];
+        // The first word is mixed with the preceding row's, the rest is copied from it
+        assert_eq!(
+            corrupted_row(Model::Dmg, OamCorruption::Write),
+            [0x78, 0x95, 0x12, 0x47, 0x7C, 0xB1, 0xE6, 0x1B]
+        );
+        assert_eq!(
+            corrupted_row(Model::Dmg, OamCorruption::Read),
+            [0xF8, 0xDD, 0x12, 0x47, 0x7C, 0xB1, 0xE6, 0x1B]
+        );
+        // Rows before the fourth aren't corrupted by increments during reads
+        assert_eq!(
+            corrupted_row(Model::Dmg, OamCorruption::IncreaseDuringRead),
+            untouched
+        );
+        assert_eq!(corrupted_row(Model::Cgb, OamCorruption::Write), untouched);
+    }
+
+    #[test]
+    fn test_stat_write_bug() {
+        for (model, expected) in [(Model::Dmg, true), (Model::Cgb, false)] {
+            let mut ppu = Ppu::new(model);
+            ppu.write_display(0xFF40, LCDC);
+            ppu.write_display(0xFF45, 0x90);
+            // Into HBlank on line 0
+            let mut interrupt_flag = InterruptFlags::empty();
+            while ppu.read_display(0xFF41) & 0x03 != 0 || ppu.dot < OAM_SCAN_DOTS {
+                ppu.tick(&mut interrupt_flag, true);
+            }
+            assert!(!interrupt_flag.contains(InterruptFlags::STAT));
+
+            // No sources enabled, the DMG requests an interrupt anyway
+            ppu.write_status(0x00, &mut interrupt_flag);
+            assert_eq!(interrupt_flag.contains(InterruptFlags::STAT), expected);
+        }
+    }
+
+    #[test]
+    fn test_dmg_background_enabled() {
+        let line = render_first_line(Model::Dmg, LCDC | 1);
+        // The sprite is hidden behind the non-zero background
+        assert!(line.iter().all(|&shade| shade == 3));
+    }
+
+    #[test]
+    fn test_dmg_background_disabled_is_white() {
+        let line = render_first_line(Model::Dmg, LCDC);
+        assert_eq!(&line[..8], &[1; 8]);
+        assert!(line[8..].iter().all(|&shade| shade == 0));
+    }
+
+    #[test]
+    fn test_cgb_background_disabled_loses_priority() {
+        let line = render_first_line(Model::Cgb, LCDC);
+        assert_eq!(&line[..8], &[1; 8]);
+        assert!(line[8..].iter().all(|&shade| shade == 3));
+    }
+
+    /// Places sprites given as (Y, X) in OAM, using tile 1.
+    fn ppu_with_sprites(sprites: &[(u8, u8)]) -> Ppu {
+        let mut ppu = Ppu::new(Model::Dmg);
+        for (index, (y, x)) in sprites.iter().enumerate() {
+            let offset = index as u16 * 4;
+            ppu.write_sprite(offset, *y);
+            ppu.write_sprite(offset + 1, *x);
+            ppu.write_sprite(offset + 2, 1);
+        }
+        ppu.write_display(0xFF40, LCDC);
+        ppu
+    }
+
+    fn selected_sprites(ppu: &mut Ppu, ly: u8) -> Vec<u8> {
+        ppu.ly = ly;
+        ppu.scan_oam();
+        ppu.line_sprites[..ppu.line_sprite_count].to_vec()
+    }
+
+    #[test]
+    fn test_hidden_sprites_count_toward_limit() {
+        let mut sprites = vec![(16, 0); 5];
+        sprites.extend([(16, 168); 4]);
+        sprites.extend([(16, 255), (16, 8)]);
+        let mut ppu = ppu_with_sprites(&sprites);
+        assert_eq!(selected_sprites(&mut ppu, 0), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sprites_off_screen_vertically() {
+        let mut ppu = ppu_with_sprites(&[(0, 8), (160, 8), (255, 8), (2, 8), (10, 8)]);
+        for ly in 0..144 {
+            let expected = match ly {
+                0..=1 => vec![4],
+                _ => vec![],
+            };
+            assert_eq!(selected_sprites(&mut ppu, ly), expected, "line {ly}");
+        }
+        // In 8x16 mode the sprite at Y 2 overlaps the first two lines as well
+        ppu.write_display(0xFF40, LCDC | 0b100);
+        assert_eq!(selected_sprites(&mut ppu, 1), [3, 4]);
+        assert_eq!(selected_sprites(&mut ppu, 2), [4]);
+        assert_eq!(selected_sprites(&mut ppu, 10), []);
+    }
+
+    /// Runs a frame, returning the length of mode 3 on line 0.
+    fn drawing_length(ppu: &mut Ppu) -> u16 {
+        let mut interrupt_flag = InterruptFlags::empty();
+        for _ in 0..(u32::from(LINES_PER_FRAME) * u32::from(DOTS_PER_LINE) / 4) {
+            ppu.tick(&mut interrupt_flag, true);
+        }
+        ppu.scanline_metrics()[0].mode3_length
+    }
+
+    #[test]
+    fn test_hidden_sprite_penalties() {
+        let sprite_drawing_length = |x| drawing_length(&mut ppu_with_sprites(&[(16, x)]));
+        // Fetched before the first pixel, always the longest penalty
+        assert_eq!(sprite_drawing_length(0), 172 + 11);
+        assert_eq!(sprite_drawing_length(8), 172 + 5 + 6);
+        assert_eq!(sprite_drawing_length(167), 172 + 6);
+        // Never reached by the fetcher
+        assert_eq!(sprite_drawing_length(168), 172);
+        assert_eq!(sprite_drawing_length(255), 172);
+    }
+
+    #[test]
+    fn test_scroll_and_window_lengthen_drawing() {
+        let mut ppu = ppu_with_sprites(&[]);
+        ppu.write_display(0xFF43, 5);
+        assert_eq!(drawing_length(&mut ppu), 172 + 5);
+
+        // The window restarts the fetcher where it begins
+        let mut ppu = ppu_with_sprites(&[]);
+        ppu.write_display(0xFF4B, 87);
+        ppu.write_display(0xFF40, LCDC | 0b0010_0001);
+        assert_eq!(drawing_length(&mut ppu), 172 + 6);
+    }
+
+    #[test]
+    fn test_mid_line_scroll_write() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        // Tile 1 is color 3, the first row of the map alternates tiles 0 and 1
+        for row in 0..8 {
+            ppu.write_vram(16 + row * 2, 0xFF);
+            ppu.write_vram(16 + row * 2 + 1, 0xFF);
+        }
+        for column in (1..32).step_by(2) {
+            ppu.write_vram(0x1800 + column, 1);
+        }
+        ppu.write_display(0xFF47, 0b1110_0100);
+        ppu.write_display(0xFF40, LCDC | 1);
+
+        // Scroll by a tile halfway through mode 3 on line 0
+        let mut interrupt_flag = InterruptFlags::empty();
+        let dots = OAM_SCAN_DOTS + 12 + 80;
+        for _ in 0..dots / 4 {
+            ppu.tick(&mut interrupt_flag, true);
+        }
+        ppu.write_display(0xFF43, 8);
+        for _ in 0..(u32::from(LINES_PER_FRAME) * u32::from(DOTS_PER_LINE) - u32::from(dots)) / 4 {
+            ppu.tick(&mut interrupt_flag, true);
+        }
+
+        // Tiles fetched after the write come from one column further right
+        let stripe = |x: usize, shifted: bool| if (x / 8 % 2 == 1) != shifted { 3 } else { 0 };
+        let line = &ppu.frame()[..SCREEN_WIDTH];
+        for (x, &shade) in line.iter().enumerate() {
+            if x < 72 {
+                assert_eq!(shade, stripe(x, false), "pixel {x}");
+            } else if x >= 96 {
+                assert_eq!(shade, stripe(x, true), "pixel {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_sprite_partially_above_screen() {
+        // 8x16 sprite at Y 2: only the last two rows, from the bottom tile, are visible
+        let mut ppu = ppu_with_sprites(&[(2, 8)]);
+        for row in 0..8 {
+            ppu.write_vram(3 * 16 + row * 2, 0xFF);
+        }
+        ppu.write_sprite(2, 2);
+        ppu.write_display(0xFF48, 0b1110_0100);
+        ppu.write_display(0xFF40, LCDC | 0b100);
+        let mut interrupt_flag = InterruptFlags::empty();
+        for _ in 0..(u32::from(LINES_PER_FRAME) * u32::from(DOTS_PER_LINE) / 4) {
+            ppu.tick(&mut interrupt_flag, true);
+        }
+        let line = |ly: usize| &ppu.frame()[ly * SCREEN_WIDTH..][..8];
+        assert_eq!(line(0), &[1; 8]);
+        assert_eq!(line(1), &[1; 8]);
+        assert_eq!(line(2), &[0; 8]);
+    }
+
+    #[test]
+    fn test_dirty_lines() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        let mut interrupt_flag = InterruptFlags::empty();
+        let mut run_frame = |ppu: &mut Ppu| {
+            for _ in 0..(u32::from(LINES_PER_FRAME) * u32::from(DOTS_PER_LINE) / 4) {
+                ppu.tick(&mut interrupt_flag, true);
+            }
+        };
+        assert_eq!(ppu.take_dirty_lines().iter().count(), 144);
+        run_frame(&mut ppu);
+        assert!(ppu.take_dirty_lines().is_empty());
+
+        // An 8x8 sprite covering lines 10-17
+        for row in 0..8 {
+            ppu.write_vram(16 + row * 2, 0xFF);
+        }
+        ppu.write_sprite(0, 16 + 10);
+        ppu.write_sprite(1, 8);
+        ppu.write_sprite(2, 1);
+        ppu.write_display(0xFF40, LCDC);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        let dirty = ppu.take_dirty_lines();
+        assert_eq!(
+            dirty.iter().collect::<Vec<_>>(),
+            (10..18).collect::<Vec<_>>()
+        );
+        assert!(dirty.contains(17) && !dirty.contains(18) && !dirty.contains(500));
+    }
+
+    #[test]
+    fn test_palette_auto_increment() {
+        let mut ppu = Ppu::new(Model::Cgb);
+        ppu.write_display(0xFF40, 0);
+        // Auto-increment from the last byte wraps to the first
+        ppu.write_color_palette(0xFF68, 0x80 | 63);
+        assert_eq!(ppu.read_color_palette(0xFF68), 0xC0 | 63);
+        ppu.write_color_palette(0xFF69, 0x12);
+        ppu.write_color_palette(0xFF69, 0x34);
+        assert_eq!(ppu.read_color_palette(0xFF68), 0xC1);
+        // Reads don't increment
+        assert_eq!(ppu.read_color_palette(0xFF69), 0);
+        assert_eq!(ppu.read_color_palette(0xFF68), 0xC1);
+
+        ppu.write_color_palette(0xFF68, 63);
+        assert_eq!(ppu.read_color_palette(0xFF69), 0x12);
+        ppu.write_color_palette(0xFF69, 0x56);
+        assert_eq!(ppu.read_color_palette(0xFF68), 0x40 | 63);
+        assert_eq!(ppu.read_color_palette(0xFF69), 0x56);
+
+        // Object palettes are separate
+        ppu.write_color_palette(0xFF6A, 0);
+        assert_eq!(ppu.read_color_palette(0xFF6B), 0);
+    }
+
+    #[test]
+    fn test_palette_blocked_during_drawing() {
+        let mut ppu = Ppu::new(Model::Cgb);
+        ppu.write_color_palette(0xFF6A, 0x80);
+        let mut interrupt_flag = InterruptFlags::empty();
+        for _ in 0..=OAM_SCAN_DOTS / 4 {
+            ppu.tick(&mut interrupt_flag, true);
+        }
+        assert_eq!(ppu.read_display(0xFF41) & 0b11, 3);
+
+        // Writes are dropped, but the address still increments
+        ppu.write_color_palette(0xFF6B, 0x12);
+        assert_eq!(ppu.read_color_palette(0xFF6A), 0xC1);
+        assert_eq!(ppu.read_color_palette(0xFF6B), 0xFF);
+
+        ppu.write_display(0xFF40, 0);
+        ppu.write_color_palette(0xFF6A, 0);
+        assert_eq!(ppu.read_color_palette(0xFF6B), 0);
+    }
+
+    #[test]
+    fn test_dmg_has_no_palette_ram() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        ppu.write_display(0xFF40, 0);
+        ppu.write_color_palette(0xFF68, 0x80);
+        ppu.write_color_palette(0xFF69, 0x12);
+        for addr in 0xFF68..=0xFF6B {
+            assert_eq!(ppu.read_color_palette(addr), 0xFF);
+        }
+    }
+
+    #[test]
+    fn test_frame_skip_keeps_state() {
+        let mut drawn = Ppu::new(Model::Dmg);
+        let mut skipped = Ppu::new(Model::Dmg);
+        skipped.set_frame_skip(2);
+        for ppu in [&mut drawn, &mut skipped] {
+            ppu.write_display(0xFF47, 0b1110_0100);
+            // Window from line 64, covering the whole width
+            ppu.write_display(0xFF4A, 0x40);
+            ppu.write_display(0xFF4B, 0x07);
+            ppu.write_display(0xFF40, LCDC | 0x21);
+        }
+
+        let mut interrupt_flag = InterruptFlags::empty();
+        for frame in 0..6 {
+            // Different pixels every frame
+            drawn.write_vram(0, frame);
+            skipped.write_vram(0, frame);
+            for _ in 0..LINES_PER_FRAME as usize * DOTS_PER_LINE as usize / 4 {
+                drawn.tick(&mut interrupt_flag, true);
+                skipped.tick(&mut interrupt_flag, true);
+                assert_eq!(skipped.window_line, drawn.window_line);
+            }
+            assert_eq!(skipped.frame_drawn(), frame % 3 == 0);
+            assert_eq!(skipped.scanline_metrics(), drawn.scanline_metrics());
+            if skipped.frame_drawn() {
+                assert_eq!(skipped.frame(), drawn.frame());
+            } else {
+                assert_ne!(skipped.frame(), drawn.frame());
+            }
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Mode 3 as the hardware runs it: a fetcher reads background or window tiles into a pixel
+//! FIFO that shifts one pixel out per dot, pausing while sprites are fetched.
+//!
+//! Mode 3 ends once the 160th pixel is out, so its length follows from fine scrolling, the
+//! window and sprites, and registers written mid-line apply from the next pixel or tile
+//! fetched.
+
+use crate::consts::SCREEN_WIDTH;
+use crate::error::SavestateError;
+use crate::hardware::Model;
+use crate::ppu::{
+    DisplayControl, Ppu, SPRITE_PALETTE, SPRITE_PRIORITY, SPRITE_SIZE, SPRITE_X_FLIP,
+    SPRITE_Y_FLIP, TILE_MAP_WIDTH,
+};
+use crate::savestate::{StateReader, StateWriter};
+use crate::tile::{decode_row, TILE_PIXELS, TILE_SIZE};
+
+// Dots the fetcher takes to read a tile's number, low byte and high byte, two dots each
+const FETCH_DOTS: u8 = 6;
+// Dots into a fetch after which the fetcher can be interrupted to fetch a sprite
+const SPRITE_FETCH_READY: u8 = 5;
+// Dots taken by a sprite fetch, during which no pixel is shifted out
+const SPRITE_FETCH_DOTS: u8 = 6;
+// Bits of a sprite FIFO entry holding its color, the rest are its attributes
+const SPRITE_COLOR: u8 = 0b0000_0011;
+// Pixel counts in the types the pipeline keeps them in
+#[allow(clippy::cast_possible_truncation)]
+const TILE_PIXELS_U8: u8 = TILE_PIXELS as u8;
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+const TILE_PIXELS_I16: i16 = TILE_PIXELS as i16;
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+const SCREEN_WIDTH_I16: i16 = SCREEN_WIDTH as i16;
+
+/// State of the pixel pipeline on the current line.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Pipeline {
+    // Dots spent on the current fetch, stays at `FETCH_DOTS` until the FIFO can take it
+    fetch_dots: u8,
+    // Tile column fetched next, counted from the scroll position or the window's left edge
+    fetch_column: u8,
+    tile_number: u8,
+    tile_low: u8,
+    tile_high: u8,
+    // The first tile of a line is fetched twice, the first copy is shifted out unseen
+    first_fetch: bool,
+    // Whether the window started on this line, fetching window tiles from then on
+    window: bool,
+    // Background colors waiting to be shifted out, the last `background_len` are left
+    background: [u8; TILE_PIXELS],
+    background_len: u8,
+    // Sprite pixels lined up with the background FIFO, as color and attribute bits.
+    // Color 0 is transparent or empty
+    sprites: [u8; TILE_PIXELS],
+    // Pixels shifted out on this line, starting at -8 for the first fetch and counting
+    // those discarded for fine scrolling
+    x: i16,
+    // SCX modulo 8 at the start of the line, the pixels discarded for fine scrolling
+    fine_scroll: u8,
+    // Bit set for each line sprite fetched, by index into the line sprites
+    fetched_sprites: u16,
+    // Line sprite being fetched and the dots left, 0 when not fetching
+    sprite_fetch_index: u8,
+    sprite_fetch_dots: u8,
+}
+
+impl Pipeline {
+    pub(super) const fn new() -> Self {
+        Self {
+            fetch_dots: 0,
+            fetch_column: 0,
+            tile_number: 0,
+            tile_low: 0,
+            tile_high: 0,
+            first_fetch: true,
+            window: false,
+            background: [0; TILE_PIXELS],
+            background_len: 0,
+            sprites: [0; TILE_PIXELS],
+            x: -TILE_PIXELS_I16,
+            fine_scroll: 0,
+            fetched_sprites: 0,
+            sprite_fetch_index: 0,
+            sprite_fetch_dots: 0,
+        }
+    }
+
+    /// Returns the screen X of the next pixel shifted out, negative while discarding.
+    const fn screen_x(&self) -> i16 {
+        self.x - self.fine_scroll as i16
+    }
+
+    /// Returns whether all pixels of the line were shifted out, ending mode 3.
+    pub(super) const fn is_line_done(&self) -> bool {
+        self.screen_x() >= SCREEN_WIDTH_I16
+    }
+
+    /// Returns whether the window was drawn on this line.
+    pub(super) const fn showed_window(&self) -> bool {
+        self.window
+    }
+
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.field("fetcher");
+        for value in [
+            self.fetch_dots,
+            self.fetch_column,
+            self.tile_number,
+            self.tile_low,
+            self.tile_high,
+        ] {
+            writer.write_u8(value);
+        }
+        writer.write_bool(self.first_fetch);
+        writer.write_bool(self.window);
+        writer.field("background FIFO");
+        writer.write_bytes(&self.background);
+        writer.write_u8(self.background_len);
+        writer.field("sprite FIFO");
+        writer.write_bytes(&self.sprites);
+        writer.field("pixels shifted");
+        #[allow(clippy::cast_sign_loss)]
+        writer.write_u16(self.x as u16);
+        writer.write_u8(self.fine_scroll);
+        writer.field("sprite fetch");
+        writer.write_u16(self.fetched_sprites);
+        writer.write_u8(self.sprite_fetch_index);
+        writer.write_u8(self.sprite_fetch_dots);
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.fetch_dots = reader.read_u8()?.min(FETCH_DOTS);
+        self.fetch_column = reader.read_u8()?;
+        self.tile_number = reader.read_u8()?;
+        self.tile_low = reader.read_u8()?;
+        self.tile_high = reader.read_u8()?;
+        self.first_fetch = reader.read_bool()?;
+        self.window = reader.read_bool()?;
+        reader.read_bytes(&mut self.background)?;
+        self.background_len = reader.read_u8()?.min(TILE_PIXELS_U8);
+        reader.read_bytes(&mut self.sprites)?;
+        #[allow(clippy::cast_possible_wrap)]
+        let x = reader.read_u16()? as i16;
+        self.x = x;
+        self.fine_scroll = reader.read_u8()?;
+        self.fetched_sprites = reader.read_u16()?;
+        self.sprite_fetch_index = reader.read_u8()?;
+        self.sprite_fetch_dots = reader.read_u8()?.min(SPRITE_FETCH_DOTS);
+        Ok(())
+    }
+}
+
+impl Ppu {
+    /// Sets up the pipeline for the line, at the first dot of mode 3.
+    pub(super) fn start_drawing(&mut self) {
+        self.pipeline = Pipeline {
+            // The tile number of the first fetch is read on the last dot of mode 2
+            fetch_dots: 1,
+            fine_scroll: self.scroll_x % TILE_PIXELS_U8,
+            ..Pipeline::new()
+        };
+    }
+
+    /// Runs the pipeline for one dot of mode 3.
+    pub(super) fn tick_drawing(&mut self) {
+        self.tick_fetcher();
+
+        let pipeline = &mut self.pipeline;
+        if pipeline.sprite_fetch_dots > 0 {
+            pipeline.sprite_fetch_dots -= 1;
+            if pipeline.sprite_fetch_dots == 0 {
+                let index = self.line_sprites[pipeline.sprite_fetch_index as usize];
+                self.fetch_sprite(index);
+            }
+            return;
+        }
+
+        if pipeline.background_len == 0 && pipeline.fetch_dots == FETCH_DOTS {
+            pipeline.background = decode_row(pipeline.tile_low, pipeline.tile_high);
+            pipeline.background_len = TILE_PIXELS_U8;
+            pipeline.fetch_dots = 0;
+            if !std::mem::take(&mut pipeline.first_fetch) {
+                pipeline.fetch_column = pipeline.fetch_column.wrapping_add(1);
+            }
+        }
+
+        let screen_x = pipeline.screen_x();
+        if !pipeline.window && self.window_starts_at(screen_x) {
+            // The window replaces the background from here, starting with a fresh fetch
+            let pipeline = &mut self.pipeline;
+            pipeline.window = true;
+            pipeline.first_fetch = false;
+            pipeline.background_len = 0;
+            pipeline.fetch_dots = 0;
+            pipeline.fetch_column = 0;
+            return;
+        }
+
+        if let Some(index) = self.next_sprite() {
+            let pipeline = &mut self.pipeline;
+            // The background fetch in progress finishes first
+            if pipeline.fetch_dots >= SPRITE_FETCH_READY {
+                pipeline.fetched_sprites |= 1 << index;
+                pipeline.sprite_fetch_index = index;
+                pipeline.sprite_fetch_dots = SPRITE_FETCH_DOTS - 1;
+            }
+            return;
+        }
+
+        self.shift_pixel();
+    }
+
+    /// Advances the background fetcher by a dot, reading VRAM on the second dot of each step.
+    fn tick_fetcher(&mut self) {
+        let pipeline = &mut self.pipeline;
+        if pipeline.fetch_dots == FETCH_DOTS {
+            return;
+        }
+        pipeline.fetch_dots += 1;
+        match pipeline.fetch_dots {
+            2 => {
+                let (map_area, column, y) = if pipeline.window {
+                    (
+                        DisplayControl::WINDOW_TILE_MAP_AREA,
+                        pipeline.fetch_column,
+                        self.window_line,
+                    )
+                } else {
+                    (
+                        DisplayControl::BACKGROUND_TILE_MAP_AREA,
+                        (self.scroll_x / TILE_PIXELS_U8).wrapping_add(pipeline.fetch_column),
+                        self.ly.wrapping_add(self.scroll_y),
+                    )
+                };
+                let map = if self.control.contains(map_area) {
+                    0x1C00
+                } else {
+                    0x1800
+                };
+                let column = column as usize % TILE_MAP_WIDTH;
+                let row = y as usize / TILE_PIXELS;
+                self.pipeline.tile_number = self.video_ram[map + row * TILE_MAP_WIDTH + column];
+            }
+            4 => self.pipeline.tile_low = self.video_ram[self.tile_data_address()],
+            6 => self.pipeline.tile_high = self.video_ram[self.tile_data_address() + 1],
+            _ => {}
+        }
+    }
+
+    /// Returns the VRAM offset of the row of the background or window tile being fetched.
+    fn tile_data_address(&self) -> usize {
+        let y = if self.pipeline.window {
+            self.window_line
+        } else {
+            self.ly.wrapping_add(self.scroll_y)
+        };
+        self.background_tile(self.pipeline.tile_number) + (y as usize % TILE_PIXELS) * 2
+    }
+
+    /// Returns whether the window starts with the pixel at `screen_x`. WX is offset by 7, so
+    /// the window may start left of the screen, its first pixels discarded.
+    fn window_starts_at(&self, screen_x: i16) -> bool {
+        self.control.contains(DisplayControl::WINDOW_ENABLE)
+            // On DMG, the background enable bit hides the window as well
+            && (self
+                .control
+                .contains(DisplayControl::BACKGROUND_AND_WINDOW_ENABLE)
+                || self.model == Model::Cgb)
+            && self.ly >= self.window_y
+            && screen_x == i16::from(self.window_x) - 7
+    }
+
+    /// Returns the index of the first line sprite starting at the next pixel that wasn't
+    /// fetched yet. Sprites are matched in OAM order, so the first in OAM wins ties.
+    ///
+    /// Only pixels in the FIFO are matched, so sprites at the left edge wait for the first
+    /// fetch.
+    fn next_sprite(&self) -> Option<u8> {
+        if !self.control.contains(DisplayControl::SPRITE_ENABLE)
+            || self.pipeline.background_len == 0
+        {
+            return None;
+        }
+        let screen_x = self.pipeline.screen_x();
+        (0..self.line_sprite_count).find_map(|index| {
+            let offset = self.line_sprites[index] as usize * SPRITE_SIZE;
+            let x = i16::from(self.sprite_ram[offset + 1]) - TILE_PIXELS_I16;
+            #[allow(clippy::cast_possible_truncation)]
+            let index = index as u8;
+            (x == screen_x && self.pipeline.fetched_sprites & (1 << index) == 0).then_some(index)
+        })
+    }
+
+    /// Reads the row of sprite `index` on the current line into the sprite FIFO, where it
+    /// only fills transparent pixels: sprites fetched earlier, with a smaller X or first in
+    /// OAM, stay on top.
+    fn fetch_sprite(&mut self, index: u8) {
+        let offset = index as usize * SPRITE_SIZE;
+        let y = self.sprite_ram[offset];
+        let mut tile_number = self.sprite_ram[offset + 2];
+        let attributes = self.sprite_ram[offset + 3];
+        let height = self.control.sprite_height();
+
+        let mut row = (self.ly + 16).wrapping_sub(y);
+        if row >= height {
+            // Moved or resized since the OAM scan
+            return;
+        }
+        if attributes & SPRITE_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+        if height == 16 {
+            tile_number &= 0xFE;
+        }
+        // Rows of 8x16 sprites continue into the next tile
+        let mut colors = self.tile_row(tile_number as usize * TILE_SIZE, row as usize);
+        if attributes & SPRITE_X_FLIP != 0 {
+            colors.reverse();
+        }
+        for (pixel, color) in self.pipeline.sprites.iter_mut().zip(colors) {
+            if *pixel & SPRITE_COLOR == 0 && color != 0 {
+                *pixel = color | attributes & (SPRITE_PRIORITY | SPRITE_PALETTE);
+            }
+        }
+    }
+
+    /// Shifts the next pixel out of the FIFOs, drawing it unless it is discarded.
+    fn shift_pixel(&mut self) {
+        let pipeline = &mut self.pipeline;
+        if pipeline.background_len == 0 {
+            return;
+        }
+        let color = pipeline.background[TILE_PIXELS - pipeline.background_len as usize];
+        pipeline.background_len -= 1;
+        let sprite = pipeline.sprites[0];
+        pipeline.sprites.copy_within(1.., 0);
+        pipeline.sprites[TILE_PIXELS - 1] = 0;
+        let screen_x = pipeline.screen_x();
+        pipeline.x += 1;
+        if screen_x < 0 || self.frames_to_skip > 0 {
+            return;
+        }
+
+        // On DMG, LCDC bit 0 blanks the background and window to white.
+        // On CGB, they are always drawn and the bit only removes their priority over sprites.
+        let background_enabled = self
+            .control
+            .contains(DisplayControl::BACKGROUND_AND_WINDOW_ENABLE);
+        let mut shade = if background_enabled || self.model == Model::Cgb {
+            self.background_shades[color as usize]
+        } else {
+            0
+        };
+        let sprite_color = sprite & SPRITE_COLOR;
+        if sprite_color != 0
+            && self.control.contains(DisplayControl::SPRITE_ENABLE)
+            && !(sprite & SPRITE_PRIORITY != 0 && background_enabled && color != 0)
+        {
+            let palette = usize::from(sprite & SPRITE_PALETTE != 0);
+            shade = self.object_shades[palette][sprite_color as usize];
+        }
+        #[allow(clippy::cast_sign_loss)]
+        let screen_x = screen_x as usize;
+        self.frame[self.ly as usize * SCREEN_WIDTH + screen_x] = shade;
+    }
+}
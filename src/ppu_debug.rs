@@ -0,0 +1,166 @@
+//! Structured views of VRAM and OAM for tile, map and sprite viewers, read without side
+//! effects like [`GameboyHardware::peek_byte`].
+
+use crate::hardware::GameboyHardware;
+use crate::tile::{decode_tile, TILE_PIXELS, TILE_SIZE};
+
+/// Number of tiles in VRAM.
+pub const TILE_COUNT: usize = 384;
+/// Width and height of a tile map in tiles.
+pub const MAP_SIZE: usize = 32;
+/// Number of sprites in OAM.
+pub const SPRITE_COUNT: usize = 40;
+
+/// Color indices (0-3) of a tile's pixels, row by row, see [`crate::tile`].
+pub type Tile = [u8; TILE_PIXELS * TILE_PIXELS];
+
+const TILE_DATA: u16 = 0x8000;
+const TILE_MAPS: [u16; 2] = [0x9800, 0x9C00];
+const OAM: u16 = 0xFE00;
+const LCDC: u16 = 0xFF40;
+// LCDC bit selecting unsigned tile numbers from 0x8000 for the background and window
+const LCDC_TILE_DATA: u8 = 0x10;
+
+/// An entry of a tile map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MapEntry {
+    /// Tile number as stored in the map.
+    pub index: u8,
+    /// Position in [`tiles`] of the tile drawn, under the addressing mode selected by LCDC.
+    pub tile: usize,
+}
+
+/// A sprite's entry in OAM.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sprite {
+    /// Screen position of the top-left corner, negative when partly off screen.
+    pub x: i16,
+    pub y: i16,
+    /// Tile number, from 0x8000. The lowest bit is ignored for 8x16 sprites.
+    pub tile: u8,
+    /// Drawn behind background colors 1-3.
+    pub behind_background: bool,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// 0 for OBP0, 1 for OBP1.
+    pub palette: u8,
+}
+
+/// Returns all tiles in VRAM, from 0x8000.
+#[must_use]
+pub fn tiles(gameboy: &GameboyHardware) -> Vec<Tile> {
+    (0..TILE_COUNT)
+        .map(|index| {
+            let mut tile = [0; TILE_SIZE];
+            #[allow(clippy::cast_possible_truncation)]
+            let start = TILE_DATA + (index * TILE_SIZE) as u16;
+            for (offset, byte) in (start..).zip(&mut tile) {
+                *byte = gameboy.peek_byte(offset);
+            }
+            decode_tile(&tile)
+        })
+        .collect()
+}
+
+/// Returns tile map `map` (0 at 0x9800, 1 at 0x9C00) as rows of entries.
+///
+/// # Panics
+///
+/// Panics if `map` isn't 0 or 1.
+#[must_use]
+pub fn background_map(gameboy: &GameboyHardware, map: usize) -> [[MapEntry; MAP_SIZE]; MAP_SIZE] {
+    assert!(map < TILE_MAPS.len(), "map must be 0 or 1");
+    let unsigned = gameboy.peek_byte(LCDC) & LCDC_TILE_DATA != 0;
+    let mut entries = [[MapEntry::default(); MAP_SIZE]; MAP_SIZE];
+    for (addr, entry) in (TILE_MAPS[map]..).zip(entries.iter_mut().flatten()) {
+        let index = gameboy.peek_byte(addr);
+        // Numbers 0-127 are tiles 256-383 in the signed mode from 0x8800
+        let tile = if unsigned || index >= 0x80 {
+            usize::from(index)
+        } else {
+            usize::from(index) + 256
+        };
+        *entry = MapEntry { index, tile };
+    }
+    entries
+}
+
+/// Returns the sprites in OAM, in order.
+#[must_use]
+pub fn sprites(gameboy: &GameboyHardware) -> [Sprite; SPRITE_COUNT] {
+    let mut sprites = [Sprite::default(); SPRITE_COUNT];
+    for (addr, sprite) in (OAM..).step_by(4).zip(&mut sprites) {
+        let [y, x, tile, flags] = [0, 1, 2, 3].map(|offset| gameboy.peek_byte(addr + offset));
+        *sprite = Sprite {
+            x: i16::from(x) - 8,
+            y: i16::from(y) - 16,
+            tile,
+            behind_background: flags & 0x80 != 0,
+            flip_y: flags & 0x40 != 0,
+            flip_x: flags & 0x20 != 0,
+            palette: (flags >> 4) & 1,
+        };
+    }
+    sprites
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use crate::ppu_debug::{background_map, sprites, tiles, MapEntry, Sprite};
+
+    #[test]
+    fn test_viewers() {
+        let program = [
+            0x3E, 0x00, 0xE0, 0x40, // LD A, 0; LDH (LCDC), A: LCD off, signed tile numbers
+            0x21, 0x10, 0x80, 0x36, 0xFF, // LD HL, 0x8010; LD (HL), 0xFF
+            0x21, 0x00, 0x98, 0x36, 0x01, // LD HL, 0x9800; LD (HL), 0x01
+            0x21, 0x00, 0x9C, 0x36, 0x81, // LD HL, 0x9C00; LD (HL), 0x81
+            0x21, 0x00, 0xFE, // LD HL, 0xFE00
+            0x3E, 0x20, 0x22, 0x3E, 0x10, 0x22, 0x3E, 0x05, 0x22, // LD A, n; LD (HL+), A
+            0x36, 0xF0, // LD (HL), 0xF0
+            0x18, 0xFE, // loop: JR loop
+        ];
+        let rom = HeaderBuilder::new().build(&program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        for _ in 0..32 {
+            gameboy.step();
+        }
+
+        let tiles = tiles(&gameboy);
+        assert_eq!(tiles.len(), 384);
+        assert_eq!(tiles[1][..8], [1; 8]);
+        assert_eq!(tiles[1][8..], [0; 56]);
+
+        let map = background_map(&gameboy, 0);
+        assert_eq!(
+            map[0][0],
+            MapEntry {
+                index: 1,
+                tile: 257
+            }
+        );
+        let map = background_map(&gameboy, 1);
+        assert_eq!(
+            map[0][0],
+            MapEntry {
+                index: 0x81,
+                tile: 0x81
+            }
+        );
+
+        assert_eq!(
+            sprites(&gameboy)[0],
+            Sprite {
+                x: 8,
+                y: 16,
+                tile: 5,
+                behind_background: true,
+                flip_x: true,
+                flip_y: true,
+                palette: 1,
+            }
+        );
+    }
+}
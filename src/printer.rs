@@ -0,0 +1,412 @@
+//! The Game Boy Printer, connected to the serial port with
+//! [`GameboyHardware::set_serial_link`](crate::hardware::GameboyHardware::set_serial_link).
+//!
+//! Games drive the clock and send packets made of:
+//!
+//! | Bytes | Content |
+//! |-------|---------|
+//! | 2 | Sync bytes `0x88 0x33` |
+//! | 1 | Command: `0x01` initialize, `0x02` print, `0x04` image data, `0x0F` status |
+//! | 1 | 1 if the data is compressed |
+//! | 2 | Data length, little-endian |
+//! | n | Data |
+//! | 2 | Checksum, the sum of the command, compression, length and data bytes |
+//! | 2 | Sent as 0, the printer answers `0x81` then its status |
+//!
+//! Image data is 2bpp tiles, 20 tiles per row. Printed images are queued for the frontend,
+//! see [`PrinterOutput`]. Paper feed (margins) and exposure aren't rendered.
+
+use crate::framebuffer::Palette;
+use crate::link::SerialLink;
+use crate::tile::{apply_palette_to, decode_tiles, TILE_SIZE};
+use std::sync::{Arc, Mutex, PoisonError};
+
+const SYNC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_BUSY: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+/// Width of printed images in tiles.
+const TILES_PER_ROW: usize = 20;
+/// Image data the printer holds, 9 bands of 2 tile rows (144 lines).
+const IMAGE_CAPACITY: usize = 9 * 2 * TILES_PER_ROW * TILE_SIZE;
+/// Status packets answered as busy after a print, games wait for it to finish.
+const PRINT_STATUS_PACKETS: u8 = 4;
+
+/// Where the printer is in a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    // Waiting for the sync bytes, with how many were received
+    Sync(usize),
+    // Command, compression and length, with how many bytes were received
+    Header(usize),
+    Data,
+    // With how many checksum bytes were received
+    Checksum(usize),
+    // The two bytes the printer answers
+    Alive,
+    Status,
+}
+
+/// An image printed by a [`Printer`], as shades (0-3, 0 being white) like
+/// [`GameboyHardware::frame`](crate::hardware::GameboyHardware::frame).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintedImage {
+    pub width: usize,
+    pub height: usize,
+    pub shades: Vec<u8>,
+}
+
+impl PrintedImage {
+    /// Returns the image as RGBA8888 in `palette`'s colors, e.g. for encoding as PNG.
+    #[must_use]
+    pub fn rgba(&self, palette: Palette) -> Vec<u8> {
+        self.shades
+            .iter()
+            .flat_map(|&shade| palette.rgba(shade))
+            .collect()
+    }
+}
+
+/// Images printed by a [`Printer`] and not taken yet, shared with the printer once it's
+/// connected.
+#[derive(Debug, Clone, Default)]
+pub struct PrinterOutput(Arc<Mutex<Vec<PrintedImage>>>);
+
+impl PrinterOutput {
+    /// Returns the images printed since the last call, oldest first.
+    #[must_use]
+    pub fn take_images(&self) -> Vec<PrintedImage> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn push(&self, image: PrintedImage) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(image);
+    }
+}
+
+/// A Game Boy Printer on the other end of the link cable.
+#[derive(Debug)]
+pub struct Printer {
+    // Byte being shifted in and how many of its bits were
+    shift_in: u8,
+    bits: u8,
+    // Answer being shifted out, starting with bit 7
+    shift_out: u8,
+    stage: Stage,
+    // Command, compression and length low and high bytes of the packet
+    header: [u8; 4],
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    // Image data received since the last print
+    image: Vec<u8>,
+    status_errors: u8,
+    // Status packets left until the print finishes
+    busy: u8,
+    output: PrinterOutput,
+}
+
+impl Printer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            shift_in: 0,
+            bits: 0,
+            shift_out: 0,
+            stage: Stage::Sync(0),
+            header: [0; 4],
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            image: Vec::new(),
+            status_errors: 0,
+            busy: 0,
+            output: PrinterOutput::default(),
+        }
+    }
+
+    /// Returns the queue of printed images, to be kept by the frontend before the printer
+    /// is connected.
+    #[must_use]
+    pub fn output(&self) -> PrinterOutput {
+        self.output.clone()
+    }
+
+    fn data_length(&self) -> usize {
+        usize::from(u16::from_le_bytes([self.header[2], self.header[3]]))
+    }
+
+    /// Handles a byte sent by the console, returning the answer to the next one.
+    fn receive(&mut self, byte: u8) -> u8 {
+        match self.stage {
+            Stage::Sync(received) if byte == SYNC[received] => {
+                self.stage = if received + 1 == SYNC.len() {
+                    self.checksum = 0;
+                    Stage::Header(0)
+                } else {
+                    Stage::Sync(received + 1)
+                };
+            }
+            Stage::Sync(_) => {
+                self.stage = Stage::Sync(usize::from(byte == SYNC[0]));
+            }
+            Stage::Header(received) => {
+                self.header[received] = byte;
+                self.checksum = self.checksum.wrapping_add(u16::from(byte));
+                if received + 1 < self.header.len() {
+                    self.stage = Stage::Header(received + 1);
+                } else {
+                    self.data.clear();
+                    self.stage = if self.data_length() == 0 {
+                        Stage::Checksum(0)
+                    } else {
+                        Stage::Data
+                    };
+                }
+            }
+            Stage::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(u16::from(byte));
+                if self.data.len() == self.data_length() {
+                    self.stage = Stage::Checksum(0);
+                }
+            }
+            Stage::Checksum(0) => {
+                self.received_checksum = u16::from(byte);
+                self.stage = Stage::Checksum(1);
+            }
+            Stage::Checksum(_) => {
+                self.received_checksum |= u16::from(byte) << 8;
+                self.stage = Stage::Alive;
+                return ALIVE;
+            }
+            Stage::Alive => {
+                self.run_command();
+                self.stage = Stage::Status;
+                return self.status();
+            }
+            Stage::Status => self.stage = Stage::Sync(0),
+        }
+        0
+    }
+
+    fn run_command(&mut self) {
+        if self.received_checksum != self.checksum {
+            self.status_errors = STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status_errors = 0;
+        let [command, compression, ..] = self.header;
+        match command {
+            COMMAND_INIT => {
+                self.image.clear();
+                self.busy = 0;
+            }
+            COMMAND_DATA => {
+                let data = if compression & 1 != 0 {
+                    decompress(&self.data)
+                } else {
+                    std::mem::take(&mut self.data)
+                };
+                let free = IMAGE_CAPACITY - self.image.len();
+                self.image.extend(data.iter().take(free));
+            }
+            COMMAND_PRINT => {
+                let Some(&palette) = self.data.get(2) else {
+                    self.status_errors = STATUS_PACKET_ERROR;
+                    return;
+                };
+                self.print(palette);
+            }
+            COMMAND_STATUS => self.busy = self.busy.saturating_sub(1),
+            _ => self.status_errors = STATUS_PACKET_ERROR,
+        }
+    }
+
+    fn print(&mut self, palette: u8) {
+        // Whole rows of tiles only
+        let length = self.image.len() / (TILES_PER_ROW * TILE_SIZE) * TILES_PER_ROW * TILE_SIZE;
+        if length > 0 {
+            let (mut shades, width, height) = decode_tiles(&self.image[..length], TILES_PER_ROW);
+            // Games print with 0 for the usual palette
+            let palette = if palette == 0 { 0xE4 } else { palette };
+            apply_palette_to(palette, &mut shades);
+            self.output.push(PrintedImage {
+                width,
+                height,
+                shades,
+            });
+        }
+        self.image.clear();
+        self.busy = PRINT_STATUS_PACKETS;
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = self.status_errors;
+        if self.busy > 0 {
+            status |= STATUS_BUSY;
+        }
+        if self.image.len() >= IMAGE_CAPACITY {
+            status |= STATUS_FULL;
+        }
+        if !self.image.is_empty() {
+            status |= STATUS_UNPROCESSED;
+        }
+        status
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerialLink for Printer {
+    fn clock_out(&mut self, out_bit: bool) -> bool {
+        let in_bit = self.shift_out & 0x80 != 0;
+        self.shift_out <<= 1;
+        self.shift_in = (self.shift_in << 1) | u8::from(out_bit);
+        self.bits += 1;
+        if self.bits == 8 {
+            self.bits = 0;
+            self.shift_out = self.receive(self.shift_in);
+        }
+        in_bit
+    }
+
+    // The printer never drives the clock
+    fn clock_in(&mut self) -> Option<bool> {
+        None
+    }
+
+    fn set_line(&mut self, _level: bool) {}
+}
+
+/// Decodes run-length encoded image data: a control byte with bit 7 set is followed by a
+/// byte repeated `(control & 0x7F) + 2` times, otherwise by `control + 1` bytes as is.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bytes = data.iter().copied();
+    while let Some(control) = bytes.next() {
+        if control & 0x80 != 0 {
+            if let Some(byte) = bytes.next() {
+                let count = usize::from(control & 0x7F) + 2;
+                output.extend(std::iter::repeat_n(byte, count));
+            }
+        } else {
+            output.extend(bytes.by_ref().take(usize::from(control) + 1));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::link::SerialLink;
+    use crate::printer::{decompress, PrintedImage, Printer};
+
+    /// Sends a byte the way the console does, MSB first, returning the byte received.
+    fn send_byte(printer: &mut Printer, byte: u8) -> u8 {
+        (0..8).fold(0, |received, bit| {
+            let in_bit = printer.clock_out(byte & (0x80 >> bit) != 0);
+            (received << 1) | u8::from(in_bit)
+        })
+    }
+
+    /// Sends a packet, returning the two bytes answered at its end.
+    fn send_packet(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> [u8; 2] {
+        #[allow(clippy::cast_possible_truncation)]
+        let length = (data.len() as u16).to_le_bytes();
+        let mut body = vec![command, u8::from(compressed), length[0], length[1]];
+        body.extend_from_slice(data);
+        let checksum = body
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(u16::from(byte)));
+        for byte in [0x88, 0x33]
+            .iter()
+            .chain(&body)
+            .chain(&checksum.to_le_bytes())
+        {
+            assert_eq!(send_byte(printer, *byte), 0);
+        }
+        [send_byte(printer, 0), send_byte(printer, 0)]
+    }
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(
+            decompress(&[0x81, 0xAA, 0x01, 0x12, 0x34, 0x80, 0xFF]),
+            [0xAA, 0xAA, 0xAA, 0x12, 0x34, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let mut printer = Printer::new();
+        let output = printer.output();
+        assert_eq!(send_packet(&mut printer, 0x01, false, &[]), [0x81, 0x00]);
+
+        // A row of 20 tiles: the first with color 3 everywhere and the others color 1
+        let mut data = vec![0xFF; 16];
+        for _ in 1..20 {
+            data.extend([0xFF, 0x00].repeat(8));
+        }
+        assert_eq!(send_packet(&mut printer, 0x04, false, &data), [0x81, 0x08]);
+        // A second row all color 3, 129 + 129 + 62 bytes of 0xFF compressed
+        let compressed = [0xFF, 0xFF, 0xFF, 0xFF, 0xBC, 0xFF];
+        let status = send_packet(&mut printer, 0x04, true, &compressed);
+        assert_eq!(status, [0x81, 0x08]);
+        assert_eq!(send_packet(&mut printer, 0x04, false, &[]), [0x81, 0x08]);
+
+        // One sheet, no margins, palette with only color 1 black
+        let status = send_packet(&mut printer, 0x02, false, &[0x01, 0x00, 0x0C, 0x40]);
+        assert_eq!(status, [0x81, 0x02]);
+        let mut polls = 0;
+        while send_packet(&mut printer, 0x0F, false, &[])[1] & 0x02 != 0 {
+            polls += 1;
+        }
+        assert!(polls > 0);
+
+        let images = output.take_images();
+        assert_eq!(images.len(), 1);
+        let PrintedImage {
+            width,
+            height,
+            shades,
+        } = &images[0];
+        assert_eq!((*width, *height), (160, 16));
+        for y in 0..8 {
+            let row = &shades[y * 160..(y + 1) * 160];
+            assert!(row[..8].iter().all(|&shade| shade == 0));
+            assert!(row[8..].iter().all(|&shade| shade == 3));
+        }
+        assert!(shades[160 * 8..].iter().all(|&shade| shade == 0));
+        assert!(output.take_images().is_empty());
+    }
+
+    #[test]
+    fn test_checksum_error() {
+        let mut printer = Printer::new();
+        for byte in [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x10, 0x00] {
+            send_byte(&mut printer, byte);
+        }
+        assert_eq!(send_byte(&mut printer, 0), 0x81);
+        assert_eq!(send_byte(&mut printer, 0), 0x01);
+        // Cleared by the next good packet
+        assert_eq!(send_packet(&mut printer, 0x0F, false, &[]), [0x81, 0x00]);
+    }
+}
@@ -0,0 +1,248 @@
+//! Rewinding through snapshots taken while running, see
+//! [`GameboyHardware::set_rewind`](crate::hardware::GameboyHardware::set_rewind).
+//!
+//! Only the latest snapshot is kept in full. Older ones are stored as the bytes that differ
+//! from the snapshot after them, with unchanged runs left out, so a frame's worth of changes
+//! costs a few hundred bytes instead of a whole savestate.
+
+use std::collections::VecDeque;
+
+/// Bounded history of savestates taken every few frames.
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    // Frames between snapshots
+    interval: u32,
+    // Most snapshots kept, the oldest are dropped first
+    capacity: usize,
+    // Frames completed since the latest snapshot was taken or returned to
+    frames_since_snapshot: u32,
+    latest: Option<Vec<u8>>,
+    // Older snapshots, oldest first, each encoded against the one after it
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// Creates a history taking a snapshot every `interval` frames and keeping up to
+    /// `capacity` of them, so it reaches `interval * capacity` frames back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` or `capacity` is zero.
+    #[must_use]
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        assert!(interval > 0, "Rewind interval must not be zero.");
+        assert!(capacity > 0, "Rewind capacity must not be zero.");
+        Self {
+            interval,
+            capacity,
+            frames_since_snapshot: 0,
+            latest: None,
+            deltas: VecDeque::with_capacity(capacity - 1),
+        }
+    }
+
+    #[must_use]
+    pub const fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of snapshots kept.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.latest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Returns the bytes taken by the snapshots.
+    #[must_use]
+    pub fn memory_usage(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    /// Forgets all snapshots.
+    pub fn clear(&mut self) {
+        self.frames_since_snapshot = 0;
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Counts a completed frame, taking a snapshot with `save_state` if one is due.
+    pub(crate) fn end_frame(&mut self, save_state: impl FnOnce() -> Vec<u8>) {
+        self.frames_since_snapshot += 1;
+        if self.latest.is_some() && self.frames_since_snapshot < self.interval {
+            return;
+        }
+        let state = save_state();
+        if let Some(previous) = self.latest.take() {
+            if self.deltas.len() == self.capacity - 1 {
+                self.deltas.pop_front();
+            }
+            if self.capacity > 1 {
+                self.deltas.push_back(encode_delta(&state, &previous));
+            }
+        }
+        self.latest = Some(state);
+        self.frames_since_snapshot = 0;
+    }
+
+    /// Returns the latest snapshot taken before the current frame, which becomes the
+    /// latest snapshot, or `None` if there is none left to go back to.
+    pub(crate) fn step_back(&mut self) -> Option<&[u8]> {
+        if self.frames_since_snapshot == 0 {
+            // Already at the latest snapshot, go to the one before
+            let delta = self.deltas.pop_back()?;
+            apply_delta(self.latest.as_mut()?, &delta);
+        }
+        self.frames_since_snapshot = 0;
+        self.latest.as_deref()
+    }
+}
+
+/// Encodes `target` as the bytes that differ from `base`, which has the same length: pairs
+/// of an unchanged run length and a changed run, lengths as LEB128, changed bytes XORed
+/// with `base`.
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(base.len(), target.len());
+    let mut delta = Vec::new();
+    let mut offset = 0;
+    while offset < target.len() {
+        let unchanged = (offset..target.len())
+            .find(|&index| base[index] != target[index])
+            .unwrap_or(target.len());
+        let changed = (unchanged..target.len())
+            .find(|&index| base[index] == target[index])
+            .unwrap_or(target.len());
+        write_length(&mut delta, unchanged - offset);
+        write_length(&mut delta, changed - unchanged);
+        delta.extend((unchanged..changed).map(|index| base[index] ^ target[index]));
+        offset = changed;
+    }
+    delta
+}
+
+/// Turns `state` into the snapshot encoded against it by [`encode_delta`].
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut offset = 0;
+    let mut bytes = delta;
+    while !bytes.is_empty() {
+        offset += read_length(&mut bytes);
+        let changed = read_length(&mut bytes);
+        for (byte, xor) in state[offset..offset + changed]
+            .iter_mut()
+            .zip(&bytes[..changed])
+        {
+            *byte ^= xor;
+        }
+        offset += changed;
+        bytes = &bytes[changed..];
+    }
+}
+
+// Each byte takes the low 7 bits
+#[allow(clippy::cast_possible_truncation)]
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        output.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    output.push(length as u8);
+}
+
+fn read_length(input: &mut &[u8]) -> usize {
+    let mut length = 0;
+    for shift in (0..).step_by(7) {
+        let byte = input[0];
+        *input = &input[1..];
+        length |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    length
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use crate::rewind::{apply_delta, encode_delta, Rewind};
+
+    #[test]
+    fn test_delta_round_trip() {
+        let base: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut target = base.clone();
+        target[0] = 0xAA;
+        target[500..700].fill(0);
+        target[999] ^= 1;
+        let delta = encode_delta(&target, &base);
+        assert!(delta.len() < 220);
+        apply_delta(&mut target, &delta);
+        assert_eq!(target, base);
+        assert!(encode_delta(&base, &base).len() <= 3);
+    }
+
+    #[test]
+    fn test_step_back() {
+        // loop: INC A; LD (0xC000), A; HALT; JR loop, with VBlank interrupts
+        let program = [
+            0x3E, 0x01, 0xE0, 0xFF, 0xFB, 0x3C, 0xEA, 0x00, 0xC0, 0x76, 0x18, 0xF9,
+        ];
+        let mut rom = HeaderBuilder::new().build(&program);
+        rom[0x40] = 0xD9;
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.set_rewind(Some(Rewind::new(2, 5)));
+        assert!(!gameboy.step_back());
+
+        let mut states = Vec::new();
+        for _ in 0..20 {
+            gameboy.run_frame();
+            states.push(gameboy.save_state());
+        }
+        let rewind = gameboy.rewind_history().unwrap();
+        assert_eq!(rewind.len(), 5);
+        assert!(rewind.memory_usage() < states[0].len() * 3 / 2);
+
+        // Snapshots are taken every other frame starting with the first, frame 20 wasn't
+        for frame in [19, 17, 15, 13, 11] {
+            assert!(gameboy.step_back());
+            assert_eq!(gameboy.save_state(), states[frame - 1]);
+        }
+        assert!(!gameboy.step_back());
+        assert_eq!(gameboy.rewind_history().unwrap().len(), 1);
+
+        // Running again continues from the snapshot returned to
+        for _ in 0..3 {
+            gameboy.run_frame();
+        }
+        for frame in [13, 11] {
+            assert!(gameboy.step_back());
+            assert_eq!(gameboy.save_state(), states[frame - 1]);
+        }
+    }
+
+    #[test]
+    fn test_step_back_to_another_cartridge() {
+        let mut gameboy = GameboyHardware::new(Cartridge::new(HeaderBuilder::new().build(&[])));
+        gameboy.set_rewind(Some(Rewind::new(1, 5)));
+        gameboy.run_frame();
+        gameboy.run_frame();
+
+        // Snapshots of the old cartridge are refused rather than loaded
+        let other = GameboyHardware::new(Cartridge::new(HeaderBuilder::new().build(&[0x18, 0xFE])));
+        let mut parts = gameboy.into_parts();
+        parts.bus = other.into_parts().bus;
+        let mut gameboy = GameboyHardware::from_parts(parts);
+        let state = gameboy.save_state();
+        assert!(!gameboy.step_back());
+        assert_eq!(gameboy.save_state(), state);
+    }
+}
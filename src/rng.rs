@@ -0,0 +1,52 @@
+//! The source of every random value the emulator uses, so "random" behavior can be pinned
+//! with a seed and replayed exactly by movies, netplay and tests.
+
+/// A generator of pseudo-random numbers, see [`GameboyHardware::set_random_source`].
+///
+/// [`GameboyHardware::set_random_source`]: crate::hardware::GameboyHardware::set_random_source
+pub trait RandomSource: Send + Sync {
+    fn next_u64(&mut self) -> u64;
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn next_u8(&mut self) -> u8 {
+        self.next_u64() as u8
+    }
+}
+
+/// SplitMix64 generator, a small deterministic source of pseudo-random numbers and the
+/// default [`RandomSource`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rng::{RandomSource, SplitMix64};
+
+    #[test]
+    fn test_splitmix64() {
+        // Reference output for seed 0
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(rng.next_u8(), 0x4F);
+    }
+}
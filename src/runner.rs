@@ -0,0 +1,286 @@
+//! Running the emulator on its own thread, driven by commands and reporting events over
+//! channels, for GUI frontends keeping their UI thread free.
+
+use crate::audio::AudioSample;
+use crate::cartridge::Cartridge;
+use crate::error::SavestateError;
+use crate::handle::EmulatorHandle;
+use crate::hardware::{Button, GameboyHardware};
+use crate::watch::WatchHit;
+use std::panic;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A command for an [`EmulatorThread`], run between frames in the order sent.
+pub enum Command {
+    /// Replaces the console with a new one for the cartridge, keeping the model, the audio
+    /// and notification sinks and the palette.
+    LoadRom(Box<Cartridge>),
+    SetButton(Button, bool),
+    /// Answered with [`Event::StateSaved`].
+    SaveState,
+    /// Answered with [`Event::StateLoadFailed`] if the state can't be loaded.
+    LoadState(Vec<u8>),
+    Pause,
+    Resume,
+    /// Runs one frame while paused.
+    FrameAdvance,
+    /// Stops the thread, see [`EmulatorThread::join`].
+    Quit,
+}
+
+/// Something an [`EmulatorThread`] reports, queued until received.
+#[derive(Debug)]
+pub enum Event {
+    /// A frame was completed, with its shades, see [`GameboyHardware::frame`].
+    FrameReady(Vec<u8>),
+    /// Audio samples generated during the last frame, only sent while the console has an
+    /// audio sink, see [`GameboyHardware::set_audio_sink`].
+    AudioReady(Vec<AudioSample>),
+    /// Accesses that hit a watchpoint during the last frame, see
+    /// [`GameboyHardware::add_watchpoint`]. Emulation is paused when they are sent.
+    Breakpoint(Vec<WatchHit>),
+    StateSaved(Vec<u8>),
+    StateLoadFailed(SavestateError),
+}
+
+/// The emulator running on its own thread, at real time speed (scaled by the speed of its
+/// [`EmulatorHandle`]) unless paused.
+#[derive(Debug)]
+pub struct EmulatorThread {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    handle: EmulatorHandle,
+    thread: Option<JoinHandle<GameboyHardware>>,
+}
+
+impl EmulatorThread {
+    /// Starts running `gameboy` on a new thread.
+    #[must_use]
+    pub fn spawn(gameboy: GameboyHardware) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let handle = EmulatorHandle::new();
+        let thread = {
+            let handle = handle.clone();
+            thread::spawn(move || run(gameboy, &handle, &command_receiver, &event_sender))
+        };
+        Self {
+            commands,
+            events,
+            handle,
+            thread: Some(thread),
+        }
+    }
+
+    /// Sends a command, returning false if the thread has stopped.
+    pub fn send(&self, command: Command) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    /// Returns the receiver of the thread's events.
+    #[must_use]
+    pub const fn events(&self) -> &Receiver<Event> {
+        &self.events
+    }
+
+    /// Returns the handle pacing the thread, for changing the speed. It also pauses and
+    /// steps frames, independently of [`Command::Pause`].
+    #[must_use]
+    pub fn handle(&self) -> EmulatorHandle {
+        self.handle.clone()
+    }
+
+    /// Stops the thread after the commands already sent and returns the console.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the thread's panic if emulation panicked.
+    #[must_use]
+    pub fn join(mut self) -> GameboyHardware {
+        self.stop().expect("the thread is only joined once")
+    }
+
+    fn stop(&mut self) -> Option<GameboyHardware> {
+        let thread = self.thread.take()?;
+        let _ = self.commands.send(Command::Quit);
+        Some(
+            thread
+                .join()
+                .unwrap_or_else(|err| panic::resume_unwind(err)),
+        )
+    }
+}
+
+impl Drop for EmulatorThread {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            self.stop();
+        }
+    }
+}
+
+fn run(
+    mut gameboy: GameboyHardware,
+    handle: &EmulatorHandle,
+    commands: &Receiver<Command>,
+    events: &Sender<Event>,
+) -> GameboyHardware {
+    let mut paused = false;
+    let mut next_frame = Instant::now();
+    loop {
+        // Waits for the next frame, or for a command while paused
+        let timeout = if paused {
+            Duration::MAX
+        } else {
+            next_frame.saturating_duration_since(Instant::now())
+        };
+        let mut advance = false;
+        let mut command = match commands.recv_timeout(timeout) {
+            Ok(command) => Some(command),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return gameboy,
+        };
+        while let Some(current) = command {
+            match current {
+                Command::LoadRom(cartridge) => {
+                    let model = gameboy.capabilities().model;
+                    let mut new = GameboyHardware::with_model(*cartridge, model);
+                    new.set_audio_sink(gameboy.set_audio_sink(None));
+                    new.set_notification_sink(gameboy.set_notification_sink(None));
+                    new.set_palette(gameboy.palette());
+                    gameboy = new;
+                }
+                Command::SetButton(button, pressed) => gameboy.set_button(button, pressed),
+                Command::SaveState => {
+                    let _ = events.send(Event::StateSaved(gameboy.save_state()));
+                }
+                Command::LoadState(state) => {
+                    if let Err(err) = gameboy.load_state(&state) {
+                        let _ = events.send(Event::StateLoadFailed(err));
+                    }
+                }
+                Command::Pause => paused = true,
+                Command::Resume => {
+                    paused = false;
+                    next_frame = Instant::now();
+                }
+                Command::FrameAdvance => advance = paused,
+                Command::Quit => return gameboy,
+            }
+            // Later commands run after the advanced frame
+            command = if advance {
+                None
+            } else {
+                commands.try_recv().ok()
+            };
+        }
+        let run_frame = if paused {
+            advance
+        } else {
+            Instant::now() >= next_frame && handle.should_run_frame()
+        };
+        if !run_frame {
+            if !paused && Instant::now() >= next_frame {
+                next_frame += handle.frame_duration();
+            }
+            continue;
+        }
+
+        if let Some(output) = gameboy.run_frame_output() {
+            let audio = (!output.audio_samples.is_empty()).then(|| output.audio_samples.to_vec());
+            let _ = events.send(Event::FrameReady(output.framebuffer.to_vec()));
+            if let Some(audio) = audio {
+                let _ = events.send(Event::AudioReady(audio));
+            }
+            let hits = gameboy.take_watch_hits();
+            if !hits.is_empty() {
+                paused = true;
+                let _ = events.send(Event::Breakpoint(hits));
+            }
+        }
+        // Catches up after falling behind by less than a frame, and skips ahead otherwise
+        next_frame += handle.frame_duration();
+        let now = Instant::now();
+        if next_frame + handle.frame_duration() < now {
+            next_frame = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use crate::runner::{Command, EmulatorThread, Event};
+    use crate::watch::{WatchKind, Watchpoint};
+    use std::time::Duration;
+
+    // loop: LD A, (0xC000); INC A; LD (0xC000), A; JR loop
+    const PROGRAM: [u8; 9] = [0xFA, 0x00, 0xC0, 0x3C, 0xEA, 0x00, 0xC0, 0x18, 0xF7];
+
+    fn next_event(thread: &EmulatorThread) -> Event {
+        thread
+            .events()
+            .recv_timeout(Duration::from_secs(10))
+            .expect("the thread sends an event")
+    }
+
+    #[test]
+    fn test_emulator_thread() {
+        let rom = HeaderBuilder::new().build(&PROGRAM);
+        let gameboy = GameboyHardware::new(Cartridge::new(rom));
+        let thread = EmulatorThread::spawn(gameboy);
+        assert!(matches!(next_event(&thread), Event::FrameReady(frame) if frame.len() == 23_040));
+
+        assert!(thread.send(Command::Pause));
+        assert!(thread.send(Command::SaveState));
+        let state = loop {
+            if let Event::StateSaved(state) = next_event(&thread) {
+                break state;
+            }
+        };
+        // Nothing runs while paused, except frames advanced one at a time
+        assert!(thread.send(Command::FrameAdvance));
+        assert!(thread.send(Command::SaveState));
+        let mut frames = 0;
+        let advanced = loop {
+            match next_event(&thread) {
+                Event::FrameReady(_) => frames += 1,
+                Event::StateSaved(state) => break state,
+                _ => {}
+            }
+        };
+        assert_eq!(frames, 1);
+        assert_ne!(advanced, state);
+
+        assert!(thread.send(Command::LoadState(state)));
+        assert!(thread.send(Command::LoadState(vec![0; 4])));
+        assert!(matches!(next_event(&thread), Event::StateLoadFailed(_)));
+        let gameboy = thread.join();
+        assert!(gameboy.cycles() > 0);
+    }
+
+    #[test]
+    fn test_breakpoint_pauses() {
+        let rom = HeaderBuilder::new().build(&PROGRAM);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.add_watchpoint(Watchpoint::new(0xC000..=0xC000, WatchKind::Write));
+        let thread = EmulatorThread::spawn(gameboy);
+        let hits = loop {
+            if let Event::Breakpoint(hits) = next_event(&thread) {
+                break hits;
+            }
+        };
+        assert_eq!(hits[0].location.pc, 0x154);
+        assert_eq!(hits[0].value, 0x01);
+        // Paused, so no more frames until resumed
+        assert!(thread
+            .events()
+            .recv_timeout(Duration::from_millis(100))
+            .is_err());
+        assert!(thread.send(Command::Resume));
+        assert!(matches!(next_event(&thread), Event::FrameReady(_)));
+    }
+}
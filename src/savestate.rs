@@ -0,0 +1,390 @@
+//! Versioned binary savestates.
+//!
+//! A savestate starts with a header identifying the format, the ROM and the model,
+//! followed by the state of each component in a fixed order.
+//!
+//! The state covers everything a game can observe: the CPU registers and interrupt state,
+//! WRAM, HRAM, VRAM, OAM and cartridge RAM, the memory bank controller registers, every
+//! I/O register, the internal counters of the PPU, APU, timer, serial port and OAM DMA, and
+//! the PPU's pixel FIFOs and fetcher mid-scanline, so a state saved between any two
+//! instructions resumes cycle for cycle.
+//!
+//! Devices attached with
+//! [`Cartridge::attach_device`](crate::cartridge::Cartridge::attach_device) and host-side
+//! settings (e.g. coverage, write logging, audio sinks) are not part of the state.
+//!
+//! Two savestates of the same ROM can be compared field by field with
+//! [`GameboyHardware::diff_states`](crate::hardware::GameboyHardware::diff_states).
+
+use crate::error::SavestateError;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+pub(crate) const SAVESTATE_MAGIC: &[u8; 4] = b"GBSS";
+pub(crate) const SAVESTATE_VERSION: u16 = 9;
+// Fields up to this size are shown with their values in diffs, larger ones as byte ranges
+const MAX_VALUE_FIELD_SIZE: usize = 4;
+
+/// Where a named field starts in a savestate, recorded by [`StateWriter::with_layout`].
+#[derive(Debug, Clone, Copy)]
+struct StateField {
+    component: &'static str,
+    name: &'static str,
+    // Address of the first byte, for memory mapped in the address space
+    base: Option<u16>,
+    start: usize,
+}
+
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+    // Only recorded when diffing savestates
+    layout: Option<Vec<StateField>>,
+    component: &'static str,
+}
+
+impl StateWriter {
+    pub(crate) const fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            layout: None,
+            component: "",
+        }
+    }
+
+    /// Creates a writer recording which field each byte belongs to.
+    pub(crate) const fn with_layout() -> Self {
+        Self {
+            bytes: Vec::new(),
+            layout: Some(Vec::new()),
+            component: "",
+        }
+    }
+
+    /// Starts the fields of another component.
+    pub(crate) fn component(&mut self, name: &'static str) {
+        self.component = name;
+    }
+
+    /// Marks the following bytes, up to the next field, as field `name`.
+    pub(crate) fn field(&mut self, name: &'static str) {
+        self.push_field(name, None);
+    }
+
+    /// Marks the following bytes as memory `name`, mapped from address `base`.
+    pub(crate) fn memory(&mut self, name: &'static str, base: u16) {
+        self.push_field(name, Some(base));
+    }
+
+    fn push_field(&mut self, name: &'static str, base: Option<u16>) {
+        if let Some(layout) = &mut self.layout {
+            layout.push(StateField {
+                component: self.component,
+                name,
+                base,
+                start: self.bytes.len(),
+            });
+        }
+    }
+
+    pub(crate) fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn write_bool(&mut self, value: bool) {
+        self.write_u8(u8::from(value));
+    }
+
+    pub(crate) fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_option_u8(&mut self, value: Option<u8>) {
+        self.write_bool(value.is_some());
+        self.write_u8(value.unwrap_or_default());
+    }
+
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// A field that differs between two savestates, see [`StateDiff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// The component holding the field (e.g. "CPU" or "PPU").
+    pub component: &'static str,
+    pub field: &'static str,
+    /// Address of the first byte of the field, for memory mapped in the address space.
+    pub base: Option<u16>,
+    /// Byte ranges that differ, relative to the start of the field.
+    pub ranges: Vec<Range<usize>>,
+    /// The field in the first and second savestate.
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+impl Display for FieldDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}:", self.component, self.field)?;
+        // Small fields are numbers, shown with the most significant byte first
+        if self.before.len() <= MAX_VALUE_FIELD_SIZE {
+            let hex = |bytes: &[u8]| {
+                bytes
+                    .iter()
+                    .rev()
+                    .map(|b| format!("{b:02X}"))
+                    .collect::<String>()
+            };
+            return write!(f, " {} -> {}", hex(&self.before), hex(&self.after));
+        }
+        let position = |offset: usize| match self.base {
+            Some(base) => format!("${:04X}", base as usize + offset),
+            None => format!("+{offset:#X}"),
+        };
+        let bytes: usize = self.ranges.iter().map(ExactSizeIterator::len).sum();
+        write!(f, " {bytes} bytes differ at")?;
+        for range in &self.ranges {
+            write!(f, " {}", position(range.start))?;
+            if range.len() > 1 {
+                write!(f, "-{}", position(range.end - 1))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fields that differ between two savestates of the same ROM, see
+/// [`GameboyHardware::diff_states`].
+///
+/// [`GameboyHardware::diff_states`]: crate::hardware::GameboyHardware::diff_states
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub fields: Vec<FieldDiff>,
+}
+
+impl StateDiff {
+    /// Compares two savestates laid out like the state in `writer`, which must have been
+    /// created with [`StateWriter::with_layout`].
+    pub(crate) fn new(writer: StateWriter, first: &[u8], second: &[u8]) -> Self {
+        let layout = writer.layout.unwrap_or_default();
+        let ends = layout
+            .iter()
+            .skip(1)
+            .map(|field| field.start)
+            .chain([writer.bytes.len()]);
+        let fields = layout
+            .iter()
+            .zip(ends)
+            .filter_map(|(field, end)| {
+                let before = &first[field.start..end];
+                let after = &second[field.start..end];
+                let ranges = differing_ranges(before, after);
+                (!ranges.is_empty()).then(|| FieldDiff {
+                    component: field.component,
+                    field: field.name,
+                    base: field.base,
+                    ranges,
+                    before: before.to_vec(),
+                    after: after.to_vec(),
+                })
+            })
+            .collect();
+        Self { fields }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.fields.is_empty() {
+            return writeln!(f, "Savestates are identical");
+        }
+        for field in &self.fields {
+            writeln!(f, "{field}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the ranges of consecutive bytes that differ.
+fn differing_ranges(first: &[u8], second: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (index, _) in first
+        .iter()
+        .zip(second)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+    {
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SavestateError> {
+        let (taken, rest) = self
+            .bytes
+            .split_at_checked(len)
+            .ok_or(SavestateError::Truncated)?;
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, SavestateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, SavestateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, SavestateError> {
+        let mut bytes = [0; 2];
+        self.read_bytes(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, SavestateError> {
+        let mut bytes = [0; 4];
+        self.read_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, SavestateError> {
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_option_u8(&mut self) -> Result<Option<u8>, SavestateError> {
+        let is_some = self.read_bool()?;
+        let value = self.read_u8()?;
+        Ok(is_some.then_some(value))
+    }
+
+    pub(crate) fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), SavestateError> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+
+    pub(crate) const fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::error::SavestateError;
+    use crate::fault::Fault;
+    use crate::hardware::GameboyHardware;
+
+    // LD HL, 0xC000; loop: LD (HL), A; INC L; INC A; JR loop
+    const PROGRAM: [u8; 8] = [0x21, 0x00, 0xC0, 0x77, 0x2C, 0x3C, 0x18, 0xFB];
+
+    fn gameboy() -> GameboyHardware {
+        let rom = HeaderBuilder::new()
+            .cartridge_type(0x03)
+            .ram_banks(1)
+            .build(&PROGRAM);
+        GameboyHardware::new(Cartridge::new(rom))
+    }
+
+    fn run(gameboy: &mut GameboyHardware) -> (u64, u16) {
+        for _ in 0..3 {
+            gameboy.run_frame();
+        }
+        (gameboy.frame_hash(), gameboy.registers().pc)
+    }
+
+    #[test]
+    fn test_load_restores_state() {
+        let mut gameboy = gameboy();
+        gameboy.run_frame();
+        let state = gameboy.save_state();
+        let expected = run(&mut gameboy);
+        let work_ram = gameboy.peek_word(0xC010);
+
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(run(&mut gameboy), expected);
+        assert_eq!(gameboy.peek_word(0xC010), work_ram);
+    }
+
+    #[test]
+    fn test_rejects_invalid_states() {
+        let mut gameboy = gameboy();
+        let state = gameboy.save_state();
+
+        assert_eq!(
+            gameboy.load_state(b"not a savestate"),
+            Err(SavestateError::InvalidFormat)
+        );
+        assert_eq!(
+            gameboy.load_state(&state[..state.len() - 1]),
+            Err(SavestateError::Truncated)
+        );
+
+        let mut other = GameboyHardware::new(Cartridge::new(HeaderBuilder::new().build(&[])));
+        assert!(matches!(
+            other.load_state(&state),
+            Err(SavestateError::RomMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_diff_states() {
+        let mut gameboy = gameboy();
+        gameboy.run_frame();
+        let first = gameboy.save_state();
+        assert!(gameboy.diff_states(&first, &first).unwrap().is_empty());
+
+        let mut second = first.clone();
+        second[first.len() - 1] ^= 0x04;
+        let diff = gameboy.diff_states(&first, &second).unwrap();
+        assert_eq!(diff.to_string(), "Interrupts IE: E0 -> E4\n");
+
+        for addr in [0xC123, 0xC124, 0xC200] {
+            gameboy.inject_fault(&Fault::FlipBits { addr, mask: 0x01 });
+        }
+        let second = gameboy.save_state();
+        let diff = gameboy.diff_states(&first, &second).unwrap();
+        assert_eq!(
+            diff.to_string(),
+            "Memory WRAM: 3 bytes differ at $C123-$C124 $C200\n"
+        );
+        assert_eq!(diff.fields[0].base, Some(0xC000));
+        assert_eq!(diff.fields[0].ranges, [0x123..0x125, 0x200..0x201]);
+
+        assert_eq!(
+            gameboy.diff_states(&first, b"not a savestate"),
+            Err(SavestateError::InvalidFormat)
+        );
+    }
+}
@@ -1,6 +1,14 @@
+use crate::clock::ClockEdges;
+use crate::error::SavestateError;
+use crate::interrupts::InterruptFlags;
+use crate::link::SerialLink;
+use crate::savestate::{StateReader, StateWriter};
+
 const MEM_SERIAL_TRANSFER_DATA: u16 = 0xFF01;
 const MEM_SERIAL_TRANSFER_CONTROL: u16 = 0xFF02;
 
+const BITS_PER_TRANSFER: u8 = 8;
+
 #[derive(Debug, Clone, Copy)]
 pub struct SerialTransferControl(u8);
 
@@ -8,7 +16,6 @@ impl SerialTransferControl {
     const TRANSFER_ENABLE: u8 = 0b1000_0000;
     const CLOCK_SELECT: u8 = 0b0000_0001;
     const UNUSED: u8 = 0b0111_1110;
-    const TRANSFER_REQUESTED: u8 = Self::TRANSFER_ENABLE | Self::CLOCK_SELECT;
 
     const fn empty() -> Self {
         Self::from_bits(0)
@@ -30,8 +37,12 @@ impl SerialTransferControl {
         }
     }
 
-    const fn is_transfer_requested(self) -> bool {
-        self.0 & Self::TRANSFER_REQUESTED == Self::TRANSFER_REQUESTED
+    const fn is_transfer_enabled(self) -> bool {
+        self.0 & Self::TRANSFER_ENABLE == Self::TRANSFER_ENABLE
+    }
+
+    const fn is_internal_clock(self) -> bool {
+        self.0 & Self::CLOCK_SELECT == Self::CLOCK_SELECT
     }
 }
 
@@ -41,6 +52,12 @@ pub struct SerialPort {
     pub(crate) data: u8,
     // SC
     pub(crate) control: SerialTransferControl,
+    // Byte being sent, kept to report it once the transfer completes
+    outgoing: u8,
+    // Number of bits shifted in the current transfer
+    bits_shifted: u8,
+    // Byte sent by the transfer that just completed, until taken
+    sent: Option<u8>,
 }
 
 impl SerialPort {
@@ -48,16 +65,92 @@ impl SerialPort {
         Self {
             data: 0,
             control: SerialTransferControl::empty(),
+            outgoing: 0,
+            bits_shifted: 0,
+            sent: None,
+        }
+    }
+
+    /// Advances the serial port by one M-cycle, exchanging bits over `link` if connected.
+    ///
+    /// Only transfers using the internal clock progress here, external clock
+    /// transfers wait for the counterpart device to pulse the clock.
+    pub fn tick(
+        &mut self,
+        edges: ClockEdges,
+        interrupt_flag: &mut InterruptFlags,
+        link: Option<&mut Box<dyn SerialLink>>,
+    ) {
+        if !self.control.is_transfer_enabled() || !self.control.is_internal_clock() {
+            return;
+        }
+
+        // The internal clock comes from the system counter, so the first bit of a transfer
+        // can take less than a full period
+        if edges.serial() {
+            // Nothing connected, the input line is pulled high
+            let in_bit = link.is_none_or(|link| link.clock_out(self.out_line()));
+            self.shift(in_bit, interrupt_flag);
         }
     }
 
-    pub fn step(&mut self) {
-        if self.control.is_transfer_requested() {
-            //let c = char::from(self.data);
-            //print!("{c}");
-            println!("{}", self.data);
+    /// Returns the level of the output line, the bit shifted out next.
+    pub const fn out_line(&self) -> bool {
+        self.data & 0x80 != 0
+    }
+
+    /// Handles one clock pulse driven by the device on the other end of the link cable.
+    ///
+    /// Shifts in `in_bit` and returns the bit shifted out. Pulses are ignored unless
+    /// a transfer using the external clock is in progress, in which case the line reads high.
+    pub fn external_clock_pulse(
+        &mut self,
+        in_bit: bool,
+        interrupt_flag: &mut InterruptFlags,
+    ) -> bool {
+        if !self.control.is_transfer_enabled() || self.control.is_internal_clock() {
+            return true;
+        }
+        self.shift(in_bit, interrupt_flag)
+    }
+
+    fn shift(&mut self, in_bit: bool, interrupt_flag: &mut InterruptFlags) -> bool {
+        let out_bit = self.out_line();
+        self.data = (self.data << 1) | in_bit as u8;
+        self.bits_shifted += 1;
+
+        if self.bits_shifted == BITS_PER_TRANSFER {
+            self.sent = Some(self.outgoing);
+            self.bits_shifted = 0;
             self.control.set_transfer_enable(false);
+            interrupt_flag.set(InterruptFlags::SERIAL, true);
         }
+        out_bit
+    }
+
+    /// Returns the byte sent by the transfer completed by the last tick or clock pulse.
+    pub fn take_sent(&mut self) -> Option<u8> {
+        self.sent.take()
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.component("Serial");
+        writer.field("SB");
+        writer.write_u8(self.data);
+        writer.field("SC");
+        writer.write_u8(self.control.bits());
+        writer.field("outgoing");
+        writer.write_u8(self.outgoing);
+        writer.field("bits shifted");
+        writer.write_u8(self.bits_shifted);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.data = reader.read_u8()?;
+        self.control = SerialTransferControl::from_bits(reader.read_u8()?);
+        self.outgoing = reader.read_u8()?;
+        self.bits_shifted = reader.read_u8()?;
+        Ok(())
     }
 
     pub const fn read_byte(&self, addr: u16) -> u8 {
@@ -75,8 +168,97 @@ impl SerialPort {
             }
             MEM_SERIAL_TRANSFER_CONTROL => {
                 self.control = SerialTransferControl::from_bits(value);
+                if self.control.is_transfer_enabled() {
+                    self.outgoing = self.data;
+                    self.bits_shifted = 0;
+                }
             }
             _ => unreachable!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{ClockEdges, SERIAL_CLOCK_BIT};
+    use crate::interrupts::InterruptFlags;
+    use crate::serial_port::{SerialPort, MEM_SERIAL_TRANSFER_CONTROL, MEM_SERIAL_TRANSFER_DATA};
+
+    // M-cycles between two falling edges of the serial clock bit
+    const INTERNAL_CLOCK_PERIOD: u16 = SERIAL_CLOCK_BIT / 2;
+
+    /// Ticks the serial port for `cycles` M-cycles, advancing the system counter.
+    fn tick(
+        serial: &mut SerialPort,
+        counter: &mut u16,
+        cycles: u16,
+        interrupt_flag: &mut InterruptFlags,
+    ) {
+        for _ in 0..cycles {
+            let previous = *counter;
+            *counter = counter.wrapping_add(4);
+            serial.tick(
+                ClockEdges::between(previous, *counter),
+                interrupt_flag,
+                None,
+            );
+        }
+    }
+
+    #[test]
+    fn test_internal_clock() {
+        let mut serial = SerialPort::new();
+        let mut interrupt_flag = InterruptFlags::empty();
+        serial.write_byte(MEM_SERIAL_TRANSFER_DATA, 0x00);
+        serial.write_byte(MEM_SERIAL_TRANSFER_CONTROL, 0x81);
+
+        // Nothing connected, so ones are shifted in, one bit every period
+        let mut counter = 0;
+        tick(
+            &mut serial,
+            &mut counter,
+            8 * INTERNAL_CLOCK_PERIOD - 1,
+            &mut interrupt_flag,
+        );
+        assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_DATA), 0x7F);
+        assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_CONTROL), 0xFF);
+        assert!(!interrupt_flag.contains(InterruptFlags::SERIAL));
+
+        tick(&mut serial, &mut counter, 1, &mut interrupt_flag);
+        assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_DATA), 0xFF);
+        assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_CONTROL), 0x7F);
+        assert!(interrupt_flag.contains(InterruptFlags::SERIAL));
+    }
+
+    #[test]
+    fn test_external_clock() {
+        let mut serial = SerialPort::new();
+        let mut interrupt_flag = InterruptFlags::empty();
+        serial.write_byte(MEM_SERIAL_TRANSFER_DATA, 0xA5);
+
+        // Pulses are ignored until a transfer is started
+        assert!(serial.external_clock_pulse(false, &mut interrupt_flag));
+        serial.write_byte(MEM_SERIAL_TRANSFER_CONTROL, 0x80);
+
+        // The internal clock doesn't shift anything
+        tick(
+            &mut serial,
+            &mut 0,
+            8 * INTERNAL_CLOCK_PERIOD,
+            &mut interrupt_flag,
+        );
+        assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_DATA), 0xA5);
+
+        // 0x5A is shifted in while 0xA5 is shifted out, the transfer ends on the 8th pulse
+        let mut sent = 0;
+        for bit in [false, true, false, true, true, false, true, false] {
+            assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_CONTROL), 0xFE);
+            assert!(!interrupt_flag.contains(InterruptFlags::SERIAL));
+            sent = (sent << 1) | u8::from(serial.external_clock_pulse(bit, &mut interrupt_flag));
+        }
+        assert_eq!(sent, 0xA5);
+        assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_DATA), 0x5A);
+        assert_eq!(serial.read_byte(MEM_SERIAL_TRANSFER_CONTROL), 0x7E);
+        assert!(interrupt_flag.contains(InterruptFlags::SERIAL));
+    }
+}
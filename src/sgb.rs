@@ -0,0 +1,555 @@
+//! Super Game Boy support: the commands games send through P1, the border they transfer
+//! and the palettes they color the screen with, for frontends drawing the SNES side.
+//!
+//! Enabled with [`GameboyHardware::set_sgb`], usually for cartridges that
+//! [support it](crate::cartridge::Cartridge::supports_sgb). The SNES itself isn't
+//! emulated: commands take effect as soon as their last packet is received, and VRAM
+//! transfers read the 4 KiB of background tile data LCDC selects rather than the screen,
+//! which games fill in the order the screen shows it.
+//!
+//! Colors are RGB555 as on the SNES, red in the low bits, see [`rgb555_to_rgba`].
+//!
+//! [`GameboyHardware::set_sgb`]: crate::hardware::GameboyHardware::set_sgb
+
+use crate::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::tile::{decode_row, TILE_PIXELS};
+
+/// Width of the border in pixels.
+pub const BORDER_WIDTH: usize = 256;
+/// Height of the border in pixels.
+pub const BORDER_HEIGHT: usize = 224;
+/// Column of the Game Boy screen's left edge in the border.
+pub const SCREEN_LEFT: usize = 48;
+/// Row of the Game Boy screen's top edge in the border.
+pub const SCREEN_TOP: usize = 40;
+/// Width of the attribute map in 8x8 cells.
+pub const ATTRIBUTE_WIDTH: usize = SCREEN_WIDTH / TILE_PIXELS;
+/// Height of the attribute map in 8x8 cells.
+pub const ATTRIBUTE_HEIGHT: usize = SCREEN_HEIGHT / TILE_PIXELS;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+// Bytes of VRAM read by CHR_TRN, PCT_TRN, PAL_TRN and ATTR_TRN
+const TRANSFER_SIZE: usize = 0x1000;
+const SYSTEM_PALETTES: usize = 512;
+const ATTRIBUTE_FILES: usize = 45;
+const ATTRIBUTE_FILE_SIZE: usize = ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT / 4;
+// Border tiles are 4bpp, 256 of them in two CHR_TRN halves
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_MAP_WIDTH: usize = BORDER_WIDTH / TILE_PIXELS;
+const BORDER_MAP_SIZE: usize = BORDER_MAP_WIDTH * BORDER_HEIGHT / TILE_PIXELS;
+// PCT_TRN holds the border map, then palettes 4-7 from this offset
+const BORDER_PALETTES_OFFSET: usize = 0x800;
+// Grayscale until a game sets its palettes
+const DEFAULT_PALETTE: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+// Command codes, the upper 5 bits of a command's first byte
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const MASK_EN: u8 = 0x17;
+
+// P1 select lines, active low
+const SELECT_P14: u8 = 0b0001_0000;
+const SELECT_P15: u8 = 0b0010_0000;
+const SELECT: u8 = SELECT_P14 | SELECT_P15;
+const INPUT: u8 = 0b0000_1111;
+
+/// How the Game Boy screen is masked, set by MASK_EN while games prepare the next screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenMask {
+    #[default]
+    None,
+    /// The last frame completed before the mask stays on screen.
+    Freeze,
+    Black,
+    /// Filled with color 0.
+    Color0,
+}
+
+/// State of the Super Game Boy, see
+/// [`GameboyHardware::sgb`](crate::hardware::GameboyHardware::sgb).
+#[derive(Debug, Clone)]
+pub struct Sgb {
+    // Packet being received through P1, least significant bit first
+    packet: [u8; PACKET_SIZE],
+    // Bits of the packet received, None until a reset pulse starts one
+    bit: Option<usize>,
+    // Set when both select lines go back high, which each bit needs
+    ready_for_bit: bool,
+    // Select lines last written to P1
+    select: u8,
+    // Packets of the command being received
+    command: Vec<u8>,
+    // Joypads read in turn after MLT_REQ, and the one read next
+    players: u8,
+    player: u8,
+    palettes: [[u16; 4]; 4],
+    // Palettes PAL_SET picks from, sent with PAL_TRN
+    system_palettes: Box<[[u16; 4]; SYSTEM_PALETTES]>,
+    // Palette of each 8x8 cell of the screen
+    attributes: [[u8; ATTRIBUTE_WIDTH]; ATTRIBUTE_HEIGHT],
+    // Attribute maps PAL_SET picks from, sent with ATTR_TRN
+    attribute_files: Box<[u8; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE]>,
+    border_tiles: Box<[u8; 2 * TRANSFER_SIZE]>,
+    border_map: Box<[u16; BORDER_MAP_SIZE]>,
+    border_palettes: [[u16; 16]; 4],
+    // Set once PCT_TRN sends a border
+    has_border: bool,
+    mask: ScreenMask,
+    // Frame shown while the screen is frozen
+    frozen_frame: Option<Vec<u8>>,
+}
+
+impl Sgb {
+    pub(crate) fn new() -> Self {
+        Self {
+            packet: [0; PACKET_SIZE],
+            bit: None,
+            ready_for_bit: false,
+            select: 0,
+            command: Vec::new(),
+            players: 1,
+            player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: Box::new([DEFAULT_PALETTE; SYSTEM_PALETTES]),
+            attributes: [[0; ATTRIBUTE_WIDTH]; ATTRIBUTE_HEIGHT],
+            attribute_files: Box::new([0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE]),
+            border_tiles: Box::new([0; 2 * TRANSFER_SIZE]),
+            border_map: Box::new([0; BORDER_MAP_SIZE]),
+            border_palettes: [[0; 16]; 4],
+            has_border: false,
+            mask: ScreenMask::None,
+            frozen_frame: None,
+        }
+    }
+
+    /// Drops any packet being received and goes back to one player, like resetting the Game
+    /// Boy side. The border and palettes are kept.
+    pub(crate) fn reset(&mut self) {
+        self.bit = None;
+        self.ready_for_bit = false;
+        self.select = 0;
+        self.command.clear();
+        self.players = 1;
+        self.player = 0;
+    }
+
+    /// Returns the colors of palettes 0-3, color 0 being shared by all of them.
+    #[must_use]
+    pub const fn palettes(&self) -> &[[u16; 4]; 4] {
+        &self.palettes
+    }
+
+    /// Returns the palette (0-3) of each 8x8 cell of the screen, row by row.
+    #[must_use]
+    pub const fn attributes(&self) -> &[[u8; ATTRIBUTE_WIDTH]; ATTRIBUTE_HEIGHT] {
+        &self.attributes
+    }
+
+    /// Returns how many joypads the game reads, 1, 2 or 4, set with MLT_REQ. Only the first
+    /// one has buttons, see
+    /// [`GameboyHardware::set_button`](crate::hardware::GameboyHardware::set_button).
+    #[must_use]
+    pub const fn players(&self) -> u8 {
+        self.players
+    }
+
+    #[must_use]
+    pub const fn mask(&self) -> ScreenMask {
+        self.mask
+    }
+
+    /// Returns whether the game sent a border.
+    #[must_use]
+    pub const fn has_border(&self) -> bool {
+        self.has_border
+    }
+
+    /// Returns the border as [`BORDER_WIDTH`] by [`BORDER_HEIGHT`] RGBA pixels, or `None`
+    /// until the game sends one. Transparent pixels show the backdrop, color 0 of the
+    /// palettes. The screen goes over it at [`SCREEN_LEFT`], [`SCREEN_TOP`].
+    #[must_use]
+    pub fn border_rgba(&self) -> Option<Vec<u8>> {
+        if !self.has_border {
+            return None;
+        }
+        let backdrop = rgb555_to_rgba(self.palettes[0][0]);
+        let mut pixels = backdrop.repeat(BORDER_WIDTH * BORDER_HEIGHT);
+        for (index, &entry) in self.border_map.iter().enumerate() {
+            let tile = usize::from(entry & 0xFF) * BORDER_TILE_SIZE;
+            let tile = &self.border_tiles[tile..tile + BORDER_TILE_SIZE];
+            // Palettes 4-7
+            let palette = &self.border_palettes[usize::from((entry >> 10) & 0x03)];
+            let flip_x = entry & 0x4000 != 0;
+            let flip_y = entry & 0x8000 != 0;
+            let left = index % BORDER_MAP_WIDTH * TILE_PIXELS;
+            let top = index / BORDER_MAP_WIDTH * TILE_PIXELS;
+            for y in 0..TILE_PIXELS {
+                let row = if flip_y { TILE_PIXELS - 1 - y } else { y };
+                // Bitplanes 0 and 1 for every row, then 2 and 3
+                let low = decode_row(tile[row * 2], tile[row * 2 + 1]);
+                let high = decode_row(tile[16 + row * 2], tile[16 + row * 2 + 1]);
+                for x in 0..TILE_PIXELS {
+                    let column = if flip_x { TILE_PIXELS - 1 - x } else { x };
+                    let color = low[column] | high[column] << 2;
+                    if color != 0 {
+                        let offset = ((top + y) * BORDER_WIDTH + left + x) * 4;
+                        pixels[offset..offset + 4]
+                            .copy_from_slice(&rgb555_to_rgba(palette[usize::from(color)]));
+                    }
+                }
+            }
+        }
+        Some(pixels)
+    }
+
+    /// Colors `frame`, shades as returned by
+    /// [`GameboyHardware::frame`](crate::hardware::GameboyHardware::frame), with the
+    /// palette of each cell and the mask, returning RGBA pixels.
+    #[must_use]
+    pub fn screen_rgba(&self, frame: &[u8]) -> Vec<u8> {
+        let frame = match (&self.frozen_frame, self.mask) {
+            (Some(frozen), ScreenMask::Freeze) => frozen,
+            _ => frame,
+        };
+        let mut pixels = Vec::with_capacity(frame.len() * 4);
+        for (index, &shade) in frame.iter().enumerate() {
+            let color = match self.mask {
+                ScreenMask::Black => 0x0000,
+                ScreenMask::Color0 => self.palettes[0][0],
+                ScreenMask::None | ScreenMask::Freeze => {
+                    let cell = self.attributes[index / SCREEN_WIDTH / TILE_PIXELS]
+                        [index % SCREEN_WIDTH / TILE_PIXELS];
+                    self.palettes[usize::from(cell)][usize::from(shade & 0x03)]
+                }
+            };
+            pixels.extend_from_slice(&rgb555_to_rgba(color));
+        }
+        pixels
+    }
+
+    /// Returns P1 as read with `bits` from the joypad: the joypad being read is in the
+    /// input bits while neither group is selected, and only the first one has buttons.
+    pub(crate) const fn joypad_bits(&self, bits: u8) -> u8 {
+        if self.players > 1 && bits & SELECT == SELECT {
+            (bits & !INPUT) | (INPUT - self.player)
+        } else if self.player != 0 {
+            bits | INPUT
+        } else {
+            bits
+        }
+    }
+
+    /// Receives a write to P1. Packets are sent one bit per write, P14 low for 0 and P15
+    /// low for 1, with both lines going back high in between, after a reset pulse with
+    /// both low. VRAM transfers read `tile_data`, and freezing the screen keeps `frame`.
+    pub(crate) fn write_joypad(&mut self, value: u8, tile_data: &[u8], frame: &[u8]) {
+        let select = value & SELECT;
+        let previous = std::mem::replace(&mut self.select, select);
+        // The next joypad is read after P15 goes back high
+        if self.players > 1 && previous & SELECT_P15 == 0 && select & SELECT_P15 != 0 {
+            self.player = (self.player + 1) % self.players;
+        }
+        match select {
+            0 => {
+                self.packet = [0; PACKET_SIZE];
+                self.bit = Some(0);
+                self.ready_for_bit = false;
+            }
+            SELECT => self.ready_for_bit = true,
+            _ => {
+                let Some(bit) = self.bit.filter(|_| self.ready_for_bit) else {
+                    return;
+                };
+                self.ready_for_bit = false;
+                let one = select == SELECT_P14;
+                if bit < PACKET_BITS {
+                    self.packet[bit / 8] |= u8::from(one) << (bit % 8);
+                    self.bit = Some(bit + 1);
+                } else {
+                    // Packets end with a 0 bit
+                    self.bit = None;
+                    if !one {
+                        self.receive_packet(tile_data, frame);
+                    }
+                }
+            }
+        }
+    }
+
+    fn receive_packet(&mut self, tile_data: &[u8], frame: &[u8]) {
+        // The first packet says how many the command takes, zero is invalid
+        if self.command.is_empty() && self.packet[0] & 0x07 == 0 {
+            return;
+        }
+        self.command.extend_from_slice(&self.packet);
+        if self.command.len() == usize::from(self.command[0] & 0x07) * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command, tile_data, frame);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8], tile_data: &[u8], frame: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palette_pair(data, 0, 1),
+            PAL23 => self.set_palette_pair(data, 2, 3),
+            PAL03 => self.set_palette_pair(data, 0, 3),
+            PAL12 => self.set_palette_pair(data, 1, 2),
+            ATTR_BLK => self.set_attribute_blocks(data),
+            PAL_SET => self.set_system_palettes(data),
+            PAL_TRN => {
+                for (palette, colors) in self
+                    .system_palettes
+                    .iter_mut()
+                    .zip(tile_data.chunks_exact(8))
+                {
+                    *palette = [0, 1, 2, 3].map(|color| word(colors, color * 2));
+                }
+            }
+            MLT_REQ => {
+                self.players = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            CHR_TRN => {
+                let start = usize::from(data[1] & 0x01) * TRANSFER_SIZE;
+                self.border_tiles[start..start + TRANSFER_SIZE].copy_from_slice(tile_data);
+            }
+            PCT_TRN => {
+                for (index, entry) in self.border_map.iter_mut().enumerate() {
+                    *entry = word(tile_data, index * 2);
+                }
+                for (index, palette) in self.border_palettes.iter_mut().enumerate() {
+                    let start = BORDER_PALETTES_OFFSET + index * 32;
+                    *palette = std::array::from_fn(|color| word(tile_data, start + color * 2));
+                }
+                self.has_border = true;
+            }
+            ATTR_TRN => {
+                let size = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&tile_data[..size]);
+            }
+            MASK_EN => {
+                self.mask = match data[1] & 0x03 {
+                    1 => ScreenMask::Freeze,
+                    2 => ScreenMask::Black,
+                    3 => ScreenMask::Color0,
+                    _ => ScreenMask::None,
+                };
+                self.frozen_frame = (self.mask == ScreenMask::Freeze).then(|| frame.to_vec());
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets colors 1-3 of two palettes and the shared color 0 (PAL01, PAL23, PAL03, PAL12).
+    fn set_palette_pair(&mut self, data: &[u8], first: usize, second: usize) {
+        let shared = word(data, 1);
+        for palette in &mut self.palettes {
+            palette[0] = shared;
+        }
+        for color in 1..4 {
+            self.palettes[first][color] = word(data, 1 + color * 2);
+            self.palettes[second][color] = word(data, 7 + color * 2);
+        }
+    }
+
+    /// Sets the palette of rectangles of cells (ATTR_BLK), with a palette each for the cells
+    /// inside, on the edge and outside.
+    fn set_attribute_blocks(&mut self, data: &[u8]) {
+        let count = usize::from(data[1] & 0x1F);
+        for block in data[2..].chunks_exact(6).take(count) {
+            let [control, palettes, left, top, right, bottom] = block.try_into().unwrap();
+            let inside = (control & 0x01 != 0).then_some(palettes & 0x03);
+            let mut edge = (control & 0x02 != 0).then_some((palettes >> 2) & 0x03);
+            let outside = (control & 0x04 != 0).then_some((palettes >> 4) & 0x03);
+            // Changing only the inside or the outside changes the edge with it
+            if edge.is_none() && inside.is_some() != outside.is_some() {
+                edge = inside.or(outside);
+            }
+            for (y, row) in (0..).zip(&mut self.attributes) {
+                for (x, cell) in (0..).zip(row.iter_mut()) {
+                    let within = (left..=right).contains(&x) && (top..=bottom).contains(&y);
+                    let on_edge = x == left || x == right || y == top || y == bottom;
+                    let palette = match (within, on_edge) {
+                        (true, true) => edge,
+                        (true, false) => inside,
+                        (false, _) => outside,
+                    };
+                    if let Some(palette) = palette {
+                        *cell = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Picks the four palettes from those sent with PAL_TRN (PAL_SET), and optionally an
+    /// attribute file sent with ATTR_TRN.
+    fn set_system_palettes(&mut self, data: &[u8]) {
+        for palette in 0..4 {
+            let index = usize::from(word(data, 1 + palette * 2)) % SYSTEM_PALETTES;
+            self.palettes[palette] = self.system_palettes[index];
+        }
+        let shared = self.palettes[0][0];
+        for palette in &mut self.palettes {
+            palette[0] = shared;
+        }
+
+        let flags = data[9];
+        if flags & 0x80 != 0 {
+            let file = usize::from(flags & 0x3F) % ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE;
+            let file = &self.attribute_files[file..file + ATTRIBUTE_FILE_SIZE];
+            // Four cells per byte, the first in the upper bits
+            for (index, cell) in self.attributes.iter_mut().flatten().enumerate() {
+                *cell = (file[index / 4] >> (6 - index % 4 * 2)) & 0x03;
+            }
+        }
+        if flags & 0x40 != 0 {
+            self.mask = ScreenMask::None;
+            self.frozen_frame = None;
+        }
+    }
+}
+
+/// Converts an RGB555 color, red in the low bits, to opaque RGBA.
+#[must_use]
+pub const fn rgb555_to_rgba(color: u16) -> [u8; 4] {
+    [
+        expand_channel(color),
+        expand_channel(color >> 5),
+        expand_channel(color >> 10),
+        0xFF,
+    ]
+}
+
+/// Scales the 5-bit channel in the low bits of `value` to 8 bits.
+#[allow(clippy::cast_possible_truncation)]
+const fn expand_channel(value: u16) -> u8 {
+    let value = (value & 0x1F) as u8;
+    (value << 3) | (value >> 2)
+}
+
+/// Returns the little-endian word at `index`.
+fn word(data: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([data[index], data[index + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sgb::{rgb555_to_rgba, ScreenMask, Sgb, BORDER_HEIGHT, BORDER_WIDTH};
+
+    static FRAME: [u8; 160 * 144] = [0; 160 * 144];
+
+    /// Sends `command`, padded to whole packets, one bit per write like games do.
+    fn send(sgb: &mut Sgb, command: &[u8], tile_data: &[u8]) {
+        for packet in command.chunks(16) {
+            let mut bytes = [0; 16];
+            bytes[..packet.len()].copy_from_slice(packet);
+            sgb.write_joypad(0x00, tile_data, &FRAME);
+            sgb.write_joypad(0x30, tile_data, &FRAME);
+            let bits = (0..128).map(|bit| bytes[bit / 8] >> (bit % 8) & 1 != 0);
+            for one in bits.chain([false]) {
+                sgb.write_joypad(if one { 0x10 } else { 0x20 }, tile_data, &FRAME);
+                sgb.write_joypad(0x30, tile_data, &FRAME);
+            }
+        }
+    }
+
+    #[test]
+    fn test_palettes_and_attributes() {
+        let mut sgb = Sgb::new();
+        // PAL12: shared color 0x1234, palette 1 colors 1-3, palette 2 colors 1-3
+        let command = [
+            0x19, 0x34, 0x12, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00, 0x05, 0x00, 0x06,
+            0x00,
+        ];
+        send(&mut sgb, &command, &[]);
+        assert_eq!(sgb.palettes()[0][0], 0x1234);
+        assert_eq!(sgb.palettes()[1], [0x1234, 1, 2, 3]);
+        assert_eq!(sgb.palettes()[2], [0x1234, 4, 5, 6]);
+
+        // ATTR_BLK: one block from (1, 1) to (3, 3), palette 1 inside and 2 outside, leaving
+        // the edge alone
+        send(&mut sgb, &[0x21, 0x01, 0x05, 0x21, 1, 1, 3, 3], &[]);
+        assert_eq!(sgb.attributes()[0][..5], [2, 2, 2, 2, 2]);
+        assert_eq!(sgb.attributes()[1][..5], [2, 0, 0, 0, 2]);
+        assert_eq!(sgb.attributes()[2][..5], [2, 0, 1, 0, 2]);
+        // Only the inside changes the edge with it
+        send(&mut sgb, &[0x21, 0x01, 0x01, 0x03, 1, 1, 3, 3], &[]);
+        assert_eq!(sgb.attributes()[1][..5], [2, 3, 3, 3, 2]);
+
+        // Each cell is colored with its palette
+        let mut frame = FRAME;
+        frame[0] = 3;
+        let screen = sgb.screen_rgba(&frame);
+        assert_eq!(screen[..4], rgb555_to_rgba(6));
+        assert_eq!(screen[4..8], rgb555_to_rgba(0x1234));
+
+        // MASK_EN black, then PAL_SET with a system palette from PAL_TRN cancelling it
+        send(&mut sgb, &[0xB9, 0x02], &[]);
+        assert_eq!(sgb.mask(), ScreenMask::Black);
+        assert_eq!(sgb.screen_rgba(&frame)[..4], [0, 0, 0, 0xFF]);
+        let mut system_palettes = vec![0; 0x1000];
+        system_palettes[8..16].copy_from_slice(&[0x1F, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00]);
+        send(&mut sgb, &[0x59], &system_palettes);
+        send(&mut sgb, &[0x51, 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0x40], &[]);
+        assert_eq!(sgb.mask(), ScreenMask::None);
+        assert_eq!(sgb.palettes()[0], [0x1F, 1, 2, 3]);
+        assert_eq!(rgb555_to_rgba(0x1F), [0xFF, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_multiplayer() {
+        let mut sgb = Sgb::new();
+        assert_eq!(sgb.joypad_bits(0xFF), 0xFF);
+        // MLT_REQ for 4 players
+        send(&mut sgb, &[0x89, 0x03], &[]);
+        assert_eq!(sgb.players(), 4);
+        assert_eq!(sgb.joypad_bits(0xFF), 0xFF);
+        for player in 1..4 {
+            // Selecting the buttons and deselecting them moves on to the next joypad
+            sgb.write_joypad(0x10, &[], &FRAME);
+            sgb.write_joypad(0x30, &[], &FRAME);
+            assert_eq!(sgb.joypad_bits(0xFF), 0xFF - player);
+            // Only the first joypad has buttons
+            assert_eq!(sgb.joypad_bits(0xE0), 0xEF);
+        }
+        sgb.reset();
+        assert_eq!(sgb.players(), 1);
+    }
+
+    #[test]
+    fn test_border() {
+        let mut sgb = Sgb::new();
+        assert!(sgb.border_rgba().is_none());
+        // CHR_TRN: tile 1 has color 15 in its top-left pixel
+        let mut tiles = vec![0; 0x1000];
+        for plane in [32, 33, 48, 49] {
+            tiles[plane] = 0x80;
+        }
+        send(&mut sgb, &[0x99, 0x00], &tiles);
+        // PCT_TRN: the first entry is tile 1 flipped horizontally with palette 5
+        let mut map = vec![0; 0x1000];
+        map[..2].copy_from_slice(&0x4401_u16.to_le_bytes());
+        map[0x800 + 32 + 30..0x800 + 32 + 32].copy_from_slice(&0x7C00_u16.to_le_bytes());
+        send(&mut sgb, &[0xA1], &map);
+        let border = sgb.border_rgba().unwrap();
+        assert_eq!(border.len(), BORDER_WIDTH * BORDER_HEIGHT * 4);
+        assert_eq!(border[7 * 4..8 * 4], rgb555_to_rgba(0x7C00));
+        // Color 0 shows the backdrop
+        assert_eq!(border[..4], rgb555_to_rgba(0x7FFF));
+    }
+}
@@ -0,0 +1,557 @@
+//! Runs the community SM83 single-instruction tests against the CPU on a flat 64 KiB bus.
+//!
+//! Each JSON file holds cases for one opcode: the registers and RAM before and after running
+//! one instruction, and the bus activity of each M-cycle as `[address, value, pins]`, where
+//! the pins read `r` for a read and `w` for a write. A case passes if the CPU ends in the
+//! same state, takes as many M-cycles and makes the same reads and writes in the same order.
+//! Interrupts aren't part of the tests, so none are ever pending.
+
+use gb_emulator::hardware::{BusInterface, Cpu, CpuRegisters};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+const USAGE: &str = "Usage: gb-emulator sm83-tests <json file or directory>...";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Object(members) => members
+                .iter()
+                .find_map(|(name, value)| (name == key).then_some(value)),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn as_u16(&self) -> Option<u16> {
+        match self {
+            Self::Number(number) if (0.0..=f64::from(u16::MAX)).contains(number) => {
+                Some(*number as u16)
+            }
+            _ => None,
+        }
+    }
+
+    fn as_u8(&self) -> Option<u8> {
+        self.as_u16().and_then(|number| u8::try_from(number).ok())
+    }
+}
+
+/// Parser for the subset of JSON the tests use, which is all of it but `\u` escapes
+/// outside the BMP.
+struct Parser<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(input: &'a str) -> Result<Json, String> {
+        let mut parser = Self {
+            input: input.as_bytes(),
+            offset: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.offset == parser.input.len() {
+            Ok(value)
+        } else {
+            Err(parser.error("trailing characters"))
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{message} at byte {}", self.offset)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.offset)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.offset += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.offset).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.offset += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", char::from(byte))))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        if self.input[self.offset..].starts_with(keyword.as_bytes()) {
+            self.offset += keyword.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.offset += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            let name = self.string()?;
+            self.expect(b':')?;
+            members.push((name, self.value()?));
+            if self.peek() == Some(b',') {
+                self.offset += 1;
+            } else {
+                self.expect(b'}')?;
+                return Ok(Json::Object(members));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.offset += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.peek() == Some(b',') {
+                self.offset += 1;
+            } else {
+                self.expect(b']')?;
+                return Ok(Json::Array(items));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut string = String::new();
+        loop {
+            let Some(&byte) = self.input.get(self.offset) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.offset += 1;
+            match byte {
+                b'"' => return Ok(string),
+                b'\\' => {
+                    let escaped = match self.input.get(self.offset) {
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'u') => {
+                            let digits = self
+                                .input
+                                .get(self.offset + 1..self.offset + 5)
+                                .and_then(|digits| std::str::from_utf8(digits).ok())
+                                .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                                .and_then(char::from_u32);
+                            self.offset += 4;
+                            digits.ok_or_else(|| self.error("invalid escape"))?
+                        }
+                        Some(&other) => char::from(other),
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    self.offset += 1;
+                    string.push(escaped);
+                }
+                _ => {
+                    // Copies a whole UTF-8 sequence at once
+                    let start = self.offset - 1;
+                    while self
+                        .input
+                        .get(self.offset)
+                        .is_some_and(|byte| byte & 0xC0 == 0x80)
+                    {
+                        self.offset += 1;
+                    }
+                    let text = std::str::from_utf8(&self.input[start..self.offset])
+                        .map_err(|_| self.error("invalid UTF-8"))?;
+                    string.push_str(text);
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.offset;
+        while self
+            .input
+            .get(self.offset)
+            .is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.offset += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.offset])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    registers: CpuRegisters,
+    ime: bool,
+    ram: Vec<(u16, u8)>,
+}
+
+impl State {
+    fn parse(json: &Json) -> Option<Self> {
+        let register = |name| json.get(name).and_then(Json::as_u8);
+        let registers = CpuRegisters {
+            a: register("a")?,
+            f: register("f")?,
+            b: register("b")?,
+            c: register("c")?,
+            d: register("d")?,
+            e: register("e")?,
+            h: register("h")?,
+            l: register("l")?,
+            sp: json.get("sp")?.as_u16()?,
+            pc: json.get("pc")?.as_u16()?,
+        };
+        let ime = json.get("ime").and_then(Json::as_u8).unwrap_or(0) != 0;
+        let ram = json
+            .get("ram")?
+            .as_array()?
+            .iter()
+            .map(|entry| match entry.as_array()? {
+                [addr, value] => Some((addr.as_u16()?, value.as_u8()?)),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            registers,
+            ime,
+            ram,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Case {
+    name: String,
+    initial: State,
+    expected: State,
+    m_cycles: usize,
+    accesses: Vec<Access>,
+}
+
+impl Case {
+    fn parse(json: &Json) -> Option<Self> {
+        let Json::String(name) = json.get("name")? else {
+            return None;
+        };
+        let cycles = json.get("cycles")?.as_array()?;
+        let mut accesses = Vec::new();
+        for cycle in cycles {
+            // Internal cycles are null or have neither pin set
+            let Some([addr, value, Json::String(pins)]) = cycle.as_array() else {
+                continue;
+            };
+            let pins = pins.as_bytes();
+            if pins.first() == Some(&b'r') {
+                accesses.push(Access::Read(addr.as_u16()?, value.as_u8()?));
+            } else if pins.get(1) == Some(&b'w') {
+                accesses.push(Access::Write(addr.as_u16()?, value.as_u8()?));
+            }
+        }
+        Some(Self {
+            name: name.clone(),
+            initial: State::parse(json.get("initial")?)?,
+            expected: State::parse(json.get("final")?)?,
+            m_cycles: cycles.len(),
+            accesses,
+        })
+    }
+
+    /// Runs the instruction, returning what differs from the expected outcome.
+    fn run(&self) -> Result<(), String> {
+        let mut bus = FlatBus {
+            memory: vec![0; 0x10000],
+            accesses: Vec::new(),
+        };
+        for &(addr, value) in &self.initial.ram {
+            bus.memory[usize::from(addr)] = value;
+        }
+        let mut cpu = Cpu::new();
+        cpu.set_registers(self.initial.registers);
+        cpu.set_ime(self.initial.ime);
+
+        let cycles = cpu.step(&mut bus);
+        let mut differences = Vec::new();
+        if cycles != self.m_cycles * 4 {
+            differences.push(format!(
+                "took {} M-cycles, expected {}",
+                cycles / 4,
+                self.m_cycles
+            ));
+        }
+        if cpu.registers() != self.expected.registers {
+            differences.push(format!(
+                "registers {:?}, expected {:?}",
+                cpu.registers(),
+                self.expected.registers
+            ));
+        }
+        if cpu.ime() != self.expected.ime {
+            differences.push(format!("IME {}, expected {}", cpu.ime(), self.expected.ime));
+        }
+        for &(addr, value) in &self.expected.ram {
+            let actual = bus.memory[usize::from(addr)];
+            if actual != value {
+                differences.push(format!("[{addr:04X}] = {actual:02X}, expected {value:02X}"));
+            }
+        }
+        if bus.accesses != self.accesses {
+            differences.push(format!(
+                "bus activity {:?}, expected {:?}",
+                bus.accesses, self.accesses
+            ));
+        }
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(differences.join("; "))
+        }
+    }
+}
+
+/// 64 KiB of RAM recording every access, with no interrupts.
+struct FlatBus {
+    memory: Vec<u8>,
+    accesses: Vec<Access>,
+}
+
+impl BusInterface for FlatBus {
+    fn read_byte(&mut self, addr: u16) -> u8 {
+        let value = self.memory[usize::from(addr)];
+        self.accesses.push(Access::Read(addr, value));
+        value
+    }
+
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        self.accesses.push(Access::Write(addr, value));
+        self.memory[usize::from(addr)] = value;
+    }
+
+    fn peek_byte(&self, addr: u16) -> u8 {
+        self.memory[usize::from(addr)]
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        0
+    }
+
+    fn acknowledge_interrupt(&mut self, _mask: u8) {}
+}
+
+/// Outcome of the cases in one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileResult {
+    passed: usize,
+    total: usize,
+    // Name and differences of the first failing case
+    first_failure: Option<(String, String)>,
+}
+
+fn run_cases(json: &str) -> Result<FileResult, String> {
+    let json = Parser::parse(json)?;
+    let cases = json.as_array().ok_or("expected an array of cases")?;
+    let mut result = FileResult {
+        passed: 0,
+        total: cases.len(),
+        first_failure: None,
+    };
+    for (index, case) in cases.iter().enumerate() {
+        let case = Case::parse(case).ok_or_else(|| format!("case {index} is malformed"))?;
+        match case.run() {
+            Ok(()) => result.passed += 1,
+            Err(differences) => {
+                result.first_failure.get_or_insert((case.name, differences));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Lists the JSON files in `paths`, sorted within directories.
+fn collect_files(paths: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        if path.is_dir() {
+            let mut entries: Vec<_> = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<_>>()?;
+            entries.retain(|entry| {
+                entry
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+            });
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Runs the test files or directories in `args`, returning whether every case passed.
+pub fn main(args: &[String]) -> io::Result<bool> {
+    if args.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+    }
+    let (mut passed, mut total) = (0, 0);
+    for path in collect_files(args)? {
+        let result = run_cases(&fs::read_to_string(&path)?).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {err}", path.display()),
+            )
+        })?;
+        let status = if result.passed == result.total {
+            "PASS"
+        } else {
+            "FAIL"
+        };
+        println!(
+            "{status:<8} {} ({}/{})",
+            path.display(),
+            result.passed,
+            result.total
+        );
+        if let Some((name, differences)) = &result.first_failure {
+            println!("         {name}: {differences}");
+        }
+        passed += result.passed;
+        total += result.total;
+    }
+    println!("{passed}/{total} cases passed");
+    Ok(passed == total)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sm83_tests::{run_cases, Json, Parser};
+
+    const CASES: &str = r#"[
+        {
+            "name": "06 0000",
+            "initial": {
+                "pc": 256, "sp": 65534, "a": 1, "b": 0, "c": 19, "d": 0, "e": 216,
+                "f": 176, "h": 1, "l": 77, "ime": 0, "ie": 0,
+                "ram": [[256, 6], [257, 66]]
+            },
+            "final": {
+                "pc": 258, "sp": 65534, "a": 1, "b": 66, "c": 19, "d": 0, "e": 216,
+                "f": 176, "h": 1, "l": 77, "ime": 0, "ie": 0,
+                "ram": [[256, 6], [257, 66]]
+            },
+            "cycles": [[256, 6, "r-m"], [257, 66, "r-m"]]
+        },
+        {
+            "name": "C5 0000",
+            "initial": {
+                "pc": 4096, "sp": 53248, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 1,
+                "ram": [[4096, 197]]
+            },
+            "final": {
+                "pc": 4097, "sp": 53246, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 1,
+                "ram": [[4096, 197], [53247, 18], [53246, 52]]
+            },
+            "cycles": [
+                [4096, 197, "r-m"], [53248, null, "---"],
+                [53247, 18, "-wm"], [53246, 52, "-wm"]
+            ]
+        }
+    ]"#;
+
+    #[test]
+    fn test_parser() {
+        let json = Parser::parse(r#" {"a": [1, -2.5e1, true, null], "b\"é": "x"} "#).unwrap();
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(json.get("b\"é"), Some(&Json::String("x".to_string())));
+        assert!(Parser::parse("[1, 2").is_err());
+        assert!(Parser::parse("[1] 2").is_err());
+    }
+
+    #[test]
+    fn test_cases() {
+        let result = run_cases(CASES).unwrap();
+        assert_eq!((result.passed, result.total), (2, 2), "{result:?}");
+
+        // One M-cycle too few and a wrong value pushed
+        let wrong = CASES
+            .replace(r#"[53248, null, "---"],"#, "")
+            .replace("[53247, 18]", "[53247, 19]");
+        let result = run_cases(&wrong).unwrap();
+        assert_eq!((result.passed, result.total), (1, 2));
+        let (name, differences) = result.first_failure.unwrap();
+        assert_eq!(name, "C5 0000");
+        assert_eq!(
+            differences,
+            "took 4 M-cycles, expected 3; [CFFF] = 12, expected 13"
+        );
+    }
+}
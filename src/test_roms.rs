@@ -0,0 +1,449 @@
+//! Runs test ROMs in parallel and reports the outcome of each.
+//!
+//! Results are detected the way the common test suites report them:
+//! blargg's ROMs print "Passed" or "Failed" over the serial port, and mooneye's execute
+//! `LD B, B` with the Fibonacci numbers 3, 5, 8, 13, 21, 34 in B-L on success.
+//! ROMs that do neither within the time limit time out.
+//!
+//! The instructions executed by all ROMs are recorded, and the total is printed after the
+//! results. `--coverage` also lists the instructions none of the ROMs executed.
+
+use crate::control::quote;
+use gb_emulator::cartridge::Cartridge;
+use gb_emulator::consts::CPU_HZ;
+use gb_emulator::coverage::InstructionCoverage;
+use gb_emulator::hardware::GameboyHardware;
+use std::fmt::Write as _;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{fs, io, thread};
+
+const USAGE: &str = "Usage: gb-emulator test-roms <rom or directory>... [--jobs <n>] [--timeout <seconds>] [--json <path>] [--junit <path>] [--coverage]";
+
+// Emulated seconds before a ROM times out, blargg's cpu_instrs takes about a minute
+const DEFAULT_TIMEOUT_SECONDS: u64 = 120;
+// Tests that finished print their result well before a second of looping
+const SOFT_LOCK_FRAMES: u32 = 60;
+
+const OPCODE_LD_B_B: u8 = 0x40;
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Timeout,
+    Error(String),
+}
+
+impl Outcome {
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail(_) => "fail",
+            Self::Timeout => "timeout",
+            Self::Error(_) => "error",
+        }
+    }
+
+    fn message(&self) -> Option<&str> {
+        match self {
+            Self::Fail(message) | Self::Error(message) => Some(message),
+            Self::Pass | Self::Timeout => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RomResult {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    /// T-cycles emulated until the outcome was known.
+    pub cycles: u64,
+    pub duration: Duration,
+    /// Instructions executed, `None` if the emulator panicked.
+    pub coverage: Option<InstructionCoverage>,
+}
+
+struct Options {
+    roms: Vec<PathBuf>,
+    jobs: usize,
+    timeout_cycles: u64,
+    json: Option<PathBuf>,
+    junit: Option<PathBuf>,
+    coverage: bool,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut paths = Vec::new();
+        let mut options = Self {
+            roms: Vec::new(),
+            jobs: thread::available_parallelism().map_or(1, usize::from),
+            timeout_cycles: DEFAULT_TIMEOUT_SECONDS * u64::from(CPU_HZ),
+            json: None,
+            junit: None,
+            coverage: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--jobs" => {
+                    options.jobs = value()?
+                        .parse()
+                        .ok()
+                        .filter(|jobs| *jobs > 0)
+                        .ok_or("--jobs must be a positive number")?;
+                }
+                "--timeout" => {
+                    let seconds: u64 = value()?
+                        .parse()
+                        .map_err(|_| "--timeout must be a number of seconds")?;
+                    options.timeout_cycles = seconds * u64::from(CPU_HZ);
+                }
+                "--json" => options.json = Some(value()?.into()),
+                "--junit" => options.junit = Some(value()?.into()),
+                "--coverage" => options.coverage = true,
+                path => paths.push(PathBuf::from(path)),
+            }
+        }
+        if paths.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        for path in paths {
+            collect_roms(&path, &mut options.roms)
+                .map_err(|err| format!("{}: {err}", path.display()))?;
+        }
+        options.roms.sort();
+        Ok(options)
+    }
+}
+
+/// Adds `path` if it's a file, or the `.gb`/`.gbc` files under it if it's a directory.
+fn collect_roms(path: &Path, roms: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        roms.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        let is_rom = path
+            .extension()
+            .is_some_and(|extension| extension == "gb" || extension == "gbc");
+        if path.is_dir() || is_rom {
+            collect_roms(&path, roms)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the ROMs given on the command line, returning whether all of them passed.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid or a report can't be written.
+pub fn main(args: &[String]) -> io::Result<bool> {
+    let options =
+        Options::parse(args).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let results = run_all(&options.roms, options.jobs, options.timeout_cycles);
+
+    for result in &results {
+        let message = result.outcome.message().unwrap_or_default();
+        println!(
+            "{:<8} {} ({} cycles) {message}",
+            result.outcome.name().to_uppercase(),
+            result.path.display(),
+            result.cycles
+        );
+    }
+    let passed = results
+        .iter()
+        .filter(|result| result.outcome == Outcome::Pass)
+        .count();
+    println!("{passed}/{} passed", results.len());
+
+    let mut coverage = InstructionCoverage::new();
+    for result in &results {
+        if let Some(rom_coverage) = &result.coverage {
+            coverage.merge(rom_coverage);
+        }
+    }
+    if options.coverage {
+        print!("{coverage}");
+    } else {
+        let (executed, total) = coverage.summary();
+        println!("Instruction coverage: {executed}/{total}");
+    }
+
+    if let Some(path) = &options.json {
+        fs::write(path, json_report(&results))?;
+    }
+    if let Some(path) = &options.junit {
+        fs::write(path, junit_report(&results))?;
+    }
+    Ok(passed == results.len())
+}
+
+/// Runs ROMs on `jobs` threads, returning results in the order of `roms`.
+pub fn run_all(roms: &[PathBuf], jobs: usize, timeout_cycles: u64) -> Vec<RomResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(roms.len()));
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(roms.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = roms.get(index) else { break };
+                let result = run_rom(path, timeout_cycles);
+                results
+                    .lock()
+                    .expect("no worker panics while holding the lock")
+                    .push((index, result));
+            });
+        }
+    });
+    let mut results = results
+        .into_inner()
+        .expect("no worker panics while holding the lock");
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn run_rom(path: &Path, timeout_cycles: u64) -> RomResult {
+    let start = Instant::now();
+    let (outcome, cycles, coverage) = match fs::read(path) {
+        Ok(rom) => {
+            // A malformed ROM or an emulation bug shouldn't take down the whole run
+            let mut cycles = 0;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+                gameboy.enable_instruction_coverage();
+                let outcome = run_until_outcome(&mut gameboy, timeout_cycles, &mut cycles);
+                (outcome, gameboy.instruction_coverage().cloned())
+            }));
+            let (outcome, coverage) = result.unwrap_or_else(|payload| {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(ToString::to_string)
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                (
+                    Outcome::Error(format!("emulator panicked: {message}")),
+                    None,
+                )
+            });
+            (outcome, cycles, coverage)
+        }
+        Err(err) => (Outcome::Error(err.to_string()), 0, None),
+    };
+    RomResult {
+        path: path.to_path_buf(),
+        outcome,
+        cycles,
+        duration: start.elapsed(),
+        coverage,
+    }
+}
+
+fn run_until_outcome(
+    gameboy: &mut GameboyHardware,
+    timeout_cycles: u64,
+    cycles: &mut u64,
+) -> Outcome {
+    gameboy.set_crash_detection(Some(SOFT_LOCK_FRAMES));
+    let mut serial_output = String::new();
+
+    while *cycles < timeout_cycles {
+        if gameboy.peek_byte(gameboy.registers().pc) == OPCODE_LD_B_B {
+            let r = gameboy.registers();
+            let values = [r.b, r.c, r.d, r.e, r.h, r.l];
+            if values == MOONEYE_PASS {
+                return Outcome::Pass;
+            }
+            if values == MOONEYE_FAIL {
+                return Outcome::Fail("failure signature in registers".to_string());
+            }
+        }
+        *cycles += gameboy.step() as u64;
+        if let Some(crash) = gameboy.crash() {
+            return Outcome::Error(format!("game crashed: {crash}"));
+        }
+
+        let bytes = gameboy.take_serial_output();
+        if !bytes.is_empty() {
+            serial_output.extend(bytes.into_iter().map(char::from));
+            if serial_output.contains("Passed") {
+                return Outcome::Pass;
+            }
+            if serial_output.contains("Failed") {
+                return Outcome::Fail(serial_output.trim().to_string());
+            }
+        }
+    }
+    Outcome::Timeout
+}
+
+fn json_report(results: &[RomResult]) -> String {
+    let mut json = String::from("{\"results\":[");
+    for (index, result) in results.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        let _ = write!(
+            json,
+            "{{\"rom\":{},\"outcome\":\"{}\",\"cycles\":{},\"seconds\":{:.3}",
+            quote(&result.path.to_string_lossy()),
+            result.outcome.name(),
+            result.cycles,
+            result.duration.as_secs_f64()
+        );
+        if let Some(message) = result.outcome.message() {
+            let _ = write!(json, ",\"message\":{}", quote(message));
+        }
+        json.push('}');
+    }
+    json.push_str("]}\n");
+    json
+}
+
+fn junit_report(results: &[RomResult]) -> String {
+    let count = |name| {
+        results
+            .iter()
+            .filter(|result| result.outcome.name() == name)
+            .count()
+    };
+    let total: f64 = results
+        .iter()
+        .map(|result| result.duration.as_secs_f64())
+        .sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuite name=\"test-roms\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{total:.3}\">",
+        results.len(),
+        count("fail") + count("timeout"),
+        count("error")
+    );
+    for result in results {
+        let _ = write!(
+            xml,
+            "  <testcase classname=\"test-roms\" name=\"{}\" time=\"{:.3}\">",
+            escape_xml(&result.path.to_string_lossy()),
+            result.duration.as_secs_f64()
+        );
+        let cycles = result.cycles;
+        match &result.outcome {
+            Outcome::Pass => {}
+            Outcome::Fail(message) => {
+                let _ = write!(xml, "<failure message=\"{}\"/>", escape_xml(message));
+            }
+            Outcome::Timeout => {
+                let _ = write!(
+                    xml,
+                    "<failure message=\"timed out after {cycles} cycles\"/>"
+                );
+            }
+            Outcome::Error(message) => {
+                let _ = write!(xml, "<error message=\"{}\"/>", escape_xml(message));
+            }
+        }
+        let _ = writeln!(xml, "<system-out>cycles={cycles}</system-out></testcase>");
+    }
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_roms::{escape_xml, json_report, run_until_outcome, Outcome, RomResult};
+    use gb_emulator::cartridge::{Cartridge, HeaderBuilder};
+    use gb_emulator::coverage::{InstructionCoverage, Opcode};
+    use gb_emulator::hardware::GameboyHardware;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn run(program: &[u8]) -> (Outcome, u64, InstructionCoverage) {
+        let rom = HeaderBuilder::new().build(program);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        gameboy.enable_instruction_coverage();
+        let mut cycles = 0;
+        let outcome = run_until_outcome(&mut gameboy, 1_000_000, &mut cycles);
+        let coverage = gameboy.instruction_coverage().unwrap().clone();
+        (outcome, cycles, coverage)
+    }
+
+    #[test]
+    fn test_mooneye_signatures() {
+        // LD B, 3; LD C, 5; LD D, 8; LD E, 13; LD H, 21; LD L, 34; LD B, B
+        let pass = [
+            0x06, 3, 0x0E, 5, 0x16, 8, 0x1E, 13, 0x26, 21, 0x2E, 34, 0x40,
+        ];
+        let (outcome, cycles, coverage) = run(&pass);
+        assert_eq!(outcome, Outcome::Pass);
+        assert!(cycles > 0);
+        // Each load ran once, and the result is read before LD B, B runs
+        assert_eq!(coverage.count(Opcode::Unprefixed(0x06)), 1);
+        assert_eq!(coverage.count(Opcode::Unprefixed(0x2E)), 1);
+        assert_eq!(coverage.count(Opcode::Unprefixed(0x40)), 0);
+
+        // JR -2
+        assert_eq!(run(&[0x18, 0xFE]).0, Outcome::Timeout);
+        assert_eq!(
+            run(&[0xD3]).0,
+            Outcome::Error("game crashed: undefined opcode D3 at 0150".to_string())
+        );
+    }
+
+    #[test]
+    fn test_serial_output() {
+        // For each byte of the message: LD A, byte; LDH (SB), A; LD A, 0x81; LDH (SC), A,
+        // then wait: LDH A, (SC); BIT 7, A; JR NZ, wait
+        let mut program = Vec::new();
+        for byte in b"Failed" {
+            program.extend_from_slice(&[0x3E, *byte, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02]);
+            program.extend_from_slice(&[0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA]);
+        }
+        program.extend_from_slice(&[0x18, 0xFE]);
+        assert_eq!(run(&program).0, Outcome::Fail("Failed".to_string()));
+    }
+
+    #[test]
+    fn test_reports_escape() {
+        let results = [RomResult {
+            path: PathBuf::from("a\"b.gb"),
+            outcome: Outcome::Fail("<bad>".to_string()),
+            cycles: 4,
+            duration: Duration::ZERO,
+            coverage: None,
+        }];
+        assert_eq!(
+            json_report(&results),
+            "{\"results\":[{\"rom\":\"a\\\"b.gb\",\"outcome\":\"fail\",\"cycles\":4,\"seconds\":0.000,\"message\":\"<bad>\"}]}\n"
+        );
+        assert_eq!(escape_xml("a<\"b\">"), "a&lt;&quot;b&quot;&gt;");
+    }
+}
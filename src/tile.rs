@@ -0,0 +1,184 @@
+//! Decoding of the 2bpp tile format and monochrome palettes.
+//!
+//! Tiles are 8x8 pixels stored in 16 bytes, two bytes per row. The first byte of a row
+//! holds the low bit of each pixel's color index and the second the high bit, with the
+//! leftmost pixel in bit 7. Color indices (0-3) are mapped to shades (0-3, 0 being white)
+//! by a palette register (BGP/OBP0/OBP1).
+
+/// Size of a tile in bytes.
+pub const TILE_SIZE: usize = 16;
+/// Width and height of a tile in pixels.
+pub const TILE_PIXELS: usize = 8;
+
+/// Returns the color index (0-3) of pixel `x` (0 being leftmost) in a row of a tile.
+#[must_use]
+pub const fn color_index(low: u8, high: u8, x: u8) -> u8 {
+    let bit = 7 - x;
+    (((high >> bit) & 1) << 1) | ((low >> bit) & 1)
+}
+
+// Each byte spread over a u64, one bit per byte with bit 7 in the lowest byte,
+// so a row decodes with two lookups instead of a loop over its pixels
+const SPREAD_BITS: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            if byte & (0x80 >> bit) != 0 {
+                table[byte] |= 1 << (bit * 8);
+            }
+            bit += 1;
+        }
+        byte += 1;
+    }
+    table
+};
+
+/// Decodes a row of a tile into the color indices of its 8 pixels, leftmost first.
+#[must_use]
+pub const fn decode_row(low: u8, high: u8) -> [u8; TILE_PIXELS] {
+    (SPREAD_BITS[low as usize] | (SPREAD_BITS[high as usize] << 1)).to_le_bytes()
+}
+
+/// Decodes a tile into its 64 color indices in row-major order.
+#[must_use]
+pub fn decode_tile(tile: &[u8; TILE_SIZE]) -> [u8; TILE_PIXELS * TILE_PIXELS] {
+    let mut pixels = [0; TILE_PIXELS * TILE_PIXELS];
+    for (row, bytes) in pixels
+        .chunks_exact_mut(TILE_PIXELS)
+        .zip(tile.chunks_exact(2))
+    {
+        row.copy_from_slice(&decode_row(bytes[0], bytes[1]));
+    }
+    pixels
+}
+
+/// Decodes consecutive tiles (e.g. a VRAM dump) into a sheet of color indices,
+/// `tiles_per_row` tiles wide, in row-major order.
+///
+/// Returns the pixels with the sheet's width and height. Trailing bytes that don't
+/// make up a whole tile are ignored, and unused space in the last row is color 0.
+///
+/// # Panics
+///
+/// Panics if `tiles_per_row` is 0.
+#[must_use]
+pub fn decode_tiles(data: &[u8], tiles_per_row: usize) -> (Vec<u8>, usize, usize) {
+    assert!(tiles_per_row > 0, "tiles_per_row must be positive");
+    let tiles = data.len() / TILE_SIZE;
+    let width = tiles_per_row * TILE_PIXELS;
+    let height = tiles.div_ceil(tiles_per_row) * TILE_PIXELS;
+    let mut pixels = vec![0; width * height];
+
+    for (index, tile) in data.chunks_exact(TILE_SIZE).enumerate() {
+        let tile = decode_tile(tile.try_into().expect("chunk is a whole tile"));
+        let left = (index % tiles_per_row) * TILE_PIXELS;
+        let top = (index / tiles_per_row) * TILE_PIXELS;
+        for (y, row) in tile.chunks_exact(TILE_PIXELS).enumerate() {
+            let start = (top + y) * width + left;
+            pixels[start..start + TILE_PIXELS].copy_from_slice(row);
+        }
+    }
+    (pixels, width, height)
+}
+
+/// Maps a color index (0-3) to a shade using a palette register (BGP/OBP0/OBP1).
+#[must_use]
+pub const fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+/// Returns the shade of each color index (0-3) under a palette register, so a line of
+/// pixels can be mapped with a table lookup each.
+#[must_use]
+pub const fn palette_shades(palette: u8) -> [u8; 4] {
+    [
+        apply_palette(palette, 0),
+        apply_palette(palette, 1),
+        apply_palette(palette, 2),
+        apply_palette(palette, 3),
+    ]
+}
+
+/// Maps color indices to shades in place, see [`apply_palette`].
+///
+/// Object palettes are applied the same way, the caller treats color 0 as transparent.
+pub fn apply_palette_to(palette: u8, pixels: &mut [u8]) {
+    let shades = palette_shades(palette);
+    for pixel in pixels {
+        *pixel = shades[*pixel as usize & 0b11];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tile::{
+        apply_palette, apply_palette_to, color_index, decode_row, decode_tile, decode_tiles,
+        palette_shades,
+    };
+
+    // Top row with colors 0-3 repeated, the rest blank
+    const TILE: [u8; 16] = [
+        0b0101_0101,
+        0b0011_0011,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ];
+
+    #[test]
+    fn test_decode_row() {
+        for (low, high) in [(0x00, 0xFF), (0b1010_0101, 0b0110_0011), (0xFF, 0x0F)] {
+            let row = decode_row(low, high);
+            for x in 0..8 {
+                assert_eq!(row[x as usize], color_index(low, high, x));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_tile() {
+        let pixels = decode_tile(&TILE);
+        assert_eq!(pixels[..8], [0, 1, 2, 3, 0, 1, 2, 3]);
+        assert!(pixels[8..].iter().all(|pixel| *pixel == 0));
+    }
+
+    #[test]
+    fn test_decode_tiles() {
+        let mut data = TILE.to_vec();
+        data.extend_from_slice(&[0xFF; 16]);
+        data.extend_from_slice(&TILE);
+        let (pixels, width, height) = decode_tiles(&data, 2);
+        assert_eq!((width, height), (16, 16));
+        assert_eq!(
+            pixels[..16],
+            [0, 1, 2, 3, 0, 1, 2, 3, 3, 3, 3, 3, 3, 3, 3, 3]
+        );
+        assert_eq!(pixels[8 * 16..8 * 16 + 4], [0, 1, 2, 3]);
+        // Unused space after the last tile
+        assert_eq!(pixels[8 * 16 + 8..9 * 16], [0; 8]);
+    }
+
+    #[test]
+    fn test_apply_palette() {
+        // The default BGP, shades 0, 3, 3, 3
+        assert_eq!(apply_palette(0xFC, 0), 0);
+        assert_eq!(apply_palette(0xFC, 1), 3);
+        assert_eq!(palette_shades(0xFC), [0, 3, 3, 3]);
+        let mut pixels = [0, 1, 2, 3];
+        apply_palette_to(0b0001_1011, &mut pixels);
+        assert_eq!(pixels, [3, 2, 1, 0]);
+    }
+}
@@ -1,4 +1,7 @@
+use crate::clock::ClockEdges;
+use crate::error::SavestateError;
 use crate::interrupts::InterruptFlags;
+use crate::savestate::{StateReader, StateWriter};
 
 const MEM_DIV: u16 = 0xFF04;
 const MEM_TIMA: u16 = 0xFF05;
@@ -29,12 +32,13 @@ impl TimerControl {
         (self.0 & Self::ENABLE) == Self::ENABLE
     }
 
+    // Bit of the system counter that increments TIMA when it falls
     fn counter_mask(self) -> u16 {
         match self.0 & Self::CLOCK_SELECT {
-            0b00 => 128,
-            0b01 => 2,
-            0b10 => 8,
-            0b11 => 32,
+            0b00 => 1 << 9,
+            0b01 => 1 << 3,
+            0b10 => 1 << 5,
+            0b11 => 1 << 7,
             _ => unreachable!(),
         }
     }
@@ -42,9 +46,10 @@ impl TimerControl {
 
 #[derive(Debug, Clone)]
 pub struct Timer {
-    // DIV
-    // Note: only uses 14 bits
+    // Counts T-cycles, DIV is the upper byte. Drives the whole system, see `crate::clock`
     system_counter: u16,
+    // Value at the end of the previous M-cycle, for finding falling edges
+    previous_counter: u16,
     // TIMA
     counter: u8,
     // TMA
@@ -61,7 +66,8 @@ impl Timer {
     pub const fn new() -> Self {
         Self {
             // TODO: between 0x2C and 0x3F
-            system_counter: (0xAB << 6) + 0x2C,
+            system_counter: (0xAB << 8) + (0x2C << 2),
+            previous_counter: (0xAB << 8) + (0x2C << 2),
             counter: 0,
             modulo: 0,
             control: TimerControl::empty(),
@@ -73,7 +79,7 @@ impl Timer {
     pub const fn read_byte(&self, addr: u16) -> u8 {
         match addr {
             #[allow(clippy::cast_possible_truncation)]
-            MEM_DIV => (self.system_counter >> 6) as u8,
+            MEM_DIV => (self.system_counter >> 8) as u8,
             MEM_TIMA => self.counter,
             MEM_TMA => self.modulo,
             MEM_TAC => self.control.bits(),
@@ -94,8 +100,12 @@ impl Timer {
         }
     }
 
-    pub fn tick(&mut self, interrupt_flag: &mut InterruptFlags) {
-        self.system_counter = self.system_counter.wrapping_add(1);
+    /// Advances the system counter by one M-cycle, returning the counter bits that fell
+    /// since the previous M-cycle, including those cleared by writing to DIV.
+    pub fn tick(&mut self, interrupt_flag: &mut InterruptFlags) -> ClockEdges {
+        self.system_counter = self.system_counter.wrapping_add(4);
+        let edges = ClockEdges::between(self.previous_counter, self.system_counter);
+        self.previous_counter = self.system_counter;
 
         let new_signal = self.counter_bit() && self.control.is_enabled();
 
@@ -116,6 +126,36 @@ impl Timer {
             interrupt_flag.set(InterruptFlags::TIMER, true);
             self.overflow_delay_counter = None;
         }
+        edges
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.component("Timer");
+        writer.field("system counter");
+        writer.write_u16(self.system_counter);
+        writer.field("previous counter");
+        writer.write_u16(self.previous_counter);
+        writer.field("TIMA");
+        writer.write_u8(self.counter);
+        writer.field("TMA");
+        writer.write_u8(self.modulo);
+        writer.field("TAC");
+        writer.write_u8(self.control.bits());
+        writer.field("interrupt signal");
+        writer.write_bool(self.interrupt_signal);
+        writer.field("overflow delay");
+        writer.write_option_u8(self.overflow_delay_counter);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SavestateError> {
+        self.system_counter = reader.read_u16()?;
+        self.previous_counter = reader.read_u16()?;
+        self.counter = reader.read_u8()?;
+        self.modulo = reader.read_u8()?;
+        self.control = TimerControl::from_bits(reader.read_u8()?);
+        self.interrupt_signal = reader.read_bool()?;
+        self.overflow_delay_counter = reader.read_option_u8()?;
+        Ok(())
     }
 
     fn counter_bit(&self) -> bool {
@@ -0,0 +1,131 @@
+//! Remembering the last instructions executed, so there is context to look at when a game
+//! crashes without having to trace the whole run.
+
+use crate::watch::CodeAddress;
+use std::fmt::{Display, Formatter};
+
+/// An instruction recorded by [`GameboyHardware::set_trace_buffer`].
+///
+/// [`GameboyHardware::set_trace_buffer`]: crate::hardware::GameboyHardware::set_trace_buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TracedInstruction {
+    pub location: CodeAddress,
+    /// The opcode followed by the next two bytes, which hold the operands if it has any.
+    pub bytes: [u8; 3],
+}
+
+impl TracedInstruction {
+    // Bank, PC and bytes take 56 bits, so each instruction fits in a u64
+    const fn pack(self) -> u64 {
+        (self.location.bank as u64 & 0xFFFF) << 40
+            | (self.location.pc as u64) << 24
+            | (self.bytes[0] as u64) << 16
+            | (self.bytes[1] as u64) << 8
+            | self.bytes[2] as u64
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn unpack(entry: u64) -> Self {
+        Self {
+            location: CodeAddress {
+                bank: (entry >> 40) as usize & 0xFFFF,
+                pc: (entry >> 24) as u16,
+            },
+            bytes: [(entry >> 16) as u8, (entry >> 8) as u8, entry as u8],
+        }
+    }
+}
+
+impl Display for TracedInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let [opcode, first, second] = self.bytes;
+        write!(f, "{} {opcode:02X} {first:02X} {second:02X}", self.location)
+    }
+}
+
+/// Ring buffer of the last instructions executed.
+#[derive(Debug, Clone)]
+pub(crate) struct TraceBuffer {
+    entries: Box<[u64]>,
+    // Index the next instruction is written at
+    next: usize,
+    len: usize,
+}
+
+impl TraceBuffer {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            entries: vec![0; capacity.max(1)].into_boxed_slice(),
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, instruction: TracedInstruction) {
+        self.entries[self.next] = instruction.pack();
+        self.next = (self.next + 1) % self.entries.len();
+        self.len = (self.len + 1).min(self.entries.len());
+    }
+
+    /// Returns the last `n` instructions recorded, oldest first.
+    pub(crate) fn tail(&self, n: usize) -> Vec<TracedInstruction> {
+        let n = n.min(self.len);
+        let capacity = self.entries.len();
+        (capacity - n..capacity)
+            .map(|age| TracedInstruction::unpack(self.entries[(self.next + age) % capacity]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use crate::trace::{TraceBuffer, TracedInstruction};
+    use crate::watch::CodeAddress;
+
+    fn instruction(bank: usize, pc: u16) -> TracedInstruction {
+        TracedInstruction {
+            location: CodeAddress { bank, pc },
+            bytes: [0xCD, 0x34, 0x12],
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut trace = TraceBuffer::new(3);
+        assert!(trace.tail(5).is_empty());
+        trace.record(instruction(0x1FF, 0x4000));
+        assert_eq!(trace.tail(5), [instruction(0x1FF, 0x4000)]);
+        for pc in 1..=4 {
+            trace.record(instruction(1, pc));
+        }
+        assert_eq!(
+            trace.tail(5),
+            [instruction(1, 2), instruction(1, 3), instruction(1, 4)]
+        );
+        assert_eq!(trace.tail(1), [instruction(1, 4)]);
+    }
+
+    #[test]
+    fn test_trace_tail() {
+        // LD A, 0x12; INC A; undefined opcode
+        let rom = HeaderBuilder::new().build(&[0x3E, 0x12, 0x3C, 0xD3]);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        assert!(gameboy.trace_tail(10).is_empty());
+        gameboy.set_trace_buffer(Some(3));
+        for _ in 0..10 {
+            gameboy.step();
+        }
+        let tail: Vec<_> = gameboy
+            .trace_tail(10)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        // The undefined opcode hangs the CPU, so it is the last instruction executed
+        assert_eq!(
+            tail,
+            ["00:0150 3E 12 3C", "00:0152 3C D3 00", "00:0153 D3 00 00"]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Terminal frontend, for a quick look at a ROM over SSH.
+//!
+//! The screen is drawn with Unicode half blocks (two pixels per cell, one in the
+//! foreground and one in the background colour) or braille patterns (2x4 pixels per
+//! cell, only the darker two shades lit) for terminals without true colour. Next to it a
+//! debugger pane shows the registers and takes the same commands as the control
+//! socket, e.g. `:writers $C123`.
+//!
+//! | Key | Action |
+//! |-----|--------|
+//! | Arrow keys | D-pad |
+//! | `x`, `z` | A, B |
+//! | Enter, Backspace | Start, Select |
+//! | `p` | Pause or resume |
+//! | `m` | Switch between half blocks and braille |
+//! | `:` | Enter a debugger command, Esc to cancel |
+//! | `q`, Ctrl+C | Quit |
+//!
+//! Most terminals only report key presses, so without the kitty keyboard protocol a
+//! button is released once its key hasn't repeated for [`HOLD_FRAMES`] frames.
+
+use crate::control::{Command, Session};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, queue};
+use gb_emulator::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gb_emulator::disasm;
+use gb_emulator::hardware::{Button, DirtyLines};
+use gb_emulator::pacer::Pacer;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+/// Frames a button stays pressed after its last key event when releases aren't reported.
+const HOLD_FRAMES: u8 = 12;
+/// Columns between the screen and the debugger pane.
+const PANE_GAP: u16 = 2;
+/// Debugger responses kept in the pane.
+const LOG_LINES: usize = 8;
+// Last instructions logged when the game crashes
+const CRASH_TRACE_LINES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Renderer {
+    HalfBlock,
+    Braille,
+}
+
+impl Renderer {
+    /// Returns the size of the screen in terminal cells.
+    pub const fn size(self) -> (u16, u16) {
+        let (cell_width, cell_height) = self.cell_pixels();
+        (
+            (SCREEN_WIDTH / cell_width) as u16,
+            (SCREEN_HEIGHT / cell_height) as u16,
+        )
+    }
+
+    const fn cell_pixels(self) -> (usize, usize) {
+        match self {
+            Self::HalfBlock => (1, 2),
+            Self::Braille => (2, 4),
+        }
+    }
+
+    const fn toggle(self) -> Self {
+        match self {
+            Self::HalfBlock => Self::Braille,
+            Self::Braille => Self::HalfBlock,
+        }
+    }
+
+    /// Returns the rows of cells covering a dirty line.
+    fn dirty_rows(self, lines: &DirtyLines) -> Vec<u16> {
+        let (_, cell_height) = self.cell_pixels();
+        let mut rows: Vec<u16> = lines.iter().map(|ly| (ly / cell_height) as u16).collect();
+        rows.dedup();
+        rows
+    }
+
+    fn draw_row(self, out: &mut impl Write, frame: &[u8], row: u16) -> io::Result<()> {
+        queue!(out, cursor::MoveTo(0, row))?;
+        let y = usize::from(row);
+        match self {
+            Self::HalfBlock => {
+                let upper = &frame[2 * y * SCREEN_WIDTH..][..SCREEN_WIDTH];
+                let lower = &frame[(2 * y + 1) * SCREEN_WIDTH..][..SCREEN_WIDTH];
+                let mut colors = None;
+                for (&top, &bottom) in upper.iter().zip(lower) {
+                    if colors != Some((top, bottom)) {
+                        colors = Some((top, bottom));
+                        queue!(
+                            out,
+                            SetForegroundColor(shade_color(top)),
+                            SetBackgroundColor(shade_color(bottom))
+                        )?;
+                    }
+                    queue!(out, Print('▀'))?;
+                }
+                queue!(out, ResetColor)
+            }
+            Self::Braille => {
+                let cells: String = (0..SCREEN_WIDTH / 2)
+                    .map(|x| braille_cell(frame, x, y))
+                    .collect();
+                queue!(
+                    out,
+                    SetForegroundColor(Color::Black),
+                    SetBackgroundColor(Color::White),
+                    Print(cells),
+                    ResetColor
+                )
+            }
+        }
+    }
+}
+
+/// Returns the colour of a shade, matching the grays of screenshots.
+const fn shade_color(shade: u8) -> Color {
+    let value = 255 - shade * 85;
+    Color::Rgb {
+        r: value,
+        g: value,
+        b: value,
+    }
+}
+
+/// Returns the braille pattern for the 2x4 pixels at cell (x, y), with a dot for each
+/// pixel of shade 2 or 3.
+fn braille_cell(frame: &[u8], x: usize, y: usize) -> char {
+    // Bit of each dot, indexed by [row][column]
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let mut bits = 0;
+    for (dy, row) in DOTS.iter().enumerate() {
+        for (dx, bit) in row.iter().enumerate() {
+            if frame[(4 * y + dy) * SCREEN_WIDTH + 2 * x + dx] >= 2 {
+                bits |= bit;
+            }
+        }
+    }
+    char::from_u32(0x2800 + bits).unwrap_or(' ')
+}
+
+const fn key_button(code: KeyCode) -> Option<Button> {
+    match code {
+        KeyCode::Right => Some(Button::Right),
+        KeyCode::Left => Some(Button::Left),
+        KeyCode::Up => Some(Button::Up),
+        KeyCode::Down => Some(Button::Down),
+        KeyCode::Char('x' | 'X') => Some(Button::A),
+        KeyCode::Char('z' | 'Z') => Some(Button::B),
+        KeyCode::Enter => Some(Button::Start),
+        KeyCode::Backspace => Some(Button::Select),
+        _ => None,
+    }
+}
+
+struct Tui {
+    session: Session,
+    renderer: Renderer,
+    /// Frames left before each held button is released, when releases aren't reported.
+    held: Vec<(Button, u8)>,
+    reports_release: bool,
+    command: Option<String>,
+    log: VecDeque<String>,
+    redraw: bool,
+}
+
+impl Tui {
+    /// Handles a key, returning whether to quit.
+    fn key(&mut self, key: KeyEvent) -> bool {
+        if let Some(command) = &mut self.command {
+            if key.kind == KeyEventKind::Release {
+                return false;
+            }
+            match key.code {
+                KeyCode::Enter => {
+                    let line = self.command.take().unwrap_or_default();
+                    let (response, quit) = match Command::parse_words(&line) {
+                        Ok(command) => {
+                            self.redraw |= matches!(command, Command::LoadRom(_));
+                            self.session.execute(command)
+                        }
+                        Err(err) => (err, false),
+                    };
+                    self.log(format!(":{line}"));
+                    self.log(response);
+                    return quit;
+                }
+                KeyCode::Esc => self.command = None,
+                KeyCode::Backspace => {
+                    command.pop();
+                }
+                KeyCode::Char(c) => command.push(c),
+                _ => {}
+            }
+            return false;
+        }
+
+        if let Some(button) = key_button(key.code) {
+            let pressed = key.kind != KeyEventKind::Release;
+            self.session.gameboy.set_button(button, pressed);
+            self.held.retain(|&(held, _)| held != button);
+            if pressed && !self.reports_release {
+                self.held.push((button, HOLD_FRAMES));
+            }
+            return false;
+        }
+        if key.kind == KeyEventKind::Release {
+            return false;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('p') => self.session.paused = !self.session.paused,
+            KeyCode::Char('m') => {
+                self.renderer = self.renderer.toggle();
+                self.redraw = true;
+            }
+            KeyCode::Char(':') => self.command = Some(String::new()),
+            _ => {}
+        }
+        false
+    }
+
+    fn log(&mut self, line: String) {
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+
+    /// Counts down held buttons, releasing the ones whose key stopped repeating.
+    fn release_stale_buttons(&mut self) {
+        for (button, frames) in &mut self.held {
+            *frames -= 1;
+            if *frames == 0 {
+                self.session.gameboy.set_button(*button, false);
+            }
+        }
+        self.held.retain(|&(_, frames)| frames > 0);
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = self.renderer.size();
+        let dirty = self.session.gameboy.take_dirty_lines();
+        if self.redraw {
+            self.redraw = false;
+            queue!(out, terminal::Clear(ClearType::All))?;
+            for row in 0..height {
+                self.renderer
+                    .draw_row(out, self.session.gameboy.frame(), row)?;
+            }
+        } else {
+            for row in self.renderer.dirty_rows(&dirty) {
+                self.renderer
+                    .draw_row(out, self.session.gameboy.frame(), row)?;
+            }
+        }
+
+        let registers = self.session.gameboy.registers();
+        let state = if self.session.paused {
+            "paused"
+        } else {
+            "running"
+        };
+        let mut pane = vec![
+            format!("frame {} ({state})", self.session.frame),
+            format!(
+                "AF {:02X}{:02X}  BC {:02X}{:02X}",
+                registers.a, registers.f, registers.b, registers.c
+            ),
+            format!(
+                "DE {:02X}{:02X}  HL {:02X}{:02X}",
+                registers.d, registers.e, registers.h, registers.l
+            ),
+            format!("SP {:04X}  PC {:04X}", registers.sp, registers.pc),
+            format!(
+                "{}",
+                disasm::decode(registers.pc, &|addr| self.session.gameboy.peek_byte(addr))
+            ),
+            format!("hash {:016X}", self.session.gameboy.frame_hash()),
+            format!("input {}", self.session.gameboy.input_display()),
+            String::new(),
+        ];
+        pane.extend(self.log.iter().cloned());
+        pane.push(match &self.command {
+            Some(command) => format!(":{command}"),
+            None => "press : for a command".to_string(),
+        });
+
+        let column = width + PANE_GAP;
+        for (row, line) in pane.iter().enumerate().take(usize::from(height)) {
+            queue!(
+                out,
+                cursor::MoveTo(column, row as u16),
+                terminal::Clear(ClearType::UntilNewLine),
+                Print(line)
+            )?;
+        }
+        out.flush()
+    }
+}
+
+/// Runs the emulator in the terminal until the user quits.
+///
+/// # Errors
+///
+/// Returns an error if the terminal can't be set up or written to.
+pub fn run(session: Session, renderer: Renderer) -> io::Result<()> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    let reports_release = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if reports_release {
+        queue!(
+            out,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )?;
+    }
+    queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let mut tui = Tui {
+        session,
+        renderer,
+        held: Vec::new(),
+        reports_release,
+        command: None,
+        log: VecDeque::new(),
+        redraw: true,
+    };
+    let result = run_loop(&mut tui, &mut out);
+
+    if reports_release {
+        queue!(out, PopKeyboardEnhancementFlags)?;
+    }
+    queue!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    out.flush()?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn run_loop(tui: &mut Tui, out: &mut impl Write) -> io::Result<()> {
+    let mut pacer = Pacer::new();
+    loop {
+        while event::poll(std::time::Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) if tui.key(key) => return Ok(()),
+                Event::Resize(..) => tui.redraw = true,
+                _ => {}
+            }
+        }
+        if !tui.session.paused {
+            let crashed = tui.session.gameboy.crash().is_some();
+            tui.session.gameboy.run_frame();
+            tui.session.frame += 1;
+            tui.release_stale_buttons();
+            // Pauses so the state can be inspected in the debugger
+            if let Some(crash) = tui.session.gameboy.crash().filter(|_| !crashed) {
+                tui.log(format!("The game crashed: {crash}"));
+                for instruction in tui.session.gameboy.trace_tail(CRASH_TRACE_LINES) {
+                    tui.log(format!("  {instruction}"));
+                }
+                tui.session.paused = true;
+            }
+        }
+        tui.draw(out)?;
+        pacer.wait(tui.session.gameboy.cycles());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tui::{braille_cell, Renderer};
+    use gb_emulator::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn test_braille_cell() {
+        let mut frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        assert_eq!(braille_cell(&frame, 0, 0), '\u{2800}');
+
+        // Top left and bottom right dots of the second cell, shade 1 stays blank
+        frame[2] = 3;
+        frame[3 * SCREEN_WIDTH + 3] = 2;
+        frame[SCREEN_WIDTH + 2] = 1;
+        assert_eq!(braille_cell(&frame, 1, 0), '\u{2881}');
+        assert_eq!(braille_cell(&frame, 0, 0), '\u{2800}');
+    }
+
+    #[test]
+    fn test_renderer_size() {
+        assert_eq!(Renderer::HalfBlock.size(), (160, 72));
+        assert_eq!(Renderer::Braille.size(), (80, 36));
+    }
+}
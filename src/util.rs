@@ -1,54 +1,16 @@
-#[derive(Debug, Copy, Clone)]
-pub struct Delay<T> {
-    value: T,
-    delayed_value: Option<T>,
-    counter: Option<u8>,
+/// 64-bit FNV-1a hash, stable across platforms and versions
+pub fn fnv1a_64(bytes: &[u8]) -> u64 {
+    fnv1a_64_iter(bytes.iter().copied())
 }
 
-impl<T> Delay<T> {
-    pub const fn new(value: T) -> Self {
-        Self {
-            value,
-            delayed_value: None,
-            counter: None,
-        }
-    }
-
-    pub const fn get(&self) -> &T {
-        &self.value
-    }
-
-    pub fn get_and_advance(&mut self) -> &T {
-        if self.counter == Some(0) {
-            self.value = self.delayed_value.take().unwrap();
-            self.counter = None;
-        }
-        self.counter = self.counter.map(|count| count - 1);
-        &self.value
-    }
-
-    pub fn set_delay(&mut self, delayed_value: T, counter: u8) {
-        self.delayed_value = Some(delayed_value);
-        self.counter = Some(counter);
-    }
+/// [`fnv1a_64`] of bytes produced one at a time, without collecting them first
+pub fn fnv1a_64_iter(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
 
-    pub fn clear_delay(&mut self) {
-        self.delayed_value = None;
-        self.counter = None;
-    }
-
-    pub fn into_inner(self) -> T {
-        self.value
-    }
-}
-
-impl<T> Default for Delay<T>
-where
-    T: Default,
-{
-    fn default() -> Self {
-        Self::new(T::default())
-    }
+    bytes.into_iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
 }
 
 /// Returns number of bits needed to represent n
@@ -58,15 +20,12 @@ pub const fn bits_needed(n: usize) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use crate::util::{bits_needed, Delay};
+    use crate::util::{bits_needed, fnv1a_64};
 
     #[test]
-    fn test_delay() {
-        let mut delay = Delay::new(false);
-        delay.set_delay(true, 2);
-        assert_eq!(*delay.get_and_advance(), false);
-        assert_eq!(*delay.get_and_advance(), false);
-        assert_eq!(*delay.get_and_advance(), true);
+    fn test_fnv1a_64() {
+        assert_eq!(fnv1a_64(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(fnv1a_64(b"a"), 0xAF63_DC4C_8601_EC8C);
     }
 
     #[test]
@@ -0,0 +1,281 @@
+//! Recording which code writes to watched addresses, to answer "what writes this variable"
+//! without stepping through breakpoints, and watchpoints queueing every CPU access to
+//! watched address ranges.
+
+use crate::bus_log::Access;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+
+/// Location of an instruction, with the bank mapped at its address when it ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CodeAddress {
+    pub bank: usize,
+    pub pc: u16,
+}
+
+impl Display for CodeAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02X}:{:04X}", self.bank, self.pc)
+    }
+}
+
+/// An instruction that wrote to a watched address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Writer {
+    pub location: CodeAddress,
+    /// Number of writes made by the instruction.
+    pub count: u64,
+    /// Value of the most recent write.
+    pub last_value: u8,
+}
+
+impl Display for Writer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} x{} last={:#04X}",
+            self.location, self.count, self.last_value
+        )
+    }
+}
+
+/// Writers recorded for each watched address.
+#[derive(Debug)]
+pub(crate) struct WriteWatches {
+    watches: BTreeMap<u16, BTreeMap<CodeAddress, Writer>>,
+}
+
+impl WriteWatches {
+    pub(crate) const fn new() -> Self {
+        Self {
+            watches: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// Starts watching an address, keeping writers already recorded for it.
+    pub(crate) fn watch(&mut self, addr: u16) {
+        self.watches.entry(addr).or_default();
+    }
+
+    pub(crate) fn unwatch(&mut self, addr: u16) {
+        self.watches.remove(&addr);
+    }
+
+    pub(crate) fn record(&mut self, addr: u16, location: CodeAddress, value: u8) {
+        if let Some(writers) = self.watches.get_mut(&addr) {
+            let writer = writers.entry(location).or_insert(Writer {
+                location,
+                count: 0,
+                last_value: value,
+            });
+            writer.count += 1;
+            writer.last_value = value;
+        }
+    }
+
+    /// Returns the writers of an address ordered by location, or `None` if it isn't watched.
+    pub(crate) fn writers(&self, addr: u16) -> Option<Vec<Writer>> {
+        self.watches
+            .get(&addr)
+            .map(|writers| writers.values().copied().collect())
+    }
+}
+
+/// Accesses a [`Watchpoint`] is hit by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Both reads and writes.
+    Access,
+}
+
+impl WatchKind {
+    const fn matches(self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (Self::Access, _) | (Self::Read, Access::Read) | (Self::Write, Access::Write)
+        )
+    }
+}
+
+/// A range of addresses watched for CPU accesses, see
+/// [`GameboyHardware::add_watchpoint`](crate::hardware::GameboyHardware::add_watchpoint).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    #[must_use]
+    pub const fn new(range: RangeInclusive<u16>, kind: WatchKind) -> Self {
+        Self { range, kind }
+    }
+}
+
+/// A CPU access hitting a [`Watchpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Instruction making the access.
+    pub location: CodeAddress,
+    pub addr: u16,
+    pub access: Access,
+    /// Value read or written.
+    pub value: u8,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+        };
+        write!(
+            f,
+            "{} {access} {:#06X} = {:#04X}",
+            self.location, self.addr, self.value
+        )
+    }
+}
+
+/// Watchpoints and the hits not taken yet.
+#[derive(Debug)]
+pub(crate) struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    hits: Vec<WatchHit>,
+}
+
+impl Watchpoints {
+    pub(crate) const fn new() -> Self {
+        Self {
+            watchpoints: Vec::new(),
+            hits: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    pub(crate) fn add(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|other| other != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub(crate) fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Queues a hit if the access matches a watchpoint, only once for overlapping ones.
+    pub(crate) fn record(&mut self, addr: u16, access: Access, value: u8, location: CodeAddress) {
+        let hit = self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.kind.matches(access) && watchpoint.range.contains(&addr));
+        if hit {
+            self.hits.push(WatchHit {
+                location,
+                addr,
+                access,
+                value,
+            });
+        }
+    }
+
+    pub(crate) fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus_log::Access;
+    use crate::cartridge::{Cartridge, HeaderBuilder};
+    use crate::hardware::GameboyHardware;
+    use crate::watch::{CodeAddress, WatchHit, WatchKind, Watchpoint, Writer};
+
+    // LD A, 0x12; loop: LD (0xC123), A; INC A; LD (0xC123), A; JR loop
+    const PROGRAM: [u8; 11] = [
+        0x3E, 0x12, 0xEA, 0x23, 0xC1, 0x3C, 0xEA, 0x23, 0xC1, 0x18, 0xF7,
+    ];
+
+    #[test]
+    fn test_records_writers() {
+        let rom = HeaderBuilder::new().build(&PROGRAM);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        assert_eq!(gameboy.writers(0xC123), None);
+
+        gameboy.watch_writers(0xC123);
+        // Entry point, then three times through the loop
+        for _ in 0..(3 + 4 * 3) {
+            gameboy.step();
+        }
+        let writer = |pc, last_value| Writer {
+            location: CodeAddress { bank: 0, pc },
+            count: 3,
+            last_value,
+        };
+        assert_eq!(
+            gameboy.writers(0xC123),
+            Some(vec![writer(0x152, 0x14), writer(0x156, 0x15)])
+        );
+        assert_eq!(gameboy.writers(0xC124), None);
+
+        gameboy.unwatch_writers(0xC123);
+        assert_eq!(gameboy.writers(0xC123), None);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let rom = HeaderBuilder::new().build(&PROGRAM);
+        let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+        let writes = Watchpoint::new(0xC120..=0xC12F, WatchKind::Write);
+        gameboy.add_watchpoint(writes.clone());
+        // Overlapping watchpoints hit once
+        gameboy.add_watchpoint(Watchpoint::new(0xC123..=0xC123, WatchKind::Access));
+        gameboy.add_watchpoint(Watchpoint::new(0xC123..=0xC123, WatchKind::Read));
+        assert_eq!(gameboy.watchpoints().len(), 3);
+
+        // Entry point, LD A, 0x12, then the first write
+        for _ in 0..4 {
+            gameboy.step();
+        }
+        let hit = |pc, value| WatchHit {
+            location: CodeAddress { bank: 0, pc },
+            addr: 0xC123,
+            access: Access::Write,
+            value,
+        };
+        assert_eq!(gameboy.take_watch_hits(), [hit(0x152, 0x12)]);
+        assert!(gameboy.take_watch_hits().is_empty());
+        gameboy.step();
+        assert!(gameboy.take_watch_hits().is_empty());
+        gameboy.step();
+        assert_eq!(gameboy.take_watch_hits(), [hit(0x156, 0x13)]);
+        assert_eq!(hit(0x156, 0x13).to_string(), "00:0156 write 0xC123 = 0x13");
+
+        // Fetching JR loop and its offset
+        gameboy.add_watchpoint(Watchpoint::new(0x0159..=0x015A, WatchKind::Read));
+        gameboy.step();
+        let hits = gameboy.take_watch_hits();
+        assert_eq!(
+            hits.iter().map(|hit| hit.addr).collect::<Vec<_>>(),
+            [0x159, 0x15A]
+        );
+        assert!(gameboy.remove_watchpoint(&writes));
+        assert!(!gameboy.remove_watchpoint(&writes));
+    }
+}
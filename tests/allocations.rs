@@ -0,0 +1,121 @@
+//! Checks that emulation allocates nothing once running, so frontends with tight latency
+//! budgets don't stall on the allocator.
+//!
+//! The ROM keeps the LCD, sprites, the window, the timer and a sound channel busy, and every
+//! frame goes through the audio and notification sinks, input and frame APIs a frontend would
+//! use.
+
+use gb_emulator::audio::{AudioSample, AudioSink};
+use gb_emulator::cartridge::{Cartridge, HeaderBuilder};
+use gb_emulator::cheat::Cheat;
+use gb_emulator::hardware::{Button, GameboyHardware};
+use gb_emulator::journal::Journal;
+use gb_emulator::notification::{Notification, NotificationSink};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    // Allocations made by this thread, counted while set
+    static ALLOCATIONS: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+struct CountingAllocator;
+
+impl CountingAllocator {
+    fn count() {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get().map(|count| count + 1)));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations `f` makes on this thread.
+fn allocations(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|count| count.set(Some(0)));
+    f();
+    ALLOCATIONS.with(|count| count.replace(None)).unwrap()
+}
+
+struct Sink;
+
+impl AudioSink for Sink {
+    fn sample_rate(&self) -> u32 {
+        48_000
+    }
+
+    fn push_sample(&mut self, _sample: &AudioSample) {}
+}
+
+impl NotificationSink for Sink {
+    fn notify(&mut self, _notification: &Notification) {}
+}
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 37] = [
+    // LCD on with sprites and the window, a sprite at the top left, a square wave
+    0x3E, 0xE3, 0xE0, 0x40,             // LD A, 0xE3; LDH (LCDC), A
+    0x3E, 0x10, 0xEA, 0x00, 0xFE,       // LD A, 0x10; LD (0xFE00), A
+    0xEA, 0x01, 0xFE,                   // LD (0xFE01), A
+    0x3E, 0xF0, 0xE0, 0x17,             // LD A, 0xF0; LDH (NR22), A
+    0x3E, 0x87, 0xE0, 0x19,             // LD A, 0x87; LDH (NR24), A
+    // Timer interrupts at 16 KiHz
+    0x3E, 0x05, 0xE0, 0x07,             // LD A, 0x05; LDH (TAC), A
+    0x3E, 0x05, 0xE0, 0xFF,             // LD A, VBLANK | TIMER; LDH (IE), A
+    0xFB,                               // EI
+    // loop: copy the joypad to VRAM, HALT
+    0xF0, 0x00, 0xEA, 0x00, 0x98,       // LDH A, (P1); LD (0x9800), A
+    0x76, 0x18, 0xF8,                   // HALT; JR loop
+];
+
+#[test]
+fn test_no_allocations_when_running() {
+    // Interrupt handlers return right away
+    let mut rom = HeaderBuilder::new().build(&PROGRAM);
+    rom[0x40] = 0xD9;
+    rom[0x50] = 0xD9;
+    let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+    gameboy.set_audio_sink(Some(Box::new(Sink)));
+    gameboy.set_notification_sink(Some(Box::new(Sink)));
+    // Optional features a frontend may keep on all the time
+    gameboy.set_lazy_ppu(true);
+    gameboy.set_crash_detection(Some(300));
+    gameboy.add_hash_region(0xC000..=0xDFFF);
+    gameboy.set_journal(Some(Journal::new()));
+    gameboy.set_trace_buffer(Some(1024));
+    gameboy.add_cheat(Cheat::new("Lives", "010399C1", true).unwrap());
+    for _ in 0..10 {
+        gameboy.run_frame();
+    }
+
+    let count = allocations(|| {
+        for frame in 0..120 {
+            gameboy.set_button(Button::A, frame % 2 == 0);
+            gameboy.run_frame();
+            let _ = gameboy.frame_hash();
+            let _ = gameboy.take_dirty_lines();
+            let _ = gameboy.input_display();
+            for _ in 0..100 {
+                gameboy.step();
+            }
+        }
+    });
+    assert_eq!(count, 0, "allocations while running");
+    assert_eq!(allocations(|| drop(vec![0u8; 1])), 1);
+}
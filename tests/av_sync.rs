@@ -0,0 +1,91 @@
+//! Checks that the APU sample clock stays locked to the PPU frame clock.
+//!
+//! The ROM starts a square wave and halts with interrupts disabled, so every step is a
+//! single M-cycle and frames end exactly [`FRAME_CYCLES`] apart.
+
+use gb_emulator::audio::{AudioSample, AudioSink};
+use gb_emulator::cartridge::{Cartridge, HeaderBuilder};
+use gb_emulator::consts::{AUDIO_NATIVE_HZ, FRAMES_PER_SECOND, FRAME_CYCLES};
+use gb_emulator::hardware::GameboyHardware;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// DI; LD A, 0xF0; LDH (NR22), A; LD A, 0x87; LDH (NR24), A; loop: HALT; JR loop
+const PROGRAM: [u8; 12] = [
+    0xF3, 0x3E, 0xF0, 0xE0, 0x17, 0x3E, 0x87, 0xE0, 0x19, 0x76, 0x18, 0xFD,
+];
+
+const SECONDS: f64 = 10.0;
+
+struct CountingSink {
+    sample_rate: u32,
+    samples: Arc<AtomicU64>,
+}
+
+impl AudioSink for CountingSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_sample(&mut self, _sample: &AudioSample) {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Runs the ROM for [`SECONDS`], returning the number of samples produced in each frame
+/// after the first, which starts partway through.
+fn samples_per_frame(sample_rate: u32) -> Vec<u64> {
+    let rom = HeaderBuilder::new().build(&PROGRAM);
+    let mut gameboy = GameboyHardware::new(Cartridge::new(rom));
+    let samples = Arc::new(AtomicU64::new(0));
+    gameboy.set_audio_sink(Some(Box::new(CountingSink {
+        sample_rate,
+        samples: Arc::clone(&samples),
+    })));
+
+    assert!(gameboy.run_frame());
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let frames = (SECONDS * FRAMES_PER_SECOND) as usize;
+    let mut counts = Vec::with_capacity(frames);
+    let mut previous = samples.load(Ordering::Relaxed);
+    for _ in 0..frames {
+        assert!(gameboy.run_frame());
+        let total = samples.load(Ordering::Relaxed);
+        counts.push(total - previous);
+        previous = total;
+    }
+    counts
+}
+
+#[test]
+fn test_whole_samples_per_frame() {
+    // A quarter of the native rate divides a frame evenly, so no frame may differ
+    let sample_rate = AUDIO_NATIVE_HZ / 4;
+    let expected = u64::from(FRAME_CYCLES / 4 / 4);
+    let counts = samples_per_frame(sample_rate);
+    assert!(counts.iter().all(|count| *count == expected), "{counts:?}");
+}
+
+#[test]
+fn test_fractional_samples_per_frame() {
+    // 803.6 samples per frame, every frame gets one of the neighbouring counts
+    // and the total never drifts more than a sample from the exact rate
+    let sample_rate = 48_000;
+    let per_frame =
+        f64::from(sample_rate) * f64::from(FRAME_CYCLES) / f64::from(AUDIO_NATIVE_HZ * 4);
+    let counts = samples_per_frame(sample_rate);
+
+    let mut total = 0;
+    for (frame, count) in (1..).zip(&counts) {
+        assert!(
+            *count == per_frame.floor() as u64 || *count == per_frame.ceil() as u64,
+            "frame {frame} produced {count} samples"
+        );
+        total += count;
+        let exact = per_frame * f64::from(frame);
+        assert!(
+            (total as f64 - exact).abs() < 1.0,
+            "drifted to {total} samples after {frame} frames, expected {exact}"
+        );
+    }
+}
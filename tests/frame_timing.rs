@@ -0,0 +1,66 @@
+//! Checks that frames are exactly [`FRAME_CYCLES`] T-cycles (154 lines of 456 dots) apart,
+//! so changes to the scheduler or the PPU can't add or drop a cycle per frame unnoticed.
+//!
+//! The CGB model is only checked in normal speed, double speed isn't emulated.
+
+use gb_emulator::cartridge::{Cartridge, HeaderBuilder};
+use gb_emulator::consts::FRAME_CYCLES;
+use gb_emulator::hardware::{GameboyHardware, Model};
+
+// DI; loop: HALT; JR loop
+const HALT_LOOP: [u8; 4] = [0xF3, 0x76, 0x18, 0xFD];
+// loop: JR loop
+const BUSY_LOOP: [u8; 2] = [0x18, 0xFE];
+
+// About 20 seconds
+const FRAMES: u64 = 1_200;
+// Longest instruction (CALL) plus an interrupt dispatch
+const MAX_STEP_CYCLES: u64 = 24 + 20;
+
+/// Runs `program` for [`FRAMES`] frames after the first, which starts partway through,
+/// returning the cycles between the end of each frame.
+fn frame_cycles(program: &[u8], model: Model) -> Vec<u64> {
+    let rom = HeaderBuilder::new().build(program);
+    let mut gameboy = GameboyHardware::with_model(Cartridge::new(rom), model);
+    assert!(gameboy.run_frame());
+    let mut previous = gameboy.cycles();
+    (0..FRAMES)
+        .map(|_| {
+            assert!(gameboy.run_frame());
+            let cycles = gameboy.cycles();
+            let frame = cycles - previous;
+            previous = cycles;
+            frame
+        })
+        .collect()
+}
+
+#[test]
+fn test_halted_frames_are_exact() {
+    // While halted every step is a single M-cycle, so each frame ends right on time
+    for model in [Model::Dmg, Model::Cgb] {
+        let frames = frame_cycles(&HALT_LOOP, model);
+        assert!(
+            frames
+                .iter()
+                .all(|&cycles| cycles == u64::from(FRAME_CYCLES)),
+            "{model:?} frame lengths: {:?}",
+            frames
+                .iter()
+                .find(|&&cycles| cycles != u64::from(FRAME_CYCLES))
+        );
+    }
+}
+
+#[test]
+fn test_frames_do_not_drift() {
+    // Instructions overrun the end of a frame, but the next frame ends on time regardless
+    for model in [Model::Dmg, Model::Cgb] {
+        let total: u64 = frame_cycles(&BUSY_LOOP, model).iter().sum();
+        let expected = FRAMES * u64::from(FRAME_CYCLES);
+        assert!(
+            total.abs_diff(expected) < MAX_STEP_CYCLES,
+            "{model:?} ran {total} T-cycles in {FRAMES} frames, expected {expected}"
+        );
+    }
+}